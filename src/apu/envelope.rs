@@ -0,0 +1,104 @@
+//! The volume of the square and noise channels can be made to increase or decrease over time.
+//! The envelope is configured through `NRx2` and clocked at 64 Hz.
+
+use crate::save_state::impl_save_state;
+
+#[derive(Default)]
+pub(super) struct Envelope {
+    /// The raw value of `NRx2`.
+    register: u8,
+    volume: u8,
+    /// The clocks left until the volume changes.
+    timer: u8,
+}
+
+impl_save_state!(Envelope {
+    register,
+    volume,
+    timer,
+});
+
+/// Bits 4-7 of `NRx2`: The volume the channel starts with when triggered.
+const INITIAL_VOLUME_SHIFT: u8 = 4;
+/// Bit 3 of `NRx2`: Whether the volume increases instead of decreasing.
+const INCREASE_BIT: u8 = 0b1000;
+/// Bits 0-2 of `NRx2`: How many clocks pass between volume changes. 0 stops the envelope.
+const PERIOD_MASK: u8 = 0b111;
+/// The DAC is turned on as long as any of the upper 5 bits of `NRx2` are set.
+const DAC_MASK: u8 = 0b1111_1000;
+
+impl Envelope {
+    pub(super) fn register(&self) -> u8 {
+        self.register
+    }
+
+    pub(super) fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    pub(super) fn is_dac_enabled(&self) -> bool {
+        self.register & DAC_MASK != 0
+    }
+
+    pub(super) fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub(super) fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+    }
+
+    pub(super) fn trigger(&mut self) {
+        self.volume = self.register >> INITIAL_VOLUME_SHIFT;
+        self.timer = self.register & PERIOD_MASK;
+    }
+
+    pub(super) fn clock(&mut self) {
+        let period = self.register & PERIOD_MASK;
+        if period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+
+        self.timer = period;
+        if self.register & INCREASE_BIT != 0 {
+            self.volume = (self.volume + 1).min(15);
+        } else {
+            self.volume = self.volume.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrease() {
+        let mut envelope = Envelope::default();
+        envelope.write(0xF2);
+        envelope.trigger();
+        assert_eq!(envelope.volume(), 15);
+
+        envelope.clock();
+        assert_eq!(envelope.volume(), 15);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 14);
+    }
+
+    #[test]
+    fn increase_stops_at_maximum() {
+        let mut envelope = Envelope::default();
+        envelope.write(0xE9);
+        envelope.trigger();
+
+        for _ in 0..5 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 15);
+        assert!(envelope.is_dac_enabled());
+    }
+}
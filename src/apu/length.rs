@@ -0,0 +1,100 @@
+//! Every channel can be turned off automatically after a fixed amount of time. The length counter
+//! is loaded through `NRx1` and counts down at 256 Hz while enabled through `NRx4`.
+
+use crate::save_state::impl_save_state;
+
+pub(super) struct LengthCounter {
+    /// 64 for most channels, 256 for the wave channel.
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl_save_state!(LengthCounter { counter, enabled });
+
+impl LengthCounter {
+    pub(super) fn new(max: u16) -> Self {
+        Self {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Handles a write to the length bits of `NRx1`.
+    pub(super) fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16;
+    }
+
+    /// Clocks the counter. Returns whether it just ran out, which disables the channel.
+    pub(super) fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    /// Handles a write to the length enable bit of `NRx4`. Enabling the counter while the frame
+    /// sequencer's next step doesn't clock it clocks it once more. Returns whether that made it
+    /// run out.
+    pub(super) fn set_enabled(&mut self, enabled: bool, next_step_clocks_length: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = enabled;
+        if !was_enabled && enabled && !next_step_clocks_length {
+            return self.clock();
+        }
+        false
+    }
+
+    /// Clears the counter together with the rest of the APU. On the DMG, the counter itself
+    /// survives.
+    pub(super) fn power_off(&mut self, keep_counter: bool) {
+        self.enabled = false;
+        if !keep_counter {
+            self.counter = 0;
+        }
+    }
+
+    /// Reloads an expired counter when the channel is triggered. If that happens while the next
+    /// step doesn't clock the counter, it starts out one lower.
+    pub(super) fn trigger(&mut self, next_step_clocks_length: bool) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && !next_step_clocks_length {
+                self.counter -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_out() {
+        let mut length = LengthCounter::new(64);
+        length.load(62);
+        assert!(!length.clock());
+
+        length.set_enabled(true, true);
+        assert!(!length.clock());
+        assert!(length.clock());
+        assert!(!length.clock());
+    }
+
+    #[test]
+    fn extra_clock_when_enabled() {
+        let mut length = LengthCounter::new(64);
+        length.load(63);
+        assert!(length.set_enabled(true, false));
+
+        length.trigger(false);
+        assert_eq!(length.counter, 63);
+    }
+}
@@ -0,0 +1,376 @@
+//! The APU generates sound with four channels: Two square waves, a custom wave and noise. Each
+//! channel is panned to the left and right output, which are mixed and turned into stereo samples
+//! at a configurable sample rate.
+//! The length counters, envelopes and the sweep are clocked by the frame sequencer, which itself
+//! is clocked by the timer's `DIV` register at 512 Hz.
+
+mod envelope;
+mod length;
+mod noise;
+mod square;
+mod wave;
+
+use crate::memory_map::*;
+use crate::model::Model;
+use crate::save_state::impl_save_state;
+use noise::Noise;
+use square::Square;
+use wave::Wave;
+
+/// The sample rate used unless another one is configured.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+/// The registers of a channel are 5 bytes apart, starting with `NR10`.
+const CHANNEL_REGISTERS: usize = 5;
+/// Bit 7 of `NR52`: Turns the whole APU on or off.
+const POWER_BIT: u8 = 0b1000_0000;
+
+/// How much of the charge the high-pass filter's capacitor keeps per CPU cycle.
+const CAPACITOR_CHARGE_FACTOR: f32 = 0.999_958;
+
+pub(crate) struct Apu {
+    enabled: bool,
+    square_1: Square,
+    square_2: Square,
+    wave: Wave,
+    noise: Noise,
+    /// `NR50`: The volume of each output.
+    master_volume: u8,
+    /// `NR51`: Which channels are output on the left (upper nibble) and right (lower nibble).
+    panning: u8,
+    /// The step the frame sequencer executes next.
+    frame_sequencer_step: u8,
+    /// The CGB clears the length counters when the APU is turned off, and allows writing them
+    /// only while it is on.
+    is_cgb: bool,
+    clock_rate: u32,
+    sample_rate: u32,
+    /// Accumulates `cycles * sample_rate`. Whenever it reaches the clock rate, a sample is due.
+    sample_clock: u64,
+    /// The charge of the high-pass filter's capacitors for the left and right output.
+    capacitors: [f32; 2],
+    capacitor_factor: f32,
+    /// Interleaved stereo samples that haven't been taken yet.
+    samples: Vec<f32>,
+}
+
+// The sample rate is a setting of the frontend, so it isn't part of the state.
+impl_save_state!(Apu {
+    enabled,
+    square_1,
+    square_2,
+    wave,
+    noise,
+    master_volume,
+    panning,
+    frame_sequencer_step,
+    capacitors,
+});
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new(Model::default(), DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl Apu {
+    pub(crate) fn new(model: Model, sample_rate: u32) -> Self {
+        let clock_rate = model.clock_rate();
+        Self {
+            enabled: false,
+            square_1: Square::new(true),
+            square_2: Square::new(false),
+            wave: Wave::default(),
+            noise: Noise::default(),
+            master_volume: 0,
+            panning: 0,
+            frame_sequencer_step: 0,
+            is_cgb: model.is_cgb(),
+            clock_rate,
+            sample_rate,
+            sample_clock: 0,
+            capacitors: [0.0; 2],
+            capacitor_factor: CAPACITOR_CHARGE_FACTOR.powf(clock_rate as f32 / sample_rate as f32),
+            samples: Vec::new(),
+        }
+    }
+
+    /// The APU as the boot ROM leaves it after playing its startup sound on channel 1.
+    pub(crate) fn after_boot(model: Model, sample_rate: u32) -> Self {
+        let mut apu = Self::new(model, sample_rate);
+        apu.write_register(SOUND_CONTROL_REGISTER, POWER_BIT);
+        apu.write_register(0xFF11, 0xBF);
+        apu.write_register(0xFF12, 0xF3);
+        apu.write_register(0xFF24, 0x77);
+        apu.write_register(0xFF25, 0xF3);
+        // The SGB's boot ROM doesn't play the sound.
+        if !model.is_sgb() {
+            apu.square_1.finish_boot_sound();
+        }
+        apu
+    }
+
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Changes the rate at which samples are generated.
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_clock = 0;
+        self.capacitor_factor =
+            CAPACITOR_CHARGE_FACTOR.powf(self.clock_rate as f32 / sample_rate as f32);
+    }
+
+    /// Returns the samples generated since the last call, interleaved as left and right.
+    pub(crate) fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Advances the channels by the given amount of cycles at normal speed, generating samples
+    /// along the way.
+    pub(crate) fn step(&mut self, cycles: u32) {
+        if self.enabled {
+            self.square_1.step(cycles);
+            self.square_2.step(cycles);
+            self.wave.step(cycles);
+            self.noise.step(cycles);
+        }
+
+        self.sample_clock += cycles as u64 * self.sample_rate as u64;
+        while self.sample_clock >= self.clock_rate as u64 {
+            self.sample_clock -= self.clock_rate as u64;
+            let [left, right] = self.mix();
+            self.samples.push(left);
+            self.samples.push(right);
+        }
+    }
+
+    /// Clocks the frame sequencer, which clocks the length counters at 256 Hz, the sweep at
+    /// 128 Hz and the envelopes at 64 Hz.
+    pub(crate) fn clock_frame_sequencer(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        let step = self.frame_sequencer_step;
+        if step.is_multiple_of(2) {
+            self.square_1.clock_length();
+            self.square_2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if step == 2 || step == 6 {
+            self.square_1.clock_sweep();
+        }
+        if step == 7 {
+            self.square_1.clock_envelope();
+            self.square_2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.frame_sequencer_step = (step + 1) % 8;
+    }
+
+    /// Mixes the output of all channels into a sample for the left and right output.
+    fn mix(&mut self) -> [f32; 2] {
+        let outputs = [
+            self.square_1.output(),
+            self.square_2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ];
+        let mut mixed = [0.0; 2];
+        for (channel, output) in outputs.into_iter().enumerate() {
+            // The DACs turn the digital value of 0 to 15 into an analog one from 1 to -1.
+            let Some(output) = output else {
+                continue;
+            };
+            let analog = 1.0 - output as f32 / 7.5;
+            if self.panning & (0x10 << channel) != 0 {
+                mixed[0] += analog;
+            }
+            if self.panning & (0x01 << channel) != 0 {
+                mixed[1] += analog;
+            }
+        }
+
+        let volumes = [
+            (self.master_volume >> 4) & 0b111,
+            self.master_volume & 0b111,
+        ];
+        let mut sample = [0.0; 2];
+        for side in 0..2 {
+            let input = mixed[side] / 4.0 * (volumes[side] + 1) as f32 / 8.0;
+            // A capacitor removes the DC offset, just like on the real hardware.
+            let output = input - self.capacitors[side];
+            self.capacitors[side] = input - output * self.capacitor_factor;
+            sample[side] = if self.enabled { output } else { 0.0 };
+        }
+        sample
+    }
+
+    /// Whether the frame sequencer's next step clocks the length counters, which decides some of
+    /// their quirks.
+    fn next_step_clocks_length(&self) -> bool {
+        self.frame_sequencer_step.is_multiple_of(2)
+    }
+
+    pub(crate) fn read_register(&self, address: usize) -> u8 {
+        match address {
+            SOUND_CONTROL_REGISTER => {
+                let channels = [
+                    self.square_1.is_enabled(),
+                    self.square_2.is_enabled(),
+                    self.wave.is_enabled(),
+                    self.noise.is_enabled(),
+                ];
+                let status = channels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |status, (channel, &enabled)| {
+                        status | (enabled as u8) << channel
+                    });
+                let power = if self.enabled { POWER_BIT } else { 0 };
+                power | 0b0111_0000 | status
+            }
+            MASTER_VOLUME_REGISTER => self.master_volume,
+            SOUND_PANNING_REGISTER => self.panning,
+            AUDIO_REGISTER_START..SOUND_CONTROL_REGISTER => {
+                let offset = address - AUDIO_REGISTER_START;
+                let register = offset % CHANNEL_REGISTERS;
+                match offset / CHANNEL_REGISTERS {
+                    0 => self.square_1.read(register),
+                    1 => self.square_2.read(register),
+                    2 => self.wave.read(register),
+                    _ => self.noise.read(register),
+                }
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => self.wave.read_ram(address - WAVE_RAM_START),
+            _ => 0xFF,
+        }
+    }
+
+    pub(crate) fn write_register(&mut self, address: usize, value: u8) {
+        match address {
+            SOUND_CONTROL_REGISTER => {
+                let enabled = value & POWER_BIT != 0;
+                if self.enabled && !enabled {
+                    self.power_off();
+                } else if !self.enabled && enabled {
+                    self.frame_sequencer_step = 0;
+                }
+                self.enabled = enabled;
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => self.wave.write_ram(address - WAVE_RAM_START, value),
+            // While the APU is off, only the length counters can be written on the DMG.
+            _ if !self.enabled => match address {
+                _ if self.is_cgb => (),
+                0xFF11 => self.square_1.load_length(value),
+                0xFF16 => self.square_2.load_length(value),
+                0xFF1B => self.wave.load_length(value),
+                0xFF20 => self.noise.load_length(value),
+                _ => (),
+            },
+            MASTER_VOLUME_REGISTER => self.master_volume = value,
+            SOUND_PANNING_REGISTER => self.panning = value,
+            AUDIO_REGISTER_START..SOUND_CONTROL_REGISTER => {
+                let offset = address - AUDIO_REGISTER_START;
+                let register = offset % CHANNEL_REGISTERS;
+                let next_step_clocks_length = self.next_step_clocks_length();
+                match offset / CHANNEL_REGISTERS {
+                    0 => self
+                        .square_1
+                        .write(register, value, next_step_clocks_length),
+                    1 => self
+                        .square_2
+                        .write(register, value, next_step_clocks_length),
+                    2 => self.wave.write(register, value, next_step_clocks_length),
+                    _ => self.noise.write(register, value, next_step_clocks_length),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Turning the APU off clears all of its registers.
+    fn power_off(&mut self) {
+        let keep_length = !self.is_cgb;
+        self.square_1.power_off(keep_length);
+        self.square_2.power_off(keep_length);
+        self.wave.power_off(keep_length);
+        self.noise.power_off(keep_length);
+        self.master_volume = 0;
+        self.panning = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_read_masks() {
+        let mut apu = Apu::default();
+        apu.write_register(SOUND_CONTROL_REGISTER, POWER_BIT);
+        for address in AUDIO_REGISTER_START..=AUDIO_REGISTER_END {
+            if address != SOUND_CONTROL_REGISTER {
+                apu.write_register(address, 0x00);
+            }
+        }
+
+        let reads: Vec<_> = (AUDIO_REGISTER_START..=AUDIO_REGISTER_END)
+            .map(|address| apu.read_register(address))
+            .collect();
+        assert_eq!(
+            reads,
+            [
+                0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF,
+                0xBF, 0xFF, 0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0xF0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                0xFF, 0xFF, 0xFF, 0xFF,
+            ]
+        );
+    }
+
+    #[test]
+    fn power_off_clears_registers() {
+        let mut apu = Apu::after_boot(Model::Dmg, DEFAULT_SAMPLE_RATE);
+        assert_eq!(apu.read_register(SOUND_CONTROL_REGISTER), 0xF1);
+
+        apu.write_register(SOUND_CONTROL_REGISTER, 0x00);
+        assert_eq!(apu.read_register(SOUND_CONTROL_REGISTER), 0x70);
+        assert_eq!(apu.read_register(MASTER_VOLUME_REGISTER), 0x00);
+
+        // Writes are ignored while the APU is off.
+        apu.write_register(MASTER_VOLUME_REGISTER, 0x77);
+        assert_eq!(apu.read_register(MASTER_VOLUME_REGISTER), 0x00);
+    }
+
+    #[test]
+    fn length_counter_disables_channel() {
+        let mut apu = Apu::default();
+        apu.write_register(SOUND_CONTROL_REGISTER, POWER_BIT);
+        apu.write_register(0xFF17, 0xF0);
+        // A length of 2, then trigger with the length counter enabled.
+        apu.write_register(0xFF16, 62);
+        apu.write_register(0xFF19, 0xC0);
+        assert_eq!(apu.read_register(SOUND_CONTROL_REGISTER), 0xF2);
+
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read_register(SOUND_CONTROL_REGISTER), 0xF2);
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read_register(SOUND_CONTROL_REGISTER), 0xF0);
+    }
+
+    #[test]
+    fn generates_samples_at_sample_rate() {
+        let mut apu = Apu::new(Model::Dmg, 32_768);
+        for _ in 0..Model::Dmg.clock_rate() / 4 {
+            apu.step(4);
+        }
+
+        let samples = apu.take_samples();
+        assert_eq!(samples.len(), 32_768 * 2);
+        assert!(samples.iter().all(|&sample| sample == 0.0));
+        assert!(apu.take_samples().is_empty());
+    }
+}
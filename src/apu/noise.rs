@@ -0,0 +1,177 @@
+//! Channel 4 outputs pseudo-random noise, generated by a linear feedback shift register.
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+use crate::save_state::impl_save_state;
+
+/// Bits 0-2 of `NR43` select one of these divisors for the shift register's clock.
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Unused bits of `NR40`-`NR44` that always read as set. `NR40` doesn't exist.
+const READ_MASKS: [u8; 5] = [0xFF, 0xFF, 0x00, 0x00, 0xBF];
+
+/// Bit 3 of `NR43`: Use a 7-bit shift register, which produces a more regular sound.
+const SHORT_MODE_BIT: u8 = 0b1000;
+
+pub(super) struct Noise {
+    enabled: bool,
+    /// The raw value of `NR43`.
+    polynomial: u8,
+    /// The linear feedback shift register. Only the lower 15 bits are used.
+    lfsr: u16,
+    /// The cycles left until the shift register is clocked.
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl_save_state!(Noise {
+    enabled,
+    polynomial,
+    lfsr,
+    timer,
+    length,
+    envelope,
+});
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            polynomial: 0,
+            lfsr: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+}
+
+impl Noise {
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The cycles between two clocks of the shift register.
+    fn period(&self) -> u32 {
+        DIVISORS[(self.polynomial & 0b111) as usize] << (self.polynomial >> 4)
+    }
+
+    pub(super) fn step(&mut self, cycles: u32) {
+        let mut remaining = cycles;
+        while remaining >= self.timer {
+            remaining -= self.timer;
+            self.timer = self.period();
+
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            if self.polynomial & SHORT_MODE_BIT != 0 {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        }
+        self.timer -= remaining;
+    }
+
+    /// The digital output between 0 and 15, or [`None`] if the DAC is turned off.
+    pub(super) fn output(&self) -> Option<u8> {
+        if !self.envelope.is_dac_enabled() {
+            return None;
+        }
+        Some(if self.enabled && self.lfsr & 1 == 0 {
+            self.envelope.volume()
+        } else {
+            0
+        })
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Reads `NR40`-`NR44`, given as an index from 0 to 4.
+    pub(super) fn read(&self, register: usize) -> u8 {
+        let value = match register {
+            2 => self.envelope.register(),
+            3 => self.polynomial,
+            4 if self.length.is_enabled() => 0b0100_0000,
+            _ => 0,
+        };
+        value | READ_MASKS[register]
+    }
+
+    /// Writes `NR40`-`NR44`, given as an index from 0 to 4.
+    pub(super) fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            1 => self.length.load(value & 0b0011_1111),
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.polynomial = value,
+            4 => {
+                if self
+                    .length
+                    .set_enabled(value & 0b0100_0000 != 0, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if value & 0b1000_0000 != 0 {
+                    self.trigger(next_step_clocks_length);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Writes `NR41`. The only write possible while the APU is off on the DMG.
+    pub(super) fn load_length(&mut self, value: u8) {
+        self.length.load(value & 0b0011_1111);
+    }
+
+    /// Resets the channel together with the rest of the APU.
+    pub(super) fn power_off(&mut self, keep_length: bool) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(64));
+        length.power_off(keep_length);
+        *self = Self {
+            length,
+            ..Self::default()
+        };
+    }
+
+    fn trigger(&mut self, next_step_clocks_length: bool) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger(next_step_clocks_length);
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_mode_repeats_every_127_clocks() {
+        let mut noise = Noise::default();
+        noise.write(2, 0xF0, true);
+        noise.write(3, SHORT_MODE_BIT, true);
+        noise.write(4, 0x80, true);
+
+        let mut outputs = Vec::new();
+        for _ in 0..254 {
+            noise.step(8);
+            outputs.push(noise.output().unwrap());
+        }
+        assert_eq!(outputs[..127], outputs[127..]);
+        assert!(outputs.contains(&0) && outputs.contains(&15));
+    }
+}
@@ -0,0 +1,338 @@
+//! Channels 1 and 2 output a square wave with a selectable duty cycle. Channel 1 can additionally
+//! sweep its frequency up or down through `NR10`.
+
+use super::envelope::Envelope;
+use super::length::LengthCounter;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter, impl_save_state};
+
+/// The waveforms for the duty cycles 12.5%, 25%, 50% and 75%, played from bit 0 upwards.
+const DUTY_PATTERNS: [u8; 4] = [0b1000_0000, 0b1000_0001, 0b1110_0001, 0b0111_1110];
+
+/// The highest frequency value. A sweep past it disables the channel.
+const MAX_FREQUENCY: u16 = 0x7FF;
+
+/// Unused bits of `NRx0`-`NRx4` that always read as set.
+const READ_MASKS: [u8; 5] = [0x80, 0x3F, 0x00, 0xFF, 0xBF];
+
+#[derive(Default)]
+struct Sweep {
+    /// The raw value of `NR10`.
+    register: u8,
+    /// The frequency the sweep works with, copied from the channel's when triggered.
+    shadow_frequency: u16,
+    timer: u8,
+    enabled: bool,
+    /// Whether a frequency was calculated in subtraction mode since the last trigger.
+    negate_used: bool,
+}
+
+impl_save_state!(Sweep {
+    register,
+    shadow_frequency,
+    timer,
+    enabled,
+    negate_used,
+});
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0b111
+    }
+
+    fn negate(&self) -> bool {
+        self.register & 0b1000 != 0
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0b111
+    }
+
+    /// Calculates the next frequency. Returns [`None`] if it overflows.
+    fn next_frequency(&mut self) -> Option<u16> {
+        let delta = self.shadow_frequency >> self.shift();
+        let frequency = if self.negate() {
+            self.negate_used = true;
+            self.shadow_frequency - delta
+        } else {
+            self.shadow_frequency + delta
+        };
+        (frequency <= MAX_FREQUENCY).then_some(frequency)
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of 0 is treated as 8.
+        self.timer = match self.period() {
+            0 => 8,
+            period => period,
+        };
+    }
+}
+
+pub(super) struct Square {
+    /// Only channel 1 has a sweep unit.
+    sweep: Option<Sweep>,
+    enabled: bool,
+    /// Bits 6-7 of `NRx1`.
+    duty: u8,
+    /// The bit of the duty pattern that is currently output.
+    duty_position: u8,
+    frequency: u16,
+    /// The cycles left until the next step of the duty pattern.
+    timer: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl SaveState for Square {
+    fn save(&self, writer: &mut StateWriter) {
+        self.sweep.save(writer);
+        self.enabled.save(writer);
+        self.duty.save(writer);
+        self.duty_position.save(writer);
+        self.frequency.save(writer);
+        self.timer.save(writer);
+        SaveState::save(&self.length, writer);
+        self.envelope.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.sweep.load(reader)?;
+        self.enabled.load(reader)?;
+        self.duty.load(reader)?;
+        self.duty_position.load(reader)?;
+        self.frequency.load(reader)?;
+        self.timer.load(reader)?;
+        SaveState::load(&mut self.length, reader)?;
+        self.envelope.load(reader)?;
+        if self.duty as usize >= DUTY_PATTERNS.len() || self.duty_position >= 8 {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl Square {
+    pub(super) fn new(with_sweep: bool) -> Self {
+        Self {
+            sweep: with_sweep.then(Sweep::default),
+            enabled: false,
+            duty: 0,
+            duty_position: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Puts the channel into the state the boot ROM leaves it in after playing its sound: Still
+    /// enabled, but with a volume of 0.
+    pub(super) fn finish_boot_sound(&mut self) {
+        self.enabled = true;
+        self.envelope.set_volume(0);
+    }
+
+    /// The cycles between two steps of the duty pattern.
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    pub(super) fn step(&mut self, cycles: u32) {
+        let mut remaining = cycles;
+        while remaining >= self.timer {
+            remaining -= self.timer;
+            self.timer = self.period();
+            self.duty_position = (self.duty_position + 1) % 8;
+        }
+        self.timer -= remaining;
+    }
+
+    /// The digital output between 0 and 15, or [`None`] if the DAC is turned off.
+    pub(super) fn output(&self) -> Option<u8> {
+        if !self.envelope.is_dac_enabled() {
+            return None;
+        }
+        let high = DUTY_PATTERNS[self.duty as usize] >> self.duty_position & 1 != 0;
+        Some(if self.enabled && high {
+            self.envelope.volume()
+        } else {
+            0
+        })
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period() == 0 {
+            return;
+        }
+
+        match sweep.next_frequency() {
+            Some(frequency) if sweep.shift() != 0 => {
+                sweep.shadow_frequency = frequency;
+                self.frequency = frequency;
+                // The new frequency is checked for an overflow once more right away.
+                if sweep.next_frequency().is_none() {
+                    self.enabled = false;
+                }
+            }
+            Some(_) => (),
+            None => self.enabled = false,
+        }
+    }
+
+    /// Reads `NRx0`-`NRx4`, given as an index from 0 to 4.
+    pub(super) fn read(&self, register: usize) -> u8 {
+        let value = match register {
+            0 => self.sweep.as_ref().map_or(0xFF, |sweep| sweep.register),
+            1 => self.duty << 6,
+            2 => self.envelope.register(),
+            4 if self.length.is_enabled() => 0b0100_0000,
+            _ => 0,
+        };
+        value | READ_MASKS[register]
+    }
+
+    /// Writes `NRx0`-`NRx4`, given as an index from 0 to 4.
+    pub(super) fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.register = value;
+                    // Leaving subtraction mode after it has been used disables the channel.
+                    if !sweep.negate() && sweep.negate_used {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0b0011_1111);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8);
+                if self
+                    .length
+                    .set_enabled(value & 0b0100_0000 != 0, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if value & 0b1000_0000 != 0 {
+                    self.trigger(next_step_clocks_length);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Writes the length bits of `NRx1`. The only write possible while the APU is off on the DMG.
+    pub(super) fn load_length(&mut self, value: u8) {
+        self.length.load(value & 0b0011_1111);
+    }
+
+    /// Resets the channel together with the rest of the APU.
+    pub(super) fn power_off(&mut self, keep_length: bool) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(64));
+        length.power_off(keep_length);
+        *self = Self {
+            length,
+            ..Self::new(self.sweep.is_some())
+        };
+    }
+
+    fn trigger(&mut self, next_step_clocks_length: bool) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.trigger(next_step_clocks_length);
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow_frequency = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+            sweep.negate_used = false;
+            if sweep.shift() != 0 && sweep.next_frequency().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duty_cycle() {
+        let mut square = Square::new(false);
+        square.write(1, 0b1000_0000, true);
+        square.write(2, 0xF0, true);
+        square.write(3, 0xFF, true);
+        square.write(4, 0x87, true);
+        assert_eq!(square.period(), 4);
+
+        let outputs: Vec<_> = (0..8)
+            .map(|_| {
+                square.step(4);
+                square.output().unwrap()
+            })
+            .collect();
+        // The 50% pattern, starting from its second step.
+        assert_eq!(outputs, [0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn sweep_overflow_disables_channel() {
+        let mut square = Square::new(true);
+        // Period 1, addition, shift 1.
+        square.write(0, 0b0001_0001, true);
+        square.write(2, 0xF0, true);
+        square.write(3, 0x00, true);
+        square.write(4, 0x84, true);
+        assert!(square.is_enabled());
+
+        square.clock_sweep();
+        assert_eq!(square.frequency, 0x600);
+        // 0x600 + 0x300 overflows.
+        assert!(!square.is_enabled());
+    }
+
+    #[test]
+    fn dac_off_disables_channel() {
+        let mut square = Square::new(false);
+        square.write(2, 0xF0, true);
+        square.write(4, 0x80, true);
+        assert!(square.is_enabled());
+
+        square.write(2, 0x07, true);
+        assert!(!square.is_enabled());
+        assert_eq!(square.output(), None);
+    }
+}
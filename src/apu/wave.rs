@@ -0,0 +1,234 @@
+//! Channel 3 plays back 32 4-bit samples from wave RAM at a selectable volume.
+
+use super::length::LengthCounter;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter};
+
+/// Wave RAM holds 32 samples, two per byte with the upper nibble first.
+pub(super) const WAVE_RAM_SIZE: usize = 16;
+const SAMPLE_COUNT: u8 = 32;
+
+/// Unused bits of `NR30`-`NR34` that always read as set.
+const READ_MASKS: [u8; 5] = [0x7F, 0xFF, 0x9F, 0xFF, 0xBF];
+
+/// Bits 5-6 of `NR32`: Mute, 100%, 50% or 25% volume, implemented as a right shift.
+const VOLUME_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+
+pub(super) struct Wave {
+    enabled: bool,
+    /// Bit 7 of `NR30`.
+    dac_enabled: bool,
+    /// Bits 5-6 of `NR32`.
+    volume: u8,
+    frequency: u16,
+    /// The cycles left until the next sample is played.
+    timer: u32,
+    /// The index of the sample that is currently played.
+    position: u8,
+    /// The sample that is currently played. Only updated when the position advances.
+    sample: u8,
+    length: LengthCounter,
+    ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl SaveState for Wave {
+    fn save(&self, writer: &mut StateWriter) {
+        self.enabled.save(writer);
+        self.dac_enabled.save(writer);
+        self.volume.save(writer);
+        self.frequency.save(writer);
+        self.timer.save(writer);
+        self.position.save(writer);
+        self.sample.save(writer);
+        SaveState::save(&self.length, writer);
+        self.ram.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.enabled.load(reader)?;
+        self.dac_enabled.load(reader)?;
+        self.volume.load(reader)?;
+        self.frequency.load(reader)?;
+        self.timer.load(reader)?;
+        self.position.load(reader)?;
+        self.sample.load(reader)?;
+        SaveState::load(&mut self.length, reader)?;
+        self.ram.load(reader)?;
+        if self.volume as usize >= VOLUME_SHIFTS.len() || self.position >= SAMPLE_COUNT {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            volume: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            length: LengthCounter::new(256),
+            ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+}
+
+impl Wave {
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The cycles between two samples.
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    pub(super) fn step(&mut self, cycles: u32) {
+        if !self.enabled {
+            return;
+        }
+        let mut remaining = cycles;
+        while remaining >= self.timer {
+            remaining -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % SAMPLE_COUNT;
+            let byte = self.ram[self.position as usize / 2];
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0xF
+            };
+        }
+        self.timer -= remaining;
+    }
+
+    /// The digital output between 0 and 15, or [`None`] if the DAC is turned off.
+    pub(super) fn output(&self) -> Option<u8> {
+        if !self.dac_enabled {
+            return None;
+        }
+        Some(if self.enabled {
+            self.sample >> VOLUME_SHIFTS[self.volume as usize]
+        } else {
+            0
+        })
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Reads wave RAM. While the channel is playing, the byte that is currently being played is
+    /// read instead, regardless of the address.
+    pub(super) fn read_ram(&self, offset: usize) -> u8 {
+        if self.enabled {
+            self.ram[self.position as usize / 2]
+        } else {
+            self.ram[offset]
+        }
+    }
+
+    /// Writes wave RAM, with the same redirection as [`Self::read_ram`].
+    pub(super) fn write_ram(&mut self, offset: usize, value: u8) {
+        if self.enabled {
+            self.ram[self.position as usize / 2] = value;
+        } else {
+            self.ram[offset] = value;
+        }
+    }
+
+    /// Reads `NR30`-`NR34`, given as an index from 0 to 4.
+    pub(super) fn read(&self, register: usize) -> u8 {
+        let value = match register {
+            0 if self.dac_enabled => 0b1000_0000,
+            2 => self.volume << 5,
+            4 if self.length.is_enabled() => 0b0100_0000,
+            _ => 0,
+        };
+        value | READ_MASKS[register]
+    }
+
+    /// Writes `NR30`-`NR34`, given as an index from 0 to 4.
+    pub(super) fn write(&mut self, register: usize, value: u8, next_step_clocks_length: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0b1000_0000 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => self.volume = (value >> 5) & 0b11,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value as u16 & 0b111) << 8);
+                if self
+                    .length
+                    .set_enabled(value & 0b0100_0000 != 0, next_step_clocks_length)
+                {
+                    self.enabled = false;
+                }
+                if value & 0b1000_0000 != 0 {
+                    self.trigger(next_step_clocks_length);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Writes `NR31`. The only write possible while the APU is off on the DMG.
+    pub(super) fn load_length(&mut self, value: u8) {
+        self.length.load(value);
+    }
+
+    fn trigger(&mut self, next_step_clocks_length: bool) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger(next_step_clocks_length);
+        // Playback starts at the second sample, after a short delay.
+        self.position = 0;
+        self.timer = self.period() + 6;
+    }
+
+    /// Turns the channel off together with the APU. Wave RAM keeps its contents.
+    pub(super) fn power_off(&mut self, keep_length: bool) {
+        let mut length = std::mem::replace(&mut self.length, LengthCounter::new(256));
+        length.power_off(keep_length);
+        *self = Self {
+            ram: self.ram,
+            length,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_samples_at_volume() {
+        let mut wave = Wave::default();
+        for offset in 0..WAVE_RAM_SIZE {
+            wave.write_ram(offset, 0x8F);
+        }
+        wave.write(0, 0x80, true);
+        // 50% volume.
+        wave.write(2, 0x40, true);
+        wave.write(3, 0xFF, true);
+        wave.write(4, 0x87, true);
+        assert!(wave.is_enabled());
+
+        wave.step(2 + 6);
+        assert_eq!(wave.output(), Some(0xF >> 1));
+        wave.step(2);
+        assert_eq!(wave.output(), Some(0x8 >> 1));
+        // While playing, wave RAM accesses go to the current byte.
+        wave.write_ram(0xA, 0x12);
+        assert_eq!(wave.read_ram(0x0), 0x12);
+    }
+}
@@ -0,0 +1,576 @@
+//! Assembles SM83 source code in the syntax of RGBDS into machine code, for writing test programs
+//! and patches.
+//!
+//! Every line holds an optional label like `loop:`, followed by an instruction or a data
+//! directive, and comments start with `;`. Mnemonics and registers aren't case-sensitive.
+//! Instructions are written like the disassembler shows them, and some common shortcuts are
+//! understood as well: A can be left out of arithmetic instructions (`add b`), `[HLI]` and
+//! `[HLD]` can be used for `[HL+]` and `[HL-]`, and `LDH` also takes the lower byte of the
+//! address. Data is written with `DB` (numbers or strings), `DW` and `DS <count>[, <fill>]`.
+//!
+//! Numbers are decimal, hexadecimal with `$` or `0x`, or binary with `%` or `0b`. Operands can
+//! add and subtract numbers and labels, like `table + 2`.
+
+use crate::cpu::opcode_patterns;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Why source code couldn't be assembled.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AssembleError {
+    /// The number of the invalid line, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// Assembles a program, panicking if it's invalid. The address the program is loaded at can be
+/// given first, which is needed if it jumps to its labels with absolute jumps.
+///
+/// ```
+/// let program = gameboy_emu::asm!("ld a, $12\nadd b\nhalt");
+/// assert_eq!(program, [0x3E, 0x12, 0x80, 0x76]);
+/// let program = gameboy_emu::asm!(0xC000, "loop: jp loop");
+/// assert_eq!(program, [0xC3, 0x00, 0xC0]);
+/// ```
+#[macro_export]
+macro_rules! asm {
+    ($source:expr) => {
+        $crate::asm!(0, $source)
+    };
+    ($origin:expr, $source:expr) => {
+        $crate::assemble($source, $origin).unwrap_or_else(|error| panic!("{error}"))
+    };
+}
+
+/// A 32 KiB cartridge without an MBC that runs the given program from its entry point.
+#[cfg(test)]
+pub(crate) fn rom_with_program(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom
+}
+
+/// A cartridge that adds the state of the directional pad to 0xC000 in a loop, so the memory
+/// depends on the buttons held during every frame.
+#[cfg(test)]
+pub(crate) fn joypad_accumulator_rom() -> Vec<u8> {
+    rom_with_program(&asm!(
+        0x0100,
+        "
+        loop:
+            ld a, $20
+            ldh [$00], a
+            ldh a, [$00]
+            ld hl, $C000
+            add a, [hl]
+            ld [hl], a
+            jr loop
+        "
+    ))
+}
+
+/// How an operand stored after the opcode is encoded.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Immediate {
+    /// A byte, which may also be given as a negative number.
+    Byte,
+    /// A 16-bit word, stored little-endian.
+    Word,
+    /// A signed byte, used as an offset to SP.
+    Signed,
+    /// The target of a relative jump, stored as the offset from the next instruction.
+    Relative,
+    /// An address in the last page, stored as its lower byte.
+    High,
+}
+
+impl Immediate {
+    fn length(self) -> usize {
+        match self {
+            Immediate::Word => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// How to encode an instruction.
+struct Encoding {
+    opcode: Vec<u8>,
+    length: u16,
+    immediate: Option<Immediate>,
+}
+
+/// The instructions by their text with a `#` in place of the immediate operand.
+fn encodings() -> &'static HashMap<String, Encoding> {
+    static ENCODINGS: OnceLock<HashMap<String, Encoding>> = OnceLock::new();
+    ENCODINGS.get_or_init(|| {
+        opcode_patterns()
+            .into_iter()
+            .map(|(text, opcode, length)| {
+                let immediate = if text.starts_with("JR") {
+                    Some(Immediate::Relative)
+                } else if text.starts_with("LDH") && text.contains("n16") {
+                    Some(Immediate::High)
+                } else if text.contains("n16") {
+                    Some(Immediate::Word)
+                } else if text.contains("n8") {
+                    Some(Immediate::Byte)
+                } else if text.contains("e8") {
+                    Some(Immediate::Signed)
+                } else {
+                    None
+                };
+                let key = text
+                    .replace("n16", "#")
+                    .replace("n8", "#")
+                    .replace("e8", "#");
+                let encoding = Encoding {
+                    opcode,
+                    length,
+                    immediate,
+                };
+                (key, encoding)
+            })
+            .collect()
+    })
+}
+
+/// The operands that are written as they are, rather than as a number.
+const KEYWORDS: [&str; 21] = [
+    "A", "B", "C", "D", "E", "H", "L", "AF", "BC", "DE", "HL", "SP", "NZ", "Z", "NC", "[BC]",
+    "[DE]", "[HL]", "[HL+]", "[HL-]", "[C]",
+];
+/// The instructions that operate on A, which doesn't have to be written.
+const ARITHMETIC: [&str; 8] = ["ADD", "ADC", "SUB", "SBC", "AND", "OR", "XOR", "CP"];
+
+/// An operand whose value is only known once all labels are.
+struct Fixup {
+    line: usize,
+    /// Where the operand is stored in the output.
+    offset: usize,
+    /// The address of the instruction after the operand, for relative jumps.
+    next_address: u16,
+    immediate: Immediate,
+    expression: String,
+}
+
+/// Assembles source code into machine code, which is loaded at `origin`.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AssembleError> {
+    let mut output = Vec::new();
+    let mut labels = HashMap::new();
+    let mut fixups = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message| AssembleError {
+            line: line_number,
+            message,
+        };
+        let mut statement = strip_comment(line).trim();
+        if let Some((label, rest)) = statement.split_once(':')
+            && is_identifier(label.trim())
+        {
+            let address = origin.wrapping_add(output.len() as u16);
+            if labels.insert(label.trim().to_string(), address).is_some() {
+                return Err(error(format!("duplicate label: {}", label.trim())));
+            }
+            statement = rest.trim_start_matches(':').trim();
+        }
+        if statement.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match statement.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic.to_ascii_uppercase(), split_operands(operands)),
+            None => (statement.to_ascii_uppercase(), Vec::new()),
+        };
+        let mut add_fixup = |output: &mut Vec<u8>, immediate: Immediate, expression: &str| {
+            let offset = output.len();
+            output.resize(offset + immediate.length(), 0);
+            fixups.push(Fixup {
+                line: line_number,
+                offset,
+                next_address: origin.wrapping_add(output.len() as u16),
+                immediate,
+                expression: expression.to_string(),
+            });
+        };
+        match mnemonic.as_str() {
+            "DB" => {
+                for operand in &operands {
+                    match operand
+                        .strip_prefix('"')
+                        .and_then(|text| text.strip_suffix('"'))
+                    {
+                        Some(text) => output.extend(text.bytes()),
+                        None => add_fixup(&mut output, Immediate::Byte, operand),
+                    }
+                }
+            }
+            "DW" => {
+                for operand in &operands {
+                    add_fixup(&mut output, Immediate::Word, operand);
+                }
+            }
+            "DS" => {
+                let (count, fill) = match &operands[..] {
+                    [count] => (count, None),
+                    [count, fill] => (count, Some(fill)),
+                    _ => return Err(error("expected `DS <count>[, <fill>]`".to_string())),
+                };
+                let count = evaluate(count, &HashMap::new()).map_err(error)?;
+                let fill = match fill {
+                    Some(fill) => evaluate(fill, &HashMap::new()).map_err(error)?,
+                    None => 0,
+                };
+                let count =
+                    usize::try_from(count).map_err(|_| error(format!("invalid count: {count}")))?;
+                output.resize(output.len() + count, byte(fill).map_err(error)?);
+            }
+            _ => {
+                let (key, expression) = instruction_key(&mnemonic, &operands).map_err(error)?;
+                let encoding = encodings()
+                    .get(&key)
+                    .ok_or_else(|| error(format!("unknown instruction: {statement}")))?;
+                let start = output.len();
+                output.extend(&encoding.opcode);
+                if let (Some(immediate), Some(expression)) = (encoding.immediate, &expression) {
+                    add_fixup(&mut output, immediate, expression);
+                }
+                // `STOP` is followed by a 0.
+                output.resize(start + encoding.length as usize, 0);
+            }
+        }
+    }
+
+    for fixup in fixups {
+        let error = |message| AssembleError {
+            line: fixup.line,
+            message,
+        };
+        let value = evaluate(&fixup.expression, &labels).map_err(error)?;
+        let out_of_range = || error(format!("{} is out of range", fixup.expression));
+        match fixup.immediate {
+            Immediate::Byte => output[fixup.offset] = byte(value).map_err(error)?,
+            Immediate::Word => {
+                if !(-0x8000..=0xFFFF).contains(&value) {
+                    return Err(out_of_range());
+                }
+                let bytes = (value as u16).to_le_bytes();
+                output[fixup.offset..fixup.offset + 2].copy_from_slice(&bytes);
+            }
+            Immediate::Signed => {
+                let value = i8::try_from(value).map_err(|_| out_of_range())?;
+                output[fixup.offset] = value as u8;
+            }
+            Immediate::Relative => {
+                let offset = value - fixup.next_address as i64;
+                let offset = i8::try_from(offset)
+                    .map_err(|_| error(format!("{} is too far away", fixup.expression)))?;
+                output[fixup.offset] = offset as u8;
+            }
+            Immediate::High => {
+                output[fixup.offset] = match value {
+                    0xFF00..=0xFFFF => value as u8,
+                    0x00..=0xFF => value as u8,
+                    _ => return Err(out_of_range()),
+                };
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Turns an instruction into the key of its encoding, e.g. `LD A, #`, together with the
+/// expression that takes the place of the `#`.
+fn instruction_key(
+    mnemonic: &str,
+    operands: &[String],
+) -> Result<(String, Option<String>), String> {
+    let mut mnemonic = mnemonic.to_string();
+    let mut operands: Vec<String> = operands.to_vec();
+    if ARITHMETIC.contains(&mnemonic.as_str())
+        && operands.len() == 1
+        && !(mnemonic == "ADD" && operands[0].eq_ignore_ascii_case("sp"))
+    {
+        operands.insert(0, "A".to_string());
+    }
+
+    let mut expression = None;
+    let mut parts = Vec::new();
+    for (index, operand) in operands.iter().enumerate() {
+        let upper = operand.to_ascii_uppercase().replace(' ', "");
+        let part = match upper.as_str() {
+            "[HLI]" => "[HL+]".to_string(),
+            "[HLD]" => "[HL-]".to_string(),
+            "[$FF00+C]" | "[0XFF00+C]" => {
+                mnemonic = "LDH".to_string();
+                "[C]".to_string()
+            }
+            keyword if KEYWORDS.contains(&keyword) => keyword.to_string(),
+            _ if matches!(mnemonic.as_str(), "BIT" | "RES" | "SET") && index == 0 => {
+                let bit = evaluate(operand, &HashMap::new())?;
+                if !(0..=7).contains(&bit) {
+                    return Err(format!("invalid bit: {operand}"));
+                }
+                bit.to_string()
+            }
+            _ if mnemonic == "RST" => {
+                let address = evaluate(operand, &HashMap::new())?;
+                if !(0..=0x38).contains(&address) || address % 8 != 0 {
+                    return Err(format!("invalid RST vector: {operand}"));
+                }
+                format!("${address:02X}")
+            }
+            _ => {
+                let (part, value) = if let Some(offset) = upper.strip_prefix("SP+") {
+                    ("SP+#", offset.to_string())
+                } else if upper.starts_with("SP-") {
+                    ("SP+#", operand.trim()[2..].to_string())
+                } else if let Some(inner) = operand
+                    .trim()
+                    .strip_prefix('[')
+                    .and_then(|operand| operand.strip_suffix(']'))
+                {
+                    ("[#]", inner.to_string())
+                } else {
+                    ("#", operand.to_string())
+                };
+                if expression.replace(value).is_some() {
+                    return Err("only one operand can be a number".to_string());
+                }
+                part.to_string()
+            }
+        };
+        parts.push(part);
+    }
+    let key = if parts.is_empty() {
+        mnemonic
+    } else {
+        format!("{mnemonic} {}", parts.join(", "))
+    };
+    Ok((key, expression))
+}
+
+/// Evaluates a sum of numbers and labels, like `label + 2`.
+fn evaluate(expression: &str, labels: &HashMap<String, u16>) -> Result<i64, String> {
+    let mut total = 0;
+    let mut sign = 1;
+    let mut rest = expression.trim();
+    if rest.is_empty() {
+        return Err("expected a number".to_string());
+    }
+    loop {
+        if let Some(term) = rest.strip_prefix('-') {
+            sign = -sign;
+            rest = term.trim_start();
+            continue;
+        }
+        let end = rest[1..]
+            .find(['+', '-'])
+            .map_or(rest.len(), |index| index + 1);
+        let term = rest[..end].trim();
+        let value = match parse_number(term) {
+            Some(value) => value,
+            None if is_identifier(term) => *labels
+                .get(term)
+                .ok_or_else(|| format!("unknown label: {term}"))?
+                as i64,
+            None => return Err(format!("invalid number: {term}")),
+        };
+        total += sign * value;
+        rest = rest[end..].trim_start();
+        match rest.chars().next() {
+            None => return Ok(total),
+            Some('+') => sign = 1,
+            Some(_) => sign = -1,
+        }
+        rest = rest[1..].trim_start();
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix('$').or_else(|| lower.strip_prefix("0x")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix('%').or_else(|| lower.strip_prefix("0b")) {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+fn byte(value: i64) -> Result<u8, String> {
+    match value {
+        -0x80..=0xFF => Ok(value as u8),
+        _ => Err(format!("{value} doesn't fit into a byte")),
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_' || first == '.')
+        && text
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '.')
+}
+
+/// Removes a comment, unless the `;` is part of a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits operands at commas, except for those inside of strings.
+fn split_operands(operands: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut in_string = false;
+    for char in operands.chars() {
+        match char {
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().unwrap().push(char);
+    }
+    parts.iter().map(|part| part.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::disassemble;
+
+    #[test]
+    fn instructions() {
+        let program = asm!(
+            "ld a, $12
+            ADD A, B
+            add b ; shortcut
+            ld [hl+], a
+            ld a, [hli]
+            ldh [$FF44], a
+            ldh a, [$44]
+            ld [$ff00+c], a
+            ld hl, sp-2
+            add sp, 5
+            bit 7, [hl]
+            rst $38
+            stop
+            halt"
+        );
+        assert_eq!(
+            program,
+            [
+                0x3E, 0x12, 0x80, 0x80, 0x22, 0x2A, 0xE0, 0x44, 0xF0, 0x44, 0xE2, 0xF8, 0xFE, 0xE8,
+                0x05, 0xCB, 0x7E, 0xFF, 0x10, 0x00, 0x76,
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_and_jumps() {
+        let program = asm!(
+            0xC000,
+            "start:
+                dec b
+                jr nz, start
+                call function
+                jp start
+            function: ret"
+        );
+        assert_eq!(
+            program,
+            [0x05, 0x20, 0xFD, 0xCD, 0x09, 0xC0, 0xC3, 0x00, 0xC0, 0xC9]
+        );
+    }
+
+    #[test]
+    fn data() {
+        let program = asm!(
+            0x0200,
+            "table: db 1, -1, \"Hi; there\"
+            dw table + 2, $1234
+            ds 3, $FF"
+        );
+        let mut expected = vec![0x01, 0xFF];
+        expected.extend(b"Hi; there");
+        expected.extend([0x02, 0x02, 0x34, 0x12, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(program, expected);
+    }
+
+    #[test]
+    fn numbers() {
+        let labels = HashMap::from([("label".to_string(), 0x100)]);
+        assert_eq!(evaluate("$1F", &labels), Ok(0x1F));
+        assert_eq!(evaluate("0x1F", &labels), Ok(0x1F));
+        assert_eq!(evaluate("%101", &labels), Ok(5));
+        assert_eq!(evaluate("-12", &labels), Ok(-12));
+        assert_eq!(evaluate("label - 1 + 3", &labels), Ok(0x102));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| assemble(source, 0).unwrap_err();
+        assert_eq!(
+            error("nop\nld [hl], sp"),
+            AssembleError {
+                line: 2,
+                message: "unknown instruction: ld [hl], sp".to_string()
+            }
+        );
+        assert_eq!(error("jp nowhere").message, "unknown label: nowhere");
+        assert_eq!(error("a: nop\na: nop").message, "duplicate label: a");
+        assert_eq!(error("ld a, 256").message, "256 doesn't fit into a byte");
+        assert_eq!(error("bit 8, a").message, "invalid bit: 8");
+        assert!(
+            error("jr far\nds 200\nfar: nop")
+                .message
+                .contains("too far away")
+        );
+        assert_eq!(
+            error("ld [1], 2").message,
+            "only one operand can be a number"
+        );
+    }
+
+    /// Assembling the disassembly of every instruction gives back the same bytes.
+    #[test]
+    fn round_trip() {
+        for opcode in 0..=0xFF {
+            for bytes in [[opcode, 0x34, 0x12], [0xCB, opcode, 0x00]] {
+                let disassembly = disassemble(0x0150, |address| bytes[address as usize - 0x0150]);
+                if disassembly.text.starts_with("DB") {
+                    continue;
+                }
+                let program = assemble(&disassembly.text, 0x0150)
+                    .unwrap_or_else(|error| panic!("{}: {error}", disassembly.text));
+                let expected = match disassembly.bytes[..] {
+                    // `STOP` is always assembled with a 0 after it.
+                    [0x10, _] => vec![0x10, 0x00],
+                    _ => disassembly.bytes.clone(),
+                };
+                assert_eq!(program, expected, "{}", disassembly.text);
+            }
+        }
+    }
+}
@@ -0,0 +1,532 @@
+//! Parses the command line. Every option takes at most one value, given as the next argument.
+
+use gameboy_emu::{ManualPalette, Model, Theme};
+use std::path::PathBuf;
+
+pub(crate) const USAGE: &str = "\
+Usage: gameboy-emu [OPTIONS] <ROM>
+       gameboy-emu --terminal [OPTIONS] <ROM>
+       gameboy-emu --debug [OPTIONS] <ROM>
+       gameboy-emu --gdb <PORT> [OPTIONS] <ROM>
+       gameboy-emu --play-movie <FILE> [OPTIONS] <ROM>
+       gameboy-emu --disassemble <START>[:<END>] [--model <MODEL>] <ROM>
+       gameboy-emu --disassemble-rom <DIR> <ROM>
+
+Runs a ROM without a display until one of the stop conditions is met, plays it inside the
+terminal, debugs it, plays back a movie of a recorded session, or disassembles it.
+
+Stop conditions:
+  --frames <N>           Stop after N frames
+  --cycles <N>           Stop after N CPU cycles
+  --until-pc <ADDRESS>   Stop when the program counter reaches ADDRESS
+  --until-serial <TEXT>  Stop when the serial output contains TEXT
+  --until-breakpoint     Stop at the software breakpoint `LD B,B`
+
+Options:
+  --terminal             Play the game in the terminal until Q or Ctrl+C is pressed. The arrow
+                         keys or WASD are the directional pad, X is A, Z is B, Enter is Start
+                         and Space or Backspace is Select. Holding R rewinds the game
+  --debug                Debug the game with commands read from the standard input, like
+                         breakpoints and stepping through instructions. Enter `help` to list
+                         the commands
+  --gdb <PORT>           Wait for GDB or another client of its remote serial protocol to
+                         connect to PORT on localhost, and let it debug the game
+  --disassemble <RANGE>  Print the instructions from START to END, as the memory is mapped at
+                         power-on, and exit. Without END, 256 bytes are disassembled
+  --disassemble-rom <DIR>
+                         Disassemble the whole ROM into RGBDS source files in DIR, which
+                         assemble back into the same ROM, and exit
+  --symbols <FILE>       Name addresses with the symbols in FILE, an RGBDS symbol file, in
+                         disassemblies, traces and the debugger. Defaults to the ROM's path
+                         with the extension .sym, if that file exists
+  --model <MODEL>        Emulate dmg0, dmg, mgb, sgb, sgb2, cgb or agb instead of picking
+                         the model from the cartridge header
+  --palette <PALETTE>    Colorize a DMG game on the CGB with the palettes selected by holding
+                         buttons during the boot animation: up, left, down or right, alone or
+                         followed by +a or +b. Implies --model cgb
+  --load-state <FILE>    Continue from the save state in FILE instead of starting at power-on
+  --save-state <FILE>    Write a save state to FILE when stopping
+  --record-movie <FILE>  Record the buttons held in every frame to FILE, starting at power-on
+                         or from the state given with --load-state. Frames that the stop
+                         conditions interrupt are left out
+  --play-movie <FILE>    Run the frames recorded in FILE with the same buttons, on the model
+                         they were recorded on, and check that the console ends up in the
+                         same state
+  --input <FILE>         Press buttons as described in FILE, with one event per line:
+                         `<frame> press|release <button>`
+  --screenshot <FILE>    Write the last frame to FILE as a PNG image
+  --scale <N>            Enlarge the screenshot N times
+  --theme <THEME>        Show the DMG's shades in the screenshot as gray, green or pocket
+  --serial-log <FILE>    Write the serial output to FILE
+  --debug-messages       Print the debug messages of the game, written with `LD D,D` like in
+                         BGB and Emulicious, to the standard error or in the debugger
+  --trace <FILE>         Write the registers before every instruction to FILE, or to the
+                         standard output if FILE is -, in the format of Gameboy Doctor. LY
+                         always reads as 0x90 while tracing
+  -h, --help             Print this help
+
+Exit codes:
+  0  One of the --until conditions was met, or the limit was reached if there are none
+  1  The frame or cycle limit was reached before any --until condition was met
+  2  The arguments or files are invalid
+  3  Playing back the movie ended in another state than recording it";
+
+/// Larger screenshots would take up hundreds of megabytes.
+const MAX_SCALE: usize = 16;
+/// How many bytes `--disassemble` covers if no end is given.
+const DEFAULT_DISASSEMBLY_LENGTH: u16 = 0x100;
+
+#[derive(PartialEq, Eq, Debug, Default)]
+pub(crate) struct Options {
+    pub(crate) rom: PathBuf,
+    pub(crate) model: Option<Model>,
+    pub(crate) palette: Option<ManualPalette>,
+    pub(crate) frames: Option<u64>,
+    pub(crate) cycles: Option<u64>,
+    pub(crate) until_pc: Option<u16>,
+    pub(crate) until_serial: Option<String>,
+    pub(crate) until_breakpoint: bool,
+    pub(crate) input: Option<PathBuf>,
+    pub(crate) load_state: Option<PathBuf>,
+    pub(crate) save_state: Option<PathBuf>,
+    pub(crate) record_movie: Option<PathBuf>,
+    pub(crate) play_movie: Option<PathBuf>,
+    pub(crate) screenshot: Option<PathBuf>,
+    pub(crate) scale: Option<usize>,
+    pub(crate) theme: Option<Theme>,
+    pub(crate) serial_log: Option<PathBuf>,
+    pub(crate) debug_messages: bool,
+    /// Where to write the trace log, with `-` for the standard output.
+    pub(crate) trace: Option<PathBuf>,
+    pub(crate) terminal: bool,
+    pub(crate) debug: bool,
+    /// The port to serve GDB's remote serial protocol on.
+    pub(crate) gdb: Option<u16>,
+    /// The first and last address to disassemble.
+    pub(crate) disassemble: Option<(u16, u16)>,
+    /// Where to write the disassembly of the whole ROM.
+    pub(crate) disassemble_rom: Option<PathBuf>,
+    /// The symbol file given instead of the one next to the ROM.
+    pub(crate) symbols: Option<PathBuf>,
+}
+
+impl Options {
+    /// Whether any of the `--until` conditions is set, as opposed to only a limit.
+    pub(crate) fn has_condition(&self) -> bool {
+        self.until_pc.is_some() || self.until_serial.is_some() || self.until_breakpoint
+    }
+}
+
+/// Parses the arguments, without the name of the executable. Returns [`None`] if the help was
+/// requested.
+pub(crate) fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    let mut rom = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} requires a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--frames" => options.frames = Some(parse_number(&value()?)?),
+            "--cycles" => options.cycles = Some(parse_number(&value()?)?),
+            "--until-pc" => options.until_pc = Some(parse_address(&value()?)?),
+            "--until-serial" => options.until_serial = Some(value()?),
+            "--until-breakpoint" => options.until_breakpoint = true,
+            "--terminal" => options.terminal = true,
+            "--debug" => options.debug = true,
+            "--gdb" => {
+                let port = parse_number(&value()?)?;
+                let port = u16::try_from(port).map_err(|_| format!("invalid port: {port}"))?;
+                options.gdb = Some(port);
+            }
+            "--disassemble" => options.disassemble = Some(parse_range(&value()?)?),
+            "--disassemble-rom" => options.disassemble_rom = Some(value()?.into()),
+            "--symbols" => options.symbols = Some(value()?.into()),
+            "--model" => options.model = Some(parse_model(&value()?)?),
+            "--palette" => options.palette = Some(parse_palette(&value()?)?),
+            "--input" => options.input = Some(value()?.into()),
+            "--load-state" => options.load_state = Some(value()?.into()),
+            "--save-state" => options.save_state = Some(value()?.into()),
+            "--record-movie" => options.record_movie = Some(value()?.into()),
+            "--play-movie" => options.play_movie = Some(value()?.into()),
+            "--screenshot" => options.screenshot = Some(value()?.into()),
+            "--scale" => {
+                let scale = parse_number(&value()?)?;
+                let scale = usize::try_from(scale)
+                    .ok()
+                    .filter(|scale| (1..=MAX_SCALE).contains(scale))
+                    .ok_or(format!("scale must be between 1 and {MAX_SCALE}"))?;
+                options.scale = Some(scale);
+            }
+            "--theme" => options.theme = Some(parse_theme(&value()?)?),
+            "--serial-log" => options.serial_log = Some(value()?.into()),
+            "--debug-messages" => options.debug_messages = true,
+            "--trace" => options.trace = Some(value()?.into()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ if rom.is_some() => return Err(format!("unexpected argument: {arg}")),
+            _ => rom = Some(arg.into()),
+        }
+    }
+
+    options.rom = rom.ok_or("no ROM given")?;
+    let has_limit = options.frames.is_some() || options.cycles.is_some();
+    if (options.scale.is_some() || options.theme.is_some()) && options.screenshot.is_none() {
+        return Err("--scale and --theme require --screenshot".to_string());
+    }
+    let is_cgb = |model| matches!(model, Model::Cgb | Model::Agb);
+    if options.palette.is_some() && options.model.is_some_and(|model| !is_cgb(model)) {
+        return Err("--palette requires the CGB or AGB".to_string());
+    }
+    // The modes that run until the user quits.
+    let modes: Vec<_> = [
+        (options.terminal, "--terminal"),
+        (options.debug, "--debug"),
+        (options.gdb.is_some(), "--gdb"),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, name)| enabled.then_some(name))
+    .collect();
+    if options.disassemble.is_some() || options.disassemble_rom.is_some() {
+        if options.disassemble.is_some() && options.disassemble_rom.is_some() {
+            return Err("--disassemble can't be combined with --disassemble-rom".to_string());
+        }
+        if !modes.is_empty()
+            || has_limit
+            || options.has_condition()
+            || options.input.is_some()
+            || options.trace.is_some()
+            || options.debug_messages
+            || options.load_state.is_some()
+            || options.save_state.is_some()
+            || options.record_movie.is_some()
+            || options.play_movie.is_some()
+        {
+            return Err(
+                "disassembling can't be combined with --terminal, --debug, --gdb, stop \
+                 conditions, --input, --trace, --debug-messages, save states or movies"
+                    .to_string(),
+            );
+        }
+    } else if options.play_movie.is_some() {
+        if !modes.is_empty()
+            || has_limit
+            || options.has_condition()
+            || options.input.is_some()
+            || options.model.is_some()
+            || options.palette.is_some()
+            || options.load_state.is_some()
+            || options.record_movie.is_some()
+        {
+            return Err(
+                "--play-movie can't be combined with --terminal, --debug, --gdb, stop \
+                 conditions, --input, --model, --palette, --load-state or --record-movie"
+                    .to_string(),
+            );
+        }
+    } else if let [mode, rest @ ..] = &modes[..] {
+        if let Some(other) = rest.first() {
+            return Err(format!("{mode} can't be combined with {other}"));
+        }
+        if has_limit || options.has_condition() || options.input.is_some() {
+            return Err(format!(
+                "{mode} can't be combined with stop conditions or --input"
+            ));
+        }
+        if *mode != "--gdb" && options.trace.as_deref() == Some("-".as_ref()) {
+            return Err(format!(
+                "{mode} can't write the trace to the standard output"
+            ));
+        }
+        if *mode == "--terminal" && options.debug_messages {
+            return Err("--terminal can't print debug messages".to_string());
+        }
+        if *mode != "--terminal" && options.record_movie.is_some() {
+            return Err(format!("{mode} can't record a movie"));
+        }
+    } else if !has_limit && !options.has_condition() {
+        return Err("no stop condition given".to_string());
+    }
+    Ok(Some(options))
+}
+
+/// Parses a decimal number, or a hexadecimal one prefixed with `0x` or `$`.
+pub(crate) fn parse_number(text: &str) -> Result<u64, String> {
+    let result = match text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    result.map_err(|_| format!("invalid number: {text}"))
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let address = parse_number(text)?;
+    u16::try_from(address).map_err(|_| format!("address out of range: {address:#X}"))
+}
+
+/// Parses `START[:END]`, where both addresses are included.
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+    let (start, end) = match text.split_once(':') {
+        Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+        None => {
+            let start = parse_address(text)?;
+            (start, start.saturating_add(DEFAULT_DISASSEMBLY_LENGTH - 1))
+        }
+    };
+    if end < start {
+        return Err(format!("invalid range: {text}"));
+    }
+    Ok((start, end))
+}
+
+fn parse_model(name: &str) -> Result<Model, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "dmg0" => Ok(Model::Dmg0),
+        "dmg" => Ok(Model::Dmg),
+        "mgb" => Ok(Model::Mgb),
+        "sgb" => Ok(Model::Sgb),
+        "sgb2" => Ok(Model::Sgb2),
+        "cgb" => Ok(Model::Cgb),
+        "agb" => Ok(Model::Agb),
+        _ => Err(format!("unknown model: {name}")),
+    }
+}
+
+/// Parses the buttons that select a palette, like `left+a`.
+fn parse_palette(name: &str) -> Result<ManualPalette, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "up" => Ok(ManualPalette::Up),
+        "up+a" => Ok(ManualPalette::UpA),
+        "up+b" => Ok(ManualPalette::UpB),
+        "left" => Ok(ManualPalette::Left),
+        "left+a" => Ok(ManualPalette::LeftA),
+        "left+b" => Ok(ManualPalette::LeftB),
+        "down" => Ok(ManualPalette::Down),
+        "down+a" => Ok(ManualPalette::DownA),
+        "down+b" => Ok(ManualPalette::DownB),
+        "right" => Ok(ManualPalette::Right),
+        "right+a" => Ok(ManualPalette::RightA),
+        "right+b" => Ok(ManualPalette::RightB),
+        _ => Err(format!("unknown palette: {name}")),
+    }
+}
+
+fn parse_theme(name: &str) -> Result<Theme, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "gray" | "grey" => Ok(Theme::GRAY),
+        "green" => Ok(Theme::GREEN),
+        "pocket" => Ok(Theme::POCKET),
+        _ => Err(format!("unknown theme: {name}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Option<Options>, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn all_options() {
+        let options = parse_args(&[
+            "--frames",
+            "600",
+            "--until-pc",
+            "$C000",
+            "--until-serial",
+            "Passed",
+            "--until-breakpoint",
+            "--model",
+            "CGB",
+            "--screenshot",
+            "out.png",
+            "--scale",
+            "3",
+            "--theme",
+            "green",
+            "--trace",
+            "-",
+            "--debug-messages",
+            "game.gb",
+        ]);
+        let expected = Options {
+            rom: "game.gb".into(),
+            model: Some(Model::Cgb),
+            frames: Some(600),
+            until_pc: Some(0xC000),
+            until_serial: Some("Passed".to_string()),
+            until_breakpoint: true,
+            screenshot: Some("out.png".into()),
+            scale: Some(3),
+            theme: Some(Theme::GREEN),
+            trace: Some("-".into()),
+            debug_messages: true,
+            ..Options::default()
+        };
+        assert_eq!(options, Ok(Some(expected)));
+    }
+
+    #[test]
+    fn terminal() {
+        let options = parse_args(&["--terminal", "game.gb"]).unwrap().unwrap();
+        assert!(options.terminal);
+        assert!(parse_args(&["--terminal", "--frames", "1", "game.gb"]).is_err());
+        assert!(parse_args(&["--terminal", "--trace", "-", "game.gb"]).is_err());
+        assert!(parse_args(&["--terminal", "--trace", "trace.log", "game.gb"]).is_ok());
+        assert!(parse_args(&["--terminal", "--debug-messages", "game.gb"]).is_err());
+    }
+
+    #[test]
+    fn debug() {
+        let options = parse_args(&["--debug", "game.gb"]).unwrap().unwrap();
+        assert!(options.debug);
+        assert!(parse_args(&["--debug", "--terminal", "game.gb"]).is_err());
+        assert!(parse_args(&["--debug", "--until-breakpoint", "game.gb"]).is_err());
+        assert!(parse_args(&["--debug", "--disassemble", "0", "game.gb"]).is_err());
+
+        let options = parse_args(&["--gdb", "2345", "--trace", "-", "game.gb"]);
+        assert_eq!(options.unwrap().unwrap().gdb, Some(2345));
+        assert!(parse_args(&["--gdb", "65536", "game.gb"]).is_err());
+        assert!(parse_args(&["--gdb", "2345", "--debug", "game.gb"]).is_err());
+        assert!(parse_args(&["--gdb", "2345", "--frames", "1", "game.gb"]).is_err());
+    }
+
+    #[test]
+    fn disassemble() {
+        let options = parse_args(&["--disassemble", "$150:$1FF", "game.gb"]);
+        let range = options.unwrap().unwrap().disassemble;
+        assert_eq!(range, Some((0x0150, 0x01FF)));
+        let options = parse_args(&["--disassemble", "0xFFC0", "game.gb"]);
+        let range = options.unwrap().unwrap().disassemble;
+        assert_eq!(range, Some((0xFFC0, 0xFFFF)));
+        assert!(parse_args(&["--disassemble", "$200:$100", "game.gb"]).is_err());
+        assert!(parse_args(&["--disassemble", "0", "--frames", "1", "game.gb"]).is_err());
+
+        let options = parse_args(&["--disassemble-rom", "out", "game.gb"]);
+        let dir = options.unwrap().unwrap().disassemble_rom;
+        assert_eq!(dir, Some("out".into()));
+        let both = ["--disassemble", "0", "--disassemble-rom", "out", "game.gb"];
+        assert!(parse_args(&both).is_err());
+    }
+
+    #[test]
+    fn save_states() {
+        let options = parse_args(&[
+            "--load-state",
+            "in.state",
+            "--save-state",
+            "out.state",
+            "--frames",
+            "60",
+            "game.gb",
+        ]);
+        let options = options.unwrap().unwrap();
+        assert_eq!(options.load_state, Some("in.state".into()));
+        assert_eq!(options.save_state, Some("out.state".into()));
+        assert!(parse_args(&["--terminal", "--save-state", "out.state", "game.gb"]).is_ok());
+        let disassemble = ["--disassemble", "0", "--load-state", "in.state", "game.gb"];
+        assert!(parse_args(&disassemble).is_err());
+    }
+
+    #[test]
+    fn movies() {
+        let options = parse_args(&["--record-movie", "out.movie", "--frames", "60", "game.gb"]);
+        assert_eq!(
+            options.unwrap().unwrap().record_movie,
+            Some("out.movie".into())
+        );
+        assert!(parse_args(&["--terminal", "--record-movie", "out.movie", "game.gb"]).is_ok());
+        assert!(parse_args(&["--debug", "--record-movie", "out.movie", "game.gb"]).is_err());
+
+        let options = parse_args(&[
+            "--play-movie",
+            "in.movie",
+            "--screenshot",
+            "a.png",
+            "game.gb",
+        ]);
+        assert_eq!(
+            options.unwrap().unwrap().play_movie,
+            Some("in.movie".into())
+        );
+        for other in [
+            &["--frames", "60"][..],
+            &["--terminal"],
+            &["--model", "cgb"],
+            &["--palette", "up"],
+            &["--load-state", "in.state"],
+            &["--record-movie", "out.movie"],
+            &["--disassemble", "0"],
+        ] {
+            let args = [&["--play-movie", "in.movie"], other, &["game.gb"]].concat();
+            assert!(parse_args(&args).is_err(), "{other:?}");
+        }
+    }
+
+    #[test]
+    fn palette() {
+        let options = parse_args(&["--palette", "Left+B", "--frames", "1", "game.gb"]);
+        assert_eq!(
+            options.unwrap().unwrap().palette,
+            Some(ManualPalette::LeftB)
+        );
+        let options = parse_args(&[
+            "--palette",
+            "up",
+            "--model",
+            "agb",
+            "--frames",
+            "1",
+            "game.gb",
+        ]);
+        assert!(options.is_ok());
+        let options = parse_args(&[
+            "--palette",
+            "up",
+            "--model",
+            "dmg",
+            "--frames",
+            "1",
+            "game.gb",
+        ]);
+        assert!(options.is_err());
+        assert!(parse_args(&["--palette", "up+start", "--frames", "1", "game.gb"]).is_err());
+    }
+
+    #[test]
+    fn symbols() {
+        let options = parse_args(&["--debug", "--symbols", "game.sym", "game.gb"]);
+        assert_eq!(options.unwrap().unwrap().symbols, Some("game.sym".into()));
+        let options = parse_args(&["--disassemble-rom", "out", "--symbols", "a.sym", "game.gb"]);
+        assert!(options.is_ok());
+        assert!(parse_args(&["--debug", "game.gb", "--symbols"]).is_err());
+    }
+
+    #[test]
+    fn help() {
+        assert_eq!(parse_args(&["game.gb", "--help"]), Ok(None));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(parse_args(&["game.gb"]).is_err());
+        assert!(parse_args(&["--frames", "10"]).is_err());
+        assert!(parse_args(&["--frames"]).is_err());
+        assert!(parse_args(&["--frames", "ten", "game.gb"]).is_err());
+        assert!(parse_args(&["--until-pc", "0x10000", "game.gb"]).is_err());
+        assert!(parse_args(&["--frames", "1", "--fast", "game.gb"]).is_err());
+        assert!(parse_args(&["--frames", "1", "a.gb", "b.gb"]).is_err());
+        assert!(parse_args(&["--frames", "1", "--scale", "2", "game.gb"]).is_err());
+        let screenshot = ["--frames", "1", "--screenshot", "out.png"];
+        assert!(parse_args(&[&screenshot[..], &["--scale", "0", "game.gb"]].concat()).is_err());
+        assert!(parse_args(&[&screenshot[..], &["--theme", "blue", "game.gb"]].concat()).is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("100"), Ok(100));
+        assert_eq!(parse_number("0x100"), Ok(0x100));
+        assert_eq!(parse_number("$ff"), Ok(0xFF));
+    }
+}
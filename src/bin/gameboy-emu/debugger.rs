@@ -0,0 +1,903 @@
+//! An interactive debugger on the command line. Commands are read line by line from the standard
+//! input, and an empty line repeats the last command. While the game runs, pressing Enter
+//! interrupts it.
+//!
+//! Addresses and values are hexadecimal, with an optional `$` or `0x` prefix, while counts are
+//! decimal. Breakpoints can be limited to a ROM bank with `BANK:ADDRESS`, like in symbol files.
+//! If a symbol file was loaded, the names of its symbols can be used instead of addresses, and
+//! addresses are shown relative to the closest symbol, like `Main+12`.
+//!
+//! `LD B, B` is a software breakpoint, and debug messages printed with `LD D, D` are shown
+//! whenever running stops, if they are enabled.
+//!
+//! The state at the start of every frame is recorded, so `rewind` can go back in time. Changing
+//! registers forgets the history, as replaying it wouldn't repeat the change.
+//!
+//! The call stack is inferred from the stack pointer: An instruction that pushes the address of
+//! the next instruction and jumps is a call, an interrupt pushes the address of the instruction
+//! it interrupted, and a call has returned once its return address is popped.
+
+use crate::{INTERRUPT_CHECK_INTERVAL, disassembly_line};
+use gameboy_emu::{AccessKind, CpuRegisters, GameBoy, Rewind, Watchpoint, WatchpointHit};
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+
+const HELP: &str = "\
+Commands:
+  s, step [N]              Execute N instructions, 1 by default
+  n, next                  Execute the next instruction, running called functions and interrupt
+                           handlers completely
+  c, continue              Run until a breakpoint or watchpoint is hit, or Enter is pressed
+  b, break [BANK:]ADDRESS  Stop before the instruction at ADDRESS is executed
+  watch START[:END]        Stop after the CPU writes to the memory from START to END
+  rwatch START[:END]       Stop after the CPU reads from the memory from START to END
+  awatch START[:END]       Stop after the CPU reads from or writes to the memory
+  d, delete [N]            Delete breakpoint or watchpoint N, or all of them
+  i, info                  List the breakpoints and watchpoints
+  r, registers             Show the registers and flags
+  set REGISTER VALUE       Change a, f, b, c, d, e, h, l, af, bc, de, hl, sp or pc
+  flag FLAG 0|1            Change the flag z, n, h or c
+  x ADDRESS [LENGTH]       Show LENGTH bytes of memory, 64 by default
+  l, list [ADDRESS] [N]    Disassemble N instructions, 10 by default, around PC or from ADDRESS
+  bt, backtrace            Show the calls that led to the current instruction
+  rewind [N]               Go back N frames in time, 1 by default
+  h, help                  Show this help
+  q, quit                  Stop debugging
+
+Addresses are hexadecimal, or the names of symbols if a symbol file was loaded.";
+
+/// The names of all commands, to tell unknown commands from wrong arguments.
+const COMMANDS: &str = "s step n next c continue b break watch rwatch awatch d delete i info r \
+                        registers set flag x l list bt backtrace rewind h help q quit";
+const DEFAULT_DUMP_LENGTH: u32 = 64;
+const DEFAULT_LIST_LENGTH: usize = 10;
+/// How far back `list` looks for the start of an instruction sequence that leads to PC.
+const LIST_LOOKBEHIND: u16 = 12;
+
+const FLAGS: [(char, u8); 4] = [('Z', 0x80), ('N', 0x40), ('H', 0x20), ('C', 0x10)];
+
+/// Stops before the instruction at an address is executed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Breakpoint {
+    /// The ROM bank that has to be mapped, or [`None`] for any.
+    bank: Option<u16>,
+    address: u16,
+}
+
+impl Breakpoint {
+    fn matches(&self, gameboy: &GameBoy, pc: u16) -> bool {
+        pc == self.address
+            && self
+                .bank
+                .is_none_or(|bank| gameboy.rom_bank(pc) == Some(bank))
+    }
+}
+
+enum Point {
+    Break(Breakpoint),
+    Watch(Watchpoint),
+}
+
+/// A call that hasn't returned yet.
+struct Call {
+    /// The address of the called function or interrupt handler.
+    target: u16,
+    /// The address of the call instruction, or of the interrupted instruction.
+    site: u16,
+    interrupt: bool,
+    /// Where the return address is stored.
+    stack_pointer: u16,
+}
+
+/// Why running stopped.
+enum Stop {
+    Done,
+    Breakpoint(usize),
+    /// The game reached an `LD B, B`.
+    SoftwareBreakpoint,
+    Watchpoint(usize, WatchpointHit),
+    Interrupted,
+}
+
+#[derive(Default)]
+pub(crate) struct Debugger {
+    /// The breakpoints and watchpoints with their numbers.
+    points: Vec<(usize, Point)>,
+    next_number: usize,
+    call_stack: Vec<Call>,
+    last_command: String,
+    rewind: Rewind,
+}
+
+/// Debugs the game until the user quits or the standard input is closed.
+pub(crate) fn run(gameboy: &mut GameBoy) -> Result<(), String> {
+    let lines = spawn_stdin_reader();
+    let mut debugger = Debugger::default();
+    gameboy.set_software_breakpoints(true);
+    let mut interrupted = || lines.try_recv().is_ok();
+    println!("{}", location(gameboy));
+    loop {
+        print!("(debug) ");
+        std::io::stdout()
+            .flush()
+            .map_err(|error| error.to_string())?;
+        let Ok(line) = lines.recv() else {
+            return Ok(());
+        };
+        match debugger.execute(gameboy, &line, &mut interrupted) {
+            Ok(Some(output)) if output.is_empty() => {}
+            Ok(Some(output)) => println!("{output}"),
+            Ok(None) => return Ok(()),
+            Err(error) => println!("error: {error}"),
+        }
+    }
+}
+
+/// Reads lines from the standard input on another thread, so running can be interrupted.
+fn spawn_stdin_reader() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+impl Debugger {
+    /// Executes a command and returns what it printed, or [`None`] if the user quit. `interrupted`
+    /// is polled while the game runs.
+    pub(crate) fn execute(
+        &mut self,
+        gameboy: &mut GameBoy,
+        line: &str,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> Result<Option<String>, String> {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.trim().to_string();
+            line.trim().to_string()
+        };
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(Some(String::new()));
+        };
+        let args: Vec<_> = words.collect();
+        let mut output = String::new();
+        match (command, &args[..]) {
+            ("s" | "step", [] | [_]) => {
+                let count = match args.first() {
+                    Some(count) => count
+                        .parse::<u64>()
+                        .map_err(|_| format!("invalid count: {count}"))?,
+                    None => 1,
+                };
+                let mut executed = 0;
+                let stop = self.run(gameboy, interrupted, |_, _| {
+                    executed += 1;
+                    executed >= count
+                });
+                self.report(gameboy, stop, &mut output);
+            }
+            ("n" | "next", []) => {
+                let depth = self.call_stack.len();
+                let stop = self.run(gameboy, interrupted, |debugger, _| {
+                    debugger.call_stack.len() <= depth
+                });
+                self.report(gameboy, stop, &mut output);
+            }
+            ("c" | "continue", []) => {
+                let stop = self.run(gameboy, interrupted, |_, _| false);
+                self.report(gameboy, stop, &mut output);
+            }
+            ("b" | "break", [address]) => {
+                let breakpoint = parse_breakpoint(gameboy, address)?;
+                let number = self.add(gameboy, Point::Break(breakpoint));
+                let text = describe(gameboy, &breakpoint);
+                write!(output, "Breakpoint {number} at {text}").unwrap();
+            }
+            ("watch" | "rwatch" | "awatch", [range]) => {
+                let range = parse_range(gameboy, range)?;
+                let watchpoint = Watchpoint {
+                    range,
+                    read: command != "watch",
+                    write: command != "rwatch",
+                };
+                let text = describe_watchpoint(&watchpoint);
+                let number = self.add(gameboy, Point::Watch(watchpoint));
+                write!(output, "Watchpoint {number} on {text}").unwrap();
+            }
+            ("d" | "delete", []) => {
+                self.points.clear();
+                gameboy.set_watchpoints(Vec::new());
+            }
+            ("d" | "delete", [number]) => {
+                let number: usize = number
+                    .parse()
+                    .map_err(|_| format!("invalid number: {number}"))?;
+                let index = self
+                    .points
+                    .iter()
+                    .position(|(n, _)| *n == number)
+                    .ok_or(format!("no breakpoint or watchpoint {number}"))?;
+                self.points.remove(index);
+                gameboy.set_watchpoints(self.watchpoints());
+            }
+            ("i" | "info", []) => {
+                if self.points.is_empty() {
+                    output.push_str("No breakpoints or watchpoints");
+                }
+                for (number, point) in &self.points {
+                    let text = match point {
+                        Point::Break(breakpoint) => {
+                            format!("breakpoint at {}", describe(gameboy, breakpoint))
+                        }
+                        Point::Watch(watchpoint) => {
+                            format!("watchpoint on {}", describe_watchpoint(watchpoint))
+                        }
+                    };
+                    writeln!(output, "{number}: {text}").unwrap();
+                }
+            }
+            ("r" | "registers", []) => output = format_registers(&gameboy.registers()),
+            ("set", [register, value]) => {
+                let registers = set_register(gameboy.registers(), register, value)?;
+                gameboy.set_registers(registers);
+                self.rewind.clear();
+                output = format_registers(&gameboy.registers());
+            }
+            ("flag", [flag, value]) => {
+                let (_, mask) = FLAGS
+                    .iter()
+                    .find(|(name, _)| flag.eq_ignore_ascii_case(&name.to_string()))
+                    .ok_or(format!("unknown flag: {flag}"))?;
+                let mut registers = gameboy.registers();
+                match *value {
+                    "0" => registers.f &= !mask,
+                    "1" => registers.f |= mask,
+                    _ => return Err(format!("invalid flag value: {value}")),
+                }
+                gameboy.set_registers(registers);
+                self.rewind.clear();
+                output = format_registers(&gameboy.registers());
+            }
+            ("x", [address] | [address, _]) => {
+                let address = parse_address(gameboy, address)?;
+                let length = match args.get(1) {
+                    Some(length) => length
+                        .parse()
+                        .map_err(|_| format!("invalid length: {length}"))?,
+                    None => DEFAULT_DUMP_LENGTH,
+                };
+                dump(gameboy, address, length, &mut output);
+            }
+            ("l" | "list", [] | [_] | [_, _]) => {
+                let start = args
+                    .first()
+                    .map(|address| parse_address(gameboy, address))
+                    .transpose()?;
+                let count = match args.get(1) {
+                    Some(count) => count
+                        .parse()
+                        .map_err(|_| format!("invalid count: {count}"))?,
+                    None => DEFAULT_LIST_LENGTH,
+                };
+                list(gameboy, start, count, &mut output);
+            }
+            ("bt" | "backtrace", []) => self.backtrace(gameboy, &mut output),
+            ("rewind", [] | [_]) => {
+                let frames = match args.first() {
+                    Some(frames) => frames
+                        .parse()
+                        .map_err(|_| format!("invalid count: {frames}"))?,
+                    None => 1,
+                };
+                let rewound = self.rewind.rewind(gameboy, frames);
+                // Calls can't be tracked backwards.
+                self.call_stack.clear();
+                writeln!(output, "Rewound {rewound} frames").unwrap();
+                output.push_str(&location(gameboy));
+            }
+            ("h" | "help", []) => output.push_str(HELP),
+            ("q" | "quit", []) => return Ok(None),
+            _ if COMMANDS.split_whitespace().any(|name| name == command) => {
+                return Err(format!("wrong arguments for {command}, see `help`"));
+            }
+            _ => return Err(format!("unknown command: {command}, see `help`")),
+        }
+        Ok(Some(output.trim_end().to_string()))
+    }
+
+    fn add(&mut self, gameboy: &mut GameBoy, point: Point) -> usize {
+        self.next_number += 1;
+        self.points.push((self.next_number, point));
+        gameboy.set_watchpoints(self.watchpoints());
+        self.next_number
+    }
+
+    fn watchpoints(&self) -> Vec<Watchpoint> {
+        self.points
+            .iter()
+            .filter_map(|(_, point)| match point {
+                Point::Watch(watchpoint) => Some(watchpoint.clone()),
+                Point::Break(_) => None,
+            })
+            .collect()
+    }
+
+    /// Executes instructions until `done` returns `true`, a breakpoint or watchpoint is hit, or
+    /// the user interrupts. The first instruction is always executed, so running from a
+    /// breakpoint doesn't stop at it again.
+    fn run(
+        &mut self,
+        gameboy: &mut GameBoy,
+        interrupted: &mut dyn FnMut() -> bool,
+        mut done: impl FnMut(&Self, &GameBoy) -> bool,
+    ) -> Stop {
+        let mut executed = 0u64;
+        loop {
+            if let Some(stop) = self.step(gameboy) {
+                return stop;
+            }
+            if gameboy.is_paused_at_breakpoint() {
+                return Stop::SoftwareBreakpoint;
+            }
+            if done(self, gameboy) {
+                return Stop::Done;
+            }
+            let pc = gameboy.registers().pc;
+            let breakpoint = self.points.iter().find(|(_, point)| match point {
+                Point::Break(breakpoint) => breakpoint.matches(gameboy, pc),
+                Point::Watch(_) => false,
+            });
+            if let Some((number, _)) = breakpoint {
+                return Stop::Breakpoint(*number);
+            }
+            executed += 1;
+            if executed.is_multiple_of(INTERRUPT_CHECK_INTERVAL) && interrupted() {
+                return Stop::Interrupted;
+            }
+        }
+    }
+
+    /// Executes a single instruction or calls an interrupt handler, keeping track of the calls.
+    /// Returns the watchpoint that was hit, if any.
+    fn step(&mut self, gameboy: &mut GameBoy) -> Option<Stop> {
+        // Debugging may start in the middle of a frame, which is then the oldest point to rewind
+        // to. Afterwards, each frame is recorded when it starts.
+        let frame = gameboy.frame_count();
+        if self.rewind.oldest_frame().is_none() {
+            self.rewind.record(gameboy);
+        }
+        let before = gameboy.registers();
+        let instruction = gameboy.disassemble(before.pc);
+        gameboy.step_instruction();
+        if gameboy.frame_count() != frame {
+            self.rewind.record(gameboy);
+        }
+        let after = gameboy.registers();
+
+        while self
+            .call_stack
+            .last()
+            .is_some_and(|call| call.stack_pointer < after.sp)
+        {
+            self.call_stack.pop();
+        }
+        let next = before.pc.wrapping_add(instruction.length());
+        if after.sp == before.sp.wrapping_sub(2) && after.pc != next {
+            let return_address = u16::from_le_bytes([
+                gameboy.peek(after.sp),
+                gameboy.peek(after.sp.wrapping_add(1)),
+            ]);
+            let is_call = ["CALL", "RST"]
+                .iter()
+                .any(|mnemonic| instruction.text.starts_with(mnemonic));
+            if (is_call && return_address == next) || return_address == before.pc {
+                self.call_stack.push(Call {
+                    target: after.pc,
+                    site: before.pc,
+                    interrupt: return_address == before.pc,
+                    stack_pointer: after.sp,
+                });
+            }
+        }
+
+        let hit = gameboy.take_watchpoint_hits().into_iter().next()?;
+        let (number, _) = self.points.iter().find(|(_, point)| match point {
+            Point::Watch(watchpoint) => watchpoint.matches(hit.address, hit.kind),
+            Point::Break(_) => false,
+        })?;
+        Some(Stop::Watchpoint(*number, hit))
+    }
+
+    fn report(&self, gameboy: &mut GameBoy, stop: Stop, output: &mut String) {
+        for message in gameboy.take_debug_messages() {
+            writeln!(output, "Message: {message}").unwrap();
+        }
+        match stop {
+            Stop::Done => {}
+            Stop::Breakpoint(number) => writeln!(output, "Breakpoint {number}").unwrap(),
+            Stop::SoftwareBreakpoint => writeln!(output, "Software breakpoint").unwrap(),
+            Stop::Watchpoint(number, hit) => {
+                let access = match hit.kind {
+                    AccessKind::Read => format!("read ${:02X} from", hit.value),
+                    AccessKind::Write => format!("wrote ${:02X} to", hit.value),
+                };
+                writeln!(output, "Watchpoint {number}: {access} ${:04X}", hit.address).unwrap();
+            }
+            Stop::Interrupted => writeln!(output, "Interrupted").unwrap(),
+        }
+        output.push_str(&location(gameboy));
+    }
+
+    fn backtrace(&self, gameboy: &GameBoy, output: &mut String) {
+        let mut address = gameboy.registers().pc;
+        let mut interrupt = false;
+        for (depth, call) in self.call_stack.iter().rev().enumerate() {
+            let function = format!("${:04X}", call.target);
+            write_frame(gameboy, output, depth, address, Some(function), interrupt);
+            address = call.site;
+            interrupt = call.interrupt;
+        }
+        let depth = self.call_stack.len();
+        write_frame(gameboy, output, depth, address, None, interrupt);
+    }
+}
+
+/// Writes a frame of the call stack. With symbols, the function is named after the symbol
+/// closest to the address instead of the address it was called at.
+fn write_frame(
+    gameboy: &GameBoy,
+    output: &mut String,
+    depth: usize,
+    address: u16,
+    function: Option<String>,
+    interrupted: bool,
+) {
+    write!(output, "#{depth}  ${address:04X}").unwrap();
+    if let Some(function) = gameboy.describe_address(address).or(function) {
+        write!(output, " in {function}").unwrap();
+    }
+    if interrupted {
+        output.push_str(", interrupted");
+    }
+    output.push('\n');
+}
+
+/// The instruction at PC, followed by the symbol it belongs to.
+fn location(gameboy: &GameBoy) -> String {
+    let pc = gameboy.registers().pc;
+    let line = disassembly_line(&gameboy.disassemble(pc));
+    match gameboy.describe_address(pc) {
+        Some(symbol) => format!("{line}  ; {symbol}"),
+        None => line,
+    }
+}
+
+fn format_registers(registers: &CpuRegisters) -> String {
+    let flags: String = FLAGS
+        .iter()
+        .map(|(name, mask)| if registers.f & mask != 0 { *name } else { '-' })
+        .collect();
+    format!(
+        "A={:02X} F={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} SP={:04X} \
+         PC={:04X}  {flags}",
+        registers.a,
+        registers.f,
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+        registers.sp,
+        registers.pc,
+    )
+}
+
+fn set_register(
+    mut registers: CpuRegisters,
+    name: &str,
+    value: &str,
+) -> Result<CpuRegisters, String> {
+    let value = parse_hex(value)?;
+    let byte = || u8::try_from(value).map_err(|_| format!("{name} is only 8 bits"));
+    let [high, low] = value.to_be_bytes();
+    match name.to_ascii_lowercase().as_str() {
+        "a" => registers.a = byte()?,
+        "f" => registers.f = byte()? & 0xF0,
+        "b" => registers.b = byte()?,
+        "c" => registers.c = byte()?,
+        "d" => registers.d = byte()?,
+        "e" => registers.e = byte()?,
+        "h" => registers.h = byte()?,
+        "l" => registers.l = byte()?,
+        "af" => (registers.a, registers.f) = (high, low & 0xF0),
+        "bc" => (registers.b, registers.c) = (high, low),
+        "de" => (registers.d, registers.e) = (high, low),
+        "hl" => (registers.h, registers.l) = (high, low),
+        "sp" => registers.sp = value,
+        "pc" => registers.pc = value,
+        _ => return Err(format!("unknown register: {name}")),
+    }
+    Ok(registers)
+}
+
+/// Shows 16 bytes per line, followed by their printable ASCII characters.
+fn dump(gameboy: &GameBoy, start: u16, length: u32, output: &mut String) {
+    let end = (start as u32 + length).min(0x10000);
+    for line_start in (start as u32..end).step_by(16) {
+        let bytes: Vec<u8> = (line_start..end.min(line_start + 16))
+            .map(|address| gameboy.peek(address as u16))
+            .collect();
+        let hex: Vec<_> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        let text: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(output, "{line_start:04X}  {:<47}  {text}", hex.join(" ")).unwrap();
+    }
+}
+
+/// Disassembles `count` instructions from `start`, or around PC with PC marked by `=>`.
+fn list(gameboy: &GameBoy, start: Option<u16>, count: usize, output: &mut String) {
+    let pc = gameboy.registers().pc;
+    let start = start.unwrap_or_else(|| {
+        // Instructions can't be decoded backwards, so find an earlier address whose instructions
+        // lead to PC, and show up to half of them.
+        let candidates = (pc.saturating_sub(LIST_LOOKBEHIND)..pc).filter_map(|start| {
+            let mut addresses = vec![start];
+            let mut address = start;
+            while address < pc {
+                address = address.wrapping_add(gameboy.disassemble(address).length());
+                addresses.push(address);
+            }
+            (address == pc).then_some(addresses)
+        });
+        match candidates.max_by_key(Vec::len) {
+            Some(addresses) => addresses[addresses.len().saturating_sub(count / 2 + 1)],
+            None => pc,
+        }
+    });
+    let mut address = start;
+    for _ in 0..count {
+        let instruction = gameboy.disassemble(address);
+        for name in gameboy
+            .symbols()
+            .names_at(address, gameboy.rom_bank(address))
+        {
+            writeln!(output, "   {name}:").unwrap();
+        }
+        let marker = if address == pc { "=>" } else { "  " };
+        writeln!(output, "{marker} {}", disassembly_line(&instruction)).unwrap();
+        match address.checked_add(instruction.length()) {
+            Some(next) => address = next,
+            None => break,
+        }
+    }
+}
+
+fn describe(gameboy: &GameBoy, breakpoint: &Breakpoint) -> String {
+    let location = match breakpoint.bank {
+        Some(bank) => format!("{bank:02X}:{:04X}", breakpoint.address),
+        None => format!("${:04X}", breakpoint.address),
+    };
+    match gameboy
+        .symbols()
+        .describe(breakpoint.address, breakpoint.bank)
+    {
+        Some(symbol) => format!("{location} ({symbol})"),
+        None => location,
+    }
+}
+
+fn describe_watchpoint(watchpoint: &Watchpoint) -> String {
+    let access = match (watchpoint.read, watchpoint.write) {
+        (true, true) => "reads and writes",
+        (true, false) => "reads",
+        _ => "writes",
+    };
+    let (start, end) = (watchpoint.range.start(), watchpoint.range.end());
+    if start == end {
+        format!("{access} of ${start:04X}")
+    } else {
+        format!("{access} of ${start:04X}-${end:04X}")
+    }
+}
+
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address: {text}"))
+}
+
+/// Parses a hexadecimal address or the name of a symbol.
+fn parse_address(gameboy: &GameBoy, text: &str) -> Result<u16, String> {
+    match gameboy.symbols().address_of(text) {
+        Some((_, address)) => Ok(address),
+        None => parse_hex(text),
+    }
+}
+
+/// Parses `[BANK:]ADDRESS` or the name of a symbol. A breakpoint on a symbol in ROM is limited to
+/// the symbol's bank.
+fn parse_breakpoint(gameboy: &GameBoy, text: &str) -> Result<Breakpoint, String> {
+    if let Some((bank, address)) = gameboy.symbols().address_of(text) {
+        return Ok(Breakpoint {
+            bank: (address < 0x8000).then_some(bank),
+            address,
+        });
+    }
+    match text.split_once(':') {
+        Some((bank, address)) => Ok(Breakpoint {
+            bank: Some(parse_hex(bank)?),
+            address: parse_hex(address)?,
+        }),
+        None => Ok(Breakpoint {
+            bank: None,
+            address: parse_hex(text)?,
+        }),
+    }
+}
+
+fn parse_range(gameboy: &GameBoy, text: &str) -> Result<std::ops::RangeInclusive<u16>, String> {
+    let (start, end) = match text.split_once(':') {
+        Some((start, end)) => (parse_address(gameboy, start)?, parse_address(gameboy, end)?),
+        None => {
+            let address = parse_address(gameboy, text)?;
+            (address, address)
+        }
+    };
+    if end < start {
+        return Err(format!("invalid range: {text}"));
+    }
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{gameboy, rom_with_program};
+    use gameboy_emu::{Model, Symbols, asm};
+
+    fn execute(debugger: &mut Debugger, gameboy: &mut GameBoy, line: &str) -> String {
+        debugger
+            .execute(gameboy, line, &mut || false)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn breakpoints_and_backtrace() {
+        let mut gameboy = gameboy();
+        let mut debugger = Debugger::default();
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "break 107"),
+            "Breakpoint 1 at $0107"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "b 01:0107"),
+            "Breakpoint 2 at 01:0107"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "c"),
+            "Breakpoint 1\n0107  EA 00 C0  LD [$C000], A"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "bt"),
+            "#0  $0107 in $0107\n#1  $0102"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "info"),
+            "1: breakpoint at $0107\n2: breakpoint at 01:0107"
+        );
+        execute(&mut debugger, &mut gameboy, "delete 1");
+        execute(&mut debugger, &mut gameboy, "step 3");
+        assert_eq!(gameboy.registers().pc, 0x0105);
+        assert_eq!(execute(&mut debugger, &mut gameboy, "bt"), "#0  $0105");
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut gameboy = gameboy();
+        let mut debugger = Debugger::default();
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "watch c000:c0ff"),
+            "Watchpoint 1 on writes of $C000-$C0FF"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "c"),
+            "Watchpoint 1: wrote $01 to $C000\n010A  3C        INC A"
+        );
+    }
+
+    #[test]
+    fn next_runs_calls_completely() {
+        let mut gameboy = gameboy();
+        let mut debugger = Debugger::default();
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "n"),
+            "0102  CD 07 01  CALL $0107"
+        );
+        // An empty line repeats the last command.
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, ""),
+            "0105  18 FE     JR $0105"
+        );
+        assert_eq!(gameboy.peek(0xC000), 1);
+        assert_eq!(gameboy.registers().a, 2);
+    }
+
+    #[test]
+    fn registers() {
+        let mut gameboy = gameboy();
+        let mut debugger = Debugger::default();
+        execute(&mut debugger, &mut gameboy, "set hl $C123");
+        execute(&mut debugger, &mut gameboy, "set a 0x42");
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "flag c 1"),
+            "A=42 F=90 B=00 C=13 D=00 E=D8 H=C1 L=23 SP=FFFE PC=0100  Z--C"
+        );
+        assert!(
+            debugger
+                .execute(&mut gameboy, "set a 100", &mut || false)
+                .is_err()
+        );
+        assert!(
+            debugger
+                .execute(&mut gameboy, "flag q 1", &mut || false)
+                .is_err()
+        );
+        assert!(
+            debugger
+                .execute(&mut gameboy, "step 1 2", &mut || false)
+                .is_err()
+        );
+        assert!(
+            debugger
+                .execute(&mut gameboy, "jump", &mut || false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn memory_and_disassembly() {
+        let mut gameboy = gameboy();
+        let mut debugger = Debugger::default();
+        gameboy.poke(0xC000, b'H');
+        gameboy.poke(0xC001, b'i');
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "x c000 18"),
+            "C000  48 69 00 00 00 00 00 00 00 00 00 00 00 00 00 00  Hi..............\n\
+             C010  00 00                                            .."
+        );
+
+        execute(&mut debugger, &mut gameboy, "s 2");
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "list"),
+            "   00FE  00        NOP\n   \
+             00FF  00        NOP\n   \
+             0100  3E 01     LD A, $01\n   \
+             0102  CD 07 01  CALL $0107\n   \
+             0105  18 FE     JR $0105\n\
+             => 0107  EA 00 C0  LD [$C000], A\n   \
+             010A  3C        INC A\n   \
+             010B  C9        RET\n   \
+             010C  00        NOP\n   \
+             010D  00        NOP"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "l 105 1"),
+            "   0105  18 FE     JR $0105"
+        );
+    }
+
+    #[test]
+    fn software_breakpoints_and_messages() {
+        let program = asm!(
+            0x0100,
+            "ld a, 7
+                ld d, d
+                jr end
+                dw $6464, $0000
+                db \"A=%A%\"
+            end:
+                ld b, b
+            loop:
+                jr loop"
+        );
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.set_software_breakpoints(true);
+        gameboy.set_debug_messages(true);
+        let mut debugger = Debugger::default();
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "c"),
+            "Message: A=07\nSoftware breakpoint\n010E  40        LD B, B"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "s"),
+            "010F  18 FE     JR $010F"
+        );
+    }
+
+    #[test]
+    fn rewinding() {
+        let mut gameboy = gameboy();
+        let mut debugger = Debugger::default();
+        let start = gameboy.save_state();
+        // The loop at 0x0105 runs for a few frames.
+        while gameboy.frame_count() < 5 {
+            execute(&mut debugger, &mut gameboy, "s 100");
+        }
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "rewind 2"),
+            "Rewound 2 frames\n0105  18 FE     JR $0105"
+        );
+        assert_eq!(gameboy.frame_count(), 3);
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "rewind 10"),
+            "Rewound 3 frames\n0100  3E 01     LD A, $01"
+        );
+        assert_eq!(gameboy.save_state(), start);
+    }
+
+    #[test]
+    fn interrupting() {
+        let mut gameboy = gameboy();
+        let mut debugger = Debugger::default();
+        let output = debugger.execute(&mut gameboy, "c", &mut || true).unwrap();
+        assert_eq!(output.unwrap(), "Interrupted\n0105  18 FE     JR $0105");
+        assert_eq!(debugger.execute(&mut gameboy, "q", &mut || false), Ok(None));
+    }
+
+    #[test]
+    fn symbols() {
+        let mut gameboy = gameboy();
+        let symbols = "00:0100 Start\n00:0105 Start.loop\n00:0107 Store\n00:c000 wValue";
+        gameboy.set_symbols(Symbols::parse(symbols).unwrap());
+        let mut debugger = Debugger::default();
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "break Store"),
+            "Breakpoint 1 at 00:0107 (Store)"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "watch wValue"),
+            "Watchpoint 2 on writes of $C000"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "c"),
+            "Breakpoint 1\n0107  EA 00 C0  LD [wValue], A  ; Store"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "c"),
+            "Watchpoint 2: wrote $01 to $C000\n010A  3C        INC A  ; Store+3"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "bt"),
+            "#0  $010A in Store+3\n#1  $0102 in Start+2"
+        );
+        assert_eq!(
+            execute(&mut debugger, &mut gameboy, "l Start.loop 2"),
+            "   Start.loop:\n   0105  18 FE     JR Start.loop\n   Store:\n   0107  EA 00 C0  LD [wValue], A"
+        );
+        assert!(
+            debugger
+                .execute(&mut gameboy, "break Missing", &mut || false)
+                .is_err()
+        );
+    }
+}
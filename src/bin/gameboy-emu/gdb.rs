@@ -0,0 +1,560 @@
+//! A server for GDB's remote serial protocol, so gdb or any other client speaking the protocol can
+//! debug a game over a local TCP connection.
+//!
+//! The SM83 has no architecture of its own in GDB, so the registers are described to the client
+//! with a target description: AF, BC, DE, HL, SP and PC, each 16 bits wide and sent in
+//! little-endian order like all values in the protocol. Software and hardware breakpoints both
+//! stop before the instruction at their address is executed, without changing the memory.
+//! Watchpoints stop after the CPU's access, including instruction fetches for read watchpoints.
+
+use crate::INTERRUPT_CHECK_INTERVAL;
+use gameboy_emu::{CpuRegisters, GameBoy, Watchpoint};
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Sent by the client to interrupt the game while it runs.
+const INTERRUPT: u8 = 0x03;
+/// The largest packet the client may send, and the largest one sent to it.
+const PACKET_SIZE: usize = 0x1000;
+/// The most bytes of memory a single read returns, each encoded as two hex digits.
+const MAX_READ_LENGTH: usize = PACKET_SIZE / 2;
+/// The signals reported in stop replies.
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="gameboy-emu.sm83">
+    <reg name="af" bitsize="16" type="uint16"/>
+    <reg name="bc" bitsize="16" type="uint16"/>
+    <reg name="de" bitsize="16" type="uint16"/>
+    <reg name="hl" bitsize="16" type="uint16"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// What the server does after handling a packet.
+#[derive(PartialEq, Eq, Debug)]
+enum Response {
+    Reply(String),
+    /// Ends the session, after sending the reply if there is one.
+    Close(Option<String>),
+}
+
+/// The state of a debugging session.
+#[derive(Default)]
+struct Session {
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+/// Waits for a client on the listener and serves it until it detaches, kills the game or
+/// disconnects.
+pub(crate) fn serve(gameboy: &mut GameBoy, listener: &TcpListener) -> Result<(), String> {
+    let (stream, address) = listener.accept().map_err(|error| error.to_string())?;
+    eprintln!("GDB connected from {address}");
+    let mut connection = Connection { stream };
+    let mut session = Session::default();
+    gameboy.set_software_breakpoints(true);
+    loop {
+        let Some(packet) = connection
+            .read_packet()
+            .map_err(|error| error.to_string())?
+        else {
+            return Ok(());
+        };
+        let mut interrupted = || connection.interrupted();
+        let (reply, close) = match session.handle(gameboy, &packet, &mut interrupted) {
+            Response::Reply(reply) => (Some(reply), false),
+            Response::Close(reply) => (reply, true),
+        };
+        if let Some(reply) = reply {
+            connection.send(&reply).map_err(|error| error.to_string())?;
+        }
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    /// Reads the next packet and acknowledges it, or returns [`None`] if the client disconnected.
+    /// Acknowledgements and interrupts outside of packets are skipped.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => {}
+                }
+            }
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let valid = match checksum {
+                [Some(high), Some(low)] => {
+                    let text = [high, low];
+                    std::str::from_utf8(&text)
+                        .ok()
+                        .and_then(|text| u8::from_str_radix(text, 16).ok())
+                        == Some(checksum_of(&data))
+                }
+                _ => return Ok(None),
+            };
+            if !valid {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    /// Whether the client sent an interrupt, without waiting for one.
+    fn interrupted(&mut self) -> bool {
+        let mut byte = [0];
+        let _ = self.stream.set_nonblocking(true);
+        let result = self.stream.read(&mut byte);
+        let _ = self.stream.set_nonblocking(false);
+        matches!(result, Ok(1) if byte[0] == INTERRUPT)
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+impl Session {
+    /// Handles a packet without its framing. `interrupted` is polled while the game runs.
+    fn handle(
+        &mut self,
+        gameboy: &mut GameBoy,
+        packet: &str,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> Response {
+        let reply = |text: &str| Response::Reply(text.to_string());
+        let error = || Response::Reply("E01".to_string());
+        let (command, args) = packet.split_at(packet.len().min(1));
+        match command {
+            "?" => reply(&format!("S{SIGTRAP:02x}")),
+            "g" => reply(&encode_registers(&gameboy.registers())),
+            "G" => match decode_registers(args) {
+                Some(registers) => {
+                    gameboy.set_registers(registers);
+                    reply("OK")
+                }
+                None => error(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(index) if index < 6 => {
+                    let encoded = encode_registers(&gameboy.registers());
+                    reply(&encoded[index * 4..index * 4 + 4])
+                }
+                _ => error(),
+            },
+            "P" => {
+                let registers = args.split_once('=').and_then(|(index, value)| {
+                    let index = usize::from_str_radix(index, 16).ok().filter(|i| *i < 6)?;
+                    let mut encoded = encode_registers(&gameboy.registers());
+                    encoded.replace_range(index * 4..index * 4 + 4, value.get(..4)?);
+                    decode_registers(&encoded)
+                });
+                match registers {
+                    Some(registers) => {
+                        gameboy.set_registers(registers);
+                        reply("OK")
+                    }
+                    None => error(),
+                }
+            }
+            "m" => match parse_address_length(args) {
+                Some((address, length)) if (1..=MAX_READ_LENGTH).contains(&length) => {
+                    let end = (address as usize).saturating_add(length).min(0x10000);
+                    let mut data = String::new();
+                    for address in address as usize..end {
+                        write!(data, "{:02x}", gameboy.peek(address as u16)).unwrap();
+                    }
+                    reply(&data)
+                }
+                _ => error(),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(target, data)| {
+                    let (address, length) = parse_address_length(target)?;
+                    let bytes = decode_hex(data).filter(|bytes| bytes.len() == length)?;
+                    for (offset, byte) in bytes.into_iter().enumerate() {
+                        gameboy.poke(address.wrapping_add(offset as u16), byte);
+                    }
+                    Some(())
+                });
+                match written {
+                    Some(()) => reply("OK"),
+                    None => error(),
+                }
+            }
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match u16::from_str_radix(args, 16) {
+                        Ok(pc) => gameboy.set_registers(CpuRegisters {
+                            pc,
+                            ..gameboy.registers()
+                        }),
+                        Err(_) => return error(),
+                    }
+                }
+                reply(&self.run(gameboy, command == "s", interrupted))
+            }
+            "Z" | "z" => self.set_point(gameboy, command == "Z", args),
+            "H" | "T" => reply("OK"),
+            "D" => Response::Close(Some("OK".to_string())),
+            "k" => Response::Close(None),
+            "q" => reply(&query(args)),
+            _ => reply(""),
+        }
+    }
+
+    /// Executes a single instruction, or runs until a breakpoint or watchpoint is hit or the
+    /// client interrupts. Returns the stop reply.
+    fn run(
+        &mut self,
+        gameboy: &mut GameBoy,
+        single_step: bool,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> String {
+        let mut executed = 0u64;
+        loop {
+            gameboy.step_instruction();
+            for message in gameboy.take_debug_messages() {
+                eprintln!("{message}");
+            }
+            if gameboy.is_paused_at_breakpoint() {
+                return format!("S{SIGTRAP:02x}");
+            }
+            if let Some(hit) = gameboy.take_watchpoint_hits().first() {
+                let watchpoint = self
+                    .watchpoints
+                    .iter()
+                    .find(|watchpoint| watchpoint.matches(hit.address, hit.kind));
+                let kind = match watchpoint {
+                    Some(watchpoint) if watchpoint.read && watchpoint.write => "awatch",
+                    Some(watchpoint) if watchpoint.read => "rwatch",
+                    _ => "watch",
+                };
+                return format!("T{SIGTRAP:02x}{kind}:{:04x};", hit.address);
+            }
+            if single_step || self.breakpoints.contains(&gameboy.registers().pc) {
+                return format!("S{SIGTRAP:02x}");
+            }
+            executed += 1;
+            if executed.is_multiple_of(INTERRUPT_CHECK_INTERVAL) && interrupted() {
+                return format!("S{SIGINT:02x}");
+            }
+        }
+    }
+
+    /// Inserts or removes a breakpoint or watchpoint, from arguments like `0,c000,1`.
+    fn set_point(&mut self, gameboy: &mut GameBoy, insert: bool, args: &str) -> Response {
+        let Some((kind, target)) = args.split_once(',') else {
+            return Response::Reply("E01".to_string());
+        };
+        let Some((address, length)) =
+            parse_address_length(target).filter(|(_, length)| *length <= 0x10000)
+        else {
+            return Response::Reply("E01".to_string());
+        };
+        match kind {
+            // Software and hardware breakpoints.
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.push(address);
+                } else if let Some(index) = self.breakpoints.iter().position(|a| *a == address) {
+                    self.breakpoints.remove(index);
+                }
+            }
+            // Write, read and access watchpoints.
+            "2" | "3" | "4" => {
+                let end = address.saturating_add(length.saturating_sub(1) as u16);
+                let watchpoint = Watchpoint {
+                    range: address..=end,
+                    read: kind != "2",
+                    write: kind != "3",
+                };
+                if insert {
+                    self.watchpoints.push(watchpoint);
+                } else if let Some(index) = self.watchpoints.iter().position(|w| *w == watchpoint) {
+                    self.watchpoints.remove(index);
+                }
+                gameboy.set_watchpoints(self.watchpoints.clone());
+            }
+            _ => return Response::Reply(String::new()),
+        }
+        Response::Reply("OK".to_string())
+    }
+}
+
+/// Answers a general query, or with an empty reply if it isn't supported.
+fn query(query: &str) -> String {
+    if query.starts_with("Supported") {
+        return format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+");
+    }
+    if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+        let Some((offset, length)) = range.split_once(',').and_then(|(offset, length)| {
+            let offset = usize::from_str_radix(offset, 16).ok()?;
+            Some((offset, usize::from_str_radix(length, 16).ok()?))
+        }) else {
+            return "E01".to_string();
+        };
+        let start = offset.min(TARGET_XML.len());
+        // One byte of the reply is taken by the marker.
+        let end = start
+            .saturating_add(length.min(PACKET_SIZE - 1))
+            .min(TARGET_XML.len());
+        let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+        return format!("{marker}{}", &TARGET_XML[start..end]);
+    }
+    match query {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Encodes AF, BC, DE, HL, SP and PC as little-endian hex.
+fn encode_registers(registers: &CpuRegisters) -> String {
+    let bytes = [
+        registers.f,
+        registers.a,
+        registers.c,
+        registers.b,
+        registers.e,
+        registers.d,
+        registers.l,
+        registers.h,
+    ];
+    let mut text: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    for value in [registers.sp, registers.pc] {
+        for byte in value.to_le_bytes() {
+            write!(text, "{byte:02x}").unwrap();
+        }
+    }
+    text
+}
+
+fn decode_registers(text: &str) -> Option<CpuRegisters> {
+    let bytes = decode_hex(text).filter(|bytes| bytes.len() == 12)?;
+    Some(CpuRegisters {
+        f: bytes[0],
+        a: bytes[1],
+        c: bytes[2],
+        b: bytes[3],
+        e: bytes[4],
+        d: bytes[5],
+        l: bytes[6],
+        h: bytes[7],
+        sp: u16::from_le_bytes([bytes[8], bytes[9]]),
+        pc: u16::from_le_bytes([bytes[10], bytes[11]]),
+    })
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `ADDRESS,LENGTH`, both in hex.
+fn parse_address_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    let address = u16::from_str_radix(address, 16).ok()?;
+    Some((address, usize::from_str_radix(length, 16).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::gameboy;
+
+    fn handle(session: &mut Session, gameboy: &mut GameBoy, packet: &str) -> String {
+        match session.handle(gameboy, packet, &mut || false) {
+            Response::Reply(reply) => reply,
+            Response::Close(_) => panic!("{packet} closed the session"),
+        }
+    }
+
+    #[test]
+    fn registers() {
+        let mut gameboy = gameboy();
+        let mut session = Session::default();
+        assert_eq!(
+            handle(&mut session, &mut gameboy, "g"),
+            "80011300d8004d01feff0001"
+        );
+        assert_eq!(handle(&mut session, &mut gameboy, "p5"), "0001");
+        assert_eq!(handle(&mut session, &mut gameboy, "P1=3412"), "OK");
+        assert_eq!(gameboy.registers().b, 0x12);
+        assert_eq!(gameboy.registers().c, 0x34);
+        assert_eq!(
+            handle(&mut session, &mut gameboy, "G00000000000000000000c0000c"),
+            "E01"
+        );
+        assert_eq!(
+            handle(&mut session, &mut gameboy, "G0000000000000000feff5001"),
+            "OK"
+        );
+        assert_eq!(gameboy.registers().pc, 0x0150);
+    }
+
+    #[test]
+    fn memory() {
+        let mut gameboy = gameboy();
+        let mut session = Session::default();
+        assert_eq!(handle(&mut session, &mut gameboy, "m100,3"), "3e01cd");
+        assert_eq!(handle(&mut session, &mut gameboy, "Mc000,2:abcd"), "OK");
+        assert_eq!(handle(&mut session, &mut gameboy, "mc000,2"), "abcd");
+        assert_eq!(handle(&mut session, &mut gameboy, "Mc000,2:ab"), "E01");
+        assert_eq!(handle(&mut session, &mut gameboy, "mfffe,4"), "0000");
+        assert_eq!(handle(&mut session, &mut gameboy, "m0,0"), "E01");
+        assert_eq!(
+            handle(&mut session, &mut gameboy, "m0,ffffffffffffffff"),
+            "E01"
+        );
+        assert_eq!(
+            handle(
+                &mut session,
+                &mut gameboy,
+                &format!("m0,{MAX_READ_LENGTH:x}")
+            )
+            .len(),
+            PACKET_SIZE
+        );
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut gameboy = gameboy();
+        let mut session = Session::default();
+        assert_eq!(handle(&mut session, &mut gameboy, "s"), "S05");
+        assert_eq!(gameboy.registers().pc, 0x0102);
+
+        assert_eq!(handle(&mut session, &mut gameboy, "Z0,10a,1"), "OK");
+        assert_eq!(handle(&mut session, &mut gameboy, "c"), "S05");
+        assert_eq!(gameboy.registers().pc, 0x010A);
+        assert_eq!(handle(&mut session, &mut gameboy, "z0,10a,1"), "OK");
+
+        assert_eq!(handle(&mut session, &mut gameboy, "Z4,c000,1"), "OK");
+        assert_eq!(
+            handle(&mut session, &mut gameboy, "c107"),
+            "T05awatch:c000;"
+        );
+        assert_eq!(handle(&mut session, &mut gameboy, "z4,c000,1"), "OK");
+        assert_eq!(handle(&mut session, &mut gameboy, "Z3,10a,1"), "OK");
+        assert_eq!(handle(&mut session, &mut gameboy, "c"), "T05rwatch:010a;");
+        assert_eq!(handle(&mut session, &mut gameboy, "z3,10a,1"), "OK");
+        assert_eq!(handle(&mut session, &mut gameboy, "Z2,c000,1"), "OK");
+        assert_eq!(handle(&mut session, &mut gameboy, "c107"), "T05watch:c000;");
+        assert_eq!(handle(&mut session, &mut gameboy, "Z2,c000,10001"), "E01");
+        assert_eq!(handle(&mut session, &mut gameboy, "Z2,ff00,10000"), "OK");
+        assert_eq!(session.watchpoints.last().unwrap().range, 0xFF00..=0xFFFF);
+
+        let reply = session.handle(&mut gameboy, "c", &mut || true);
+        assert_eq!(reply, Response::Reply("S02".to_string()));
+    }
+
+    #[test]
+    fn queries() {
+        let mut gameboy = gameboy();
+        let mut session = Session::default();
+        let supported = handle(&mut session, &mut gameboy, "qSupported:swbreak+");
+        assert!(supported.contains("qXfer:features:read+"));
+        let start = handle(
+            &mut session,
+            &mut gameboy,
+            "qXfer:features:read:target.xml:0,10",
+        );
+        assert_eq!(start, "m<?xml version=\"1");
+        let end = handle(
+            &mut session,
+            &mut gameboy,
+            "qXfer:features:read:target.xml:10,1000",
+        );
+        assert!(end.starts_with('l') && end.ends_with("</target>\n"));
+        let whole = handle(
+            &mut session,
+            &mut gameboy,
+            "qXfer:features:read:target.xml:0,ffffffffffffffff",
+        );
+        assert_eq!(whole, format!("l{TARGET_XML}"));
+        assert_eq!(handle(&mut session, &mut gameboy, "vMustReplyEmpty"), "");
+        assert_eq!(
+            session.handle(&mut gameboy, "D", &mut || false),
+            Response::Close(Some("OK".to_string()))
+        );
+    }
+
+    /// Talks to the server through a real socket, including the packet framing.
+    #[test]
+    fn connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            // A packet with an invalid checksum is rejected.
+            stream.write_all(b"$g#00").unwrap();
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            assert_eq!(&byte, b"-");
+
+            let mut exchange = |packet: &str| {
+                let framed = format!("${packet}#{:02x}", checksum_of(packet.as_bytes()));
+                stream.write_all(framed.as_bytes()).unwrap();
+                let mut reply = Vec::new();
+                let mut byte = [0];
+                while !reply.ends_with(b"#") {
+                    stream.read_exact(&mut byte).unwrap();
+                    reply.push(byte[0]);
+                }
+                let mut checksum = [0; 2];
+                stream.read_exact(&mut checksum).unwrap();
+                stream.write_all(b"+").unwrap();
+                String::from_utf8(reply).unwrap()
+            };
+            [exchange("?"), exchange("m100,2"), exchange("D")]
+        });
+        serve(&mut gameboy(), &listener).unwrap();
+        assert_eq!(client.join().unwrap(), ["+$S05#", "+$3e01#", "+$OK#"]);
+    }
+}
@@ -0,0 +1,293 @@
+//! A headless frontend that runs a ROM until a stop condition is met and reports the result,
+//! meant for running test ROMs in CI. Alternatively, games can be played inside the terminal.
+
+mod args;
+mod debugger;
+mod gdb;
+mod runner;
+mod terminal;
+#[cfg(test)]
+mod test_support;
+
+use args::Options;
+use gameboy_emu::{Disassembly, GameBoy, InputScript, Model, Movie, Symbols, disassemble_rom};
+use runner::StopConditions;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::Path;
+use std::process::ExitCode;
+
+/// Returned when one of the limits was reached before any of the `--until` conditions.
+const EXIT_TIMEOUT: u8 = 1;
+/// Returned when the arguments or files are invalid.
+const EXIT_ERROR: u8 = 2;
+/// Returned when playing back a movie ended in another state than recording it.
+const EXIT_DESYNC: u8 = 3;
+/// How many instructions the debuggers execute between checks whether the user or the GDB client
+/// interrupted the game.
+const INTERRUPT_CHECK_INTERVAL: u64 = 0x1000;
+
+fn main() -> ExitCode {
+    let options = match args::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", args::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("error: {error}\n\n{}", args::USAGE);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    match run(&options) {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<ExitCode, String> {
+    let rom = read(&options.rom)?;
+    let symbols = read_symbols(options)?;
+    if let Some(dir) = &options.disassemble_rom {
+        write_disassembly(dir, &rom, &symbols)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(path) = &options.play_movie {
+        return play_movie(options, path, &rom, symbols);
+    }
+    let mut script = match &options.input {
+        Some(path) => {
+            let text = String::from_utf8(read(path)?)
+                .map_err(|_| format!("{} is not valid UTF-8", path.display()))?;
+            InputScript::parse(&text).map_err(|error| format!("{}: {error}", path.display()))?
+        }
+        None => InputScript::default(),
+    };
+
+    let mut gameboy = match (options.model, options.palette) {
+        (model, Some(palette)) => GameBoy::with_palette(&rom, model.unwrap_or(Model::Cgb), palette),
+        (Some(model), None) => GameBoy::with_model(&rom, model),
+        (None, None) => GameBoy::new(&rom),
+    }
+    .map_err(|error| format!("{}: {error}", options.rom.display()))?;
+    gameboy.set_symbols(symbols);
+    gameboy.set_debug_messages(options.debug_messages);
+    if let Some(path) = &options.load_state {
+        gameboy
+            .load_state(&read(path)?)
+            .map_err(|error| format!("{}: {error}", path.display()))?;
+    }
+    let mut movie = match &options.record_movie {
+        Some(_) if options.load_state.is_some() => Some(Movie::from_state(&gameboy)),
+        Some(path) => Some(
+            Movie::from_power_on(&gameboy)
+                .map_err(|error| format!("{}: {error}", path.display()))?,
+        ),
+        None => None,
+    };
+    if let Some((start, end)) = options.disassemble {
+        print_disassembly(&gameboy, start, end);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(path) = &options.trace {
+        gameboy.set_trace_log(Some(open_trace(path)?));
+    }
+    if options.debug {
+        debugger::run(&mut gameboy)?;
+        write_outputs(options, &mut gameboy, None)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(port) = options.gdb {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .map_err(|error| format!("could not listen on port {port}: {error}"))?;
+        eprintln!("Waiting for GDB to connect to localhost:{port}");
+        gdb::serve(&mut gameboy, &listener)?;
+        write_outputs(options, &mut gameboy, None)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if options.terminal {
+        terminal::run(&mut gameboy, movie.as_mut())?;
+        write_outputs(options, &mut gameboy, movie.as_ref())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let conditions = StopConditions {
+        frames: options.frames,
+        cycles: options.cycles,
+        pc: options.until_pc,
+        serial: options.until_serial.clone(),
+        breakpoint: options.until_breakpoint,
+    };
+    let summary = runner::run(&mut gameboy, &conditions, &mut script, movie.as_mut());
+    let pc = gameboy.registers().pc;
+    eprintln!(
+        "Stopped after {} frames ({} cycles) at PC {pc:#06X} ({}): {}",
+        summary.frames,
+        summary.cycles,
+        gameboy.disassemble(pc),
+        summary.reason
+    );
+
+    write_outputs(options, &mut gameboy, movie.as_ref())?;
+    if options.has_condition() && !summary.reason.is_condition() {
+        return Ok(ExitCode::from(EXIT_TIMEOUT));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Plays back the movie in `path` and checks that it ends in the state it was recorded in.
+fn play_movie(
+    options: &Options,
+    path: &Path,
+    rom: &[u8],
+    symbols: Symbols,
+) -> Result<ExitCode, String> {
+    let with_path = |error| format!("{}: {error}", path.display());
+    let movie = Movie::decode(&read(path)?).map_err(with_path)?;
+    let mut gameboy = movie.start(rom).map_err(with_path)?;
+    gameboy.set_symbols(symbols);
+    gameboy.set_debug_messages(options.debug_messages);
+    if let Some(path) = &options.trace {
+        gameboy.set_trace_log(Some(open_trace(path)?));
+    }
+    let result = movie.play(&mut gameboy);
+    for message in gameboy.take_debug_messages() {
+        eprintln!("{message}");
+    }
+    write_outputs(options, &mut gameboy, None)?;
+    match result {
+        Ok(()) if movie.is_finished() => {
+            eprintln!(
+                "Played back {} frames, ending in the recorded state",
+                movie.len()
+            );
+        }
+        Ok(()) => eprintln!("Played back {} frames without verifying them", movie.len()),
+        Err(error) => {
+            eprintln!("error: {}", with_path(error));
+            return Ok(ExitCode::from(EXIT_DESYNC));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the instructions from `start` to `end` with their addresses and bytes.
+fn print_disassembly(gameboy: &GameBoy, start: u16, end: u16) {
+    let mut address = start;
+    loop {
+        let instruction = gameboy.disassemble(address);
+        for name in gameboy
+            .symbols()
+            .names_at(address, gameboy.rom_bank(address))
+        {
+            println!("{name}:");
+        }
+        println!("{}", disassembly_line(&instruction));
+        match address.checked_add(instruction.length()) {
+            Some(next) if next <= end => address = next,
+            _ => break,
+        }
+    }
+}
+
+/// Formats an instruction with its address and bytes, like `0150  F0 44     LDH A, [$FF44]`.
+fn disassembly_line(instruction: &Disassembly) -> String {
+    let bytes: Vec<_> = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect();
+    format!(
+        "{:04X}  {:<8}  {instruction}",
+        instruction.address,
+        bytes.join(" ")
+    )
+}
+
+/// Writes the source files of the ROM's disassembly into `dir`, creating it if needed.
+fn write_disassembly(dir: &Path, rom: &[u8], symbols: &Symbols) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|error| format!("could not create {}: {error}", dir.display()))?;
+    let files = disassemble_rom(rom, symbols);
+    for file in &files {
+        write(&dir.join(&file.name), file.text.as_bytes())?;
+    }
+    eprintln!("Wrote {} files to {}", files.len(), dir.display());
+    Ok(())
+}
+
+/// Reads the symbol file given with `--symbols`, or the one next to the ROM if there is one.
+fn read_symbols(options: &Options) -> Result<Symbols, String> {
+    let path = match &options.symbols {
+        Some(path) => path.clone(),
+        None => {
+            let path = options.rom.with_extension("sym");
+            if !path.is_file() {
+                return Ok(Symbols::default());
+            }
+            path
+        }
+    };
+    let text = String::from_utf8(read(&path)?)
+        .map_err(|_| format!("{} is not valid UTF-8", path.display()))?;
+    Symbols::parse(&text).map_err(|error| format!("{}: {error}", path.display()))
+}
+
+/// Opens the file the trace log is written to, or the standard output for `-`.
+fn open_trace(path: &Path) -> Result<Box<dyn Write + Send>, String> {
+    if path == Path::new("-") {
+        return Ok(Box::new(io::stdout()));
+    }
+    let file = File::create(path)
+        .map_err(|error| format!("could not create {}: {error}", path.display()))?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+/// Finishes the trace log, and writes the screenshot, serial log, save state and movie, if
+/// requested.
+fn write_outputs(
+    options: &Options,
+    gameboy: &mut GameBoy,
+    movie: Option<&Movie>,
+) -> Result<(), String> {
+    if let Some(path) = &options.trace {
+        let error = match gameboy.set_trace_log(None) {
+            Some(mut log) => log.flush().err(),
+            None => gameboy.take_trace_error(),
+        };
+        if let Some(error) = error {
+            return Err(format!("could not write {}: {error}", path.display()));
+        }
+    }
+    if let Some(path) = &options.screenshot {
+        let mut image = gameboy.screenshot();
+        if let Some(theme) = &options.theme {
+            image = image.with_theme(theme);
+        }
+        let image = image.scaled(options.scale.unwrap_or(1));
+        write(path, &image.encode_png())?;
+    }
+    if let Some(path) = &options.serial_log {
+        write(path, gameboy.serial_output())?;
+    }
+    if let Some(path) = &options.save_state {
+        write(path, &gameboy.save_state())?;
+    }
+    if let (Some(path), Some(movie)) = (&options.record_movie, movie) {
+        write(path, &movie.encode())?;
+    }
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|error| format!("could not read {}: {error}", path.display()))
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), String> {
+    std::fs::write(path, data)
+        .map_err(|error| format!("could not write {}: {error}", path.display()))
+}
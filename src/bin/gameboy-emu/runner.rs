@@ -0,0 +1,221 @@
+//! Runs the emulator one instruction at a time until a stop condition is met.
+
+use gameboy_emu::{GameBoy, InputScript, Movie};
+use std::fmt;
+
+/// When to stop running.
+#[derive(Default)]
+pub(crate) struct StopConditions {
+    pub(crate) frames: Option<u64>,
+    pub(crate) cycles: Option<u64>,
+    pub(crate) pc: Option<u16>,
+    pub(crate) serial: Option<String>,
+    pub(crate) breakpoint: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum StopReason {
+    FrameLimit,
+    CycleLimit,
+    ProgramCounter,
+    SerialOutput,
+    Breakpoint,
+}
+
+impl StopReason {
+    /// Whether one of the `--until` conditions was met, as opposed to reaching a limit.
+    pub(crate) fn is_condition(self) -> bool {
+        !matches!(self, StopReason::FrameLimit | StopReason::CycleLimit)
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::FrameLimit => write!(f, "frame limit reached"),
+            StopReason::CycleLimit => write!(f, "cycle limit reached"),
+            StopReason::ProgramCounter => write!(f, "program counter reached"),
+            StopReason::SerialOutput => write!(f, "serial output matched"),
+            StopReason::Breakpoint => write!(f, "breakpoint reached"),
+        }
+    }
+}
+
+/// How long the emulator ran.
+pub(crate) struct RunSummary {
+    pub(crate) reason: StopReason,
+    pub(crate) frames: u64,
+    pub(crate) cycles: u64,
+}
+
+/// Runs until one of the conditions is met, pressing buttons as the script says. The conditions
+/// on the next instruction are checked before it is executed. Debug messages are printed to the
+/// standard error as they come in, if they are enabled. Every frame that is completed is added to
+/// the movie, and the movie is finished at the end of it.
+pub(crate) fn run(
+    gameboy: &mut GameBoy,
+    conditions: &StopConditions,
+    script: &mut InputScript,
+    mut movie: Option<&mut Movie>,
+) -> RunSummary {
+    gameboy.set_software_breakpoints(conditions.breakpoint);
+    let start_frame = gameboy.frame_count();
+    let mut cycles = 0;
+    // Only the part of the serial output that could complete a match has to be searched.
+    let mut serial_searched = 0;
+    let reason = loop {
+        let frames = gameboy.frame_count() - start_frame;
+        if conditions.frames.is_some_and(|limit| frames >= limit) {
+            break StopReason::FrameLimit;
+        }
+        if conditions.cycles.is_some_and(|limit| cycles >= limit) {
+            break StopReason::CycleLimit;
+        }
+        let pc = gameboy.registers().pc;
+        if conditions.pc == Some(pc) {
+            break StopReason::ProgramCounter;
+        }
+        if gameboy.is_paused_at_breakpoint() {
+            break StopReason::Breakpoint;
+        }
+        if let Some(text) = &conditions.serial {
+            let output = gameboy.serial_output();
+            let start = serial_searched.min(output.len()).saturating_sub(text.len());
+            if contains(&output[start..], text.as_bytes()) {
+                break StopReason::SerialOutput;
+            }
+            serial_searched = output.len();
+        }
+
+        for event in script.take_due(frames) {
+            gameboy.set_button(event.button, event.pressed);
+        }
+        cycles += gameboy.step_instruction() as u64;
+        // The buttons only change at the start of a frame, so they are still the ones that were
+        // held during the frame that just ended.
+        if let Some(movie) = movie.as_deref_mut()
+            && gameboy.frame_count() - start_frame > frames
+        {
+            movie.record_frame(gameboy);
+            movie.finish(gameboy);
+        }
+        for message in gameboy.take_debug_messages() {
+            eprintln!("{message}");
+        }
+    };
+
+    RunSummary {
+        reason,
+        frames: gameboy.frame_count() - start_frame,
+        cycles,
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::rom_with_program;
+    use gameboy_emu::{Button, CYCLES_PER_FRAME, Model};
+
+    fn gameboy_with_program(program: &[u8]) -> GameBoy {
+        GameBoy::with_model(&rom_with_program(program), Model::Dmg).unwrap()
+    }
+
+    /// Sends "OK" through the serial port, then loops forever at 0x010A.
+    const PROGRAM: [u8; 29] = [
+        0x3E, b'O', // LD A, 'O'
+        0xCD, 0x10, 0x01, // CALL 0x0110
+        0x3E, b'K', // LD A, 'K'
+        0xCD, 0x10, 0x01, // CALL 0x0110
+        0x18, 0xFE, // JR -2
+        0x00, 0x00, 0x00, 0x00, // NOP
+        // 0x0110: Send A and wait for the transfer to finish.
+        0xE0, 0x01, // LDH [SB], A
+        0x3E, 0x81, // LD A, 0x81
+        0xE0, 0x02, // LDH [SC], A
+        0xF0, 0x02, // LDH A, [SC]
+        0xCB, 0x7F, // BIT 7, A
+        0x20, 0xFA, // JR NZ, -6
+        0xC9, // RET
+    ];
+
+    #[test]
+    fn frame_limit() {
+        let mut gameboy = gameboy_with_program(&[0x18, 0xFE]);
+        let conditions = StopConditions {
+            frames: Some(3),
+            ..StopConditions::default()
+        };
+        let summary = run(&mut gameboy, &conditions, &mut InputScript::default(), None);
+        assert_eq!(summary.reason, StopReason::FrameLimit);
+        assert_eq!(summary.frames, 3);
+    }
+
+    #[test]
+    fn serial_output_stops_before_breakpoint() {
+        let mut gameboy = gameboy_with_program(&PROGRAM);
+        let conditions = StopConditions {
+            frames: Some(10),
+            serial: Some("OK".to_string()),
+            breakpoint: true,
+            ..StopConditions::default()
+        };
+        let summary = run(&mut gameboy, &conditions, &mut InputScript::default(), None);
+        assert_eq!(summary.reason, StopReason::SerialOutput);
+        assert_eq!(gameboy.serial_output(), b"OK");
+    }
+
+    #[test]
+    fn program_counter() {
+        let mut gameboy = gameboy_with_program(&PROGRAM);
+        let conditions = StopConditions {
+            cycles: Some(100_000),
+            pc: Some(0x0110),
+            ..StopConditions::default()
+        };
+        let summary = run(&mut gameboy, &conditions, &mut InputScript::default(), None);
+        assert_eq!(summary.reason, StopReason::ProgramCounter);
+        assert_eq!(gameboy.serial_output(), b"");
+    }
+
+    #[test]
+    fn breakpoint() {
+        let mut program = PROGRAM;
+        program[0x0A] = 0x40; // LD B, B
+        let mut gameboy = gameboy_with_program(&program);
+        let conditions = StopConditions {
+            frames: Some(10),
+            breakpoint: true,
+            ..StopConditions::default()
+        };
+        let summary = run(&mut gameboy, &conditions, &mut InputScript::default(), None);
+        assert_eq!(summary.reason, StopReason::Breakpoint);
+        assert_eq!(gameboy.registers().pc, 0x010A);
+        assert_eq!(gameboy.serial_output(), b"OK");
+    }
+
+    #[test]
+    fn movie_of_complete_frames() {
+        let mut gameboy = gameboy_with_program(&PROGRAM);
+        let mut movie = Movie::from_power_on(&gameboy).unwrap();
+        let conditions = StopConditions {
+            cycles: Some(CYCLES_PER_FRAME as u64 * 5 / 2),
+            ..StopConditions::default()
+        };
+        let mut script = InputScript::parse("0 press a\n1 press up\n2 release a").unwrap();
+        run(&mut gameboy, &conditions, &mut script, Some(&mut movie));
+        assert_eq!(movie.len(), 2);
+        assert!(movie.is_finished());
+
+        let mut played = movie.start(&rom_with_program(&PROGRAM)).unwrap();
+        assert_eq!(movie.play(&mut played), Ok(()));
+        assert!(played.is_pressed(Button::A) && played.is_pressed(Button::Up));
+    }
+}
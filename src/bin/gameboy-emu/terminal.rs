@@ -0,0 +1,265 @@
+//! Plays a game inside the terminal. Each character cell shows two pixels stacked on top of each
+//! other: The upper half block `▀` is drawn in the color of the upper pixel, and the cell's
+//! background in the color of the lower one, so the screen takes up 160x72 cells. Colors are sent
+//! as 24-bit ANSI escape codes, which most terminals support.
+//!
+//! Terminals only report key presses, and repeat them while a key is held. A button is therefore
+//! held for a few frames after each press, long enough for the next repetition to arrive. Holding
+//! R plays the game backwards, one frame at a time.
+
+use gameboy_emu::{
+    Button, CYCLES_PER_FRAME, Color, Frame, GameBoy, Movie, Rewind, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// How many frames a button stays pressed after its key was pressed. Covers the delay until the
+/// terminal starts repeating a held key.
+const HOLD_FRAMES: u64 = 30;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Key {
+    Button(Button),
+    Rewind,
+    Quit,
+}
+
+/// Runs the game until the user quits, recording the frames in the movie, if there is one.
+/// Rewinding takes the rewound frames out of the movie again.
+pub(crate) fn run(gameboy: &mut GameBoy, mut movie: Option<&mut Movie>) -> Result<(), String> {
+    let _raw_mode = RawMode::enable()?;
+    let keys = spawn_stdin_reader();
+    let mut stdout = std::io::stdout().lock();
+    // Switch to the alternate screen and hide the cursor.
+    write!(stdout, "\x1b[?1049h\x1b[?25l").map_err(|error| error.to_string())?;
+
+    let frame_duration =
+        Duration::from_secs_f64(CYCLES_PER_FRAME as f64 / gameboy.model().clock_rate() as f64);
+    let mut rewind = Rewind::default();
+    let start_frame = gameboy.frame_count();
+    // Keys are held for a number of shown frames, as the game's frames go backwards while
+    // rewinding.
+    let mut shown_frames = 0;
+    let mut held_until = [0; Button::ALL.len()];
+    let mut rewinding_until = 0;
+    let mut next_frame = Instant::now();
+    let mut output = String::new();
+    let result = loop {
+        let mut quit = false;
+        while let Ok(bytes) = keys.try_recv() {
+            for key in parse_keys(&bytes) {
+                match key {
+                    Key::Button(button) => {
+                        let index = Button::ALL.iter().position(|b| *b == button).unwrap();
+                        held_until[index] = shown_frames + HOLD_FRAMES;
+                    }
+                    Key::Rewind => rewinding_until = shown_frames + HOLD_FRAMES,
+                    Key::Quit => quit = true,
+                }
+            }
+        }
+        if quit {
+            if let Some(movie) = movie.as_deref_mut() {
+                movie.finish(gameboy);
+            }
+            break Ok(());
+        }
+        if shown_frames < rewinding_until {
+            rewind.rewind(gameboy, 1);
+            if let Some(movie) = movie.as_deref_mut() {
+                movie.truncate((gameboy.frame_count() - start_frame) as usize);
+            }
+        } else {
+            for (button, held_until) in Button::ALL.iter().zip(held_until) {
+                gameboy.set_button(*button, shown_frames < held_until);
+            }
+            rewind.record(gameboy);
+            if let Some(movie) = movie.as_deref_mut() {
+                movie.record_frame(gameboy);
+            }
+            gameboy.run_frame();
+        }
+        shown_frames += 1;
+        output.clear();
+        render(gameboy.frame(), &mut output);
+        if let Err(error) = stdout
+            .write_all(output.as_bytes())
+            .and_then(|()| stdout.flush())
+        {
+            break Err(error.to_string());
+        }
+
+        // Fall back to the current time if emulation can't keep up, instead of trying to catch
+        // up by running frames as fast as possible.
+        next_frame += frame_duration;
+        let now = Instant::now();
+        if next_frame > now {
+            std::thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    };
+
+    // Show the cursor and leave the alternate screen.
+    write!(stdout, "\x1b[0m\x1b[?25h\x1b[?1049l").map_err(|error| error.to_string())?;
+    stdout.flush().map_err(|error| error.to_string())?;
+    result
+}
+
+/// Draws the frame starting at the upper left corner of the terminal.
+fn render(frame: &Frame, output: &mut String) {
+    output.push_str("\x1b[H");
+    let mut last_colors = None;
+    for row in (0..SCREEN_HEIGHT).step_by(2) {
+        for x in 0..SCREEN_WIDTH {
+            let upper = frame[row * SCREEN_WIDTH + x];
+            let lower = frame[(row + 1) * SCREEN_WIDTH + x];
+            // Colors only have to be sent when they change.
+            if last_colors != Some((upper, lower)) {
+                push_color(output, 38, upper);
+                push_color(output, 48, lower);
+                last_colors = Some((upper, lower));
+            }
+            output.push('▀');
+        }
+        output.push_str("\r\n");
+    }
+}
+
+/// Appends an escape code that sets the foreground (`38`) or background (`48`) color.
+fn push_color(output: &mut String, target: u8, color: Color) {
+    use std::fmt::Write;
+    let Color { r, g, b } = color;
+    let _ = write!(output, "\x1b[{target};2;{r};{g};{b}m");
+}
+
+/// Decodes the keys in the bytes read from the terminal. Unknown keys are skipped.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        // The arrow keys are sent as `ESC [ A`-`ESC [ D`, or with `O` instead of `[`.
+        if bytes[index] == 0x1B && matches!(bytes.get(index + 1), Some(b'[' | b'O')) {
+            let button = match bytes.get(index + 2) {
+                Some(b'A') => Some(Button::Up),
+                Some(b'B') => Some(Button::Down),
+                Some(b'C') => Some(Button::Right),
+                Some(b'D') => Some(Button::Left),
+                _ => None,
+            };
+            keys.extend(button.map(Key::Button));
+            index += 3;
+            continue;
+        }
+
+        let key = match bytes[index].to_ascii_lowercase() {
+            b'w' => Some(Key::Button(Button::Up)),
+            b'a' => Some(Key::Button(Button::Left)),
+            b's' => Some(Key::Button(Button::Down)),
+            b'd' => Some(Key::Button(Button::Right)),
+            b'x' => Some(Key::Button(Button::A)),
+            b'z' => Some(Key::Button(Button::B)),
+            b'\r' | b'\n' => Some(Key::Button(Button::Start)),
+            b' ' | 0x08 | 0x7F => Some(Key::Button(Button::Select)),
+            // Ctrl+C doesn't send a signal in raw mode.
+            b'r' => Some(Key::Rewind),
+            b'q' | 0x03 => Some(Key::Quit),
+            _ => None,
+        };
+        keys.extend(key);
+        index += 1;
+    }
+    keys
+}
+
+/// Reads stdin on a separate thread, so the emulation doesn't wait for keys.
+fn spawn_stdin_reader() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut buffer = [0; 64];
+        while let Ok(length @ 1..) = stdin.read(&mut buffer) {
+            if sender.send(buffer[..length].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Puts the terminal into raw mode, where keys are passed on right away without being echoed,
+/// and restores the previous mode when dropped. Uses `stty`, which is available on every Unix.
+struct RawMode {
+    previous_settings: String,
+}
+
+impl RawMode {
+    fn enable() -> Result<Self, String> {
+        let previous_settings = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Ok(Self {
+            previous_settings: previous_settings.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.previous_settings]);
+    }
+}
+
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|error| format!("could not run stty: {error}"))?;
+    if !output.status.success() {
+        return Err("stdin is not a terminal".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let keys = parse_keys(b"\x1b[Ax\x1bOD\x1b[5~ ZRq");
+        assert_eq!(
+            keys,
+            [
+                Key::Button(Button::Up),
+                Key::Button(Button::A),
+                Key::Button(Button::Left),
+                Key::Button(Button::Select),
+                Key::Button(Button::B),
+                Key::Rewind,
+                Key::Quit,
+            ]
+        );
+    }
+
+    #[test]
+    fn render_half_blocks() {
+        let mut frame = [Color::new(0xFF, 0xFF, 0xFF); SCREEN_WIDTH * SCREEN_HEIGHT];
+        frame[SCREEN_WIDTH + 1] = Color::new(1, 2, 3);
+        let mut output = String::new();
+        render(&frame, &mut output);
+
+        let rows: Vec<_> = output.split("\r\n").collect();
+        assert_eq!(rows.len(), SCREEN_HEIGHT / 2 + 1);
+        let white = "\x1b[38;2;255;255;255m\x1b[48;2;255;255;255m";
+        let first_row = format!(
+            "\x1b[H{white}▀\x1b[38;2;255;255;255m\x1b[48;2;1;2;3m▀{white}{}",
+            "▀".repeat(SCREEN_WIDTH - 2)
+        );
+        assert_eq!(rows[0], first_row);
+        assert_eq!(rows[1], "▀".repeat(SCREEN_WIDTH));
+    }
+}
@@ -0,0 +1,27 @@
+//! Cartridges and consoles shared by the tests of the binary.
+
+use gameboy_emu::{GameBoy, Model, asm};
+
+/// Calls a function that stores A at 0xC000, then loops forever at 0x0105.
+const PROGRAM: &str = "
+        ld a, 1         ; $0100
+        call function   ; $0102
+    loop:
+        jr loop         ; $0105
+    function:
+        ld [$C000], a   ; $0107
+        inc a           ; $010A
+        ret             ; $010B
+";
+
+/// A 32 KiB cartridge without an MBC that runs the given program from its entry point.
+pub(crate) fn rom_with_program(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+    rom
+}
+
+/// A DMG running [`PROGRAM`], the console the tests of the debugger and the GDB server use.
+pub(crate) fn gameboy() -> GameBoy {
+    GameBoy::with_model(&rom_with_program(&asm!(0x0100, PROGRAM)), Model::Dmg).unwrap()
+}
@@ -0,0 +1,250 @@
+//! Every cartridge starts with a header at `0x0100`-`0x014F`, which describes the game and the
+//! hardware inside the cartridge. The boot ROM uses parts of it to decide how to set up the
+//! system, e.g. whether a CGB should run the game in color or in DMG compatibility mode.
+
+use std::fmt;
+
+const TITLE_START: usize = 0x0134;
+const TITLE_END: usize = 0x0143;
+const NEW_LICENSEE_CODE_START: usize = 0x0144;
+const NEW_LICENSEE_CODE_END: usize = 0x0145;
+const CGB_FLAG: usize = 0x0143;
+const SGB_FLAG: usize = 0x0146;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const RAM_SIZE: usize = 0x0149;
+const OLD_LICENSEE_CODE: usize = 0x014B;
+const HEADER_CHECKSUM: usize = 0x014D;
+/// The last byte of the header.
+const HEADER_END: usize = 0x014F;
+
+/// The old licensee code `0x33` means that the new licensee code has to be checked instead.
+const USE_NEW_LICENSEE_CODE: u8 = 0x33;
+const NINTENDO_OLD_LICENSEE_CODE: u8 = 0x01;
+const NINTENDO_NEW_LICENSEE_CODE: [u8; 2] = *b"01";
+
+/// Why a cartridge can't be inserted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LoadRomError {
+    /// The cartridge contains hardware that isn't emulated, according to the cartridge type in
+    /// its header.
+    UnsupportedCartridgeType(u8),
+}
+
+impl fmt::Display for LoadRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadRomError::UnsupportedCartridgeType(cartridge_type) => write!(
+                f,
+                "the cartridge type 0x{cartridge_type:02X} is not supported, only cartridges \
+                 without a memory bank controller or with an MBC1, MBC3 or MBC5 are"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadRomError {}
+
+/// The memory bank controller of a cartridge, which maps banks of its ROM and RAM into memory.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) enum Mapper {
+    /// At most 32 KiB of ROM and 8 KiB of RAM, which are always mapped.
+    #[default]
+    None,
+    Mbc1,
+    Mbc3,
+    Mbc5,
+}
+
+/// Whether and how a cartridge makes use of the CGB's features.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum CgbSupport {
+    /// A DMG game, which a CGB runs in DMG compatibility mode.
+    None,
+    /// Uses CGB features, but also works on a DMG.
+    Enhanced,
+    /// Only works on a CGB.
+    Required,
+}
+
+/// The parts of the cartridge header the emulator cares about.
+#[derive(Clone, Debug)]
+pub(crate) struct Header {
+    /// The title in upper case ASCII. On newer cartridges, the last bytes are used for other
+    /// purposes, like the CGB flag.
+    title: [u8; TITLE_END - TITLE_START + 1],
+    new_licensee_code: [u8; 2],
+    old_licensee_code: u8,
+    cgb_flag: u8,
+    sgb_flag: u8,
+    /// The hardware inside the cartridge: the memory bank controller, RAM, battery and clock.
+    cartridge_type: u8,
+    /// The size of the cartridge RAM, as a code.
+    ram_size: u8,
+    /// A checksum over `0x0134`-`0x014C`, verified by the boot ROM.
+    header_checksum: u8,
+}
+
+impl Header {
+    /// Reads the header from a ROM. Returns [`None`] if the ROM is too small to contain a header.
+    pub(crate) fn parse(rom: &[u8]) -> Option<Self> {
+        if rom.len() <= HEADER_END {
+            return None;
+        }
+
+        let mut title = [0; TITLE_END - TITLE_START + 1];
+        title.copy_from_slice(&rom[TITLE_START..=TITLE_END]);
+        let mut new_licensee_code = [0; 2];
+        new_licensee_code.copy_from_slice(&rom[NEW_LICENSEE_CODE_START..=NEW_LICENSEE_CODE_END]);
+
+        Some(Self {
+            title,
+            new_licensee_code,
+            old_licensee_code: rom[OLD_LICENSEE_CODE],
+            cgb_flag: rom[CGB_FLAG],
+            sgb_flag: rom[SGB_FLAG],
+            cartridge_type: rom[CARTRIDGE_TYPE],
+            ram_size: rom[RAM_SIZE],
+            header_checksum: rom[HEADER_CHECKSUM],
+        })
+    }
+
+    /// The raw title bytes, including the bytes newer cartridges use for other purposes.
+    pub(crate) fn title_bytes(&self) -> &[u8] {
+        &self.title
+    }
+
+    /// The sum of all title bytes, used by the CGB boot ROM to look up a compatibility palette.
+    pub(crate) fn title_checksum(&self) -> u8 {
+        self.title
+            .iter()
+            .fold(0, |checksum, byte| checksum.wrapping_add(*byte))
+    }
+
+    /// Whether the game was published by Nintendo, according to the licensee code.
+    pub(crate) fn is_licensed_by_nintendo(&self) -> bool {
+        if self.old_licensee_code == USE_NEW_LICENSEE_CODE {
+            self.new_licensee_code == NINTENDO_NEW_LICENSEE_CODE
+        } else {
+            self.old_licensee_code == NINTENDO_OLD_LICENSEE_CODE
+        }
+    }
+
+    pub(crate) fn cgb_support(&self) -> CgbSupport {
+        // Only bit 7 is checked by the hardware. Bit 6 is purely informational.
+        match self.cgb_flag {
+            0xC0 => CgbSupport::Required,
+            flag if flag & 0x80 != 0 => CgbSupport::Enhanced,
+            _ => CgbSupport::None,
+        }
+    }
+
+    /// The checksum stored in the header. The DMG boot ROM leaves flags behind that depend on it.
+    pub(crate) fn header_checksum(&self) -> u8 {
+        self.header_checksum
+    }
+
+    /// Whether the game supports the SGB's functions. This also requires the old licensee code to
+    /// be set to `0x33`.
+    pub(crate) fn supports_sgb(&self) -> bool {
+        self.sgb_flag == 0x03 && self.old_licensee_code == USE_NEW_LICENSEE_CODE
+    }
+
+    /// The memory bank controller inside the cartridge. Fails for controllers that aren't
+    /// emulated. Rumble motors are ignored, as there is nothing to output them to.
+    pub(crate) fn mapper(&self) -> Result<Mapper, LoadRomError> {
+        match self.cartridge_type {
+            0x00 | 0x08 | 0x09 => Ok(Mapper::None),
+            0x01..=0x03 => Ok(Mapper::Mbc1),
+            0x0F..=0x13 => Ok(Mapper::Mbc3),
+            0x19..=0x1E => Ok(Mapper::Mbc5),
+            cartridge_type => Err(LoadRomError::UnsupportedCartridgeType(cartridge_type)),
+        }
+    }
+
+    /// Whether the cartridge contains an MBC3 with a real-time clock.
+    pub(crate) fn has_rtc(&self) -> bool {
+        matches!(self.cartridge_type, 0x0F | 0x10)
+    }
+
+    /// The size of the cartridge RAM in bytes.
+    pub(crate) fn ram_size(&self) -> usize {
+        match self.ram_size {
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with_header(title: &[u8], old_licensee_code: u8, new_licensee_code: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[TITLE_START..TITLE_START + title.len()].copy_from_slice(title);
+        rom[OLD_LICENSEE_CODE] = old_licensee_code;
+        rom[NEW_LICENSEE_CODE_START..=NEW_LICENSEE_CODE_END].copy_from_slice(new_licensee_code);
+        rom
+    }
+
+    #[test]
+    fn rom_too_small() {
+        assert!(Header::parse(&[0; 0x100]).is_none());
+    }
+
+    #[test]
+    fn title_checksum() {
+        let rom = rom_with_header(b"TETRIS", 0x01, b"\0\0");
+        let header = Header::parse(&rom).unwrap();
+
+        assert_eq!(header.title_checksum(), 0xDB);
+    }
+
+    #[test]
+    fn nintendo_licensee() {
+        let old = Header::parse(&rom_with_header(b"", 0x01, b"\0\0")).unwrap();
+        let new = Header::parse(&rom_with_header(b"", 0x33, b"01")).unwrap();
+        let other = Header::parse(&rom_with_header(b"", 0x33, b"08")).unwrap();
+
+        assert!(old.is_licensed_by_nintendo());
+        assert!(new.is_licensed_by_nintendo());
+        assert!(!other.is_licensed_by_nintendo());
+    }
+
+    #[test]
+    fn cgb_support() {
+        let mut rom = rom_with_header(b"", 0x01, b"\0\0");
+        assert_eq!(Header::parse(&rom).unwrap().cgb_support(), CgbSupport::None);
+        rom[CGB_FLAG] = 0x80;
+        assert_eq!(
+            Header::parse(&rom).unwrap().cgb_support(),
+            CgbSupport::Enhanced
+        );
+        rom[CGB_FLAG] = 0xC0;
+        assert_eq!(
+            Header::parse(&rom).unwrap().cgb_support(),
+            CgbSupport::Required
+        );
+    }
+
+    #[test]
+    fn cartridge_type() {
+        let mut rom = rom_with_header(b"", 0x01, b"\0\0");
+        rom[CARTRIDGE_TYPE] = 0x10;
+        rom[RAM_SIZE] = 0x03;
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.mapper(), Ok(Mapper::Mbc3));
+        assert!(header.has_rtc());
+        assert_eq!(header.ram_size(), 0x8000);
+
+        rom[CARTRIDGE_TYPE] = 0x05;
+        assert_eq!(
+            Header::parse(&rom).unwrap().mapper(),
+            Err(LoadRomError::UnsupportedCartridgeType(0x05))
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! When a CGB runs a DMG game, its boot ROM colorizes the game by loading one background and two
+//! object palettes. Games published by Nintendo are identified by the checksum of their title
+//! and get a hand-picked set of palettes. All other games get a default set, unless the user
+//! overrides the choice by holding a button combination while the boot logo is shown.
+
+use crate::cartridge::Header;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter};
+
+/// Four colors in the CGB's RGB555 format, in the order of the DMG shades from light to dark.
+type Palette = [u16; 4];
+
+/// The palettes the boot ROM loads into the CGB's color palette memory.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct CompatibilityPalettes {
+    /// Used by the background and window through `BGP`.
+    pub(crate) background: Palette,
+    /// Used by objects that select `OBP0`.
+    pub(crate) object0: Palette,
+    /// Used by objects that select `OBP1`.
+    pub(crate) object1: Palette,
+}
+
+const BROWN: Palette = [0x7FFF, 0x32BF, 0x00D0, 0x0000];
+const DARK_BROWN: Palette = [0x639F, 0x4279, 0x15B0, 0x04CB];
+const DARK_BLUE: Palette = [0x7FFF, 0x6E31, 0x454A, 0x0000];
+const LIGHT_GREEN: Palette = [0x7FFF, 0x1BEF, 0x0200, 0x0000];
+const RED: Palette = [0x7FFF, 0x421F, 0x1CF2, 0x0000];
+const GRAY: Palette = [0x7FFF, 0x5294, 0x294A, 0x0000];
+const YELLOW: Palette = [0x7FFF, 0x03FF, 0x012F, 0x0000];
+const LIGHT_BLUE: Palette = [0x7FFF, 0x7E8C, 0x7C00, 0x0000];
+const SEA_BLUE: Palette = [0x7FFF, 0x4E8C, 0x7C00, 0x0000];
+const PASTEL: Palette = [0x53FF, 0x4A5F, 0x7E52, 0x0000];
+const ORANGE: Palette = [0x7FFF, 0x03FF, 0x001F, 0x0000];
+const GREEN: Palette = [0x7FFF, 0x03EA, 0x011F, 0x0000];
+const GREEN_BLUE: Palette = [0x7FFF, 0x1BEF, 0x6180, 0x0000];
+const INVERTED: Palette = [0x0000, 0x4200, 0x037F, 0x7FFF];
+const OLIVE: Palette = [0x7FFF, 0x42B5, 0x3DC8, 0x0000];
+const AMBER: Palette = [0x7FFF, 0x01DF, 0x0112, 0x0000];
+
+const fn palettes(
+    background: Palette,
+    object0: Palette,
+    object1: Palette,
+) -> CompatibilityPalettes {
+    CompatibilityPalettes {
+        background,
+        object0,
+        object1,
+    }
+}
+
+const fn uniform(palette: Palette) -> CompatibilityPalettes {
+    palettes(palette, palette, palette)
+}
+
+/// Used for games that aren't found in [`GAME_PALETTES`].
+const DEFAULT_PALETTES: CompatibilityPalettes = palettes(GREEN_BLUE, RED, RED);
+
+/// The palettes a DMG game can be colorized with on a CGB by holding a button combination during
+/// the boot animation, named after the buttons.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ManualPalette {
+    /// Up: Brown
+    Up,
+    /// Up + A: Red
+    UpA,
+    /// Up + B: Dark brown
+    UpB,
+    /// Left: Blue
+    Left,
+    /// Left + A: Dark blue
+    LeftA,
+    /// Left + B: Grayscale
+    LeftB,
+    /// Down: Pastel mix
+    Down,
+    /// Down + A: Orange
+    DownA,
+    /// Down + B: Yellow
+    DownB,
+    /// Right: Green
+    Right,
+    /// Right + A: Dark green, the same as the default palettes
+    RightA,
+    /// Right + B: Inverted
+    RightB,
+}
+
+impl ManualPalette {
+    /// Every palette, grouped by direction.
+    pub const ALL: [ManualPalette; 12] = [
+        ManualPalette::Up,
+        ManualPalette::UpA,
+        ManualPalette::UpB,
+        ManualPalette::Left,
+        ManualPalette::LeftA,
+        ManualPalette::LeftB,
+        ManualPalette::Down,
+        ManualPalette::DownA,
+        ManualPalette::DownB,
+        ManualPalette::Right,
+        ManualPalette::RightA,
+        ManualPalette::RightB,
+    ];
+
+    pub(crate) fn palettes(self) -> CompatibilityPalettes {
+        match self {
+            ManualPalette::Up => uniform(BROWN),
+            ManualPalette::UpA => palettes(RED, LIGHT_GREEN, LIGHT_BLUE),
+            ManualPalette::UpB => uniform(DARK_BROWN),
+            ManualPalette::Left => palettes(SEA_BLUE, RED, LIGHT_BLUE),
+            ManualPalette::LeftA => palettes(DARK_BLUE, RED, BROWN),
+            ManualPalette::LeftB => uniform(GRAY),
+            ManualPalette::Down => uniform(PASTEL),
+            ManualPalette::DownA => uniform(ORANGE),
+            ManualPalette::DownB => palettes(YELLOW, LIGHT_BLUE, LIGHT_GREEN),
+            ManualPalette::Right => uniform(GREEN),
+            ManualPalette::RightA => DEFAULT_PALETTES,
+            ManualPalette::RightB => uniform(INVERTED),
+        }
+    }
+}
+
+/// How the colors for a DMG game are chosen.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) enum PaletteSelection {
+    /// Look the game up in the boot ROM's table.
+    #[default]
+    Automatic,
+    /// Use the palettes the user selected with a button combination.
+    Manual(ManualPalette),
+}
+
+/// Saved as 0 for [`PaletteSelection::Automatic`], or the index of the manual palette plus one.
+impl SaveState for PaletteSelection {
+    fn save(&self, writer: &mut StateWriter) {
+        let index = match self {
+            PaletteSelection::Automatic => 0,
+            PaletteSelection::Manual(palette) => {
+                1 + ManualPalette::ALL
+                    .iter()
+                    .position(|p| p == palette)
+                    .unwrap() as u8
+            }
+        };
+        index.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        let [index] = reader.read_bytes()?;
+        *self = match index.checked_sub(1) {
+            None => PaletteSelection::Automatic,
+            Some(index) => PaletteSelection::Manual(
+                *ManualPalette::ALL
+                    .get(index as usize)
+                    .ok_or(LoadStateError::InvalidValue)?,
+            ),
+        };
+        Ok(())
+    }
+}
+
+/// A game the boot ROM has dedicated palettes for.
+struct GamePalettes {
+    /// The sum of all title bytes.
+    checksum: u8,
+    /// Some checksums are shared by multiple games. The boot ROM tells them apart by the fourth
+    /// letter of their title.
+    fourth_letter: Option<u8>,
+    palettes: CompatibilityPalettes,
+}
+
+const fn game(checksum: u8, palettes: CompatibilityPalettes) -> GamePalettes {
+    GamePalettes {
+        checksum,
+        fourth_letter: None,
+        palettes,
+    }
+}
+
+const fn game_with_letter(
+    checksum: u8,
+    letter: u8,
+    palettes: CompatibilityPalettes,
+) -> GamePalettes {
+    GamePalettes {
+        checksum,
+        fourth_letter: Some(letter),
+        palettes,
+    }
+}
+
+/// The games the boot ROM recognizes, in the order it searches for them.
+const GAME_PALETTES: [GamePalettes; 93] = [
+    game(0x88, palettes(YELLOW, LIGHT_BLUE, LIGHT_GREEN)), // ALLEY WAY
+    game(0x16, uniform(BROWN)),                            // YAKUMAN
+    game(0x36, palettes(GREEN, RED, RED)),                 // BASEBALL
+    game(0xD1, palettes(GREEN, RED, LIGHT_BLUE)),          // TENNIS
+    game(0xDB, uniform(ORANGE)),                           // TETRIS
+    game(0xF2, palettes(AMBER, LIGHT_BLUE, RED)),          // QIX
+    game(0x3C, palettes(YELLOW, RED, LIGHT_BLUE)),         // DR.MARIO
+    game(0x8C, palettes(GREEN_BLUE, RED, LIGHT_BLUE)),     // RADARMISSION
+    game(0x92, uniform(BROWN)),                            // F1RACE
+    game(0x3D, palettes(PASTEL, RED, LIGHT_BLUE)),         // YOSSY NO TAMAGO
+    game(0x5C, uniform(OLIVE)),
+    game(0x58, uniform(GRAY)),                      // X
+    game(0xC9, palettes(OLIVE, RED, LIGHT_BLUE)),   // MARIOLAND2
+    game(0x3E, palettes(PASTEL, RED, LIGHT_GREEN)), // YOSSY NO COOKIE
+    game(0x70, palettes(GREEN, RED, LIGHT_BLUE)),   // ZELDA
+    game(0x1D, uniform(DARK_BROWN)),
+    game(0x59, palettes(SEA_BLUE, RED, LIGHT_BLUE)),
+    game(0x69, palettes(AMBER, LIGHT_BLUE, RED)), // TETRIS FLASH
+    game(0x19, palettes(BROWN, RED, LIGHT_BLUE)), // DONKEY KONG
+    game(0x35, uniform(BROWN)),                   // MARIO'S PICROSS
+    game(0xA8, palettes(GREEN, LIGHT_BLUE, RED)),
+    game(0x14, palettes(RED, LIGHT_GREEN, LIGHT_BLUE)), // POKEMON RED
+    game(0xAA, palettes(LIGHT_GREEN, RED, LIGHT_BLUE)), // POKEMON GREEN
+    game(0x75, uniform(BROWN)),                         // PICROSS 2
+    game(0x95, palettes(PASTEL, RED, LIGHT_BLUE)),      // YOSSY NO PANEPON
+    game(0x99, uniform(BROWN)),                         // KIRAKIRA KIDS
+    game(0x34, palettes(ORANGE, RED, LIGHT_BLUE)),      // GAMEBOY GALLERY
+    game(0x6F, uniform(GRAY)),                          // POCKETCAMERA
+    game(0x15, uniform(ORANGE)),
+    game(0xFF, palettes(SEA_BLUE, RED, LIGHT_BLUE)), // BALLOON KID
+    game(0x97, palettes(YELLOW, RED, LIGHT_BLUE)),   // KINGOFTHEZOO
+    game(0x4B, palettes(GREEN, RED, LIGHT_BLUE)),    // DMG FOOTBALL
+    game(0x90, palettes(GREEN, RED, LIGHT_BLUE)),    // WORLD CUP
+    game(0x17, palettes(LIGHT_GREEN, RED, RED)),     // OTHELLO
+    game(0x10, palettes(GREEN_BLUE, RED, LIGHT_BLUE)), // SUPER RC PRO-AM
+    game(0x39, palettes(YELLOW, RED, LIGHT_BLUE)),   // DYNABLASTER
+    game(0xF7, palettes(PASTEL, RED, LIGHT_BLUE)),   // BOY AND BLOB GB2
+    game(0xF6, palettes(GREEN_BLUE, RED, LIGHT_BLUE)), // MEGAMAN
+    game(0xA2, palettes(PASTEL, RED, LIGHT_BLUE)),   // STAR WARS-NOA
+    game(0x49, uniform(OLIVE)),
+    game(0x4E, palettes(SEA_BLUE, RED, LIGHT_GREEN)), // WAVERACE
+    game(0x43, palettes(YELLOW, LIGHT_BLUE, RED)),
+    game(0x68, palettes(GREEN_BLUE, RED, LIGHT_BLUE)), // LOLO2
+    game(0xE0, palettes(PASTEL, RED, LIGHT_GREEN)),    // YOSHI'S COOKIE
+    game(0x8B, palettes(LIGHT_GREEN, RED, RED)),       // MYSTIC QUEST
+    game(0xF0, palettes(GREEN, RED, LIGHT_BLUE)),
+    game(0xCE, palettes(GREEN, RED, LIGHT_BLUE)), // TOPRANKINGTENNIS
+    game(0x0C, uniform(BROWN)),                   // MANSELL
+    game(0x29, palettes(GREEN_BLUE, RED, LIGHT_BLUE)), // MEGAMAN3
+    game(0xE8, uniform(GRAY)),                    // SPACE INVADERS
+    game(0xB7, uniform(BROWN)),                   // GAME&WATCH
+    game(0x86, palettes(BROWN, RED, LIGHT_BLUE)), // DONKEYKONGLAND95
+    game(0x9A, palettes(GREEN, RED, LIGHT_BLUE)), // ASTEROIDS/MISCMD
+    game(0x52, palettes(GREEN_BLUE, RED, LIGHT_BLUE)), // STREET FIGHTER 2
+    game(0x01, palettes(GREEN_BLUE, RED, LIGHT_BLUE)), // DEFENDER/JOUST
+    game(0x9D, palettes(DARK_BLUE, RED, BROWN)),  // KILLERINSTINCT95
+    game(0x71, palettes(LIGHT_GREEN, RED, LIGHT_BLUE)), // TETRIS BLAST
+    game(0x9C, palettes(SEA_BLUE, RED, LIGHT_BLUE)), // PINOCCHIO
+    game(0xBD, palettes(GREEN, RED, LIGHT_BLUE)),
+    game(0x5D, uniform(BROWN)),                    // BA.TOSHINDEN
+    game(0x6D, palettes(PASTEL, RED, LIGHT_BLUE)), // NETTOU KOF 95
+    game(0x67, uniform(BROWN)),
+    game(0x3F, uniform(ORANGE)), // TETRIS PLUS
+    game(0x6B, uniform(BROWN)),  // DONKEYKONGLAND 3
+    game_with_letter(0xB3, b'B', uniform(OLIVE)),
+    game_with_letter(0x46, b'E', palettes(OLIVE, RED, LIGHT_BLUE)), // SUPER MARIOLAND
+    game_with_letter(0x28, b'F', palettes(GREEN, RED, LIGHT_BLUE)), // GOLF
+    game_with_letter(0xA5, b'A', uniform(GRAY)),                    // SOLARSTRIKER
+    game_with_letter(0xC6, b'A', palettes(GREEN, LIGHT_BLUE, RED)), // GBWARS
+    game_with_letter(0xD3, b'R', palettes(YELLOW, RED, LIGHT_BLUE)), // KAERUNOTAMENI
+    game_with_letter(0x27, b'B', uniform(OLIVE)),
+    game_with_letter(0x61, b'E', palettes(LIGHT_BLUE, RED, LIGHT_GREEN)), // POKEMON BLUE
+    game_with_letter(0x18, b'K', palettes(BROWN, RED, LIGHT_BLUE)),       // DONKEYKONGLAND
+    game_with_letter(0x66, b'E', palettes(ORANGE, RED, LIGHT_BLUE)),      // GAMEBOY GALLERY2
+    game_with_letter(0x6A, b'K', palettes(BROWN, RED, LIGHT_BLUE)),       // DONKEYKONGLAND 2
+    game_with_letter(0xBF, b' ', palettes(PASTEL, RED, LIGHT_BLUE)),      // KID ICARUS
+    game_with_letter(0x0D, b'R', palettes(AMBER, LIGHT_BLUE, RED)),       // TETRIS2
+    game_with_letter(0xF4, b'-', uniform(INVERTED)),
+    game_with_letter(0xB3, b'U', uniform(BROWN)), // MOGURANYA
+    game_with_letter(0x46, b'R', palettes(GREEN, RED, LIGHT_BLUE)),
+    game_with_letter(0x28, b'A', uniform(GRAY)), // GALAGA&GALAXIAN
+    game_with_letter(0xA5, b'R', palettes(PASTEL, RED, LIGHT_BLUE)), // BT2RAGNAROKWORLD
+    game_with_letter(0xC6, b' ', DEFAULT_PALETTES), // KEN GRIFFEY JR
+    game_with_letter(0xD3, b'I', palettes(SEA_BLUE, RED, LIGHT_BLUE)),
+    game_with_letter(0x27, b'N', palettes(LIGHT_GREEN, RED, RED)), // MAGNETIC SOCCER
+    game_with_letter(0x61, b'A', palettes(LIGHT_GREEN, RED, RED)), // VEGAS STAKES
+    game_with_letter(0x18, b'I', DEFAULT_PALETTES),
+    game_with_letter(0x66, b'L', DEFAULT_PALETTES), // MILLI/CENTI/PEDE
+    game_with_letter(0x6A, b'I', palettes(PASTEL, RED, LIGHT_BLUE)), // MARIO & YOSHI
+    game_with_letter(0xBF, b'C', palettes(GREEN, RED, LIGHT_BLUE)), // SOCCER
+    game_with_letter(0x0D, b'E', palettes(YELLOW, RED, LIGHT_BLUE)), // POKEBOM
+    game_with_letter(0xF4, b' ', palettes(ORANGE, RED, LIGHT_BLUE)), // G&W GALLERY
+    game_with_letter(0xB3, b'R', uniform(ORANGE)),  // TETRIS ATTACK
+];
+
+/// Chooses the palettes the boot ROM would load for a DMG game.
+pub(crate) fn select_palettes(
+    header: &Header,
+    selection: PaletteSelection,
+) -> CompatibilityPalettes {
+    if let PaletteSelection::Manual(manual) = selection {
+        return manual.palettes();
+    }
+    // Only Nintendo's own games are looked up, everything else gets the default palettes.
+    if !header.is_licensed_by_nintendo() {
+        return DEFAULT_PALETTES;
+    }
+
+    let checksum = header.title_checksum();
+    let fourth_letter = header.title_bytes()[3];
+    GAME_PALETTES
+        .iter()
+        .find(|game| {
+            game.checksum == checksum
+                && game
+                    .fourth_letter
+                    .is_none_or(|letter| letter == fourth_letter)
+        })
+        .map_or(DEFAULT_PALETTES, |game| game.palettes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A header of a game with the given title and licensee.
+    fn header(title: &[u8], nintendo: bool) -> Header {
+        let mut rom = vec![0; 0x150];
+        rom[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom[0x14B] = if nintendo { 0x01 } else { 0x00 };
+        Header::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn nintendo_game() {
+        let palettes = select_palettes(&header(b"TETRIS", true), PaletteSelection::Automatic);
+        assert_eq!(palettes, uniform(ORANGE));
+    }
+
+    #[test]
+    fn other_publisher_gets_default() {
+        let palettes = select_palettes(&header(b"TETRIS", false), PaletteSelection::Automatic);
+        assert_eq!(palettes, DEFAULT_PALETTES);
+    }
+
+    #[test]
+    fn unknown_game_gets_default() {
+        let palettes =
+            select_palettes(&header(b"HOMEBREW GAME", true), PaletteSelection::Automatic);
+        assert_eq!(palettes, DEFAULT_PALETTES);
+    }
+
+    #[test]
+    fn duplicate_checksums_use_fourth_letter() {
+        let blue = select_palettes(&header(b"POKEMON BLUE", true), PaletteSelection::Automatic);
+        let vegas = select_palettes(&header(b"VEGAS STAKES", true), PaletteSelection::Automatic);
+
+        assert_eq!(blue, palettes(LIGHT_BLUE, RED, LIGHT_GREEN));
+        assert_eq!(vegas, palettes(LIGHT_GREEN, RED, RED));
+    }
+
+    #[test]
+    fn duplicate_checksum_with_unknown_letter_gets_default() {
+        // Same checksum as "POKEMON BLUE", but a different fourth letter.
+        let palettes = select_palettes(&header(b"POKFMON BLUD", true), PaletteSelection::Automatic);
+        assert_eq!(palettes, DEFAULT_PALETTES);
+    }
+
+    #[test]
+    fn manual_selection_overrides_table() {
+        let selection = PaletteSelection::Manual(ManualPalette::LeftB);
+        let palettes = select_palettes(&header(b"TETRIS", true), selection);
+        assert_eq!(palettes, uniform(GRAY));
+    }
+}
@@ -0,0 +1,36 @@
+//! Behaviour specific to the Game Boy Color (CGB) that is shared by multiple components.
+
+use crate::save_state::impl_save_state_enum;
+
+pub(crate) mod compatibility;
+
+/// Which feature set the hardware exposes to the running game.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) enum CgbMode {
+    /// The hardware isn't a CGB, so none of its features are available.
+    #[default]
+    Dmg,
+    /// A CGB running a game that supports it, with all features available.
+    Cgb,
+    /// A CGB running a DMG game. The boot ROM colorizes the game with a fixed set of palettes
+    /// and locks away most CGB features afterwards.
+    DmgCompatibility,
+}
+
+impl_save_state_enum!(CgbMode {
+    Dmg,
+    Cgb,
+    DmgCompatibility,
+});
+
+impl CgbMode {
+    /// Whether the CGB-only registers, like VRAM and WRAM banking, are accessible.
+    pub(crate) fn has_cgb_features(self) -> bool {
+        self == CgbMode::Cgb
+    }
+
+    /// Whether the colors are looked up from the CGB's color palette memory.
+    pub(crate) fn has_color_palettes(self) -> bool {
+        self != CgbMode::Dmg
+    }
+}
@@ -0,0 +1,80 @@
+//! The CPU only sees the rest of the system through the bus it's connected to. Besides the
+//! Game Boy's [`MemoryBus`], this can be a plain array of memory, e.g. for testing instructions in
+//! isolation.
+
+use crate::memory_bus::MemoryBus;
+use crate::watchpoint::AccessKind;
+
+/// What the CPU reads from and writes to. Every memory access takes a machine cycle, which is
+/// started with [`Bus::tick`] right before the access. Internal cycles of an instruction only call
+/// [`Bus::tick`].
+///
+/// Only reading, writing and ticking are required. The other methods connect the CPU to the
+/// interrupt controller and other parts of the console, and do nothing by default.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Lets a machine cycle pass, i.e. 4 CPU cycles.
+    fn tick(&mut self);
+
+    /// The interrupts that are both requested and enabled, one bit each.
+    fn pending_interrupts(&self) -> u8 {
+        0
+    }
+
+    /// Clears the request of the interrupt with the given bit, once its handler is called.
+    fn acknowledge_interrupt(&mut self, _interrupt: u8) {}
+
+    /// Called when a 16-bit register is incremented or decremented, which corrupts OAM on the DMG
+    /// if the register points into it.
+    fn trigger_oam_bug(&mut self, _address: u16) {}
+
+    /// Called by `STOP`. Switches the CPU speed and returns `true` if a switch was requested.
+    fn switch_speed(&mut self) -> bool {
+        false
+    }
+
+    /// The amount of CPU cycles the CPU has to wait for a VRAM DMA that just happened.
+    fn take_dma_stall_cycles(&mut self) -> u32 {
+        0
+    }
+}
+
+impl Bus for MemoryBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.read_byte(address);
+        self.watch(address, value, AccessKind::Read);
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.write_byte(address, value);
+        self.watch(address, value, AccessKind::Write);
+    }
+
+    fn tick(&mut self) {
+        self.step(4);
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        MemoryBus::pending_interrupts(self)
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt: u8) {
+        MemoryBus::acknowledge_interrupt(self, interrupt);
+    }
+
+    fn trigger_oam_bug(&mut self, address: u16) {
+        MemoryBus::trigger_oam_bug(self, address);
+    }
+
+    fn switch_speed(&mut self) -> bool {
+        MemoryBus::switch_speed(self)
+    }
+
+    fn take_dma_stall_cycles(&mut self) -> u32 {
+        MemoryBus::take_dma_stall_cycles(self)
+    }
+}
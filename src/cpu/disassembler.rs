@@ -0,0 +1,494 @@
+//! Turns instructions back into assembly in the syntax of RGBDS, e.g. `LD A, [HL+]`.
+
+use super::PREFIX_BYTE;
+use super::instructions::Instruction;
+use super::instructions::parameter::{
+    JumpTest, LoadByteSource, LoadByteTarget, LoadType, StackTarget, TargetRegister8,
+    TargetRegister16,
+};
+use std::fmt;
+
+/// An instruction decoded from memory, together with its operands.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Disassembly {
+    /// Where the instruction starts.
+    pub address: u16,
+    /// The opcode, including the prefix, followed by the operands. Only the opcode if it's
+    /// invalid.
+    pub bytes: Vec<u8>,
+    /// The instruction in RGBDS syntax, with relative jumps resolved to their target. Invalid
+    /// opcodes are shown as data, like `DB $D3`.
+    pub text: String,
+    flow: Flow,
+}
+
+/// Where execution continues after an instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Flow {
+    /// With the next instruction.
+    Next,
+    /// At the target, or also with the next instruction if the jump is conditional.
+    Jump { target: u16, conditional: bool },
+    /// At the target, and with the next instruction once the subroutine returns. Includes `RST`.
+    Call { target: u16 },
+    /// Somewhere unknown: After returns, `JP HL` and invalid opcodes, which hang the CPU.
+    Unknown,
+}
+
+impl Disassembly {
+    /// How many bytes the instruction takes up in memory.
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub(crate) fn flow(&self) -> Flow {
+        self.flow
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Decodes the instruction at the given address. Its bytes are read through `read`, which gets
+/// the address of each byte.
+pub fn disassemble(address: u16, read: impl FnMut(u16) -> u8) -> Disassembly {
+    disassemble_with_names(address, read, |_| None)
+}
+
+/// Like [`disassemble`], but addresses in the operands are shown as the names `name` returns for
+/// them, if there are any.
+pub(crate) fn disassemble_with_names<'a>(
+    address: u16,
+    mut read: impl FnMut(u16) -> u8,
+    name: impl Fn(u16) -> Option<&'a str>,
+) -> Disassembly {
+    let opcode = read(address);
+    let (instruction, opcode_length) = if opcode == PREFIX_BYTE {
+        let byte = read(address.wrapping_add(1));
+        (Instruction::from_byte(byte, true), 2)
+    } else {
+        (Instruction::from_byte(opcode, false), 1)
+    };
+    let Some(instruction) = instruction else {
+        return Disassembly {
+            address,
+            bytes: vec![opcode],
+            text: format!("DB ${opcode:02X}"),
+            flow: Flow::Unknown,
+        };
+    };
+
+    // Prefixed instructions don't have operands, and their length doesn't count the prefix.
+    let length = instruction.length() + opcode_length - 1;
+    let bytes: Vec<_> = (0..length)
+        .map(|offset| read(address.wrapping_add(offset)))
+        .collect();
+    let operands = Operands::Values {
+        low: bytes.get(1).copied().unwrap_or_default(),
+        high: bytes.get(2).copied().unwrap_or_default(),
+        next_address: address.wrapping_add(length),
+        name: &|address| name(address),
+    };
+    Disassembly {
+        address,
+        text: text(instruction, operands),
+        bytes,
+        flow: flow(instruction, operands),
+    }
+}
+
+fn flow(instruction: Instruction, operands: Operands) -> Flow {
+    let Operands::Values {
+        low,
+        high,
+        next_address,
+        ..
+    } = operands
+    else {
+        unreachable!("the flow is only needed for decoded instructions");
+    };
+    let absolute = u16::from_le_bytes([low, high]);
+    match instruction {
+        Instruction::Jp(test) => Flow::Jump {
+            target: absolute,
+            conditional: test != JumpTest::Always,
+        },
+        Instruction::Jr(test) => Flow::Jump {
+            target: next_address.wrapping_add_signed(low as i8 as i16),
+            conditional: test != JumpTest::Always,
+        },
+        Instruction::Call(_) => Flow::Call { target: absolute },
+        Instruction::Rst(address) => Flow::Call {
+            target: address as u16,
+        },
+        Instruction::Ret(JumpTest::Always) | Instruction::Reti | Instruction::JpHl => Flow::Unknown,
+        _ => Flow::Next,
+    }
+}
+
+/// The text of every valid instruction with placeholders for its operands, like `LD A, n8`,
+/// together with its opcode, including the prefix, and its length in bytes.
+pub(crate) fn opcode_patterns() -> Vec<(String, Vec<u8>, u16)> {
+    let unprefixed = (0..=u8::MAX)
+        .filter(|opcode| *opcode != PREFIX_BYTE)
+        .filter_map(|opcode| {
+            let instruction = Instruction::from_byte(opcode, false)?;
+            Some((instruction.to_string(), vec![opcode], instruction.length()))
+        });
+    let prefixed = (0..=u8::MAX).map(|opcode| {
+        let instruction = Instruction::from_byte(opcode, true).unwrap();
+        (instruction.to_string(), vec![PREFIX_BYTE, opcode], 2)
+    });
+    unprefixed.chain(prefixed).collect()
+}
+
+/// Shows the operands stored after the opcode as placeholders, like `LD A, n8` or `JR NZ, e8`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&text(*self, Operands::Placeholders))
+    }
+}
+
+/// How the operands stored after the opcode are shown.
+#[derive(Copy, Clone)]
+enum Operands<'a> {
+    /// As the placeholders of the opcode tables: `n8`, `n16` and `e8`.
+    Placeholders,
+    /// As their values. Relative jumps are resolved against the address of the next instruction,
+    /// and addresses that have a name are replaced by it.
+    Values {
+        low: u8,
+        high: u8,
+        next_address: u16,
+        name: &'a dyn Fn(u16) -> Option<&'a str>,
+    },
+}
+
+impl Operands<'_> {
+    fn n8(self) -> String {
+        match self {
+            Operands::Placeholders => "n8".to_string(),
+            Operands::Values { low, .. } => format!("${low:02X}"),
+        }
+    }
+
+    fn n16(self) -> String {
+        match self {
+            Operands::Placeholders => "n16".to_string(),
+            Operands::Values {
+                low, high, name, ..
+            } => address(u16::from_le_bytes([low, high]), name),
+        }
+    }
+
+    fn e8(self) -> String {
+        match self {
+            Operands::Placeholders => "e8".to_string(),
+            Operands::Values { low, .. } => (low as i8).to_string(),
+        }
+    }
+
+    /// The offset of `LD HL, SP+e8`, including its sign.
+    fn sp_offset(self) -> String {
+        match self {
+            Operands::Placeholders => "SP+e8".to_string(),
+            Operands::Values { low, .. } if (low as i8) < 0 => format!("SP{}", low as i8),
+            Operands::Values { low, .. } => format!("SP+{low}"),
+        }
+    }
+
+    /// The target of a relative jump.
+    fn jump_target(self) -> String {
+        match self {
+            Operands::Placeholders => "e8".to_string(),
+            Operands::Values {
+                low,
+                next_address,
+                name,
+                ..
+            } => address(next_address.wrapping_add_signed(low as i8 as i16), name),
+        }
+    }
+
+    /// The address `LDH` accesses, which is always in the last page.
+    fn high_address(self) -> String {
+        match self {
+            Operands::Placeholders => "n16".to_string(),
+            Operands::Values { low, name, .. } => address(u16::from_le_bytes([low, 0xFF]), name),
+        }
+    }
+}
+
+/// The name of an address, or the address itself if it has none.
+fn address<'a>(address: u16, name: &dyn Fn(u16) -> Option<&'a str>) -> String {
+    match name(address) {
+        Some(name) => name.to_string(),
+        None => format!("${address:04X}"),
+    }
+}
+
+fn text(instruction: Instruction, operands: Operands) -> String {
+    let r8 = |register| register8(register, operands);
+    match instruction {
+        Instruction::Add(register) => format!("ADD A, {}", r8(register)),
+        Instruction::AddHl(register) => format!("ADD HL, {}", register16(register)),
+        Instruction::AddSp => format!("ADD SP, {}", operands.e8()),
+        Instruction::Adc(register) => format!("ADC A, {}", r8(register)),
+        Instruction::Sub(register) => format!("SUB A, {}", r8(register)),
+        Instruction::Sbc(register) => format!("SBC A, {}", r8(register)),
+        Instruction::Cp(register) => format!("CP A, {}", r8(register)),
+        Instruction::And(register) => format!("AND A, {}", r8(register)),
+        Instruction::Or(register) => format!("OR A, {}", r8(register)),
+        Instruction::Xor(register) => format!("XOR A, {}", r8(register)),
+        Instruction::Inc(register) => format!("INC {}", r8(register)),
+        Instruction::Dec(register) => format!("DEC {}", r8(register)),
+        Instruction::Inc16(register) => format!("INC {}", register16(register)),
+        Instruction::Dec16(register) => format!("DEC {}", register16(register)),
+        Instruction::Daa => "DAA".to_string(),
+        Instruction::Ccf => "CCF".to_string(),
+        Instruction::Scf => "SCF".to_string(),
+        Instruction::Cpl => "CPL".to_string(),
+        Instruction::Bit(bit, register) => format!("BIT {}, {}", bit.get(), r8(register)),
+        Instruction::Res(bit, register) => format!("RES {}, {}", bit.get(), r8(register)),
+        Instruction::Set(bit, register) => format!("SET {}, {}", bit.get(), r8(register)),
+        Instruction::Rr(register) => format!("RR {}", r8(register)),
+        Instruction::Rl(register) => format!("RL {}", r8(register)),
+        Instruction::Rrc(register) => format!("RRC {}", r8(register)),
+        Instruction::Rlc(register) => format!("RLC {}", r8(register)),
+        Instruction::Rra => "RRA".to_string(),
+        Instruction::Rla => "RLA".to_string(),
+        Instruction::Rrca => "RRCA".to_string(),
+        Instruction::Rlca => "RLCA".to_string(),
+        Instruction::Srl(register) => format!("SRL {}", r8(register)),
+        Instruction::Sra(register) => format!("SRA {}", r8(register)),
+        Instruction::Sla(register) => format!("SLA {}", r8(register)),
+        Instruction::Swap(register) => format!("SWAP {}", r8(register)),
+        Instruction::Jp(test) => format!("JP {}{}", condition(test), operands.n16()),
+        Instruction::JpHl => "JP HL".to_string(),
+        Instruction::Jr(test) => format!("JR {}{}", condition(test), operands.jump_target()),
+        Instruction::Ld(load_type) => load(load_type, operands),
+        Instruction::Push(register) => format!("PUSH {}", stack_register(register)),
+        Instruction::Pop(register) => format!("POP {}", stack_register(register)),
+        Instruction::Call(test) => format!("CALL {}{}", condition(test), operands.n16()),
+        Instruction::Rst(address) => format!("RST ${address:02X}"),
+        Instruction::Ret(JumpTest::Always) => "RET".to_string(),
+        Instruction::Ret(test) => format!("RET {}", condition(test).trim_end_matches(", ")),
+        Instruction::Reti => "RETI".to_string(),
+        Instruction::Di => "DI".to_string(),
+        Instruction::Ei => "EI".to_string(),
+        Instruction::Nop => "NOP".to_string(),
+        Instruction::Halt => "HALT".to_string(),
+        Instruction::Stop => "STOP".to_string(),
+    }
+}
+
+fn load(load_type: LoadType, operands: Operands) -> String {
+    match load_type {
+        LoadType::Byte(target, source) => {
+            let target_text = match target {
+                LoadByteTarget::A => "A".to_string(),
+                LoadByteTarget::B => "B".to_string(),
+                LoadByteTarget::C => "C".to_string(),
+                LoadByteTarget::D => "D".to_string(),
+                LoadByteTarget::E => "E".to_string(),
+                LoadByteTarget::H => "H".to_string(),
+                LoadByteTarget::L => "L".to_string(),
+                LoadByteTarget::BcIndirect => "[BC]".to_string(),
+                LoadByteTarget::DeIndirect => "[DE]".to_string(),
+                LoadByteTarget::HlIndirect => "[HL]".to_string(),
+                LoadByteTarget::Hli => "[HL+]".to_string(),
+                LoadByteTarget::Hld => "[HL-]".to_string(),
+                LoadByteTarget::Indirect => format!("[{}]", operands.n16()),
+                LoadByteTarget::HighIndirect => format!("[{}]", operands.high_address()),
+                LoadByteTarget::HighC => "[C]".to_string(),
+            };
+            let source_text = match source {
+                LoadByteSource::A => "A".to_string(),
+                LoadByteSource::B => "B".to_string(),
+                LoadByteSource::C => "C".to_string(),
+                LoadByteSource::D => "D".to_string(),
+                LoadByteSource::E => "E".to_string(),
+                LoadByteSource::H => "H".to_string(),
+                LoadByteSource::L => "L".to_string(),
+                LoadByteSource::D8 => operands.n8(),
+                LoadByteSource::BcIndirect => "[BC]".to_string(),
+                LoadByteSource::DeIndirect => "[DE]".to_string(),
+                LoadByteSource::HlIndirect => "[HL]".to_string(),
+                LoadByteSource::Hli => "[HL+]".to_string(),
+                LoadByteSource::Hld => "[HL-]".to_string(),
+                LoadByteSource::Indirect => format!("[{}]", operands.n16()),
+                LoadByteSource::HighIndirect => format!("[{}]", operands.high_address()),
+                LoadByteSource::HighC => "[C]".to_string(),
+            };
+            let is_high = matches!(target, LoadByteTarget::HighIndirect | LoadByteTarget::HighC)
+                || matches!(source, LoadByteSource::HighIndirect | LoadByteSource::HighC);
+            let mnemonic = if is_high { "LDH" } else { "LD" };
+            format!("{mnemonic} {target_text}, {source_text}")
+        }
+        LoadType::Word(register) => format!("LD {}, {}", register16(register), operands.n16()),
+        LoadType::IndirectFromSp => format!("LD [{}], SP", operands.n16()),
+        LoadType::SpFromHl => "LD SP, HL".to_string(),
+        LoadType::HlFromSpOffset => format!("LD HL, {}", operands.sp_offset()),
+    }
+}
+
+fn register8(register: TargetRegister8, operands: Operands) -> String {
+    match register {
+        TargetRegister8::A => "A".to_string(),
+        TargetRegister8::B => "B".to_string(),
+        TargetRegister8::C => "C".to_string(),
+        TargetRegister8::D => "D".to_string(),
+        TargetRegister8::E => "E".to_string(),
+        TargetRegister8::H => "H".to_string(),
+        TargetRegister8::L => "L".to_string(),
+        TargetRegister8::HlIndirect => "[HL]".to_string(),
+        TargetRegister8::D8 => operands.n8(),
+    }
+}
+
+fn register16(register: TargetRegister16) -> &'static str {
+    match register {
+        TargetRegister16::BC => "BC",
+        TargetRegister16::DE => "DE",
+        TargetRegister16::HL => "HL",
+        TargetRegister16::SP => "SP",
+    }
+}
+
+fn stack_register(register: StackTarget) -> &'static str {
+    match register {
+        StackTarget::AF => "AF",
+        StackTarget::BC => "BC",
+        StackTarget::DE => "DE",
+        StackTarget::HL => "HL",
+    }
+}
+
+/// The condition of a jump, followed by a comma if there is one.
+fn condition(test: JumpTest) -> &'static str {
+    match test {
+        JumpTest::NotZero => "NZ, ",
+        JumpTest::Zero => "Z, ",
+        JumpTest::NotCarry => "NC, ",
+        JumpTest::Carry => "C, ",
+        JumpTest::Always => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble_bytes(address: u16, bytes: &[u8]) -> Disassembly {
+        disassemble(address, |at| {
+            bytes
+                .get(at.wrapping_sub(address) as usize)
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+
+    #[test]
+    fn operands() {
+        let cases: [(&[u8], &str); 16] = [
+            (&[0x00], "NOP"),
+            (&[0x3E, 0x12], "LD A, $12"),
+            (&[0x21, 0x34, 0x12], "LD HL, $1234"),
+            (&[0x2A], "LD A, [HL+]"),
+            (&[0x32], "LD [HL-], A"),
+            (&[0xEA, 0x00, 0xC0], "LD [$C000], A"),
+            (&[0x08, 0x00, 0xC0], "LD [$C000], SP"),
+            (&[0xE0, 0x44], "LDH [$FF44], A"),
+            (&[0xF2], "LDH A, [C]"),
+            (&[0xE8, 0xFE], "ADD SP, -2"),
+            (&[0xF8, 0x05], "LD HL, SP+5"),
+            (&[0xF8, 0x80], "LD HL, SP-128"),
+            (&[0xFE, 0x90], "CP A, $90"),
+            (&[0xCB, 0x7E], "BIT 7, [HL]"),
+            (&[0xC0], "RET NZ"),
+            (&[0xFF], "RST $38"),
+        ];
+        for (bytes, text) in cases {
+            let disassembly = disassemble_bytes(0x0150, bytes);
+            assert_eq!(disassembly.text, text);
+            assert_eq!(disassembly.bytes, bytes);
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(
+            disassemble_bytes(0x0150, &[0x20, 0xFA]).text,
+            "JR NZ, $014C"
+        );
+        assert_eq!(disassemble_bytes(0x0150, &[0x18, 0xFE]).text, "JR $0150");
+        assert_eq!(
+            disassemble_bytes(0x0150, &[0xDA, 0x00, 0x40]).text,
+            "JP C, $4000"
+        );
+        assert_eq!(
+            disassemble_bytes(0x0150, &[0xCD, 0x50, 0x01]).text,
+            "CALL $0150"
+        );
+        assert_eq!(disassemble_bytes(0x0150, &[0xE9]).text, "JP HL");
+    }
+
+    #[test]
+    fn flow() {
+        let flow = |bytes: &[u8]| disassemble_bytes(0x0150, bytes).flow();
+        assert_eq!(flow(&[0x3E, 0x12]), Flow::Next);
+        assert_eq!(
+            flow(&[0x20, 0xFA]),
+            Flow::Jump {
+                target: 0x014C,
+                conditional: true
+            }
+        );
+        assert_eq!(
+            flow(&[0xC3, 0x00, 0x40]),
+            Flow::Jump {
+                target: 0x4000,
+                conditional: false
+            }
+        );
+        assert_eq!(flow(&[0xC4, 0x00, 0x40]), Flow::Call { target: 0x4000 });
+        assert_eq!(flow(&[0xEF]), Flow::Call { target: 0x0028 });
+        assert_eq!(flow(&[0xC0]), Flow::Next);
+        assert_eq!(flow(&[0xC9]), Flow::Unknown);
+        assert_eq!(flow(&[0xD3]), Flow::Unknown);
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        let disassembly = disassemble_bytes(0x0150, &[0xD3, 0x12]);
+        assert_eq!(disassembly.text, "DB $D3");
+        assert_eq!(disassembly.length(), 1);
+    }
+
+    #[test]
+    fn placeholders() {
+        let text = |byte, prefixed| Instruction::from_byte(byte, prefixed).unwrap().to_string();
+        assert_eq!(text(0x06, false), "LD B, n8");
+        assert_eq!(text(0x20, false), "JR NZ, e8");
+        assert_eq!(text(0xC3, false), "JP n16");
+        assert_eq!(text(0xF0, false), "LDH A, [n16]");
+        assert_eq!(text(0xF8, false), "LD HL, SP+e8");
+        assert_eq!(text(0x37, true), "SWAP A");
+    }
+
+    /// Every opcode is decoded with the length the CPU uses to execute it.
+    #[test]
+    fn lengths() {
+        for opcode in 0..=0xFF {
+            let disassembly = disassemble_bytes(0, &[opcode]);
+            let expected = match opcode {
+                PREFIX_BYTE => 2,
+                _ => Instruction::from_byte(opcode, false).map_or(1, Instruction::length),
+            };
+            assert_eq!(disassembly.length(), expected, "opcode {opcode:#04X}");
+        }
+    }
+}
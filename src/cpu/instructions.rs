@@ -2,6 +2,7 @@ mod opcodes;
 pub(crate) mod parameter;
 
 use super::Cpu;
+use super::bus::Bus;
 use super::registers::U3;
 use parameter::{
     JumpTest, LoadByteSource, LoadByteTarget, LoadType, StackTarget, TargetRegister8,
@@ -9,11 +10,14 @@ use parameter::{
 };
 
 /// The assembly instructions the emulator can execute.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(super) enum Instruction {
     /// Add the value in r8 to A
     Add(TargetRegister8),
     /// Add the value in r16 to HL
     AddHl(TargetRegister16),
+    /// Add the signed value e8 to SP
+    AddSp,
     /// Add the value in r8 plus the carry flag to A
     Adc(TargetRegister8),
     /// Subtract the value in r8 from A
@@ -32,6 +36,13 @@ pub(super) enum Instruction {
     Inc(TargetRegister8),
     /// Decrement the value in register r8 by 1
     Dec(TargetRegister8),
+    /// Increment the value in register r16 by 1. Doesn't affect any flags.
+    Inc16(TargetRegister16),
+    /// Decrement the value in register r16 by 1. Doesn't affect any flags.
+    Dec16(TargetRegister16),
+    /// Decimal Adjust Accumulator: Turn the result of a previous addition or subtraction of two
+    /// binary-coded decimal numbers into binary-coded decimal again
+    Daa,
     /// Complement Carry flag: Invert the Carry flag
     Ccf,
     /// Set Carry Flag to true
@@ -71,6 +82,10 @@ pub(super) enum Instruction {
     /// Jumps to a specified address if the specified condition is met:
     /// Zero flag set/not set, Carry flag set/not set or always jump
     Jp(JumpTest),
+    /// Jump to the address in HL
+    JpHl,
+    /// Relative jump by the signed value e8 if the specified condition is met
+    Jr(JumpTest),
     /// Load a value into a register or memory location
     Ld(LoadType),
     /// Push register r16 into the stack.
@@ -80,14 +95,27 @@ pub(super) enum Instruction {
     /// Call address n16 if condition cc is met. This pushes the address of the instruction after
     /// the CALL on the stack, such that RET can pop it later; then, it executes an implicit JP n16.
     Call(JumpTest),
+    /// Call the fixed address, which is one of `0x00`, `0x08`, ..., `0x38`. Shorter and faster
+    /// than a `CALL`.
+    Rst(u8),
     /// Return from subroutine if condition is met.
     /// This is basically a `POP PC` (if such an instruction existed).
     Ret(JumpTest),
+    /// Return from subroutine and enable interrupts. Used at the end of interrupt handlers.
+    Reti,
+    /// Disable interrupts by clearing the IME flag.
+    Di,
+    /// Enable interrupts by setting the IME flag. The flag is only set after the instruction
+    /// following `EI`.
+    Ei,
     /// No Operation. Does nothing.
     Nop,
     /// Enter CPU low-power consumption mode until an interrupt occurs.
     /// In our case, will set [Cpu::is_halted] to true and end the [Cpu::execute] cycle.
     Halt,
+    /// Enter CPU very low power mode. On the CGB, this is also used to switch between normal and
+    /// double speed mode if a switch has been prepared through `KEY1`.
+    Stop,
 }
 
 impl Instruction {
@@ -96,22 +124,49 @@ impl Instruction {
     /// Returns [`None`] if the opcode is invalid.
     pub(super) fn from_byte(byte: u8, prefixed: bool) -> Option<Self> {
         match prefixed {
-            true => opcodes::get_opcode_unprefixed(byte),
-            false => Some(opcodes::get_opcode_prefixed(byte)),
+            true => Some(opcodes::get_opcode_prefixed(byte)),
+            false => opcodes::get_opcode_unprefixed(byte),
         }
     }
-}
 
-impl Cpu {
-    /// Execute an instruction on the CPU
-    pub(super) fn execute(&mut self, instruction: Instruction) -> u16 {
-        if self.is_halted {
-            return 0;
+    /// How many bytes the instruction takes up in memory, including its operands. Prefixed
+    /// instructions don't count the prefix byte.
+    pub(super) fn length(self) -> u16 {
+        match self {
+            Instruction::Add(TargetRegister8::D8)
+            | Instruction::Adc(TargetRegister8::D8)
+            | Instruction::Sub(TargetRegister8::D8)
+            | Instruction::Sbc(TargetRegister8::D8)
+            | Instruction::Cp(TargetRegister8::D8)
+            | Instruction::And(TargetRegister8::D8)
+            | Instruction::Or(TargetRegister8::D8)
+            | Instruction::Xor(TargetRegister8::D8)
+            | Instruction::AddSp
+            | Instruction::Jr(_)
+            | Instruction::Stop
+            | Instruction::Ld(LoadType::HlFromSpOffset)
+            | Instruction::Ld(LoadType::Byte(_, LoadByteSource::D8))
+            | Instruction::Ld(LoadType::Byte(LoadByteTarget::HighIndirect, _))
+            | Instruction::Ld(LoadType::Byte(_, LoadByteSource::HighIndirect)) => 2,
+            Instruction::Jp(_)
+            | Instruction::Call(_)
+            | Instruction::Ld(LoadType::Word(_))
+            | Instruction::Ld(LoadType::IndirectFromSp)
+            | Instruction::Ld(LoadType::Byte(LoadByteTarget::Indirect, _))
+            | Instruction::Ld(LoadType::Byte(_, LoadByteSource::Indirect)) => 3,
+            _ => 1,
         }
+    }
+}
 
+impl<B: Bus> Cpu<B> {
+    /// Execute an instruction on the CPU and return the address of the next one.
+    /// Every memory access, including reading the instruction's operands, takes a machine cycle.
+    pub(super) fn execute(&mut self, instruction: Instruction) -> u16 {
         match instruction {
             Instruction::Add(r8) => self.add_a(r8),
-            Instruction::AddHl(r8) => self.add_hl(r8),
+            Instruction::AddHl(r16) => self.add_hl(r16),
+            Instruction::AddSp => self.add_sp(),
             Instruction::Adc(r8) => self.add_with_carry(r8),
             Instruction::Sub(r8) => self.sub(r8),
             Instruction::Sbc(r8) => self.sub_with_carry(r8),
@@ -121,6 +176,9 @@ impl Cpu {
             Instruction::Xor(r8) => self.xor(r8),
             Instruction::Inc(r8) => self.increment(r8),
             Instruction::Dec(r8) => self.decrement(r8),
+            Instruction::Inc16(r16) => self.increment_16(r16),
+            Instruction::Dec16(r16) => self.decrement_16(r16),
+            Instruction::Daa => self.decimal_adjust(),
             Instruction::Ccf => self.invert_carry_flag(),
             Instruction::Scf => self.set_carry_flag(),
             Instruction::Cpl => self.complement_a(),
@@ -131,34 +189,63 @@ impl Cpu {
             Instruction::Rl(r8) => self.rotate_left_with_carry(r8),
             Instruction::Rrc(r8) => self.rotate_right_no_carry(r8),
             Instruction::Rlc(r8) => self.rotate_left_no_carry(r8),
-            Instruction::Rla => self.rotate_left_with_carry(TargetRegister8::A),
-            Instruction::Rrca => self.rotate_right_no_carry(TargetRegister8::A),
-            Instruction::Rlca => self.rotate_left_no_carry(TargetRegister8::A),
-            Instruction::Rra => self.rotate_right_with_carry(TargetRegister8::A),
+            // The accumulator versions always clear the zero flag.
+            Instruction::Rla => {
+                self.rotate_left_with_carry(TargetRegister8::A);
+                self.registers.f.zero = false;
+            }
+            Instruction::Rrca => {
+                self.rotate_right_no_carry(TargetRegister8::A);
+                self.registers.f.zero = false;
+            }
+            Instruction::Rlca => {
+                self.rotate_left_no_carry(TargetRegister8::A);
+                self.registers.f.zero = false;
+            }
+            Instruction::Rra => {
+                self.rotate_right_with_carry(TargetRegister8::A);
+                self.registers.f.zero = false;
+            }
             Instruction::Srl(r8) => self.shift_right_logically(r8),
             Instruction::Sra(r8) => self.shift_right_arithmetically(r8),
             Instruction::Sla(r8) => self.shift_left_arithmetically(r8),
             Instruction::Swap(r8) => self.swap(r8),
             Instruction::Jp(condition) => return self.jump(condition),
-            Instruction::Ld(load_type) => return self.load(load_type),
+            Instruction::JpHl => return self.registers.get_hl(),
+            Instruction::Jr(condition) => return self.jump_relative(condition),
+            Instruction::Ld(load_type) => self.load(load_type),
             Instruction::Push(r16) => self.push(self.get_stack_target_value(r16)),
             Instruction::Pop(r16) => {
                 let res = self.pop();
                 self.set_stack_target_value(r16, res)
             }
             Instruction::Call(condition) => return self.call(condition),
+            Instruction::Rst(address) => {
+                self.push(self.pc.wrapping_add(1));
+                return address as u16;
+            }
             Instruction::Ret(condition) => return self.ret(condition),
+            Instruction::Reti => {
+                self.ime = true;
+                return self.ret(JumpTest::Always);
+            }
+            Instruction::Di => {
+                self.ime = false;
+                self.ime_scheduled = false;
+            }
+            Instruction::Ei => self.ime_scheduled = true,
             Instruction::Nop => (),
-            Instruction::Halt => self.is_halted = true,
+            Instruction::Halt => self.halt(),
+            Instruction::Stop => return self.stop(),
         };
-        // Increment the program counter by one.
+        // Move the program counter to the next instruction.
         // Instructions that modify the PC differently return early.
-        self.pc.wrapping_add(1)
+        self.pc.wrapping_add(instruction.length())
     }
 
     /// Executes [`Instruction::Add`].
     fn add_a(&mut self, target: TargetRegister8) {
-        let value = self.get_r8_value(target);
+        let value = self.read_r8(target);
         let (new_value, did_overflow) = self.registers.a.overflowing_add(value);
 
         self.registers.f.zero = new_value == 0;
@@ -171,38 +258,62 @@ impl Cpu {
     /// Executes [`Instruction::AddHl`].
     fn add_hl(&mut self, target: TargetRegister16) {
         let value = self.get_r16_value(target);
-        let (new_value, did_overflow) = self.registers.get_hl().overflowing_add(value);
+        let hl = self.registers.get_hl();
+        let (new_value, did_overflow) = hl.overflowing_add(value);
+        // The addition is done in two steps by the 8-bit ALU.
+        self.tick();
 
-        self.registers.f.zero = new_value == 0;
+        // The zero flag isn't affected. The half carry is the carry from bit 11 into bit 12.
         self.registers.f.subtract = false;
         self.registers.f.carry = did_overflow;
-        self.registers.f.half_carry = (self.registers.get_hl() & 0xF) + (value & 0xF) > 0xF;
+        self.registers.f.half_carry = (hl & 0xFFF) + (value & 0xFFF) > 0xFFF;
         self.registers.set_hl(new_value);
     }
 
+    /// Executes [`Instruction::AddSp`].
+    fn add_sp(&mut self) {
+        let new_value = self.sp_plus_offset();
+        self.tick();
+        self.tick();
+        self.sp = new_value;
+    }
+
+    /// Reads the signed offset after the instruction and adds it to the stack pointer, setting the
+    /// flags like an 8-bit addition to the lower byte. Shared by [`Instruction::AddSp`] and
+    /// [`LoadType::HlFromSpOffset`].
+    fn sp_plus_offset(&mut self) -> u16 {
+        let offset = self.read_next_byte();
+
+        self.registers.f.zero = false;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = (self.sp & 0xF) + (offset as u16 & 0xF) > 0xF;
+        self.registers.f.carry = (self.sp & 0xFF) + offset as u16 > 0xFF;
+        self.sp.wrapping_add_signed(offset as i8 as i16)
+    }
+
     /// Executes [`Instruction::Adc`].
     fn add_with_carry(&mut self, target: TargetRegister8) {
         let old_carry = if self.registers.f.carry { 1 } else { 0 };
-        let value = self.get_r8_value(target) + old_carry;
-        let (new_value, did_overflow) = self.registers.a.overflowing_add(value);
+        let value = self.read_r8(target);
+        let new_value = self.registers.a.wrapping_add(value).wrapping_add(old_carry);
 
         self.registers.f.zero = new_value == 0;
         self.registers.f.subtract = false;
-        self.registers.f.carry = did_overflow;
-        self.registers.f.half_carry = (self.registers.a & 0xF) + (value & 0xF) > 0xF;
+        self.registers.f.carry = self.registers.a as u16 + value as u16 + old_carry as u16 > 0xFF;
+        self.registers.f.half_carry = (self.registers.a & 0xF) + (value & 0xF) + old_carry > 0xF;
         self.registers.a = new_value;
     }
 
     /// Executes [`Instruction::Cp`].
     /// Returns the value so the implementation can be reused by [Instruction::Sub].
     fn compare(&mut self, target: TargetRegister8) -> u8 {
-        let value = self.get_r8_value(target);
+        let value = self.read_r8(target);
         let (new_value, did_overflow) = self.registers.a.overflowing_sub(value);
 
         self.registers.f.zero = new_value == 0;
         self.registers.f.subtract = true;
         self.registers.f.carry = did_overflow;
-        self.registers.f.half_carry = (self.registers.a & 0xF) + (new_value & 0xF) >= 0xF;
+        self.registers.f.half_carry = (self.registers.a & 0xF) < (value & 0xF);
         new_value
     }
 
@@ -215,66 +326,110 @@ impl Cpu {
     /// Executes [`Instruction::Sbc`].
     fn sub_with_carry(&mut self, target: TargetRegister8) {
         let old_carry = if self.registers.f.carry { 1 } else { 0 };
-        let value = self.get_r8_value(target) + old_carry;
-        let (new_value, did_overflow) = self.registers.a.overflowing_sub(value);
+        let value = self.read_r8(target);
+        let new_value = self.registers.a.wrapping_sub(value).wrapping_sub(old_carry);
 
         self.registers.f.zero = new_value == 0;
         self.registers.f.subtract = true;
-        self.registers.f.carry = did_overflow;
-        self.registers.f.half_carry = (self.registers.a & 0xF) + (new_value & 0xF) >= 0xF;
+        self.registers.f.carry = (self.registers.a as u16) < value as u16 + old_carry as u16;
+        self.registers.f.half_carry = (self.registers.a & 0xF) < (value & 0xF) + old_carry;
         self.registers.a = new_value;
     }
 
     /// Executes [`Instruction::And`].
     fn and(&mut self, target: TargetRegister8) {
-        let value = self.get_r8_value(target);
+        let value = self.read_r8(target);
+        self.registers.a &= value;
         self.registers.f.zero = self.registers.a == 0;
         self.registers.f.subtract = false;
         self.registers.f.half_carry = true;
         self.registers.f.carry = false;
-        self.registers.a &= value;
     }
 
     /// Executes [`Instruction::Or`].
     fn or(&mut self, target: TargetRegister8) {
-        let value = self.get_r8_value(target);
+        let value = self.read_r8(target);
+        self.registers.a |= value;
         self.registers.f.zero = self.registers.a == 0;
         self.registers.f.subtract = false;
         self.registers.f.half_carry = false;
         self.registers.f.carry = false;
-        self.registers.a |= value;
     }
 
     /// Executes [`Instruction::Xor`].
     fn xor(&mut self, target: TargetRegister8) {
-        let value = self.get_r8_value(target);
+        let value = self.read_r8(target);
+        self.registers.a ^= value;
         self.registers.f.zero = self.registers.a == 0;
         self.registers.f.subtract = false;
         self.registers.f.half_carry = false;
         self.registers.f.carry = false;
-        self.registers.a ^= value;
     }
 
     /// Executes [`Instruction::Inc`].
     fn increment(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
+        let value = self.read_r8(target);
+        let new_value = value.wrapping_add(1);
 
-        *register += 1;
-        let new_value = *register;
         self.registers.f.zero = new_value == 0;
         self.registers.f.subtract = false;
-        self.registers.f.half_carry = ((new_value - 1) & 0xF) + 1 > 0xF;
+        self.registers.f.half_carry = value & 0xF == 0xF;
+        self.write_r8(target, new_value);
     }
 
     /// Executes [`Instruction::Dec`].
     fn decrement(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
+        let value = self.read_r8(target);
+        let new_value = value.wrapping_sub(1);
 
-        *register -= 1;
-        let new_value = *register;
         self.registers.f.zero = new_value == 0;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = (new_value & 0xF) + ((new_value + 1) & 0xF) >= 0xF;
+        self.registers.f.subtract = true;
+        self.registers.f.half_carry = value & 0xF == 0;
+        self.write_r8(target, new_value);
+    }
+
+    /// Executes [`Instruction::Inc16`].
+    fn increment_16(&mut self, target: TargetRegister16) {
+        let value = self.get_r16_value(target);
+        self.bus.trigger_oam_bug(value);
+        self.tick();
+        self.set_r16_value(target, value.wrapping_add(1));
+    }
+
+    /// Executes [`Instruction::Dec16`].
+    fn decrement_16(&mut self, target: TargetRegister16) {
+        let value = self.get_r16_value(target);
+        self.bus.trigger_oam_bug(value);
+        self.tick();
+        self.set_r16_value(target, value.wrapping_sub(1));
+    }
+
+    /// Executes [`Instruction::Daa`].
+    fn decimal_adjust(&mut self) {
+        let mut adjustment = 0;
+        let mut carry = self.registers.f.carry;
+        if self.registers.f.subtract {
+            if self.registers.f.half_carry {
+                adjustment |= 0x06;
+            }
+            if self.registers.f.carry {
+                adjustment |= 0x60;
+            }
+            self.registers.a = self.registers.a.wrapping_sub(adjustment);
+        } else {
+            if self.registers.f.half_carry || self.registers.a & 0xF > 0x9 {
+                adjustment |= 0x06;
+            }
+            if self.registers.f.carry || self.registers.a > 0x99 {
+                adjustment |= 0x60;
+                carry = true;
+            }
+            self.registers.a = self.registers.a.wrapping_add(adjustment);
+        }
+
+        self.registers.f.zero = self.registers.a == 0;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = carry;
     }
 
     /// Executes [`Instruction::Ccf`].
@@ -300,9 +455,8 @@ impl Cpu {
 
     /// Executes [`Instruction::Bit`].
     fn test_bit(&mut self, index: U3, target: TargetRegister8) {
-        let value = self.get_r8_value(target);
-        let mask = 1 << index;
-        let is_bit_set = ((value & mask) >> index) == 0;
+        let value = self.read_r8(target);
+        let is_bit_set = (value >> index) & 1 != 0;
 
         self.registers.f.subtract = false;
         self.registers.f.half_carry = true;
@@ -311,129 +465,93 @@ impl Cpu {
 
     /// Executes [`Instruction::Res`].
     fn unset_bit(&mut self, index: U3, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
+        let value = self.read_r8(target);
         let zero_bit = !(1 << index);
-        *register &= zero_bit;
+        self.write_r8(target, value & zero_bit);
     }
 
     /// Executes [`Instruction::Set`].
     fn set_bit(&mut self, index: U3, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
+        let value = self.read_r8(target);
         let one_bit = 1 << index;
-        *register |= one_bit;
+        self.write_r8(target, value | one_bit);
     }
 
-    /// Executes [`Instruction::Srl`].
-    fn shift_right_logically(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
-        let lsb = *register & 0b0000_0001;
-
-        *register >>= 1;
-        self.registers.f.zero = *register == 0;
+    /// Writes the result of a shift or rotation and sets the flags accordingly.
+    fn finish_shift(&mut self, target: TargetRegister8, new_value: u8, carry: bool) {
+        self.registers.f.zero = new_value == 0;
         self.registers.f.subtract = false;
         self.registers.f.half_carry = false;
-        self.registers.f.carry = lsb == 1;
+        self.registers.f.carry = carry;
+        self.write_r8(target, new_value);
+    }
+
+    /// Executes [`Instruction::Srl`].
+    fn shift_right_logically(&mut self, target: TargetRegister8) {
+        let value = self.read_r8(target);
+        let lsb = value & 0b0000_0001;
+        self.finish_shift(target, value >> 1, lsb == 1);
     }
 
     /// Executes [`Instruction::Rr`].
     fn rotate_right_with_carry(&mut self, target: TargetRegister8) {
         let old_carry: u8 = if self.registers.f.carry { 1 } else { 0 };
-        let register = self.get_r8_ref(target);
-        let is_lsb_set = (*register & 0b0000_0001) == 1;
-        let shifted = *register >> 1;
-
-        *register = (old_carry << 7) | shifted;
-        self.registers.f.zero = *register == 0;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = false;
-        self.registers.f.carry = is_lsb_set;
+        let value = self.read_r8(target);
+        let is_lsb_set = (value & 0b0000_0001) == 1;
+        self.finish_shift(target, (old_carry << 7) | (value >> 1), is_lsb_set);
     }
 
-    /// Executes [`Instruction::Rla`].
+    /// Executes [`Instruction::Rl`].
     fn rotate_left_with_carry(&mut self, target: TargetRegister8) {
         let old_carry: u8 = if self.registers.f.carry { 1 } else { 0 };
-        let register = self.get_r8_ref(target);
-        let is_msb_set = (*register & 0b1000_0000) == 128;
-        let shifted = *register << 1;
-
-        *register = old_carry | shifted;
-        self.registers.f.zero = *register == 0;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = false;
-        self.registers.f.carry = is_msb_set;
+        let value = self.read_r8(target);
+        let is_msb_set = (value & 0b1000_0000) != 0;
+        self.finish_shift(target, old_carry | (value << 1), is_msb_set);
     }
 
     /// Executes [`Instruction::Rrc`].
     fn rotate_right_no_carry(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
-        let lsb = *register & 0b0000_0001;
-        let shifted = *register >> 1;
-
-        *register = (lsb << 7) | shifted;
-        self.registers.f.zero = *register == 0;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = false;
-        self.registers.f.carry = lsb == 1;
+        let value = self.read_r8(target);
+        let lsb = value & 0b0000_0001;
+        self.finish_shift(target, value.rotate_right(1), lsb == 1);
     }
 
     /// Executes [`Instruction::Rlc`].
     fn rotate_left_no_carry(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
-        let msb = *register & 0b1000_0000;
-        let shifted = *register << 1;
-
-        *register = msb | shifted;
-        self.registers.f.zero = false;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = false;
-        self.registers.f.carry = msb == 128;
+        let value = self.read_r8(target);
+        let msb = value & 0b1000_0000;
+        self.finish_shift(target, value.rotate_left(1), msb != 0);
     }
 
     /// Executes [`Instruction::Sra`].
     fn shift_right_arithmetically(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
-        let lsb = *register & 0b0000_0001;
-
-        *register >>= 1;
-        self.registers.f.zero = *register == 0;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = false;
-        self.registers.f.carry = lsb == 1;
+        let value = self.read_r8(target);
+        let lsb = value & 0b0000_0001;
+        // The sign bit stays in place.
+        let msb = value & 0b1000_0000;
+        self.finish_shift(target, msb | (value >> 1), lsb == 1);
     }
 
     /// Executes [`Instruction::Sla`].
     fn shift_left_arithmetically(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
-        let msb = *register & 0b1000_0000;
-
-        *register <<= 1;
-        self.registers.f.zero = *register == 0;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = false;
-        self.registers.f.carry = msb == 128;
+        let value = self.read_r8(target);
+        let msb = value & 0b1000_0000;
+        self.finish_shift(target, value << 1, msb != 0);
     }
 
     /// Executes [`Instruction::Swap`].
     fn swap(&mut self, target: TargetRegister8) {
-        let register = self.get_r8_ref(target);
-        let new_upper = (*register & 0b0000_1111) << 4;
-        let new_lower = (*register & 0b1111_0000) >> 4;
-
-        *register = new_upper | new_lower;
-        self.registers.f.zero = false;
-        self.registers.f.subtract = false;
-        self.registers.f.half_carry = false;
-        self.registers.f.carry = false;
+        let value = self.read_r8(target);
+        self.finish_shift(target, value.rotate_left(4), false);
     }
 
     /// Executes [`Instruction::Jp`].
     fn jump(&mut self, condition: JumpTest) -> u16 {
-        let should_jump = self.get_jump_test_result(condition);
-        if should_jump {
-            // The Game Boy is little endian: Read lsb first
-            let lsb = self.bus.read_byte(self.pc + 1) as u16;
-            let msb = self.bus.read_byte(self.pc + 2) as u16;
-            (msb << 8) | lsb
+        // The address is read even if the condition isn't met.
+        let address = self.read_next_word();
+        if self.get_jump_test_result(condition) {
+            self.tick();
+            address
         } else {
             // Condition not met, move to the next instruction
             // A jump instruction is 3 bytes wide (1 byte tag, 2 bytes jump address)
@@ -441,8 +559,21 @@ impl Cpu {
         }
     }
 
+    /// Executes [`Instruction::Jr`].
+    fn jump_relative(&mut self, condition: JumpTest) -> u16 {
+        let offset = self.read_next_byte() as i8;
+        // The offset is relative to the end of the 2-byte wide instruction.
+        let next_pc = self.pc.wrapping_add(2);
+        if self.get_jump_test_result(condition) {
+            self.tick();
+            next_pc.wrapping_add_signed(offset as i16)
+        } else {
+            next_pc
+        }
+    }
+
     /// Executes [`Instruction::Ld`].
-    fn load(&mut self, load_type: LoadType) -> u16 {
+    fn load(&mut self, load_type: LoadType) {
         match load_type {
             LoadType::Byte(target, source) => {
                 let source_value = match source {
@@ -454,7 +585,26 @@ impl Cpu {
                     LoadByteSource::H => self.registers.h,
                     LoadByteSource::L => self.registers.l,
                     LoadByteSource::D8 => self.read_next_byte(),
-                    LoadByteSource::Hli => self.bus.read_byte(self.registers.get_hl()),
+                    LoadByteSource::BcIndirect => self.read(self.registers.get_bc()),
+                    LoadByteSource::DeIndirect => self.read(self.registers.get_de()),
+                    LoadByteSource::HlIndirect => self.read(self.registers.get_hl()),
+                    LoadByteSource::Hli => {
+                        let address = self.step_hl(1);
+                        self.read(address)
+                    }
+                    LoadByteSource::Hld => {
+                        let address = self.step_hl(-1);
+                        self.read(address)
+                    }
+                    LoadByteSource::Indirect => {
+                        let address = self.read_next_word();
+                        self.read(address)
+                    }
+                    LoadByteSource::HighIndirect => {
+                        let offset = self.read_next_byte();
+                        self.read(0xFF00 | offset as u16)
+                    }
+                    LoadByteSource::HighC => self.read(0xFF00 | self.registers.c as u16),
                 };
 
                 match target {
@@ -465,39 +615,82 @@ impl Cpu {
                     LoadByteTarget::E => self.registers.e = source_value,
                     LoadByteTarget::H => self.registers.h = source_value,
                     LoadByteTarget::L => self.registers.l = source_value,
+                    LoadByteTarget::BcIndirect => self.write(self.registers.get_bc(), source_value),
+                    LoadByteTarget::DeIndirect => self.write(self.registers.get_de(), source_value),
+                    LoadByteTarget::HlIndirect => self.write(self.registers.get_hl(), source_value),
                     LoadByteTarget::Hli => {
-                        self.bus.write_byte(self.registers.get_hl(), source_value)
+                        let address = self.step_hl(1);
+                        self.write(address, source_value)
+                    }
+                    LoadByteTarget::Hld => {
+                        let address = self.step_hl(-1);
+                        self.write(address, source_value)
+                    }
+                    LoadByteTarget::Indirect => {
+                        let address = self.read_next_word();
+                        self.write(address, source_value)
+                    }
+                    LoadByteTarget::HighIndirect => {
+                        let offset = self.read_next_byte();
+                        self.write(0xFF00 | offset as u16, source_value)
+                    }
+                    LoadByteTarget::HighC => {
+                        self.write(0xFF00 | self.registers.c as u16, source_value)
                     }
                 }
-
-                match source {
-                    LoadByteSource::D8 => self.pc.wrapping_add(2),
-                    _ => self.pc.wrapping_add(1),
-                }
             }
-            _ => todo!(),
+            LoadType::Word(target) => {
+                let value = self.read_next_word();
+                self.set_r16_value(target, value);
+            }
+            LoadType::IndirectFromSp => {
+                let address = self.read_next_word();
+                let [lsb, msb] = self.sp.to_le_bytes();
+                self.write(address, lsb);
+                self.write(address.wrapping_add(1), msb);
+            }
+            LoadType::SpFromHl => {
+                self.tick();
+                self.sp = self.registers.get_hl();
+            }
+            LoadType::HlFromSpOffset => {
+                let value = self.sp_plus_offset();
+                self.tick();
+                self.registers.set_hl(value);
+            }
         }
     }
 
-    /// Executes [`Instruction::Push`].
+    /// Increments or decrements `HL` for the `[HL+]` and `[HL-]` operands.
+    /// Returns the value `HL` had before.
+    fn step_hl(&mut self, delta: i16) -> u16 {
+        let hl = self.registers.get_hl();
+        self.bus.trigger_oam_bug(hl);
+        self.registers.set_hl(hl.wrapping_add_signed(delta));
+        hl
+    }
+
+    /// Executes [`Instruction::Push`]. Also used to push return addresses on the stack.
+    /// Takes an internal machine cycle before writing the value.
     fn push(&mut self, value: u16) {
+        self.tick();
         // Decrease the SP and write MSB of value into memory at location of SP
         self.sp = self.sp.wrapping_sub(1);
-        self.bus.write_byte(self.sp, ((value & 0xFF00) >> 8) as u8);
+        self.write(self.sp, ((value & 0xFF00) >> 8) as u8);
 
         // Decrease the SP and write LSB of value into memory at location of SP
         self.sp = self.sp.wrapping_sub(1);
-        self.bus.write_byte(self.sp, (value & 0x00FF) as u8);
+        self.write(self.sp, (value & 0x00FF) as u8);
     }
 
     /// Executes [`Instruction::Pop`].
     fn pop(&mut self) -> u16 {
         // Read LSB of value from memory at location of SP and increase the SP
-        let lsb = self.bus.read_byte(self.sp) as u16;
+        let lsb = self.read(self.sp) as u16;
         self.sp = self.sp.wrapping_add(1);
 
         // Read MSB of value from memory at location of SP and increase the SP
-        let msb = self.bus.read_byte(self.sp) as u16;
+        let msb = self.read(self.sp) as u16;
         self.sp = self.sp.wrapping_add(1);
 
         (msb << 8) | lsb
@@ -505,22 +698,49 @@ impl Cpu {
 
     /// Executes [`Instruction::Call`].
     fn call(&mut self, condition: JumpTest) -> u16 {
-        let should_jump = self.get_jump_test_result(condition);
+        let address = self.read_next_word();
         // Set the PC to the instruction after the 3-byte wide `Call` instruction
-        let next_pc = self.sp.wrapping_add(3);
-        if should_jump {
+        let next_pc = self.pc.wrapping_add(3);
+        if self.get_jump_test_result(condition) {
             self.push(next_pc);
-            self.read_next_word()
+            address
         } else {
             next_pc
         }
     }
 
+    /// Executes [`Instruction::Halt`].
+    fn halt(&mut self) {
+        // If an interrupt is already pending but can't be handled, the CPU doesn't halt. Instead,
+        // it fails to increment the program counter after reading the next opcode.
+        if !self.ime && self.bus.pending_interrupts() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.is_halted = true;
+        }
+    }
+
+    /// Executes [`Instruction::Stop`].
+    fn stop(&mut self) -> u16 {
+        // Without a pending speed switch, the CPU sleeps until it is woken up by a button press.
+        // We treat that the same way as a halt.
+        if !self.bus.switch_speed() {
+            self.is_halted = true;
+        }
+        // `STOP` is followed by an ignored byte, making it 2 bytes wide.
+        self.pc.wrapping_add(2)
+    }
+
     /// Executes [`Instruction::Ret`].
     fn ret(&mut self, condition: JumpTest) -> u16 {
-        let should_jump = self.get_jump_test_result(condition);
-        if should_jump {
-            self.pop()
+        // Checking the condition takes an extra cycle.
+        if condition != JumpTest::Always {
+            self.tick();
+        }
+        if self.get_jump_test_result(condition) {
+            let address = self.pop();
+            self.tick();
+            address
         } else {
             self.pc.wrapping_add(1)
         }
@@ -2,6 +2,7 @@
 
 /// Which 8-bit register an instruction should affect.
 /// Note that F is missing, as it cannot be the target of an Instruction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) enum TargetRegister8 {
     A,
     B,
@@ -10,16 +11,25 @@ pub(crate) enum TargetRegister8 {
     E,
     H,
     L,
+    /// The byte in memory that `HL` points to, written as `[HL]`.
+    HlIndirect,
+    /// Direct 8-bit value, stored directly after the instruction. Only used as the operand of
+    /// arithmetic and logic instructions.
+    D8,
 }
 
 /// Combined 16-bit registers
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) enum TargetRegister16 {
     BC,
     DE,
     HL,
+    /// The stack pointer.
+    SP,
 }
 
 /// What flag state a jump should check.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) enum JumpTest {
     /// Jump if the zero flag is not set.
     NotZero,
@@ -34,25 +44,23 @@ pub(crate) enum JumpTest {
 }
 
 /// Different ways [`super::Instruction`] can load data.
-pub(super) enum LoadType {
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum LoadType {
     /// Load 8-bit values from one place to another.
     Byte(LoadByteTarget, LoadByteSource),
-    /// Load 16-bit values from one place to another.
-    Word,
-    /// Load the contents of address into the `A` register.
-    AFromIndirect,
-    /// Load the contents of the `A` register into the location of address
-    IndirectFromA,
-    /// Load the contents of the memory address stored at the very last byte of memory
-    /// into register `A`.
-    AFromByteAddress,
-    /// Load the contents of the `A` register into the location of the address stored at the
-    /// very last byte of memory.
-    ByteAddressFromA,
+    /// Load the 16-bit value stored directly after the instruction into a 16-bit register.
+    Word(TargetRegister16),
+    /// Store the stack pointer at the 16-bit address stored directly after the instruction.
+    IndirectFromSp,
+    /// Load the value of `HL` into the stack pointer.
+    SpFromHl,
+    /// Load the stack pointer plus a signed 8-bit offset into `HL`.
+    HlFromSpOffset,
 }
 
-/// Where [`super::Instruction::Ld`] will load its data from.
-pub(super) enum LoadByteTarget {
+/// Where [`super::Instruction::Ld`] will store its data to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum LoadByteTarget {
     A,
     B,
     C,
@@ -60,13 +68,30 @@ pub(super) enum LoadByteTarget {
     E,
     H,
     L,
+    /// The byte in memory that `BC` points to, written as `[BC]`.
+    BcIndirect,
+    /// The byte in memory that `DE` points to, written as `[DE]`.
+    DeIndirect,
+    /// The byte in memory that `HL` points to, written as `[HL]`.
+    HlIndirect,
     /// HL Incremented, the value in HL is incremented after it is accessed.
     /// Sometimes written as `[hl+]`.
     Hli,
+    /// HL Decremented, the value in HL is decremented after it is accessed.
+    /// Sometimes written as `[hl-]`.
+    Hld,
+    /// The byte at the 16-bit address stored directly after the instruction.
+    Indirect,
+    /// The byte at `0xFF00` plus the 8-bit value stored directly after the instruction. Used by
+    /// `LDH` to access the I/O registers and high RAM.
+    HighIndirect,
+    /// The byte at `0xFF00` plus the value in `C`.
+    HighC,
 }
 
-/// Where [`super::Instruction::Ld`] will store its data to.
-pub(super) enum LoadByteSource {
+/// Where [`super::Instruction::Ld`] will load its data from.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum LoadByteSource {
     A,
     B,
     C,
@@ -76,12 +101,29 @@ pub(super) enum LoadByteSource {
     L,
     /// Direct 8-bit value, stored directly after instruction.
     D8,
+    /// The byte in memory that `BC` points to, written as `[BC]`.
+    BcIndirect,
+    /// The byte in memory that `DE` points to, written as `[DE]`.
+    DeIndirect,
+    /// The byte in memory that `HL` points to, written as `[HL]`.
+    HlIndirect,
     /// HL Incremented, the value in HL is incremented after it is accessed.
     /// Sometimes written as `[hl+]`.
     Hli,
+    /// HL Decremented, the value in HL is decremented after it is accessed.
+    /// Sometimes written as `[hl-]`.
+    Hld,
+    /// The byte at the 16-bit address stored directly after the instruction.
+    Indirect,
+    /// The byte at `0xFF00` plus the 8-bit value stored directly after the instruction. Used by
+    /// `LDH` to access the I/O registers and high RAM.
+    HighIndirect,
+    /// The byte at `0xFF00` plus the value in `C`.
+    HighC,
 }
 
 /// Where [`super::Instruction::Push`] will store its data.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) enum StackTarget {
     AF,
     BC,
@@ -1,4 +1,5 @@
 use super::*;
+use crate::memory_bus::MemoryBus;
 #[test]
 fn add_no_overflow() {
     let mut cpu = Cpu::default();
@@ -338,7 +339,7 @@ fn dec() {
 
     assert_eq!(cpu.registers.e, result);
     assert_eq!(cpu.registers.f.zero, false);
-    assert_eq!(cpu.registers.f.subtract, false);
+    assert_eq!(cpu.registers.f.subtract, true);
     assert_eq!(cpu.registers.f.half_carry, true);
     assert_eq!(cpu.registers.f.carry, false);
 }
@@ -381,7 +382,7 @@ fn bit_set() {
     let mut cpu = Cpu::default();
     let input = 0b1101_0111;
     let index = U3::wrap(4);
-    let result = true;
+    let result = false;
 
     cpu.registers.h = input;
     let instruction = Instruction::Bit(index, TargetRegister8::H);
@@ -398,7 +399,7 @@ fn bit_unset() {
     let mut cpu = Cpu::default();
     let input = 0b1100_0111;
     let index = U3::wrap(4);
-    let result = false;
+    let result = true;
 
     cpu.registers.c = input;
     let instruction = Instruction::Bit(index, TargetRegister8::C);
@@ -502,7 +503,7 @@ fn rlc() {
     let mut cpu = Cpu::default();
     let a_input = 0b1100_1111;
     let old_carry = false;
-    let result = 0b1001_1110;
+    let result = 0b1001_1111;
     let new_carry = true;
 
     cpu.registers.l = a_input;
@@ -581,7 +582,7 @@ fn rlca() {
     let mut cpu = Cpu::default();
     let a_input = 0b1100_1111;
     let old_carry = false;
-    let result = 0b1001_1110;
+    let result = 0b1001_1111;
     let new_carry = true;
 
     cpu.registers.a = a_input;
@@ -618,7 +619,7 @@ fn srl() {
 fn sra() {
     let mut cpu = Cpu::default();
     let input = 0b1100_1111;
-    let result = 0b0110_0111;
+    let result = 0b1110_0111;
     let new_carry = true;
 
     cpu.registers.h = input;
@@ -685,3 +686,147 @@ fn push_pop() {
     assert_eq!(cpu.registers.get_de(), second);
     assert_eq!(cpu.registers.get_hl(), first);
 }
+
+#[test]
+fn stop_without_speed_switch() {
+    let mut cpu = Cpu::default();
+
+    let instruction = Instruction::Stop;
+    let next_pc = cpu.execute(instruction);
+
+    assert_eq!(next_pc, 2);
+    assert_eq!(cpu.is_halted, true);
+}
+
+/// Assembles a program into work RAM and points the program counter at it.
+fn cpu_with_program(source: &str) -> Cpu<MemoryBus> {
+    let mut cpu = Cpu::default();
+    for (offset, byte) in crate::asm!(0xC000, source).iter().enumerate() {
+        cpu.bus.write_byte(0xC000 + offset as u16, *byte);
+    }
+    cpu.pc = 0xC000;
+    cpu.sp = 0xDFFF;
+    cpu
+}
+
+#[test]
+fn daa_after_addition() {
+    let mut cpu = Cpu::default();
+    // 0x19 + 0x28 = 0x41, which is 47 in BCD.
+    cpu.registers.a = 0x41;
+    cpu.registers.f.half_carry = true;
+    cpu.execute(Instruction::Daa);
+
+    assert_eq!(cpu.registers.a, 0x47);
+    assert_eq!(cpu.registers.f.half_carry, false);
+    assert_eq!(cpu.registers.f.carry, false);
+}
+
+#[test]
+fn daa_after_subtraction() {
+    let mut cpu = Cpu::default();
+    // 0x10 - 0x01 = 0x0F, which is 9 in BCD.
+    cpu.registers.a = 0x0F;
+    cpu.registers.f.subtract = true;
+    cpu.registers.f.half_carry = true;
+    cpu.execute(Instruction::Daa);
+
+    assert_eq!(cpu.registers.a, 0x09);
+}
+
+#[test]
+fn program_until_halt() {
+    let mut cpu = cpu_with_program(
+        "ld a, $12
+        ld b, $34
+        add b
+        ld [$C100], a
+        halt",
+    );
+    while !cpu.is_halted {
+        cpu.step();
+    }
+    assert_eq!(cpu.registers.a, 0x46);
+    assert_eq!(cpu.bus.read_byte(0xC100), 0x46);
+    assert_eq!(cpu.pc, 0xC009);
+}
+
+#[test]
+fn jr_timing() {
+    // Not taken with Z set, then taken with Z unset.
+    let mut cpu = cpu_with_program(
+        "jr nz, first
+        jr nz, second
+        first: nop
+        nop
+        second: nop",
+    );
+    cpu.registers.f.zero = true;
+    assert_eq!(cpu.step(), 8);
+    assert_eq!(cpu.pc, 0xC002);
+
+    cpu.registers.f.zero = false;
+    assert_eq!(cpu.step(), 12);
+    assert_eq!(cpu.pc, 0xC006);
+}
+
+#[test]
+fn call_and_ret() {
+    let mut cpu = cpu_with_program(
+        "call function
+        ds 13
+        function: ret",
+    );
+
+    assert_eq!(cpu.step(), 24);
+    assert_eq!(cpu.pc, 0xC010);
+    assert_eq!(cpu.sp, 0xDFFD);
+
+    assert_eq!(cpu.step(), 16);
+    assert_eq!(cpu.pc, 0xC003);
+    assert_eq!(cpu.sp, 0xDFFF);
+}
+
+#[test]
+fn ei_enables_interrupts_after_next_instruction() {
+    // With a timer interrupt pending.
+    let mut cpu = cpu_with_program("ei\nnop\nnop");
+    cpu.bus.write_byte(0xFFFF, 0b0100);
+    cpu.bus.write_byte(0xFF0F, 0b0100);
+
+    cpu.step();
+    assert_eq!(cpu.ime, false);
+    cpu.step();
+    assert_eq!(cpu.ime, true);
+    assert_eq!(cpu.pc, 0xC002);
+
+    assert_eq!(cpu.step(), 20);
+    assert_eq!(cpu.pc, 0x0050);
+    assert_eq!(cpu.ime, false);
+    assert_eq!(cpu.bus.pending_interrupts(), 0);
+}
+
+#[test]
+fn halt_bug_reads_next_byte_twice() {
+    // With interrupts disabled but pending.
+    let mut cpu = cpu_with_program("halt\ninc a\nnop");
+    cpu.bus.write_byte(0xFFFF, 0b0001);
+    cpu.bus.write_byte(0xFF0F, 0b0001);
+
+    cpu.step();
+    assert_eq!(cpu.is_halted, false);
+    let a = cpu.registers.a;
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.registers.a, a.wrapping_add(2));
+    assert_eq!(cpu.pc, 0xC002);
+}
+
+#[test]
+fn invalid_opcode_locks_cpu() {
+    let mut cpu = cpu_with_program("db $D3\nnop");
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.is_locked, true);
+    assert_eq!(cpu.pc, 0xC000);
+}
@@ -1,62 +1,291 @@
+use crate::cartridge::{Header, LoadRomError};
+use crate::cgb::compatibility::PaletteSelection;
 use crate::memory_bus::MemoryBus;
+use crate::model::Model;
+use crate::save_state::impl_save_state;
+pub use bus::Bus;
+pub use disassembler::{Disassembly, disassemble};
+pub(crate) use disassembler::{Flow, disassemble_with_names, opcode_patterns};
 use instructions::{
     Instruction,
     parameter::{JumpTest, StackTarget, TargetRegister8, TargetRegister16},
 };
 use registers::Registers;
 
+mod bus;
+mod disassembler;
 mod instructions;
 mod registers;
+#[cfg(test)]
+mod single_step_tests;
 
 /// Byte that indicates a prefix instruction.
 const PREFIX_BYTE: u8 = 0xCB;
+/// Where the boot ROM hands over control to the cartridge.
+const ENTRY_POINT: u16 = 0x0100;
+const STACK_POINTER_AFTER_BOOT: u16 = 0xFFFE;
+/// The address of the first interrupt handler. The handlers are 8 bytes apart, ordered by
+/// priority: VBlank, LCD, timer, serial and joypad.
+const INTERRUPT_VECTOR_START: u16 = 0x0040;
 
-struct Cpu {
+/// The values of the CPU's registers at one point in time.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+/// The Game Boy's SM83 CPU, connected to a [`Bus`]. Normally, that's the bus of the whole console
+/// inside of a [`GameBoy`](crate::GameBoy), but the CPU can be driven by any other bus as well, like
+/// plain RAM for testing instructions in isolation.
+pub struct Cpu<B> {
     registers: Registers,
     /// The program counter of the CPU.
     pc: u16,
     /// The stack pointer of the CPU.
     sp: u16,
-    bus: MemoryBus,
+    bus: B,
     /// Set by [`Instruction::Halt`]. Is checked every cycle.
     is_halted: bool,
+    /// Set when an invalid opcode is executed. The CPU stops executing instructions until it is
+    /// reset, while the rest of the system keeps running.
+    is_locked: bool,
+    /// The interrupt master enable flag. Controls whether _any_ type of interrupt is handled.
+    /// Can only be written to, not read from. Set by `EI`, `DI` and `RETI` instructions.
+    ime: bool,
+    /// Set by `EI`, which only enables interrupts after the following instruction.
+    ime_scheduled: bool,
+    /// Set by the HALT bug: The next opcode is read without incrementing the program counter,
+    /// so the byte after it is read twice.
+    halt_bug: bool,
+    /// The amount of CPU cycles that have passed since the CPU started.
+    cycles: u64,
 }
 
-impl Default for Cpu {
+impl_save_state!(Cpu<MemoryBus> {
+    registers,
+    pc,
+    sp,
+    bus,
+    is_halted,
+    is_locked,
+    ime,
+    ime_scheduled,
+    halt_bug,
+    cycles,
+});
+
+impl Default for Cpu<MemoryBus> {
     fn default() -> Self {
+        Self::new(MemoryBus::default())
+    }
+}
+
+impl Cpu<MemoryBus> {
+    /// Loads a cartridge and puts the CPU into the state the given model's boot ROM leaves behind
+    /// when it jumps to the cartridge's entry point. Fails for cartridges with hardware that isn't
+    /// emulated.
+    pub(crate) fn load_rom(
+        &mut self,
+        rom: &[u8],
+        model: Model,
+        palette_selection: PaletteSelection,
+    ) -> Result<(), LoadRomError> {
+        self.bus.load_rom(rom, model, palette_selection)?;
+        self.registers = Registers::after_boot(model, Header::parse(rom).as_ref());
+        self.pc = ENTRY_POINT;
+        self.sp = STACK_POINTER_AFTER_BOOT;
+        Ok(())
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Creates a CPU with all registers cleared, except for the stack pointer which is 0xFFFF.
+    /// Execution starts at 0x0000.
+    pub fn new(bus: B) -> Self {
         Self {
             registers: Registers::default(),
             pc: u16::default(),
             sp: u16::MAX,
-            bus: MemoryBus::default(),
+            bus,
             is_halted: bool::default(),
+            is_locked: bool::default(),
+            ime: bool::default(),
+            ime_scheduled: bool::default(),
+            halt_bug: bool::default(),
+            cycles: u64::default(),
         }
     }
-}
 
-impl Cpu {
-    fn step(&mut self) {
-        let mut instruction_byte = self.bus.read_byte(self.pc);
+    pub fn registers(&self) -> CpuRegisters {
+        let registers = &self.registers;
+        CpuRegisters {
+            a: registers.a,
+            f: registers.f.into(),
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+            sp: self.sp,
+            pc: self.pc,
+        }
+    }
+
+    /// Overwrites all registers. The lower nibble of F always reads as 0.
+    pub fn set_registers(&mut self, registers: CpuRegisters) {
+        self.registers = Registers {
+            a: registers.a,
+            f: registers.f.into(),
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+        };
+        self.sp = registers.sp;
+        self.pc = registers.pc;
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Whether the next [`Self::step`] executes the instruction at the program counter, rather
+    /// than waiting in HALT, being locked up or calling an interrupt handler.
+    pub fn executes_instruction_next(&self) -> bool {
+        let has_pending_interrupt = self.bus.pending_interrupts() != 0;
+        if self.is_locked || (self.is_halted && !has_pending_interrupt) {
+            return false;
+        }
+        !(self.ime && has_pending_interrupt)
+    }
+
+    /// Executes the next instruction, or calls the handler of a pending interrupt instead.
+    /// While halted, only a single machine cycle passes. Returns the amount of CPU cycles that
+    /// have passed.
+    pub fn step(&mut self) -> u32 {
+        let start = self.cycles;
+        if self.is_locked {
+            self.tick();
+            return (self.cycles - start) as u32;
+        }
+        if self.is_halted {
+            // A pending interrupt ends the halt, even if it won't be handled.
+            if self.bus.pending_interrupts() == 0 {
+                self.tick();
+                return (self.cycles - start) as u32;
+            }
+            self.is_halted = false;
+            self.tick();
+        }
+
+        let ime_was_scheduled = self.ime_scheduled;
+        if self.ime && self.bus.pending_interrupts() != 0 {
+            self.handle_interrupt();
+        } else {
+            self.pc = self.fetch_and_execute();
+            // `DI` right after `EI` cancels enabling interrupts.
+            if ime_was_scheduled && self.ime_scheduled {
+                self.ime = true;
+                self.ime_scheduled = false;
+            }
+        }
+
+        // The CPU sits idle while a VRAM DMA copies data.
+        for _ in 0..self.bus.take_dma_stall_cycles() / 4 {
+            self.tick();
+        }
+        (self.cycles - start) as u32
+    }
+
+    /// Reads the instruction at the program counter and executes it. Returns the address of the
+    /// next instruction.
+    fn fetch_and_execute(&mut self) -> u16 {
+        let mut instruction_byte = self.read(self.pc);
+        if std::mem::take(&mut self.halt_bug) {
+            // Operands and the next instruction are read from one byte earlier than usual.
+            self.pc = self.pc.wrapping_sub(1);
+        }
         let is_prefixed = instruction_byte == PREFIX_BYTE;
         if is_prefixed {
-            instruction_byte = self.bus.read_byte(self.pc + 1);
+            // The prefixed instructions are executed as if they started at the second byte.
+            self.pc = self.pc.wrapping_add(1);
+            instruction_byte = self.read(self.pc);
         }
 
-        let next_pc =
-            if let Some(instruction) = Instruction::from_byte(instruction_byte, is_prefixed) {
-                self.execute(instruction)
-            } else {
-                let description = format!(
-                    "0x{}{instruction_byte:x}",
-                    if is_prefixed { "cb" } else { "" }
-                );
-                panic!("Unknown instruction found for: {}", description)
-            };
-        self.pc = next_pc;
+        match Instruction::from_byte(instruction_byte, is_prefixed) {
+            Some(instruction) => self.execute(instruction),
+            None => {
+                // Invalid opcodes hang the CPU.
+                self.is_locked = true;
+                self.pc
+            }
+        }
     }
 
-    /// Gets the value of an 8-bit register
-    fn get_r8_value(&self, target: TargetRegister8) -> u8 {
+    /// Pushes the program counter and jumps to the handler of the pending interrupt with the
+    /// highest priority. Takes 5 machine cycles.
+    fn handle_interrupt(&mut self) {
+        self.ime = false;
+        self.ime_scheduled = false;
+        self.tick();
+        self.tick();
+
+        let [lsb, msb] = self.pc.to_le_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, msb);
+        // The interrupt is only picked after the upper byte is pushed. If that write disables the
+        // interrupt in `IE`, no handler is called and execution continues at 0x0000.
+        let pending = self.bus.pending_interrupts();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, lsb);
+
+        self.pc = if pending == 0 {
+            0x0000
+        } else {
+            let interrupt = pending.trailing_zeros() as u8;
+            self.bus.acknowledge_interrupt(interrupt);
+            INTERRUPT_VECTOR_START + interrupt as u16 * 8
+        };
+        self.tick();
+    }
+
+    /// Lets a machine cycle pass without accessing memory.
+    fn tick(&mut self) {
+        self.bus.tick();
+        self.cycles += 4;
+    }
+
+    /// Reads a byte from memory, which takes a machine cycle.
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.bus.read(address)
+    }
+
+    /// Writes a byte to memory, which takes a machine cycle.
+    fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        self.bus.write(address, value);
+    }
+
+    /// Reads an 8-bit operand. Accessing `[HL]` or the byte after the instruction takes a
+    /// machine cycle.
+    fn read_r8(&mut self, target: TargetRegister8) -> u8 {
         match target {
             TargetRegister8::A => self.registers.a,
             TargetRegister8::B => self.registers.b,
@@ -65,19 +294,23 @@ impl Cpu {
             TargetRegister8::E => self.registers.e,
             TargetRegister8::H => self.registers.h,
             TargetRegister8::L => self.registers.l,
+            TargetRegister8::HlIndirect => self.read(self.registers.get_hl()),
+            TargetRegister8::D8 => self.read_next_byte(),
         }
     }
 
-    /// Gets a reference to an 8-bit register. Useful when the register needs to be written to.
-    fn get_r8_ref(&mut self, target: TargetRegister8) -> &mut u8 {
+    /// Writes an 8-bit operand. Writing to `[HL]` takes a machine cycle.
+    fn write_r8(&mut self, target: TargetRegister8, value: u8) {
         match target {
-            TargetRegister8::A => &mut self.registers.a,
-            TargetRegister8::B => &mut self.registers.b,
-            TargetRegister8::C => &mut self.registers.c,
-            TargetRegister8::D => &mut self.registers.d,
-            TargetRegister8::E => &mut self.registers.e,
-            TargetRegister8::H => &mut self.registers.h,
-            TargetRegister8::L => &mut self.registers.l,
+            TargetRegister8::A => self.registers.a = value,
+            TargetRegister8::B => self.registers.b = value,
+            TargetRegister8::C => self.registers.c = value,
+            TargetRegister8::D => self.registers.d = value,
+            TargetRegister8::E => self.registers.e = value,
+            TargetRegister8::H => self.registers.h = value,
+            TargetRegister8::L => self.registers.l = value,
+            TargetRegister8::HlIndirect => self.write(self.registers.get_hl(), value),
+            TargetRegister8::D8 => unreachable!("Immediate values can't be written to"),
         }
     }
 
@@ -87,17 +320,30 @@ impl Cpu {
             TargetRegister16::BC => self.registers.get_bc(),
             TargetRegister16::DE => self.registers.get_de(),
             TargetRegister16::HL => self.registers.get_hl(),
+            TargetRegister16::SP => self.sp,
+        }
+    }
+
+    /// Sets the value of an 16-bit register.
+    fn set_r16_value(&mut self, target: TargetRegister16, value: u16) {
+        match target {
+            TargetRegister16::BC => self.registers.set_bc(value),
+            TargetRegister16::DE => self.registers.set_de(value),
+            TargetRegister16::HL => self.registers.set_hl(value),
+            TargetRegister16::SP => self.sp = value,
         }
     }
 
     /// Reads the next byte in memory.
-    fn read_next_byte(&self) -> u8 {
-        self.bus.read_byte(self.pc.wrapping_add(1))
+    fn read_next_byte(&mut self) -> u8 {
+        self.read(self.pc.wrapping_add(1))
     }
 
     /// Reads the next two bytes in memory and combines them to a 16-bit value.
-    fn read_next_word(&self) -> u16 {
-        ((self.bus.read_byte(self.pc + 2) as u16) << 8) | (self.bus.read_byte(self.pc + 1) as u16)
+    fn read_next_word(&mut self) -> u16 {
+        let lsb = self.read(self.pc.wrapping_add(1)) as u16;
+        let msb = self.read(self.pc.wrapping_add(2)) as u16;
+        (msb << 8) | lsb
     }
 
     /// Gets the value associated with each [`Instruction`]s [JumpTest].
@@ -1,3 +1,6 @@
+use crate::cartridge::{CgbSupport, Header};
+use crate::model::Model;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter, impl_save_state};
 use std::ops::{Shl, Shr};
 
 /// The CPU registers of the Game Boy's CPU.
@@ -15,7 +18,82 @@ pub(super) struct Registers {
     pub(super) l: u8,
 }
 
+impl_save_state!(Registers {
+    a,
+    b,
+    c,
+    d,
+    e,
+    f,
+    h,
+    l,
+});
+
 impl Registers {
+    /// The values the given model's boot ROM leaves in the registers. Some of them depend on the
+    /// cartridge header, and on whether a CGB runs the game in DMG compatibility mode.
+    pub(super) fn after_boot(model: Model, header: Option<&Header>) -> Self {
+        // The flags are only left in a known state if the header checksum is zero.
+        let checksum_flags = match header {
+            Some(header) if header.header_checksum() == 0 => 0x80,
+            _ => 0xB0,
+        };
+        let cgb_game = header.is_some_and(|header| header.cgb_support() != CgbSupport::None);
+
+        match model {
+            Model::Dmg0 => Self::from_bytes([0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03]),
+            Model::Dmg => {
+                Self::from_bytes([0x01, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D])
+            }
+            Model::Mgb => {
+                Self::from_bytes([0xFF, checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D])
+            }
+            Model::Sgb => Self::from_bytes([0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60]),
+            Model::Sgb2 => Self::from_bytes([0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60]),
+            Model::Cgb | Model::Agb => {
+                let mut registers = if cgb_game {
+                    Self::from_bytes([0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D])
+                } else {
+                    // For DMG games, the boot ROM leaves behind some of the values it used to pick
+                    // the compatibility palettes.
+                    let b = match header {
+                        Some(header) if header.is_licensed_by_nintendo() => header.title_checksum(),
+                        _ => 0x00,
+                    };
+                    let [h, l] = if b == 0x43 || b == 0x58 {
+                        [0x99, 0x1A]
+                    } else {
+                        [0x00, 0x7C]
+                    };
+                    Self::from_bytes([0x11, 0x80, b, 0x00, 0x00, 0x08, h, l])
+                };
+                // The GBA's boot ROM ends with an `INC B`, which lets games detect it.
+                if model == Model::Agb {
+                    let b = registers.b;
+                    registers.b = b.wrapping_add(1);
+                    registers.f.zero = registers.b == 0;
+                    registers.f.half_carry = b & 0x0F == 0x0F;
+                }
+                registers
+            }
+        }
+    }
+
+    /// Creates the registers from their values in the order `A`, `F`, `B`, `C`, `D`, `E`, `H`
+    /// and `L`.
+    fn from_bytes([a, f, b, c, d, e, h, l]: [u8; 8]) -> Self {
+        Self {
+            a,
+            b,
+            c,
+            d,
+            e,
+            f: f.into(),
+            h,
+            l,
+        }
+    }
+
     fn get_16_bit_register(&self, high: u8, low: u8) -> u16 {
         ((high as u16) << 8) | (low as u16)
     }
@@ -103,8 +181,20 @@ impl From<u8> for FlagsRegister {
     }
 }
 
+impl SaveState for FlagsRegister {
+    fn save(&self, writer: &mut StateWriter) {
+        u8::from(*self).save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        let [byte] = reader.read_bytes()?;
+        *self = byte.into();
+        Ok(())
+    }
+}
+
 /// Index to address the individual bits 0-7 inside a register.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(super) struct U3(u8);
 
 impl U3 {
@@ -114,6 +204,10 @@ impl U3 {
     pub fn wrap(value: u8) -> Self {
         Self(value & Self::MAX)
     }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
 }
 
 /// Implement the `>>` operator for U3
@@ -165,6 +259,56 @@ mod tests {
         assert_eq!(result, output);
     }
 
+    fn header(cgb_flag: u8, title: &[u8], header_checksum: u8) -> Header {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x0143] = cgb_flag;
+        rom[0x014B] = 0x01;
+        rom[0x014D] = header_checksum;
+        Header::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn after_boot_dmg() {
+        let registers = Registers::after_boot(Model::Dmg, Some(&header(0x00, b"", 0x12)));
+        assert_eq!(registers.get_af(), 0x01B0);
+        assert_eq!(registers.get_bc(), 0x0013);
+        assert_eq!(registers.get_de(), 0x00D8);
+        assert_eq!(registers.get_hl(), 0x014D);
+
+        let registers = Registers::after_boot(Model::Dmg, Some(&header(0x00, b"", 0x00)));
+        assert_eq!(registers.get_af(), 0x0180);
+    }
+
+    #[test]
+    fn after_boot_identifies_model() {
+        let header = header(0x80, b"", 0x00);
+        let a = |model| Registers::after_boot(model, Some(&header)).a;
+        assert_eq!(a(Model::Dmg0), 0x01);
+        assert_eq!(a(Model::Mgb), 0xFF);
+        assert_eq!(a(Model::Sgb), 0x01);
+        assert_eq!(a(Model::Sgb2), 0xFF);
+        assert_eq!(a(Model::Cgb), 0x11);
+
+        let agb = Registers::after_boot(Model::Agb, Some(&header));
+        assert_eq!(agb.get_af(), 0x1100);
+        assert_eq!(agb.b, 0x01);
+    }
+
+    #[test]
+    fn after_boot_dmg_game_on_cgb() {
+        // "TETRIS" has a title checksum of 0xDB.
+        let tetris = header(0x00, b"TETRIS", 0x00);
+        let registers = Registers::after_boot(Model::Cgb, Some(&tetris));
+        assert_eq!(registers.get_af(), 0x1180);
+        assert_eq!(registers.get_bc(), 0xDB00);
+        assert_eq!(registers.get_de(), 0x0008);
+        assert_eq!(registers.get_hl(), 0x007C);
+
+        let registers = Registers::after_boot(Model::Agb, Some(&tetris));
+        assert_eq!(registers.get_bc(), 0xDC00);
+    }
+
     #[test]
     fn set_f_as_u8() {
         let mut registers = Registers::default();
@@ -0,0 +1,330 @@
+//! Runs the [SingleStepTests](https://github.com/SingleStepTests/sm83) for the SM83. There's a
+//! JSON file for every opcode, each with a thousand tests: The CPU starts in a random state on a
+//! flat 64 KiB bus, executes a single instruction, and then its registers, the memory and every
+//! machine cycle's bus activity are compared to the expected ones.
+//!
+//! The files are expected in `sm83/v1/` of the fixtures directory, see `tests/fixtures/README.md`.
+//! The test is ignored by default and fails if they're missing.
+
+use super::{Cpu, bus::Bus, registers::FlagsRegister};
+use json::Json;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+mod json;
+
+/// How many failures of an opcode are reported in detail.
+const MAX_REPORTED_FAILURES: usize = 3;
+
+/// What happened on the bus during one machine cycle.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Cycle {
+    Idle,
+    Read { address: u16, value: u8 },
+    Write { address: u16, value: u8 },
+}
+
+/// 64 KiB of RAM without any I/O registers, which records every machine cycle.
+struct FlatBus {
+    memory: Box<[u8; 0x10000]>,
+    cycles: Vec<Cycle>,
+}
+
+impl Default for FlatBus {
+    fn default() -> Self {
+        Self {
+            memory: Box::new([0; 0x10000]),
+            cycles: Vec::new(),
+        }
+    }
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.memory[address as usize];
+        *self.cycles.last_mut().unwrap() = Cycle::Read { address, value };
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+        *self.cycles.last_mut().unwrap() = Cycle::Write { address, value };
+    }
+
+    fn tick(&mut self) {
+        self.cycles.push(Cycle::Idle);
+    }
+}
+
+fn number(state: &Json, key: &str) -> Result<i64, String> {
+    state
+        .get(key)
+        .and_then(Json::as_number)
+        .ok_or_else(|| format!("`{key}` is missing"))
+}
+
+fn ram(state: &Json) -> Result<Vec<(u16, u8)>, String> {
+    let entries = state
+        .get("ram")
+        .and_then(Json::as_array)
+        .ok_or("`ram` is missing")?;
+    entries
+        .iter()
+        .map(|entry| match entry.as_array() {
+            Some([address, value]) => Ok((
+                address.as_number().ok_or("invalid address")? as u16,
+                value.as_number().ok_or("invalid value")? as u8,
+            )),
+            _ => Err("invalid RAM entry".to_string()),
+        })
+        .collect()
+}
+
+/// Parses a cycle like `[address, value, "r-m"]`. Address and value may be `null` on idle cycles.
+fn cycle(json: &Json) -> Result<Cycle, String> {
+    let Some([address, value, kind]) = json.as_array() else {
+        return Err("invalid cycle".to_string());
+    };
+    let address = address.as_number().unwrap_or_default() as u16;
+    let value = value.as_number().unwrap_or_default() as u8;
+    match kind.as_str().ok_or("invalid cycle")? {
+        kind if kind.starts_with('r') => Ok(Cycle::Read { address, value }),
+        kind if kind.contains('w') => Ok(Cycle::Write { address, value }),
+        _ => Ok(Cycle::Idle),
+    }
+}
+
+fn set_up(cpu: &mut Cpu<FlatBus>, initial: &Json) -> Result<(), String> {
+    let registers = &mut cpu.registers;
+    registers.a = number(initial, "a")? as u8;
+    registers.b = number(initial, "b")? as u8;
+    registers.c = number(initial, "c")? as u8;
+    registers.d = number(initial, "d")? as u8;
+    registers.e = number(initial, "e")? as u8;
+    registers.f = FlagsRegister::from(number(initial, "f")? as u8);
+    registers.h = number(initial, "h")? as u8;
+    registers.l = number(initial, "l")? as u8;
+    cpu.pc = number(initial, "pc")? as u16;
+    cpu.sp = number(initial, "sp")? as u16;
+    cpu.ime = number(initial, "ime").unwrap_or_default() != 0;
+    if let Ok(ie) = number(initial, "ie") {
+        cpu.bus.memory[0xFFFF] = ie as u8;
+    }
+    for (address, value) in ram(initial)? {
+        cpu.bus.memory[address as usize] = value;
+    }
+    Ok(())
+}
+
+/// Compares the CPU to the expected final state. Returns a line for each difference.
+fn differences(cpu: &Cpu<FlatBus>, test: &Json) -> Result<Vec<String>, String> {
+    let expected = test.get("final").ok_or("`final` is missing")?;
+    let mut differences = Vec::new();
+    let registers = cpu.registers();
+    let actual = [
+        ("a", registers.a as i64),
+        ("b", registers.b as i64),
+        ("c", registers.c as i64),
+        ("d", registers.d as i64),
+        ("e", registers.e as i64),
+        ("f", registers.f as i64),
+        ("h", registers.h as i64),
+        ("l", registers.l as i64),
+        ("pc", registers.pc as i64),
+        ("sp", registers.sp as i64),
+    ];
+    for (name, actual) in actual {
+        let expected = number(expected, name)?;
+        if actual != expected {
+            differences.push(format!(
+                "{name}: expected {expected:#04X}, got {actual:#04X}"
+            ));
+        }
+    }
+
+    // Older versions of the tests don't tell apart whether `EI` just scheduled enabling
+    // interrupts.
+    let (ime, ei) = match number(expected, "ei") {
+        Ok(ei) => (cpu.ime, Some((ei != 0, cpu.ime_scheduled))),
+        Err(_) => (cpu.ime || cpu.ime_scheduled, None),
+    };
+    if let Ok(expected) = number(expected, "ime")
+        && (expected != 0) != ime
+    {
+        differences.push(format!("ime: expected {expected}, got {}", ime as u8));
+    }
+    if let Some((expected, actual)) = ei
+        && expected != actual
+    {
+        differences.push(format!(
+            "ei: expected {}, got {}",
+            expected as u8, actual as u8
+        ));
+    }
+
+    for (address, expected) in ram(expected)? {
+        let actual = cpu.bus.memory[address as usize];
+        if actual != expected {
+            differences.push(format!(
+                "[{address:#06X}]: expected {expected:#04X}, got {actual:#04X}"
+            ));
+        }
+    }
+
+    let expected_cycles = test
+        .get("cycles")
+        .and_then(Json::as_array)
+        .ok_or("`cycles` is missing")?
+        .iter()
+        .map(cycle)
+        .collect::<Result<Vec<_>, _>>()?;
+    if expected_cycles != cpu.bus.cycles {
+        differences.push(format!(
+            "cycles: expected {expected_cycles:?}, got {:?}",
+            cpu.bus.cycles
+        ));
+    }
+    Ok(differences)
+}
+
+/// Runs a single test. Returns a description of what went wrong if it failed.
+fn run_test(test: &Json) -> Result<(), String> {
+    let name = test.get("name").and_then(Json::as_str).unwrap_or("?");
+    let mut cpu = Cpu::new(FlatBus::default());
+    set_up(&mut cpu, test.get("initial").ok_or("`initial` is missing")?)?;
+    cpu.step();
+    let differences = differences(&cpu, test)?;
+    if differences.is_empty() {
+        return Ok(());
+    }
+    Err(format!("{name}:\n  {}", differences.join("\n  ")))
+}
+
+/// Runs all tests of a file. Returns the amount of tests and the failures.
+fn run_file(text: &str) -> Result<(usize, Vec<String>), String> {
+    let json = Json::parse(text)?;
+    let tests = json.as_array().ok_or("expected an array of tests")?;
+    let failures = tests
+        .iter()
+        .filter_map(|test| run_test(test).err())
+        .collect();
+    Ok((tests.len(), failures))
+}
+
+fn tests_dir() -> PathBuf {
+    let fixtures = match std::env::var_os("GAMEBOY_FIXTURES") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"),
+    };
+    fixtures.join("sm83/v1")
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests, see tests/fixtures/README.md"]
+fn single_step_tests() {
+    let dir = tests_dir();
+    let entries = std::fs::read_dir(&dir).unwrap_or_else(|error| {
+        panic!(
+            "could not read {}: {error}; see tests/fixtures/README.md, or set GAMEBOY_FIXTURES",
+            dir.display()
+        )
+    });
+    let mut paths: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no tests in {}", dir.display());
+
+    let results: Vec<_> = std::thread::scope(|scope| {
+        let threads: Vec<_> = paths
+            .iter()
+            .map(|path| {
+                scope.spawn(move || {
+                    let text = std::fs::read_to_string(path).unwrap_or_else(|error| {
+                        panic!("could not read {}: {error}", path.display())
+                    });
+                    run_file(&text)
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    });
+
+    let mut report = String::new();
+    let mut failed_files = 0;
+    for (path, result) in paths.iter().zip(results) {
+        let name = path.file_name().unwrap().to_string_lossy();
+        match result {
+            Ok((_, failures)) if failures.is_empty() => continue,
+            Ok((total, failures)) => {
+                writeln!(report, "{name}: {} of {total} failed", failures.len()).unwrap();
+                for failure in failures.iter().take(MAX_REPORTED_FAILURES) {
+                    writeln!(report, "{failure}").unwrap();
+                }
+            }
+            Err(error) => writeln!(report, "{name}: {error}").unwrap(),
+        }
+        failed_files += 1;
+    }
+    assert!(
+        failed_files == 0,
+        "{failed_files} of {} opcodes failed:\n{report}",
+        paths.len()
+    );
+}
+
+/// Tests in the format of the test files: `NOP`, `LD [HL],A`, and `PUSH BC`.
+const SAMPLE: &str = r#"[
+    {
+        "name": "00 0000",
+        "initial": {"pc": 256, "sp": 65534, "a": 1, "b": 2, "c": 3, "d": 4, "e": 5, "f": 176,
+            "h": 6, "l": 7, "ime": 0, "ie": 0, "ram": [[256, 0]]},
+        "final": {"pc": 257, "sp": 65534, "a": 1, "b": 2, "c": 3, "d": 4, "e": 5, "f": 176,
+            "h": 6, "l": 7, "ime": 0, "ie": 0, "ram": [[256, 0]]},
+        "cycles": [[256, 0, "r-m"]]
+    },
+    {
+        "name": "77 0000",
+        "initial": {"pc": 49152, "sp": 0, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0,
+            "h": 192, "l": 16, "ime": 1, "ram": [[49152, 119], [49168, 0]]},
+        "final": {"pc": 49153, "sp": 0, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0,
+            "h": 192, "l": 16, "ime": 1, "ram": [[49152, 119], [49168, 66]]},
+        "cycles": [[49152, 119, "r-m"], [49168, 66, "-wm"]]
+    },
+    {
+        "name": "c5 0000",
+        "initial": {"pc": 512, "sp": 53248, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0, "f": 0,
+            "h": 0, "l": 0, "ime": 0, "ram": [[512, 197]]},
+        "final": {"pc": 513, "sp": 53246, "a": 0, "b": 18, "c": 52, "d": 0, "e": 0, "f": 0,
+            "h": 0, "l": 0, "ime": 0, "ram": [[512, 197], [53247, 18], [53246, 52]]},
+        "cycles": [[512, 197, "r-m"], [null, null, "---"], [53247, 18, "-wm"],
+            [53246, 52, "-wm"]]
+    }
+]"#;
+
+#[test]
+fn sample() {
+    assert_eq!(run_file(SAMPLE), Ok((3, Vec::new())));
+}
+
+#[test]
+fn sample_with_mismatches() {
+    let sample = SAMPLE
+        .replace(
+            r#""h": 192, "l": 16, "ime": 1, "ram": [[49152, 119], [49168, 66]]"#,
+            r#""h": 192, "l": 16, "ime": 1, "ram": [[49152, 119], [49168, 67]]"#,
+        )
+        .replace(r#"[null, null, "---"]"#, r#"[53247, 18, "-wm"]"#);
+    let (total, failures) = run_file(&sample).unwrap();
+    assert_eq!(total, 3);
+    assert_eq!(failures.len(), 2);
+    assert!(failures[0].starts_with("77 0000:\n  [0xC010]: expected 0x43, got 0x42"));
+    assert!(failures[1].starts_with("c5 0000:\n  cycles: expected"));
+}
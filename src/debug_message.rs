@@ -0,0 +1,150 @@
+//! Homebrew and test ROMs print messages to the emulator's log with the convention of BGB and
+//! Emulicious: `LD D, D`, followed by a jump over a signature and the text of the message.
+//!
+//! ```text
+//!     ld d, d
+//!     jr .end
+//!     dw $6464, $0000
+//!     db "A is %A%"
+//! .end:
+//! ```
+//!
+//! The names of registers in percent signs are replaced by their values in hexadecimal, and
+//! `%LY%` by the current scanline. Other text in percent signs is kept as it is.
+
+use crate::cpu::CpuRegisters;
+
+/// The opcode of `LD D, D`, which starts a debug message.
+pub(crate) const DEBUG_MESSAGE_OPCODE: u8 = 0x52;
+/// The opcode of `LD B, B`, which is used as a software breakpoint.
+pub(crate) const BREAKPOINT_OPCODE: u8 = 0x40;
+
+const JR_OPCODE: u8 = 0x18;
+/// The bytes between the jump and the text.
+const SIGNATURE: [u8; 4] = [0x64, 0x64, 0x00, 0x00];
+const LCD_Y_REGISTER: u16 = 0xFF44;
+
+/// Reads the message of the `LD D, D` at PC, or returns [`None`] if the instruction isn't followed
+/// by a message.
+pub(crate) fn read(registers: &CpuRegisters, peek: impl Fn(u16) -> u8) -> Option<String> {
+    let pc = registers.pc;
+    if peek(pc.wrapping_add(1)) != JR_OPCODE {
+        return None;
+    }
+    // The jump is relative to the signature that follows it.
+    let offset = usize::try_from(peek(pc.wrapping_add(2)) as i8).ok()?;
+    let length = offset.checked_sub(SIGNATURE.len())?;
+    let signature = [3, 4, 5, 6].map(|offset| peek(pc.wrapping_add(offset)));
+    if signature != SIGNATURE {
+        return None;
+    }
+    let text: Vec<u8> = (0..length as u16)
+        .map(|offset| peek(pc.wrapping_add(7).wrapping_add(offset)))
+        .collect();
+    let text = String::from_utf8_lossy(&text);
+    Some(expand(&text, registers, &peek))
+}
+
+/// Replaces the placeholders in percent signs.
+fn expand(text: &str, registers: &CpuRegisters, peek: &impl Fn(u16) -> u8) -> String {
+    let mut message = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('%') {
+        message.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('%') else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = &after[..end];
+        match placeholder(name, registers, peek) {
+            Some(value) => {
+                message.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            // The closing percent sign might open the next placeholder.
+            None => {
+                message.push('%');
+                message.push_str(name);
+                rest = &after[end..];
+            }
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+fn placeholder(name: &str, registers: &CpuRegisters, peek: &impl Fn(u16) -> u8) -> Option<String> {
+    let byte = |value: u8| Some(format!("{value:02X}"));
+    let word = |high: u8, low: u8| Some(format!("{:04X}", u16::from_be_bytes([high, low])));
+    let r = registers;
+    match name.to_ascii_uppercase().as_str() {
+        "A" => byte(r.a),
+        "F" => byte(r.f),
+        "B" => byte(r.b),
+        "C" => byte(r.c),
+        "D" => byte(r.d),
+        "E" => byte(r.e),
+        "H" => byte(r.h),
+        "L" => byte(r.l),
+        "AF" => word(r.a, r.f),
+        "BC" => word(r.b, r.c),
+        "DE" => word(r.d, r.e),
+        "HL" => word(r.h, r.l),
+        "SP" => Some(format!("{:04X}", r.sp)),
+        "PC" => Some(format!("{:04X}", r.pc)),
+        "LY" => byte(peek(LCD_Y_REGISTER)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    fn message(source: &str, registers: CpuRegisters) -> Option<String> {
+        let program = asm!(0xC000, source);
+        read(&registers, |address| {
+            program
+                .get(address.wrapping_sub(0xC000) as usize)
+                .copied()
+                .unwrap_or(0)
+        })
+    }
+
+    fn registers() -> CpuRegisters {
+        CpuRegisters {
+            a: 0x12,
+            h: 0xC1,
+            l: 0x23,
+            pc: 0xC000,
+            ..CpuRegisters::default()
+        }
+    }
+
+    #[test]
+    fn text_and_placeholders() {
+        let source = "
+                ld d, d
+                jr end
+                dw $6464, $0000
+                db \"A=%A% HL=%hl% 100%% %X%LY=%LY%\"
+            end:
+        ";
+        assert_eq!(
+            message(source, registers()).as_deref(),
+            Some("A=12 HL=C123 100%% %X%LY=00")
+        );
+    }
+
+    #[test]
+    fn other_instructions() {
+        // Without the signature, LD D, D is just an instruction.
+        let source = "ld d, d\njr end\ndw $6464, $0001\ndb \"Hi\"\nend:";
+        assert_eq!(message(source, registers()), None);
+        assert_eq!(message("ld d, d\nnop", registers()), None);
+        assert_eq!(message("ld d, d\njr end\nend:", registers()), None);
+        assert_eq!(message("start:\nld d, d\njr start", registers()), None);
+    }
+}
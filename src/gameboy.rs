@@ -0,0 +1,876 @@
+//! Ties all components together into a complete console. The CPU drives the rest of the system:
+//! Every memory access it makes advances the PPU, timer, APU, serial port and DMA by one machine
+//! cycle, so running the console means running the CPU.
+
+use crate::cartridge::{Header, LoadRomError};
+use crate::cgb::compatibility::{ManualPalette, PaletteSelection};
+use crate::cpu::{Cpu, CpuRegisters, Disassembly, disassemble_with_names};
+use crate::debug_message::{self, BREAKPOINT_OPCODE, DEBUG_MESSAGE_OPCODE};
+use crate::gpu::palette::Color;
+use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::image::{Image, crc32};
+use crate::joypad::Button;
+use crate::memory_bus::MemoryBus;
+use crate::model::Model;
+use crate::save_state::{
+    self, LoadStateError, SaveState, StateReader, StateWriter, impl_save_state,
+};
+use crate::sgb::{BORDER_HEIGHT, BORDER_WIDTH};
+use crate::symbols::Symbols;
+use crate::watchpoint::{Watchpoint, WatchpointHit};
+use std::io::{self, Write};
+
+/// The CPU cycles it takes the PPU to draw a frame at normal speed: 154 scanlines of 456 dots.
+pub const CYCLES_PER_FRAME: u32 = 70224;
+
+pub type Frame = [Color; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+/// What the console produced while running.
+pub struct Output<'a> {
+    /// The last complete frame.
+    pub frame: &'a Frame,
+    /// Interleaved stereo samples, left first.
+    pub audio: Vec<f32>,
+}
+
+pub struct GameBoy {
+    cpu: Cpu<MemoryBus>,
+    model: Model,
+    /// The CRC-32 of the cartridge's ROM, which save states are checked against.
+    rom_checksum: u32,
+    /// How a DMG game was colorized at power-on on a CGB.
+    palette_selection: PaletteSelection,
+    /// The last complete frame. The PPU's framebuffer is only copied once a frame is finished, so
+    /// half-drawn frames are never shown.
+    frame: Box<Frame>,
+    /// The amount of frames finished since the cartridge was loaded.
+    frame_count: u64,
+    /// The dots that have passed since the last frame was finished. While the LCD is off, a frame
+    /// is counted whenever drawing one would have finished, so time keeps advancing in frames.
+    frame_dots: u32,
+    /// Where a line is written before every instruction, see [`Self::set_trace_log`].
+    trace_log: Option<Box<dyn Write + Send>>,
+    /// The error that stopped the trace log.
+    trace_error: Option<io::Error>,
+    /// The names of the game's addresses, used when disassembling and tracing.
+    symbols: Symbols,
+    /// Whether `LD B, B` pauses running, see [`Self::set_software_breakpoints`].
+    software_breakpoints: bool,
+    /// Whether running paused before an `LD B, B`, which is executed by the next step.
+    paused_at_breakpoint: bool,
+    /// The debug messages printed since they were last taken, or [`None`] if they aren't read.
+    debug_messages: Option<Vec<String>>,
+    /// Whether the console is still in the state it was created in: It hasn't run, and neither
+    /// its memory, registers nor state were changed from outside.
+    at_power_on: bool,
+}
+
+impl_save_state!(GameBoy {
+    cpu,
+    model,
+    frame,
+    frame_count,
+    frame_dots,
+});
+
+impl GameBoy {
+    /// Creates a console for the cartridge, picking the model the game is meant for. Fails if the
+    /// cartridge contains hardware that isn't emulated.
+    pub fn new(rom: &[u8]) -> Result<Self, LoadRomError> {
+        Self::with_model(rom, Model::detect(Header::parse(rom).as_ref()))
+    }
+
+    /// Creates the given console with the cartridge inserted. Fails if the cartridge contains
+    /// hardware that isn't emulated.
+    pub fn with_model(rom: &[u8], model: Model) -> Result<Self, LoadRomError> {
+        Self::with_palette_selection(rom, model, PaletteSelection::Automatic)
+    }
+
+    /// Creates the given console with the cartridge inserted, like [`Self::with_model`], but
+    /// colorizes a DMG game with the given palettes on a CGB, as if the user held the buttons
+    /// during the boot animation. The palettes are ignored for CGB games and on other models.
+    pub fn with_palette(
+        rom: &[u8],
+        model: Model,
+        palette: ManualPalette,
+    ) -> Result<Self, LoadRomError> {
+        Self::with_palette_selection(rom, model, PaletteSelection::Manual(palette))
+    }
+
+    pub(crate) fn with_palette_selection(
+        rom: &[u8],
+        model: Model,
+        palette_selection: PaletteSelection,
+    ) -> Result<Self, LoadRomError> {
+        let mut cpu = Cpu::default();
+        cpu.load_rom(rom, model, palette_selection)?;
+        Ok(Self {
+            cpu,
+            model,
+            rom_checksum: crc32(rom),
+            palette_selection,
+            frame: Box::new([Color::default(); SCREEN_WIDTH * SCREEN_HEIGHT]),
+            frame_count: 0,
+            frame_dots: 0,
+            trace_log: None,
+            trace_error: None,
+            symbols: Symbols::default(),
+            software_breakpoints: false,
+            paused_at_breakpoint: false,
+            debug_messages: None,
+            at_power_on: true,
+        })
+    }
+
+    /// Which hardware is emulated.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// The CRC-32 of the cartridge's ROM.
+    pub fn rom_checksum(&self) -> u32 {
+        self.rom_checksum
+    }
+
+    /// How a DMG game was colorized at power-on on a CGB.
+    pub(crate) fn palette_selection(&self) -> PaletteSelection {
+        self.palette_selection
+    }
+
+    /// Whether the console is still in the state it was created in. Setting the buttons and the
+    /// real-time clock doesn't count as a change.
+    pub(crate) fn is_at_power_on(&self) -> bool {
+        self.at_power_on
+    }
+
+    /// The amount of frames finished since the cartridge was loaded, including the ones that
+    /// passed while the LCD was off.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The last complete frame.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// The last complete frame as an image, e.g. to save it as a screenshot with
+    /// [`Image::encode_png`].
+    pub fn screenshot(&self) -> Image {
+        Image::from_frame(&self.frame)
+    }
+
+    /// The values of the CPU's registers.
+    pub fn registers(&self) -> CpuRegisters {
+        self.cpu.registers()
+    }
+
+    /// Overwrites the CPU's registers, e.g. from a debugger.
+    pub fn set_registers(&mut self, registers: CpuRegisters) {
+        self.cpu.set_registers(registers);
+        self.at_power_on = false;
+    }
+
+    /// Executes a single instruction, or calls an interrupt handler instead. Returns the amount of
+    /// CPU cycles that have passed, which is 0 if running [paused](Self::is_paused_at_breakpoint)
+    /// at a software breakpoint instead.
+    pub fn step_instruction(&mut self) -> u32 {
+        if self.cpu.executes_instruction_next() {
+            let pc = self.cpu.registers().pc;
+            let opcode = self.peek(pc);
+            if self.software_breakpoints
+                && opcode == BREAKPOINT_OPCODE
+                && !self.paused_at_breakpoint
+            {
+                self.paused_at_breakpoint = true;
+                return 0;
+            }
+            if opcode == DEBUG_MESSAGE_OPCODE
+                && let Some(messages) = &mut self.debug_messages
+                && let Some(message) = debug_message::read(&self.cpu.registers(), |address| {
+                    self.cpu.bus().read_byte(address)
+                })
+            {
+                messages.push(message);
+            }
+            if self.trace_log.is_some() {
+                self.write_trace_line();
+            }
+        }
+        self.paused_at_breakpoint = false;
+        self.at_power_on = false;
+        let cycles = self.cpu.step();
+        let bus = self.cpu.bus_mut();
+        self.frame_dots += if bus.is_double_speed() {
+            cycles / 2
+        } else {
+            cycles
+        };
+        if bus.take_frame_finished() {
+            let bus = self.cpu.bus();
+            match bus.sgb() {
+                Some(sgb) => self.frame = sgb.screen(),
+                None => self.frame.copy_from_slice(bus.framebuffer()),
+            }
+            self.frame_count += 1;
+            self.frame_dots = 0;
+        } else if self.frame_dots >= CYCLES_PER_FRAME {
+            self.frame_count += 1;
+            self.frame_dots -= CYCLES_PER_FRAME;
+        }
+        cycles
+    }
+
+    fn write_trace_line(&mut self) {
+        let mut line = trace_line(&self.cpu);
+        if let Some(location) = self.describe_address(self.cpu.registers().pc) {
+            line.push_str(&format!(" ({location})"));
+        }
+        let Some(log) = &mut self.trace_log else {
+            return;
+        };
+        if let Err(error) = writeln!(log, "{line}") {
+            self.trace_error = Some(error);
+            self.set_trace_log(None);
+        }
+    }
+
+    /// Runs until the next frame is finished, or until running pauses at a software breakpoint.
+    pub fn run_frame(&mut self) -> Output<'_> {
+        let start = self.frame_count;
+        while self.frame_count == start {
+            self.step_instruction();
+            if self.paused_at_breakpoint {
+                break;
+            }
+        }
+        self.output()
+    }
+
+    /// Runs for at least the given amount of CPU cycles, or until running pauses at a software
+    /// breakpoint. Instructions are never interrupted, so a few more cycles may pass.
+    pub fn run_cycles(&mut self, cycles: u32) -> Output<'_> {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            elapsed += self.step_instruction();
+            if self.paused_at_breakpoint {
+                break;
+            }
+        }
+        self.output()
+    }
+
+    fn output(&mut self) -> Output<'_> {
+        Output {
+            audio: self.cpu.bus_mut().take_audio_samples(),
+            frame: &self.frame,
+        }
+    }
+
+    /// The last complete frame surrounded by the SGB's border, if the game uses the SGB's
+    /// functions.
+    pub fn border_frame(&self) -> Option<Box<[Color; BORDER_WIDTH * BORDER_HEIGHT]>> {
+        self.cpu.bus().sgb().map(|sgb| sgb.screen_with_border())
+    }
+
+    /// Changes the rate at which audio samples are generated.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.bus_mut().set_sample_rate(sample_rate);
+    }
+
+    /// Presses or releases a button.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.cpu.bus_mut().set_button(button, pressed);
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.cpu.bus().is_pressed(button)
+    }
+
+    /// The buttons that are pressed, one bit per button in the order of [`Button::ALL`].
+    pub(crate) fn pressed_buttons(&self) -> u8 {
+        Button::ALL
+            .iter()
+            .enumerate()
+            .filter(|(_, button)| self.is_pressed(**button))
+            .fold(0, |buttons, (index, _)| buttons | 1 << index)
+    }
+
+    /// Presses exactly the buttons in the bit mask of [`Self::pressed_buttons`].
+    pub(crate) fn set_pressed_buttons(&mut self, buttons: u8) {
+        for (index, button) in Button::ALL.into_iter().enumerate() {
+            self.set_button(button, buttons & 1 << index != 0);
+        }
+    }
+
+    /// Everything the game has sent through the serial port.
+    pub fn serial_output(&self) -> &[u8] {
+        self.cpu.bus().serial_output()
+    }
+
+    /// The contents of the cartridge RAM, which games with a battery use to save progress.
+    pub fn save_ram(&self) -> &[u8] {
+        self.cpu.bus().cartridge_ram()
+    }
+
+    /// Restores the cartridge RAM, e.g. from a file written with the contents of
+    /// [`Self::save_ram`]. Data that doesn't fit into the RAM is ignored.
+    pub fn load_save_ram(&mut self, data: &[u8]) {
+        let ram = self.cpu.bus_mut().cartridge_ram_mut();
+        let length = data.len().min(ram.len());
+        ram[..length].copy_from_slice(&data[..length]);
+        self.at_power_on = false;
+    }
+
+    /// Sets the real-time clock of an MBC3 cartridge to the given amount of seconds, e.g. to the
+    /// host's time of day before the game starts. Afterwards, the clock only advances with the
+    /// emulated time. Cartridges without a clock ignore it.
+    pub fn set_rtc_seconds(&mut self, seconds: u64) {
+        self.cpu.bus_mut().set_rtc_seconds(seconds);
+    }
+
+    /// The time the real-time clock shows, in seconds.
+    pub fn rtc_seconds(&self) -> u64 {
+        self.cpu.bus().rtc_seconds()
+    }
+
+    /// Captures the state of the whole console. The cartridge's ROM isn't included, so the state
+    /// can only be loaded together with the same cartridge, which is checked by its checksum.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        save_state::write_header(&mut writer, self.rom_checksum);
+        self.save(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Restores a state captured with [`Self::save_state`]. States of other ROMs or of
+    /// incompatible versions of the emulator are rejected. If the state can't be loaded, the
+    /// console is left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), LoadStateError> {
+        let mut reader = StateReader::new(data);
+        save_state::read_header(&mut reader, self.rom_checksum)?;
+        let mut previous = StateWriter::default();
+        self.save(&mut previous);
+        let result = self.load(&mut reader).and_then(|()| reader.finish());
+        match result {
+            Ok(()) => self.at_power_on = false,
+            Err(_) => self
+                .load(&mut StateReader::new(&previous.into_bytes()))
+                .expect("the previous state can always be restored"),
+        }
+        result
+    }
+
+    /// A checksum of the console's state, without the header of [`Self::save_state`]. Consoles
+    /// that ran the same frames with the same buttons from the same state end up with the same
+    /// checksum.
+    pub fn state_checksum(&self) -> u32 {
+        let mut writer = StateWriter::default();
+        self.save(&mut writer);
+        crc32(&writer.into_bytes())
+    }
+
+    /// Reads a byte from memory the way the CPU would, but without letting any time pass.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.cpu.bus().read_byte(address)
+    }
+
+    /// Writes a byte to memory the way the CPU would, but without letting any time pass. Writes
+    /// to I/O registers have the same effects as usual.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.cpu.bus_mut().write_byte(address, value);
+        self.at_power_on = false;
+    }
+
+    /// Starts writing a line before every instruction in the format of
+    /// [Gameboy Doctor](https://github.com/robert/gameboy-doctor), with the registers and the
+    /// four bytes at the program counter:
+    /// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`. While the log
+    /// is written, LY always reads as 0x90 like Gameboy Doctor expects, so games waiting for
+    /// VBlank still run. If there are [symbols](Self::set_symbols), the location of the
+    /// instruction is added at the end, like `(Main+12)`.
+    ///
+    /// [`None`] stops the log. The previous log is returned, e.g. to flush it.
+    pub fn set_trace_log(
+        &mut self,
+        log: Option<Box<dyn Write + Send>>,
+    ) -> Option<Box<dyn Write + Send>> {
+        self.cpu.bus_mut().set_stub_ly(log.is_some());
+        std::mem::replace(&mut self.trace_log, log)
+    }
+
+    /// The error that stopped the trace log, if writing to it failed.
+    pub fn take_trace_error(&mut self) -> Option<io::Error> {
+        self.trace_error.take()
+    }
+
+    /// Which ROM bank is mapped at the address, or [`None`] if the address isn't in the ROM.
+    pub fn rom_bank(&self, address: u16) -> Option<u16> {
+        self.cpu.bus().rom_bank(address)
+    }
+
+    /// Replaces the watchpoints. The CPU's accesses they match are collected until they are taken
+    /// with [`Self::take_watchpoint_hits`].
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.cpu.bus_mut().set_watchpoints(watchpoints);
+    }
+
+    /// The accesses that matched a watchpoint since the last call, in the order they happened.
+    pub fn take_watchpoint_hits(&mut self) -> Vec<WatchpointHit> {
+        self.cpu.bus_mut().take_watchpoint_hits()
+    }
+
+    /// Makes `LD B, B` a software breakpoint: Running pauses before the instruction, and the next
+    /// step executes it. Test ROMs and homebrew use it to stop in an emulator's debugger.
+    pub fn set_software_breakpoints(&mut self, enabled: bool) {
+        self.software_breakpoints = enabled;
+        self.paused_at_breakpoint &= enabled;
+    }
+
+    /// Whether running paused at a software breakpoint, with PC at the `LD B, B`.
+    pub fn is_paused_at_breakpoint(&self) -> bool {
+        self.paused_at_breakpoint
+    }
+
+    /// Collects the debug messages that the game prints with `LD D, D` until they are taken with
+    /// [`Self::take_debug_messages`], in the format of BGB and Emulicious. Placeholders like `%A%`
+    /// are replaced by the values of the registers.
+    pub fn set_debug_messages(&mut self, enabled: bool) {
+        self.debug_messages = enabled.then(Vec::new);
+    }
+
+    /// The debug messages printed since the last call, in the order they were printed.
+    pub fn take_debug_messages(&mut self) -> Vec<String> {
+        self.debug_messages
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Names addresses in disassemblies, trace logs and [`Self::describe_address`].
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Describes an address by the closest symbol before it, like `Main+12`. Symbols in ROM
+    /// only match if their bank is mapped.
+    pub fn describe_address(&self, address: u16) -> Option<String> {
+        self.symbols.describe(address, self.rom_bank(address))
+    }
+
+    /// The name of the symbol at the address, if there is one. Symbols in ROM only match if
+    /// their bank is mapped.
+    pub fn symbol_at(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .names_at(address, self.rom_bank(address))
+            .next()
+    }
+
+    /// Decodes the instruction at the given address, as the memory is currently mapped.
+    /// Addresses in the operands are replaced by the names of their symbols.
+    pub fn disassemble(&self, address: u16) -> Disassembly {
+        disassemble_with_names(
+            address,
+            |address| self.peek(address),
+            |address| self.symbol_at(address),
+        )
+    }
+}
+
+/// Formats the registers and the bytes at the program counter for the trace log.
+fn trace_line(cpu: &Cpu<MemoryBus>) -> String {
+    let registers = cpu.registers();
+    let bus = cpu.bus();
+    let pc = registers.pc;
+    let memory = [0, 1, 2, 3].map(|offset| bus.read_byte(pc.wrapping_add(offset)));
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} \
+         PC:{pc:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        registers.a,
+        registers.f,
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+        registers.sp,
+        memory[0],
+        memory[1],
+        memory[2],
+        memory[3],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::DEFAULT_SAMPLE_RATE;
+    use crate::assembler::rom_with_program;
+    use crate::watchpoint::AccessKind;
+
+    #[test]
+    fn run_frame_outputs_frame_and_audio() {
+        // JR -2
+        let mut gameboy =
+            GameBoy::with_model(&rom_with_program(&[0x18, 0xFE]), Model::Dmg).unwrap();
+        let output = gameboy.run_frame();
+        let expected_samples = 2 * DEFAULT_SAMPLE_RATE as usize * CYCLES_PER_FRAME as usize
+            / Model::Dmg.clock_rate() as usize;
+        // The first frame ends early, as the boot ROM leaves the PPU in the middle of one.
+        assert!(output.audio.len() <= expected_samples + 2);
+        assert!(!output.audio.is_empty());
+        assert_eq!(gameboy.frame_count(), 1);
+
+        let output = gameboy.run_frame();
+        assert!(output.audio.len().abs_diff(expected_samples) <= 2);
+        assert_eq!(gameboy.frame_count(), 2);
+    }
+
+    #[test]
+    fn run_cycles() {
+        let mut gameboy =
+            GameBoy::with_model(&rom_with_program(&[0x18, 0xFE]), Model::Dmg).unwrap();
+        gameboy.set_sample_rate(1000);
+        let output = gameboy.run_cycles(Model::Dmg.clock_rate());
+        assert!(output.audio.len().abs_diff(2000) <= 2);
+        assert_eq!(gameboy.frame_count(), 59);
+    }
+
+    #[test]
+    fn frames_pass_while_lcd_is_off() {
+        let program = [
+            0xAF, // XOR A
+            0xE0, 0x40, // LDH [LCDC], A
+            0x18, 0xFE, // JR -2
+        ];
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.run_cycles(CYCLES_PER_FRAME * 3);
+        assert_eq!(gameboy.frame_count(), 3);
+    }
+
+    #[test]
+    fn serial_output() {
+        let program = [
+            0x3E, b'H', // LD A, 'H'
+            0xE0, 0x01, // LDH [SB], A
+            0x3E, 0x81, // LD A, 0x81
+            0xE0, 0x02, // LDH [SC], A
+            0x18, 0xFE, // JR -2
+        ];
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.run_frame();
+        assert_eq!(gameboy.serial_output(), b"H");
+    }
+
+    /// Counts up in `A` and writes it to the background palette and work RAM every iteration, so
+    /// the state changes constantly.
+    fn counter_rom() -> Vec<u8> {
+        rom_with_program(&[
+            0x3C, // INC A
+            0xE0, 0x47, // LDH [BGP], A
+            0xEA, 0x00, 0xC0, // LD [0xC000], A
+            0x18, 0xF8, // JR -8
+        ])
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut gameboy = GameBoy::with_model(&counter_rom(), Model::Cgb).unwrap();
+        gameboy.run_frame();
+        let state = gameboy.save_state();
+        gameboy.run_frame();
+        let expected_frame = gameboy.frame().to_vec();
+        let expected_state = gameboy.save_state();
+
+        gameboy.run_frame();
+        gameboy.load_state(&state).unwrap();
+        gameboy.run_frame();
+        assert_eq!(gameboy.frame().to_vec(), expected_frame);
+        assert_eq!(gameboy.save_state(), expected_state);
+    }
+
+    #[test]
+    fn invalid_state_keeps_previous_state() {
+        let mut gameboy = GameBoy::with_model(&counter_rom(), Model::Dmg).unwrap();
+        gameboy.run_frame();
+        let state = gameboy.save_state();
+
+        let result = gameboy.load_state(&state[..state.len() / 2]);
+        assert_eq!(result, Err(LoadStateError::UnexpectedEnd));
+        assert_eq!(gameboy.save_state(), state);
+
+        let mut longer = state.clone();
+        longer.push(0);
+        assert_eq!(
+            gameboy.load_state(&longer),
+            Err(LoadStateError::TrailingData)
+        );
+        assert_eq!(gameboy.save_state(), state);
+    }
+
+    #[test]
+    fn state_of_another_rom() {
+        let gameboy = GameBoy::with_model(&counter_rom(), Model::Dmg).unwrap();
+        let state = gameboy.save_state();
+        let mut rom = counter_rom();
+        rom[0x0200] = 1;
+        let mut other = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        let result = other.load_state(&state);
+        assert!(matches!(result, Err(LoadStateError::WrongRom { .. })));
+        assert_eq!(
+            other.load_state(b"not a state"),
+            Err(LoadStateError::NotASaveState)
+        );
+    }
+
+    /// Collects what is written to it, while the console owns it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_log() {
+        let program = crate::asm!(0x0100, "ldh a, [$44]\nld b, a\nloop: jr loop");
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        let buffer = SharedBuffer::default();
+        gameboy.set_trace_log(Some(Box::new(buffer.clone())));
+        for _ in 0..4 {
+            gameboy.step_instruction();
+        }
+        assert!(gameboy.set_trace_log(None).is_some());
+        gameboy.step_instruction();
+
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            log.lines().collect::<Vec<_>>(),
+            [
+                "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:F0,44,47,18",
+                "A:90 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:47,18,FE,00",
+                "A:90 F:80 B:90 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103 PCMEM:18,FE,00,00",
+                "A:90 F:80 B:90 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103 PCMEM:18,FE,00,00",
+            ]
+        );
+        assert!(gameboy.take_trace_error().is_none());
+    }
+
+    #[test]
+    fn watchpoints() {
+        let program = crate::asm!(0x0100, "ld a, [$C000]\nld [$C001], a\nld [$D000], a");
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.poke(0xC000, 0x42);
+        gameboy.set_watchpoints(vec![
+            Watchpoint {
+                range: 0xC000..=0xC0FF,
+                read: true,
+                write: true,
+            },
+            Watchpoint {
+                range: 0xD000..=0xD000,
+                read: true,
+                write: false,
+            },
+        ]);
+        for _ in 0..3 {
+            gameboy.step_instruction();
+        }
+        assert_eq!(
+            gameboy.take_watchpoint_hits(),
+            [
+                WatchpointHit {
+                    address: 0xC000,
+                    value: 0x42,
+                    kind: AccessKind::Read,
+                },
+                WatchpointHit {
+                    address: 0xC001,
+                    value: 0x42,
+                    kind: AccessKind::Write,
+                },
+            ]
+        );
+        assert!(gameboy.take_watchpoint_hits().is_empty());
+    }
+
+    #[test]
+    fn symbols() {
+        let program = crate::asm!(
+            0x0100,
+            "call $0150\nld [$C000], a\nldh a, [$44]\nldh [$40], a\njr $0100"
+        );
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.set_symbols(
+            Symbols::parse("00:0100 Start\n00:0150 Main\n00:c000 wCounter\n00:ff40 rLCDC").unwrap(),
+        );
+        assert_eq!(gameboy.disassemble(0x0100).text, "CALL Main");
+        assert_eq!(gameboy.disassemble(0x0103).text, "LD [wCounter], A");
+        assert_eq!(gameboy.disassemble(0x0106).text, "LDH A, [$FF44]");
+        assert_eq!(gameboy.disassemble(0x0108).text, "LDH [rLCDC], A");
+        assert_eq!(gameboy.disassemble(0x010A).text, "JR Start");
+        assert_eq!(gameboy.describe_address(0x0152).as_deref(), Some("Main+2"));
+
+        let buffer = SharedBuffer::default();
+        gameboy.set_trace_log(Some(Box::new(buffer.clone())));
+        gameboy.step_instruction();
+        gameboy.step_instruction();
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(log.ends_with("PC:0150 PCMEM:00,00,00,00 (Main)\n"), "{log}");
+    }
+
+    #[test]
+    fn software_breakpoints() {
+        let program = crate::asm!(0x0100, "ld a, 1\nld b, b\ninc a\nloop:\njr loop");
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.set_software_breakpoints(true);
+        gameboy.run_frame();
+        assert!(gameboy.is_paused_at_breakpoint());
+        assert_eq!(gameboy.registers().pc, 0x0102);
+        assert_eq!(gameboy.frame_count(), 0);
+
+        // Running again continues with the breakpoint itself.
+        gameboy.run_frame();
+        assert!(!gameboy.is_paused_at_breakpoint());
+        assert_eq!(gameboy.registers().a, 2);
+        assert_eq!(gameboy.frame_count(), 1);
+    }
+
+    #[test]
+    fn debug_messages() {
+        let program = crate::asm!(
+            0x0100,
+            "ld a, $2A
+                ld d, d
+                jr end
+                dw $6464, $0000
+                db \"A is %A%\"
+            end:
+                ld d, d
+            loop:
+                jr loop"
+        );
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.run_cycles(100);
+        assert_eq!(gameboy.take_debug_messages(), Vec::<String>::new());
+
+        let mut gameboy = GameBoy::with_model(&rom_with_program(&program), Model::Dmg).unwrap();
+        gameboy.set_debug_messages(true);
+        gameboy.run_cycles(100);
+        assert_eq!(gameboy.take_debug_messages(), ["A is 2A"]);
+        assert_eq!(gameboy.take_debug_messages(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn save_ram() {
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        // ROM and 8 KiB of RAM, without an MBC.
+        rom[0x0147] = 0x08;
+        rom[0x0149] = 0x02;
+        let mut gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        assert_eq!(gameboy.save_ram().len(), 0x2000);
+        gameboy.load_save_ram(&[1, 2, 3]);
+        assert_eq!(gameboy.peek(0xA001), 2);
+
+        gameboy.poke(0xA002, 4);
+        assert_eq!(gameboy.save_ram()[..3], [1, 2, 4]);
+    }
+
+    #[test]
+    fn poke_every_address() {
+        let rom = rom_with_program(&[0x18, 0xFE]);
+        let mut gameboy = GameBoy::with_model(&rom, Model::Cgb).unwrap();
+        for address in 0..=0xFFFF {
+            gameboy.poke(address, 0xFF);
+        }
+        for address in 0..0x8000 {
+            assert_eq!(gameboy.peek(address), rom[address as usize]);
+        }
+        assert_eq!(gameboy.peek(0xC000), 0xFF);
+    }
+
+    #[test]
+    fn rom_banks() {
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x0147] = 0x01;
+        rom.resize(0x10000, 0);
+        for bank in 1..4 {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        let mut gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        assert_eq!(gameboy.rom_bank(0x4000), Some(1));
+        assert_eq!(gameboy.peek(0x4000), 1);
+
+        gameboy.poke(0x2000, 3);
+        assert_eq!(gameboy.rom_bank(0x4000), Some(3));
+        assert_eq!(gameboy.peek(0x4000), 3);
+        assert_eq!(gameboy.rom_bank(0x0000), Some(0));
+    }
+
+    #[test]
+    fn save_state_keeps_mapper_state() {
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        // MBC5 with 32 KiB of RAM.
+        rom[0x0147] = 0x1A;
+        rom[0x0149] = 0x03;
+        rom.resize(0x10000, 0);
+        rom[0xC000] = 3;
+        let mut gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        gameboy.poke(0x0000, 0x0A);
+        gameboy.poke(0x2000, 3);
+        gameboy.poke(0x4000, 2);
+        gameboy.poke(0xA000, 0x42);
+        let state = gameboy.save_state();
+
+        gameboy.poke(0xA000, 0);
+        gameboy.poke(0x4000, 1);
+        gameboy.poke(0x2000, 1);
+        gameboy.poke(0x0000, 0x00);
+        gameboy.load_state(&state).unwrap();
+        assert_eq!(gameboy.peek(0x4000), 3);
+        assert_eq!(gameboy.peek(0xA000), 0x42);
+        assert_eq!(gameboy.save_ram()[0x4000], 0x42);
+    }
+
+    #[test]
+    fn manual_palette() {
+        let program = [
+            0x3E, 0x55, // LD A, 0x55
+            0xE0, 0x47, // LDH [BGP], A
+            0x18, 0xFE, // JR -2
+        ];
+        let rom = rom_with_program(&program);
+        let mut automatic = GameBoy::with_model(&rom, Model::Cgb).unwrap();
+        let mut gray = GameBoy::with_palette(&rom, Model::Cgb, ManualPalette::LeftB).unwrap();
+        automatic.run_frame();
+        automatic.run_frame();
+        gray.run_frame();
+        gray.run_frame();
+
+        let shade_1 = Color::from_rgb555(0x5294);
+        assert_eq!(gray.frame()[0], shade_1);
+        assert_ne!(automatic.frame()[0], shade_1);
+    }
+
+    #[test]
+    fn unsupported_cartridge_type() {
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        // MBC2
+        rom[0x0147] = 0x05;
+        assert!(matches!(
+            GameBoy::new(&rom),
+            Err(LoadRomError::UnsupportedCartridgeType(0x05))
+        ));
+    }
+}
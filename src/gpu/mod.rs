@@ -0,0 +1,553 @@
+//! The Game Boy groups pixels in 8x8 squares called Tiles. Tile data is stored between `0x8000` and
+//! `0x97FF`, where two different tile sets are stored. The first tile set resides at `0x8000` to
+//! `0x8FFF`, while the second occupies `0x8800` to `0x97FF` -- meaning the chunk between `0x8800`
+//! to `0x8FFF` is shared by the two tile sets.
+
+use crate::cgb::CgbMode;
+use crate::cgb::compatibility::CompatibilityPalettes;
+use crate::memory_map::*;
+use crate::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter, impl_save_state_enum,
+};
+use palette::{Color, ColorPalettes};
+
+pub(crate) mod palette;
+mod render;
+
+pub(super) const VRAM_BEGIN: usize = 0x8000;
+pub(super) const VRAM_END: usize = 0x9FFF;
+const VRAM_SIZE: usize = VRAM_END - VRAM_BEGIN + 1;
+/// The CGB has two switchable VRAM banks, the DMG only the first one.
+const VRAM_BANK_COUNT: usize = 2;
+const TILESET_STORAGE_END: usize = 0x1800;
+
+/// The width of the LCD in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+/// The height of the LCD in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// How many dots the PPU spends searching the OAM at the start of each visible scanline.
+const OAM_SCAN_DOTS: u32 = 80;
+/// How many dots the PPU spends sending pixels to the LCD.
+/// The real duration varies with scrolling, the window and objects; this is the minimum.
+const DRAWING_DOTS: u32 = 172;
+/// Every scanline, including the invisible ones during VBlank, takes the same amount of dots.
+const SCANLINE_DOTS: u32 = 456;
+/// The amount of visible scanlines, after which VBlank starts.
+const VISIBLE_SCANLINES: u8 = SCREEN_HEIGHT as u8;
+/// Visible scanlines plus 10 scanlines of VBlank.
+const SCANLINES_PER_FRAME: u8 = 154;
+
+/// Bit 7 of `LCDC`: Whether the LCD and PPU are turned on.
+const LCD_ENABLE_BIT: u8 = 0b1000_0000;
+/// Bits 3-6 of `STAT`: Which conditions request a STAT interrupt.
+const STAT_SELECT_MASK: u8 = 0b0111_1000;
+const STAT_HBLANK_SELECT_BIT: u8 = 0b0000_1000;
+const STAT_VBLANK_SELECT_BIT: u8 = 0b0001_0000;
+const STAT_OAM_SCAN_SELECT_BIT: u8 = 0b0010_0000;
+const STAT_LY_COMPARE_SELECT_BIT: u8 = 0b0100_0000;
+/// Bit 2 of `STAT`: Set while `LY` equals `LYC`.
+const STAT_LY_COMPARE_BIT: u8 = 0b0000_0100;
+
+/// Each tile stores a color index for each of its pixels, ranging from 0 to 3
+#[derive(Copy, Clone)]
+enum TilePixelValue {
+    Zero,
+    One,
+    Two,
+    Three,
+}
+
+type Tile = [[TilePixelValue; 8]; 8];
+
+fn empty_tile() -> Tile {
+    [[TilePixelValue::Zero; 8]; 8]
+}
+
+/// The mode the PPU is in. Each scanline cycles through OAM scan, drawing and HBlank, until all
+/// visible scanlines have been drawn and VBlank is entered.
+/// The discriminants are the values reported in the lower two bits of `STAT`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(super) enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+/// What happened during a call to [`GPU::step`].
+#[derive(Default)]
+pub(super) struct StepEvents {
+    /// The PPU switched to [`Mode::HBlank`] on a visible scanline.
+    pub(super) entered_hblank: bool,
+    /// All visible scanlines have been drawn, the frame is complete.
+    pub(super) vblank_interrupt: bool,
+    /// One of the conditions selected in `STAT` became true.
+    pub(super) stat_interrupt: bool,
+}
+
+pub(super) struct GPU {
+    vram: [[u8; VRAM_SIZE]; VRAM_BANK_COUNT],
+    /// The VRAM bank the CPU currently accesses, selected by `VBK`.
+    vram_bank: usize,
+    tile_set: [[Tile; 384]; VRAM_BANK_COUNT],
+    /// The Object Attribute Memory (OAM) stores objects.
+    /// These can be moved independently of the background.
+    oam: [u8; OAM_SIZE],
+    /// Decides whether tile attributes and color palettes are used for rendering.
+    cgb_mode: CgbMode,
+    /// The `LCDC` register.
+    lcd_control: u8,
+    /// The writable bits of the `STAT` register, selecting the sources of the STAT interrupt.
+    stat_select: u8,
+    /// Whether any of the selected STAT interrupt conditions currently holds. An interrupt is only
+    /// requested when this changes from `false` to `true`.
+    stat_line: bool,
+    scroll_y: u8,
+    scroll_x: u8,
+    ly_compare: u8,
+    window_y: u8,
+    window_x: u8,
+    /// The DMG palette register `BGP`.
+    background_palette: u8,
+    /// The DMG palette registers `OBP0` and `OBP1`.
+    object_palettes: [u8; 2],
+    background_color_palettes: ColorPalettes,
+    object_color_palettes: ColorPalettes,
+    /// Set through `OPRI`: Objects are prioritized by their X coordinate instead of their
+    /// position in OAM, like on the DMG.
+    coordinate_object_priority: bool,
+    mode: Mode,
+    /// The scanline currently being processed, readable through `LY`.
+    ly: u8,
+    /// How many dots have passed since the start of the current scanline.
+    scanline_dots: u32,
+    /// The window has its own line counter, which only advances on scanlines that show the window.
+    window_line: u8,
+    /// The finished pixels of the current frame, row by row.
+    framebuffer: Box<[Color; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+    /// The shade of each pixel in [`Self::framebuffer`], from 0 (lightest) to 3 (darkest), as
+    /// picked by the DMG palette registers. This is what the LCD of a DMG actually receives.
+    shades: Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+}
+
+impl_save_state_enum!(Mode {
+    HBlank,
+    VBlank,
+    OamScan,
+    Drawing,
+});
+
+/// The decoded tiles aren't saved, they are decoded from VRAM again when loading.
+impl SaveState for GPU {
+    fn save(&self, writer: &mut StateWriter) {
+        self.vram.save(writer);
+        self.vram_bank.save(writer);
+        self.oam.save(writer);
+        self.cgb_mode.save(writer);
+        self.lcd_control.save(writer);
+        self.stat_select.save(writer);
+        self.stat_line.save(writer);
+        self.scroll_y.save(writer);
+        self.scroll_x.save(writer);
+        self.ly_compare.save(writer);
+        self.window_y.save(writer);
+        self.window_x.save(writer);
+        self.background_palette.save(writer);
+        self.object_palettes.save(writer);
+        self.background_color_palettes.save(writer);
+        self.object_color_palettes.save(writer);
+        self.coordinate_object_priority.save(writer);
+        self.mode.save(writer);
+        self.ly.save(writer);
+        self.scanline_dots.save(writer);
+        self.window_line.save(writer);
+        self.framebuffer.save(writer);
+        self.shades.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.vram.load(reader)?;
+        self.vram_bank.load(reader)?;
+        self.oam.load(reader)?;
+        self.cgb_mode.load(reader)?;
+        self.lcd_control.load(reader)?;
+        self.stat_select.load(reader)?;
+        self.stat_line.load(reader)?;
+        self.scroll_y.load(reader)?;
+        self.scroll_x.load(reader)?;
+        self.ly_compare.load(reader)?;
+        self.window_y.load(reader)?;
+        self.window_x.load(reader)?;
+        self.background_palette.load(reader)?;
+        self.object_palettes.load(reader)?;
+        self.background_color_palettes.load(reader)?;
+        self.object_color_palettes.load(reader)?;
+        self.coordinate_object_priority.load(reader)?;
+        self.mode.load(reader)?;
+        self.ly.load(reader)?;
+        self.scanline_dots.load(reader)?;
+        self.window_line.load(reader)?;
+        self.framebuffer.load(reader)?;
+        self.shades.load(reader)?;
+        if self.vram_bank >= VRAM_BANK_COUNT {
+            return Err(LoadStateError::InvalidValue);
+        }
+
+        let vram_bank = self.vram_bank;
+        for bank in 0..VRAM_BANK_COUNT {
+            self.vram_bank = bank;
+            for address in (0..TILESET_STORAGE_END).step_by(2) {
+                self.write_vram(address, self.vram[bank][address]);
+            }
+        }
+        self.vram_bank = vram_bank;
+        Ok(())
+    }
+}
+
+impl Default for GPU {
+    fn default() -> Self {
+        Self {
+            vram: [[0; VRAM_SIZE]; VRAM_BANK_COUNT],
+            vram_bank: 0,
+            tile_set: [[empty_tile(); 384]; VRAM_BANK_COUNT],
+            oam: [0; OAM_SIZE],
+            cgb_mode: CgbMode::default(),
+            lcd_control: 0,
+            stat_select: 0,
+            stat_line: false,
+            scroll_y: 0,
+            scroll_x: 0,
+            ly_compare: 0,
+            window_y: 0,
+            window_x: 0,
+            background_palette: 0,
+            object_palettes: [0; 2],
+            background_color_palettes: ColorPalettes::default(),
+            object_color_palettes: ColorPalettes::default(),
+            coordinate_object_priority: false,
+            mode: Mode::HBlank,
+            ly: 0,
+            scanline_dots: 0,
+            window_line: 0,
+            framebuffer: Box::new([Color::default(); SCREEN_WIDTH * SCREEN_HEIGHT]),
+            shades: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]),
+        }
+    }
+}
+
+impl GPU {
+    /// Advance the PPU by the given amount of dots.
+    pub(super) fn step(&mut self, dots: u32) -> StepEvents {
+        let mut events = StepEvents::default();
+        if !self.is_lcd_enabled() {
+            return events;
+        }
+
+        // Process the dots in chunks that end at mode boundaries, so no mode change is skipped.
+        let mut remaining = dots;
+        while remaining > 0 {
+            let mode_end = match self.mode {
+                Mode::OamScan => OAM_SCAN_DOTS,
+                Mode::Drawing => OAM_SCAN_DOTS + DRAWING_DOTS,
+                Mode::HBlank | Mode::VBlank => SCANLINE_DOTS,
+            };
+            let advance = remaining.min(mode_end - self.scanline_dots);
+            self.scanline_dots += advance;
+            remaining -= advance;
+
+            if self.scanline_dots < mode_end {
+                continue;
+            }
+            match self.mode {
+                Mode::OamScan => self.mode = Mode::Drawing,
+                Mode::Drawing => {
+                    self.render_scanline();
+                    self.mode = Mode::HBlank;
+                    events.entered_hblank = true;
+                }
+                Mode::HBlank | Mode::VBlank => {
+                    self.next_scanline();
+                    if self.ly == VISIBLE_SCANLINES {
+                        events.vblank_interrupt = true;
+                    }
+                }
+            }
+            events.stat_interrupt |= self.update_stat_line();
+        }
+        events
+    }
+
+    /// Moves on to the next scanline once the current one is finished.
+    fn next_scanline(&mut self) {
+        self.scanline_dots = 0;
+        self.ly = (self.ly + 1) % SCANLINES_PER_FRAME;
+        self.mode = if self.ly < VISIBLE_SCANLINES {
+            Mode::OamScan
+        } else {
+            Mode::VBlank
+        };
+        if self.ly == 0 {
+            self.window_line = 0;
+        }
+    }
+
+    /// Re-evaluates the STAT interrupt conditions. Returns whether an interrupt is requested.
+    fn update_stat_line(&mut self) -> bool {
+        let mode_selected = match self.mode {
+            Mode::HBlank => self.stat_select & STAT_HBLANK_SELECT_BIT != 0,
+            Mode::VBlank => self.stat_select & STAT_VBLANK_SELECT_BIT != 0,
+            Mode::OamScan => self.stat_select & STAT_OAM_SCAN_SELECT_BIT != 0,
+            Mode::Drawing => false,
+        };
+        let ly_compare_selected =
+            self.stat_select & STAT_LY_COMPARE_SELECT_BIT != 0 && self.ly == self.ly_compare;
+
+        let was_set = self.stat_line;
+        self.stat_line = mode_selected || ly_compare_selected;
+        !was_set && self.stat_line
+    }
+
+    pub(super) fn is_lcd_enabled(&self) -> bool {
+        self.lcd_control & LCD_ENABLE_BIT != 0
+    }
+
+    pub(super) fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The last completed frame, or the frame being drawn if called before VBlank.
+    pub(super) fn framebuffer(&self) -> &[Color; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.framebuffer
+    }
+
+    /// The shades of the last completed frame. Used by the SGB, which colorizes the shades on its
+    /// own.
+    pub(super) fn shades(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.shades
+    }
+
+    pub(super) fn set_cgb_mode(&mut self, cgb_mode: CgbMode) {
+        self.cgb_mode = cgb_mode;
+        // Outside of CGB mode, objects are always prioritized like on the DMG.
+        self.coordinate_object_priority = !cgb_mode.has_cgb_features();
+    }
+
+    /// Loads the palettes the boot ROM chose for a DMG game into the color palette memory.
+    pub(super) fn load_compatibility_palettes(&mut self, palettes: CompatibilityPalettes) {
+        self.background_color_palettes
+            .set_palette(0, palettes.background);
+        self.object_color_palettes.set_palette(0, palettes.object0);
+        self.object_color_palettes.set_palette(1, palettes.object1);
+    }
+
+    /// Sets every background color to white, like the boot ROM does for CGB games.
+    pub(super) fn clear_background_color_palettes(&mut self) {
+        for palette in 0..8 {
+            self.background_color_palettes
+                .set_palette(palette, [0x7FFF; 4]);
+        }
+    }
+
+    /// Reads one of the PPU's I/O registers.
+    pub(super) fn read_register(&self, address: usize) -> u8 {
+        let has_cgb_features = self.cgb_mode.has_cgb_features();
+        match address {
+            LCD_CONTROL_REGISTER => self.lcd_control,
+            LCD_STATUS_REGISTER => {
+                let ly_compare = if self.ly == self.ly_compare {
+                    STAT_LY_COMPARE_BIT
+                } else {
+                    0
+                };
+                // Bit 7 is unused and always reads as set.
+                0b1000_0000 | self.stat_select | ly_compare | self.mode as u8
+            }
+            SCROLL_Y_REGISTER => self.scroll_y,
+            SCROLL_X_REGISTER => self.scroll_x,
+            LCD_Y_REGISTER => self.ly,
+            LCD_Y_COMPARE_REGISTER => self.ly_compare,
+            BACKGROUND_PALETTE_REGISTER => self.background_palette,
+            OBJECT_PALETTE_0_REGISTER => self.object_palettes[0],
+            OBJECT_PALETTE_1_REGISTER => self.object_palettes[1],
+            WINDOW_Y_REGISTER => self.window_y,
+            WINDOW_X_REGISTER => self.window_x,
+            VRAM_BANK_REGISTER if has_cgb_features => {
+                // Only bit 0 is used, all others read as set.
+                0b1111_1110 | self.vram_bank as u8
+            }
+            BACKGROUND_PALETTE_INDEX_REGISTER if has_cgb_features => {
+                self.background_color_palettes.read_index()
+            }
+            BACKGROUND_PALETTE_DATA_REGISTER if has_cgb_features => {
+                self.background_color_palettes.read_data()
+            }
+            OBJECT_PALETTE_INDEX_REGISTER if has_cgb_features => {
+                self.object_color_palettes.read_index()
+            }
+            OBJECT_PALETTE_DATA_REGISTER if has_cgb_features => {
+                self.object_color_palettes.read_data()
+            }
+            OBJECT_PRIORITY_MODE_REGISTER if has_cgb_features => {
+                0b1111_1110 | self.coordinate_object_priority as u8
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes one of the PPU's I/O registers.
+    pub(super) fn write_register(&mut self, address: usize, value: u8) {
+        let has_cgb_features = self.cgb_mode.has_cgb_features();
+        match address {
+            LCD_CONTROL_REGISTER => self.write_lcd_control(value),
+            LCD_STATUS_REGISTER => self.stat_select = value & STAT_SELECT_MASK,
+            SCROLL_Y_REGISTER => self.scroll_y = value,
+            SCROLL_X_REGISTER => self.scroll_x = value,
+            // `LY` is read-only.
+            LCD_Y_REGISTER => (),
+            LCD_Y_COMPARE_REGISTER => self.ly_compare = value,
+            BACKGROUND_PALETTE_REGISTER => self.background_palette = value,
+            OBJECT_PALETTE_0_REGISTER => self.object_palettes[0] = value,
+            OBJECT_PALETTE_1_REGISTER => self.object_palettes[1] = value,
+            WINDOW_Y_REGISTER => self.window_y = value,
+            WINDOW_X_REGISTER => self.window_x = value,
+            VRAM_BANK_REGISTER if has_cgb_features => self.vram_bank = (value & 0b1) as usize,
+            BACKGROUND_PALETTE_INDEX_REGISTER if has_cgb_features => {
+                self.background_color_palettes.write_index(value)
+            }
+            BACKGROUND_PALETTE_DATA_REGISTER if has_cgb_features => {
+                self.background_color_palettes.write_data(value)
+            }
+            OBJECT_PALETTE_INDEX_REGISTER if has_cgb_features => {
+                self.object_color_palettes.write_index(value)
+            }
+            OBJECT_PALETTE_DATA_REGISTER if has_cgb_features => {
+                self.object_color_palettes.write_data(value)
+            }
+            OBJECT_PRIORITY_MODE_REGISTER if has_cgb_features => {
+                self.coordinate_object_priority = value & 0b1 != 0
+            }
+            _ => (),
+        }
+    }
+
+    fn write_lcd_control(&mut self, value: u8) {
+        let was_enabled = self.is_lcd_enabled();
+        self.lcd_control = value;
+        match (was_enabled, self.is_lcd_enabled()) {
+            // Turning the LCD off resets the PPU to the top of the screen.
+            (true, false) => {
+                self.ly = 0;
+                self.scanline_dots = 0;
+                self.window_line = 0;
+                self.mode = Mode::HBlank;
+            }
+            (false, true) => self.mode = Mode::OamScan,
+            _ => (),
+        }
+    }
+
+    pub(super) fn read_vram(&self, address: usize) -> u8 {
+        self.vram[self.vram_bank][address]
+    }
+
+    pub(super) fn write_vram(&mut self, address: usize, value: u8) {
+        let bank = self.vram_bank;
+        self.vram[bank][address] = value;
+        if address >= TILESET_STORAGE_END {
+            return;
+        }
+
+        // Rows of tiles are encoded in two bytes. The first byte is always on an even address.
+        // To get the actual index, we have to bitwise AND it with `0xFFFE` to get the first index.
+        let normalized_address = address & 0xFFFE;
+        let byte1 = self.vram[bank][normalized_address];
+        let byte2 = self.vram[bank][normalized_address + 1];
+
+        // A tile is 8 rows tall. Every row is encoded with two bytes. A tile is therefore 16 bytes
+        // in total.
+        let tile_index = address / 16;
+        // Every two bytes is a new row.
+        let row_index = (address % 16) / 2;
+
+        // Loop 8 times to get the 8 pixels of a row.
+        for pixel_index in 0..8 {
+            // The pixels are indexed from the left instead of the right, so pixel 0 is index 7.
+            // We first create a mask to get the specified pixel and `AND` it.
+            let mask = 1 << (7 - pixel_index);
+            let lsb = byte1 & mask;
+            let msb = byte2 & mask;
+
+            let value = match (lsb != 0, msb != 0) {
+                (false, false) => TilePixelValue::Zero,
+                (true, false) => TilePixelValue::One,
+                (false, true) => TilePixelValue::Two,
+                (true, true) => TilePixelValue::Three,
+            };
+
+            self.tile_set[bank][tile_index][row_index][pixel_index] = value;
+        }
+    }
+
+    pub(super) fn read_oam(&self, address: usize) -> u8 {
+        self.oam[address]
+    }
+
+    pub(super) fn write_oam(&mut self, address: usize, value: u8) {
+        self.oam[address] = value;
+    }
+
+    /// Corrupts the row of OAM the PPU is currently reading during OAM scan. The first word of the
+    /// row is mixed with the previous row, and the rest of the row is overwritten by it.
+    /// This is the corruption caused by writes; reads corrupt OAM in a slightly different way,
+    /// which isn't emulated.
+    pub(super) fn corrupt_oam(&mut self) {
+        // OAM is read in rows of 8 bytes, one row every 4 dots. The first row is never corrupted.
+        let row = (self.scanline_dots / 4) as usize * 8;
+        if self.mode != Mode::OamScan || row == 0 || row >= OAM_SIZE {
+            return;
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([self.oam[offset], self.oam[offset + 1]]);
+        let (current, previous, previous_third) = (word(row), word(row - 8), word(row - 4));
+        let corrupted = ((current ^ previous_third) & (previous ^ previous_third)) ^ previous_third;
+        self.oam[row..row + 2].copy_from_slice(&corrupted.to_le_bytes());
+        self.oam.copy_within(row - 6..row, row + 2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_oam() {
+        let mut gpu = GPU::default();
+        for (index, byte) in gpu.oam.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        gpu.write_lcd_control(LCD_ENABLE_BIT);
+        // The PPU is reading the third row.
+        gpu.step(8);
+        gpu.corrupt_oam();
+
+        let (current, previous, previous_third) = (0x1110u16, 0x0908u16, 0x0D0C);
+        let corrupted = ((current ^ previous_third) & (previous ^ previous_third)) ^ previous_third;
+        assert_eq!(gpu.oam[16..18], corrupted.to_le_bytes());
+        assert_eq!(gpu.oam[18..24], [0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F]);
+        assert_eq!(gpu.oam[8..16], [8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(gpu.oam[24], 24);
+    }
+
+    #[test]
+    fn no_corruption_outside_of_oam_scan() {
+        let mut gpu = GPU::default();
+        gpu.oam[8] = 0x12;
+        gpu.write_lcd_control(LCD_ENABLE_BIT);
+        gpu.step(OAM_SCAN_DOTS + 8);
+        gpu.corrupt_oam();
+
+        assert_eq!(gpu.oam[8], 0x12);
+    }
+}
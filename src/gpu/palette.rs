@@ -0,0 +1,165 @@
+//! The DMG maps each pixel's color index to one of four shades of gray through the palette
+//! registers `BGP`, `OBP0` and `OBP1`. The CGB instead has dedicated palette memory containing
+//! 8 background and 8 object palettes with 4 colors each, which is accessed through an index
+//! register and a data register.
+
+use crate::save_state::impl_save_state;
+
+/// How many palettes each of the CGB's palette memories contains.
+const PALETTE_COUNT: usize = 8;
+/// Every color takes up two bytes, every palette four colors.
+const PALETTE_MEMORY_SIZE: usize = PALETTE_COUNT * 4 * 2;
+
+/// Bit 7 of the palette index registers: Increment the index after every write to the data
+/// register.
+const AUTO_INCREMENT_BIT: u8 = 0b1000_0000;
+/// Bits 0-5 of the palette index registers: The byte in palette memory to access.
+const INDEX_MASK: u8 = 0b0011_1111;
+
+/// A color as it is output to the screen.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl_save_state!(Color { r, g, b });
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Converts the CGB's 15-bit colors, where each channel has 5 bits, to 8 bits per channel.
+    pub(crate) fn from_rgb555(value: u16) -> Self {
+        // Repeat the upper bits in the lower ones, so that 0x1F maps to 0xFF.
+        let expand = |channel: u16| {
+            let channel = (channel & 0x1F) as u8;
+            (channel << 3) | (channel >> 2)
+        };
+        Self {
+            r: expand(value),
+            g: expand(value >> 5),
+            b: expand(value >> 10),
+        }
+    }
+}
+
+/// The shades of gray the DMG's palette registers choose from, from lightest to darkest.
+pub(crate) const DMG_SHADES: [Color; 4] = [
+    Color::new(0xFF, 0xFF, 0xFF),
+    Color::new(0xAA, 0xAA, 0xAA),
+    Color::new(0x55, 0x55, 0x55),
+    Color::new(0x00, 0x00, 0x00),
+];
+
+/// Applies one of the DMG's palette registers to a color index, resulting in a shade from 0
+/// (lightest) to 3 (darkest).
+pub(crate) fn apply_dmg_palette(palette: u8, color_index: u8) -> u8 {
+    (palette >> (color_index * 2)) & 0b11
+}
+
+/// One of the CGB's two palette memories, together with its index register.
+pub(crate) struct ColorPalettes {
+    memory: [u8; PALETTE_MEMORY_SIZE],
+    /// The `BCPS`/`OCPS` register.
+    index: u8,
+}
+
+impl_save_state!(ColorPalettes { memory, index });
+
+impl Default for ColorPalettes {
+    fn default() -> Self {
+        Self {
+            memory: [0; PALETTE_MEMORY_SIZE],
+            index: 0,
+        }
+    }
+}
+
+impl ColorPalettes {
+    pub(crate) fn read_index(&self) -> u8 {
+        // Bit 6 is unused and always reads as set.
+        self.index | 0b0100_0000
+    }
+
+    pub(crate) fn write_index(&mut self, value: u8) {
+        self.index = value & (AUTO_INCREMENT_BIT | INDEX_MASK);
+    }
+
+    pub(crate) fn read_data(&self) -> u8 {
+        self.memory[(self.index & INDEX_MASK) as usize]
+    }
+
+    pub(crate) fn write_data(&mut self, value: u8) {
+        self.memory[(self.index & INDEX_MASK) as usize] = value;
+        if self.index & AUTO_INCREMENT_BIT != 0 {
+            let next = (self.index + 1) & INDEX_MASK;
+            self.index = AUTO_INCREMENT_BIT | next;
+        }
+    }
+
+    /// Overwrites a whole palette, like the boot ROM does in DMG compatibility mode.
+    pub(crate) fn set_palette(&mut self, palette: usize, colors: [u16; 4]) {
+        for (color_index, color) in colors.into_iter().enumerate() {
+            let offset = (palette * 4 + color_index) * 2;
+            self.memory[offset..offset + 2].copy_from_slice(&color.to_le_bytes());
+        }
+    }
+
+    /// Looks up a color in the given palette.
+    pub(crate) fn color(&self, palette: u8, color_index: u8) -> Color {
+        let offset = (palette as usize * 4 + color_index as usize) * 2;
+        let value = u16::from_le_bytes([self.memory[offset], self.memory[offset + 1]]);
+        Color::from_rgb555(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb555_conversion() {
+        assert_eq!(Color::from_rgb555(0x7FFF), Color::new(0xFF, 0xFF, 0xFF));
+        assert_eq!(Color::from_rgb555(0x001F), Color::new(0xFF, 0x00, 0x00));
+        assert_eq!(Color::from_rgb555(0x32BF), Color::new(0xFF, 0xAD, 0x63));
+    }
+
+    #[test]
+    fn dmg_palette() {
+        let palette = 0b1110_0100;
+        assert_eq!(apply_dmg_palette(palette, 0), 0);
+        assert_eq!(apply_dmg_palette(palette, 3), 3);
+        assert_eq!(apply_dmg_palette(0b0001_1011, 0), 3);
+    }
+
+    #[test]
+    fn write_with_auto_increment() {
+        let mut palettes = ColorPalettes::default();
+        palettes.write_index(AUTO_INCREMENT_BIT | 0x3E);
+        palettes.write_data(0x1F);
+        palettes.write_data(0x00);
+        // The index wraps around to the first palette.
+        palettes.write_data(0xFF);
+
+        assert_eq!(palettes.read_index(), 0b1100_0001);
+        assert_eq!(palettes.color(7, 3), Color::new(0xFF, 0x00, 0x00));
+        assert_eq!(palettes.read_data(), 0x00);
+        palettes.write_index(0);
+        assert_eq!(palettes.read_data(), 0xFF);
+    }
+
+    #[test]
+    fn set_palette() {
+        let mut palettes = ColorPalettes::default();
+        palettes.set_palette(1, [0x7FFF, 0x001F, 0x03E0, 0x7C00]);
+
+        assert_eq!(palettes.color(1, 1), Color::new(0xFF, 0x00, 0x00));
+        palettes.write_index(8 + 6);
+        assert_eq!(palettes.read_data(), 0x00);
+        palettes.write_index(8 + 7);
+        assert_eq!(palettes.read_data(), 0x7C);
+    }
+}
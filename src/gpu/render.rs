@@ -0,0 +1,400 @@
+//! Draws a scanline into the framebuffer once the PPU is done with it. Each pixel is picked from
+//! the background, the window or an object, and its color index is looked up in the palettes.
+//! The DMG uses the palette registers to get a shade of gray. On the CGB, the background map has
+//! a second layer of tile attributes in VRAM bank 1, which select one of the color palettes among
+//! other things. In DMG compatibility mode, the CGB applies the DMG palette registers first and
+//! uses the result to look up the colors the boot ROM loaded into the color palettes.
+
+use super::palette::{Color, ColorPalettes, DMG_SHADES, apply_dmg_palette};
+use super::{GPU, SCREEN_WIDTH};
+
+/// Bit 0 of `LCDC`: Enables the background and window on the DMG. On the CGB, clearing it
+/// instead lets objects be drawn above everything.
+const BACKGROUND_ENABLE_BIT: u8 = 0b0000_0001;
+/// Bit 1 of `LCDC`: Whether objects are drawn.
+const OBJECT_ENABLE_BIT: u8 = 0b0000_0010;
+/// Bit 2 of `LCDC`: Objects are 8x16 pixels instead of 8x8.
+const OBJECT_SIZE_BIT: u8 = 0b0000_0100;
+/// Bit 3 of `LCDC`: Which tile map the background uses.
+const BACKGROUND_TILE_MAP_BIT: u8 = 0b0000_1000;
+/// Bit 4 of `LCDC`: Which tile set the background and window use.
+const TILE_DATA_BIT: u8 = 0b0001_0000;
+/// Bit 5 of `LCDC`: Whether the window is drawn.
+const WINDOW_ENABLE_BIT: u8 = 0b0010_0000;
+/// Bit 6 of `LCDC`: Which tile map the window uses.
+const WINDOW_TILE_MAP_BIT: u8 = 0b0100_0000;
+
+/// The offsets of the two 32x32 tile maps in VRAM.
+const TILE_MAP_0: usize = 0x1800;
+const TILE_MAP_1: usize = 0x1C00;
+/// The width and height of a tile map in tiles.
+const TILE_MAP_SIZE: usize = 32;
+
+/// Every object takes up four bytes in OAM: Y position, X position, tile index and attributes.
+const OBJECT_COUNT: usize = 40;
+/// The PPU only picks up this many objects per scanline during OAM scan.
+const MAX_OBJECTS_PER_SCANLINE: usize = 10;
+
+// Bits of the CGB's background attributes and of the object attributes.
+/// The background is drawn above objects.
+const PRIORITY_BIT: u8 = 0b1000_0000;
+const Y_FLIP_BIT: u8 = 0b0100_0000;
+const X_FLIP_BIT: u8 = 0b0010_0000;
+/// Objects only: Use `OBP1` instead of `OBP0`.
+const DMG_PALETTE_BIT: u8 = 0b0001_0000;
+/// CGB only: Read the tile from VRAM bank 1.
+const BANK_BIT: u8 = 0b0000_1000;
+/// CGB only: The color palette to use.
+const CGB_PALETTE_MASK: u8 = 0b0000_0111;
+
+/// A pixel of the background or window. Kept around to decide whether objects are drawn above it.
+#[derive(Copy, Clone)]
+struct BackgroundPixel {
+    color_index: u8,
+    /// The CGB attribute that draws this pixel above objects.
+    priority: bool,
+    color: Color,
+    /// The shade `BGP` maps the color index to. In CGB mode, this is the color index instead.
+    shade: u8,
+}
+
+/// An object found on the current scanline.
+struct Object {
+    y: i16,
+    x: i16,
+    tile: u8,
+    attributes: u8,
+}
+
+impl GPU {
+    /// Renders the current scanline into the framebuffer.
+    pub(super) fn render_scanline(&mut self) {
+        let background = self.render_background_line();
+        let objects = self.objects_on_scanline();
+
+        let row_start = self.ly as usize * SCREEN_WIDTH;
+        for (x, background_pixel) in background.iter().enumerate() {
+            let (color, shade) = self
+                .object_pixel(&objects, x as i16, background_pixel)
+                .unwrap_or((background_pixel.color, background_pixel.shade));
+            self.framebuffer[row_start + x] = color;
+            self.shades[row_start + x] = shade;
+        }
+    }
+
+    /// Computes the background and window pixels of the current scanline.
+    fn render_background_line(&mut self) -> [BackgroundPixel; SCREEN_WIDTH] {
+        let blank = BackgroundPixel {
+            color_index: 0,
+            priority: false,
+            color: DMG_SHADES[0],
+            shade: 0,
+        };
+        let mut line = [blank; SCREEN_WIDTH];
+        // Outside of CGB mode, this bit turns off both the background and the window.
+        if !self.cgb_mode.has_cgb_features() && self.lcd_control & BACKGROUND_ENABLE_BIT == 0 {
+            return line;
+        }
+
+        let background_map = if self.lcd_control & BACKGROUND_TILE_MAP_BIT != 0 {
+            TILE_MAP_1
+        } else {
+            TILE_MAP_0
+        };
+        let window_map = if self.lcd_control & WINDOW_TILE_MAP_BIT != 0 {
+            TILE_MAP_1
+        } else {
+            TILE_MAP_0
+        };
+        // `WX` is offset by 7, so the window can be moved partially off the left edge.
+        let window_start = self.window_x as i16 - 7;
+        let shows_window = self.lcd_control & WINDOW_ENABLE_BIT != 0
+            && self.ly >= self.window_y
+            && window_start < SCREEN_WIDTH as i16;
+
+        let background_y = self.ly.wrapping_add(self.scroll_y);
+        for (x, pixel) in line.iter_mut().enumerate() {
+            *pixel = if shows_window && x as i16 >= window_start {
+                let window_x = (x as i16 - window_start) as u8;
+                self.background_pixel(window_map, window_x, self.window_line)
+            } else {
+                let background_x = (x as u8).wrapping_add(self.scroll_x);
+                self.background_pixel(background_map, background_x, background_y)
+            };
+        }
+
+        if shows_window {
+            self.window_line += 1;
+        }
+        line
+    }
+
+    /// Looks up the pixel at the given position of a tile map.
+    fn background_pixel(&self, tile_map: usize, x: u8, y: u8) -> BackgroundPixel {
+        let map_index =
+            tile_map + (y as usize / 8) * TILE_MAP_SIZE + (x as usize / 8) % TILE_MAP_SIZE;
+        let tile_number = self.vram[0][map_index];
+        let attributes = if self.cgb_mode.has_cgb_features() {
+            self.vram[1][map_index]
+        } else {
+            0
+        };
+
+        let mut row = y as usize % 8;
+        let mut column = x as usize % 8;
+        if attributes & Y_FLIP_BIT != 0 {
+            row = 7 - row;
+        }
+        if attributes & X_FLIP_BIT != 0 {
+            column = 7 - column;
+        }
+        let bank = (attributes & BANK_BIT != 0) as usize;
+        let tile = self.background_tile_index(tile_number);
+        let color_index = self.tile_set[bank][tile][row][column] as u8;
+
+        let (color, shade) = if self.cgb_mode.has_cgb_features() {
+            let color = self
+                .background_color_palettes
+                .color(attributes & CGB_PALETTE_MASK, color_index);
+            (color, color_index)
+        } else {
+            let shade = apply_dmg_palette(self.background_palette, color_index);
+            (
+                self.dmg_color(&self.background_color_palettes, 0, shade),
+                shade,
+            )
+        };
+
+        BackgroundPixel {
+            color_index,
+            priority: attributes & PRIORITY_BIT != 0,
+            color,
+            shade,
+        }
+    }
+
+    /// Maps a tile number from a tile map to an index into the tile set. Depending on `LCDC`,
+    /// tile numbers are either unsigned and relative to `0x8000`, or signed and relative to
+    /// `0x9000`.
+    fn background_tile_index(&self, tile_number: u8) -> usize {
+        if self.lcd_control & TILE_DATA_BIT != 0 {
+            tile_number as usize
+        } else {
+            (256 + tile_number as i8 as i16) as usize
+        }
+    }
+
+    fn object_height(&self) -> i16 {
+        if self.lcd_control & OBJECT_SIZE_BIT != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Collects the objects overlapping the current scanline, ordered from highest to lowest
+    /// priority.
+    fn objects_on_scanline(&self) -> Vec<Object> {
+        if self.lcd_control & OBJECT_ENABLE_BIT == 0 {
+            return Vec::new();
+        }
+
+        let ly = self.ly as i16;
+        let height = self.object_height();
+        let mut objects: Vec<Object> = self
+            .oam
+            .chunks_exact(4)
+            .take(OBJECT_COUNT)
+            .map(|entry| Object {
+                // The position is stored with an offset, so objects can be moved off screen.
+                y: entry[0] as i16 - 16,
+                x: entry[1] as i16 - 8,
+                tile: entry[2],
+                attributes: entry[3],
+            })
+            .filter(|object| ly >= object.y && ly < object.y + height)
+            .take(MAX_OBJECTS_PER_SCANLINE)
+            .collect();
+
+        if self.coordinate_object_priority {
+            // The sort is stable, so objects at the same X coordinate stay in OAM order.
+            objects.sort_by_key(|object| object.x);
+        }
+        objects
+    }
+
+    /// Finds the color and shade of the object pixel drawn at `x`, if any. Only the highest
+    /// priority object with a non-transparent pixel is considered, which may still be hidden by
+    /// the background.
+    fn object_pixel(
+        &self,
+        objects: &[Object],
+        x: i16,
+        background: &BackgroundPixel,
+    ) -> Option<(Color, u8)> {
+        let has_cgb_features = self.cgb_mode.has_cgb_features();
+        let (object, color_index) = objects.iter().find_map(|object| {
+            if x < object.x || x >= object.x + 8 {
+                return None;
+            }
+            let color_index = self.object_color_index(object, x);
+            (color_index != 0).then_some((object, color_index))
+        })?;
+
+        // Color index 0 of the background is always drawn behind objects.
+        let background_has_priority = if has_cgb_features {
+            self.lcd_control & BACKGROUND_ENABLE_BIT != 0
+                && background.color_index != 0
+                && (background.priority || object.attributes & PRIORITY_BIT != 0)
+        } else {
+            background.color_index != 0 && object.attributes & PRIORITY_BIT != 0
+        };
+        if background_has_priority {
+            return None;
+        }
+
+        if has_cgb_features {
+            let color = self
+                .object_color_palettes
+                .color(object.attributes & CGB_PALETTE_MASK, color_index);
+            Some((color, color_index))
+        } else {
+            let palette = (object.attributes & DMG_PALETTE_BIT != 0) as usize;
+            let shade = apply_dmg_palette(self.object_palettes[palette], color_index);
+            let color = self.dmg_color(&self.object_color_palettes, palette as u8, shade);
+            Some((color, shade))
+        }
+    }
+
+    /// The color of a shade picked by one of the DMG palette registers. In DMG compatibility
+    /// mode, the shade is looked up in the color palettes the boot ROM set up.
+    fn dmg_color(&self, color_palettes: &ColorPalettes, palette: u8, shade: u8) -> Color {
+        if self.cgb_mode.has_color_palettes() {
+            color_palettes.color(palette, shade)
+        } else {
+            DMG_SHADES[shade as usize]
+        }
+    }
+
+    /// The color index of an object at the given screen column of the current scanline.
+    fn object_color_index(&self, object: &Object, x: i16) -> u8 {
+        let height = self.object_height();
+        let mut row = self.ly as i16 - object.y;
+        let mut column = x - object.x;
+        if object.attributes & Y_FLIP_BIT != 0 {
+            row = height - 1 - row;
+        }
+        if object.attributes & X_FLIP_BIT != 0 {
+            column = 7 - column;
+        }
+
+        // Tall objects ignore the lowest bit of the tile index and span two consecutive tiles.
+        let first_tile = if height == 16 {
+            object.tile & 0xFE
+        } else {
+            object.tile
+        };
+        let tile = first_tile as usize + row as usize / 8;
+        let bank = (self.cgb_mode.has_cgb_features() && object.attributes & BANK_BIT != 0) as usize;
+        self.tile_set[bank][tile][row as usize % 8][column as usize] as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::palette::DMG_SHADES;
+
+    /// A DMG PPU with the identity palette everywhere, tile 0 filled with color index 1 and tile 1
+    /// filled with color index 3.
+    fn gpu() -> GPU {
+        let mut gpu = GPU::default();
+        for row in 0..8 {
+            gpu.write_vram(row * 2, 0xFF);
+            gpu.write_vram(16 + row * 2, 0xFF);
+            gpu.write_vram(16 + row * 2 + 1, 0xFF);
+        }
+        gpu.background_palette = 0b1110_0100;
+        gpu.object_palettes = [0b1110_0100, 0b0001_1011];
+        gpu.lcd_control = LCD_ENABLE | BACKGROUND_ENABLE_BIT | OBJECT_ENABLE_BIT | TILE_DATA_BIT;
+        gpu
+    }
+
+    const LCD_ENABLE: u8 = 0b1000_0000;
+
+    /// Places an object using tile 1 in the first OAM slot.
+    fn place_object(gpu: &mut GPU, x: u8, y: u8, attributes: u8) {
+        gpu.write_oam(0, y + 16);
+        gpu.write_oam(1, x + 8);
+        gpu.write_oam(2, 1);
+        gpu.write_oam(3, attributes);
+    }
+
+    fn pixel(gpu: &GPU, x: usize, y: usize) -> Color {
+        gpu.framebuffer()[y * SCREEN_WIDTH + x]
+    }
+
+    #[test]
+    fn background() {
+        let mut gpu = gpu();
+        gpu.render_scanline();
+
+        assert_eq!(pixel(&gpu, 0, 0), DMG_SHADES[1]);
+        assert_eq!(pixel(&gpu, 159, 0), DMG_SHADES[1]);
+    }
+
+    #[test]
+    fn background_disabled() {
+        let mut gpu = gpu();
+        gpu.lcd_control &= !BACKGROUND_ENABLE_BIT;
+        gpu.render_scanline();
+
+        assert_eq!(pixel(&gpu, 0, 0), DMG_SHADES[0]);
+    }
+
+    #[test]
+    fn object_above_background() {
+        let mut gpu = gpu();
+        place_object(&mut gpu, 4, 0, 0);
+        gpu.render_scanline();
+
+        assert_eq!(pixel(&gpu, 3, 0), DMG_SHADES[1]);
+        assert_eq!(pixel(&gpu, 4, 0), DMG_SHADES[3]);
+        assert_eq!(pixel(&gpu, 11, 0), DMG_SHADES[3]);
+        assert_eq!(pixel(&gpu, 12, 0), DMG_SHADES[1]);
+    }
+
+    #[test]
+    fn object_with_second_palette() {
+        let mut gpu = gpu();
+        place_object(&mut gpu, 0, 0, DMG_PALETTE_BIT);
+        gpu.render_scanline();
+
+        assert_eq!(pixel(&gpu, 0, 0), DMG_SHADES[0]);
+    }
+
+    #[test]
+    fn object_behind_background() {
+        let mut gpu = gpu();
+        place_object(&mut gpu, 0, 0, PRIORITY_BIT);
+        gpu.render_scanline();
+
+        assert_eq!(pixel(&gpu, 0, 0), DMG_SHADES[1]);
+    }
+
+    #[test]
+    fn window_uses_its_own_tile_map() {
+        let mut gpu = gpu();
+        // The window's tile map is filled with tile 1.
+        for index in 0..32 {
+            gpu.write_vram(TILE_MAP_1 + index, 1);
+        }
+        gpu.lcd_control |= WINDOW_ENABLE_BIT | WINDOW_TILE_MAP_BIT;
+        gpu.window_x = 7 + 80;
+        gpu.render_scanline();
+
+        assert_eq!(pixel(&gpu, 79, 0), DMG_SHADES[1]);
+        assert_eq!(pixel(&gpu, 80, 0), DMG_SHADES[3]);
+        assert_eq!(gpu.window_line, 1);
+    }
+}
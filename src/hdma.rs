@@ -0,0 +1,237 @@
+//! The CGB can copy data into VRAM with its own DMA controller, configured through `HDMA1`-`HDMA5`.
+//! There are two kinds of transfers: A general purpose DMA copies everything at once and halts the
+//! CPU until it is done, an HBlank DMA copies a single block at the start of every HBlank.
+//! The actual copying is done by [`crate::memory_bus::MemoryBus`], as it has access to both source
+//! and destination. This only keeps track of the registers and the transfer's progress.
+
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter};
+
+/// Data is always transferred in blocks of 16 bytes.
+pub(super) const BLOCK_SIZE: u16 = 0x10;
+
+/// How many CPU cycles the transfer of a single block takes in normal speed mode.
+/// The DMA controller runs at a fixed rate, so in double speed mode the CPU waits twice as many of
+/// its own cycles.
+const BLOCK_CYCLES: u32 = 32;
+
+/// Bit 7 of `HDMA5`: Selects the transfer mode when written, reports inactivity when read.
+const MODE_BIT: u8 = 0b1000_0000;
+/// Bits 0-6 of `HDMA5`: The amount of blocks to transfer, minus one.
+const LENGTH_MASK: u8 = 0b0111_1111;
+/// The destination is an offset into the 8 KiB of VRAM.
+const VRAM_SIZE: u16 = 0x2000;
+
+/// The transfer that has to be started after writing to `HDMA5`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(super) enum Transfer {
+    /// Copy all blocks right away, halting the CPU in the meantime.
+    GeneralPurpose,
+    /// Copy one block at the start of each HBlank.
+    HBlank,
+}
+
+#[derive(Default)]
+pub(super) struct Hdma {
+    /// The address the next block is read from.
+    source: u16,
+    /// The offset into VRAM the next block is written to.
+    destination: u16,
+    /// How many blocks are left to transfer.
+    remaining_blocks: u8,
+    /// Whether an HBlank DMA is in progress.
+    hblank_active: bool,
+}
+
+impl SaveState for Hdma {
+    fn save(&self, writer: &mut StateWriter) {
+        self.source.save(writer);
+        self.destination.save(writer);
+        self.remaining_blocks.save(writer);
+        self.hblank_active.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.source.load(reader)?;
+        self.destination.load(reader)?;
+        self.remaining_blocks.load(reader)?;
+        self.hblank_active.load(reader)?;
+        let aligned =
+            self.source.is_multiple_of(BLOCK_SIZE) && self.destination.is_multiple_of(BLOCK_SIZE);
+        let in_vram = self.destination < VRAM_SIZE;
+        let length_valid = self.remaining_blocks <= LENGTH_MASK + 1
+            && (self.remaining_blocks > 0 || !self.hblank_active);
+        if !aligned || !in_vram || !length_valid {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl Hdma {
+    /// How many CPU cycles the CPU is halted for while a single block is being transferred.
+    pub(super) fn block_cycles(double_speed: bool) -> u32 {
+        if double_speed {
+            BLOCK_CYCLES * 2
+        } else {
+            BLOCK_CYCLES
+        }
+    }
+
+    pub(super) fn is_hblank_active(&self) -> bool {
+        self.hblank_active
+    }
+
+    pub(super) fn write_source_high(&mut self, value: u8) {
+        self.source = (value as u16) << 8 | (self.source & 0x00FF);
+    }
+
+    /// The lower four bits are ignored, as the source always is aligned to a block.
+    pub(super) fn write_source_low(&mut self, value: u8) {
+        self.source = (self.source & 0xFF00) | (value & 0xF0) as u16;
+    }
+
+    /// Only bits 12-8 are used, the destination always lies in VRAM.
+    pub(super) fn write_destination_high(&mut self, value: u8) {
+        self.destination = ((value & 0x1F) as u16) << 8 | (self.destination & 0x00FF);
+    }
+
+    /// The lower four bits are ignored, as the destination always is aligned to a block.
+    pub(super) fn write_destination_low(&mut self, value: u8) {
+        self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16;
+    }
+
+    /// Reads `HDMA5`. Bit 7 is cleared while an HBlank DMA is active, the lower bits contain the
+    /// remaining amount of blocks minus one. A completed transfer therefore reads as `0xFF`.
+    pub(super) fn read_control(&self) -> u8 {
+        let status = if self.hblank_active { 0 } else { MODE_BIT };
+        status | (self.remaining_blocks.wrapping_sub(1) & LENGTH_MASK)
+    }
+
+    /// Writes `HDMA5`, which either starts a new transfer or cancels a running HBlank DMA.
+    /// Returns the transfer the caller has to perform, if any.
+    pub(super) fn write_control(&mut self, value: u8) -> Option<Transfer> {
+        let is_hblank = value & MODE_BIT != 0;
+        if self.hblank_active && !is_hblank {
+            // The remaining length is kept, so it can still be read from `HDMA5`.
+            self.hblank_active = false;
+            return None;
+        }
+
+        self.remaining_blocks = (value & LENGTH_MASK) + 1;
+        self.hblank_active = is_hblank;
+        if is_hblank {
+            Some(Transfer::HBlank)
+        } else {
+            Some(Transfer::GeneralPurpose)
+        }
+    }
+
+    /// Advances the transfer by a block and returns the source address and VRAM offset the block
+    /// has to be copied from and to. Returns [`None`] if there is nothing left to transfer.
+    pub(super) fn next_block(&mut self) -> Option<(u16, u16)> {
+        if self.remaining_blocks == 0 {
+            return None;
+        }
+
+        let block = (self.source, self.destination);
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination += BLOCK_SIZE;
+        self.remaining_blocks -= 1;
+        // The transfer ends early once it reached the end of VRAM, and `HDMA5` reads as if it was
+        // completed.
+        if self.destination == VRAM_SIZE {
+            self.destination = 0;
+            self.remaining_blocks = 0;
+        }
+        if self.remaining_blocks == 0 {
+            self.hblank_active = false;
+        }
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save_state::copy_state;
+
+    #[test]
+    fn addresses_are_masked() {
+        let mut hdma = Hdma::default();
+        hdma.write_source_high(0xC1);
+        hdma.write_source_low(0x2F);
+        hdma.write_destination_high(0xFF);
+        hdma.write_destination_low(0x3A);
+        hdma.write_control(0x00);
+
+        assert_eq!(hdma.next_block(), Some((0xC120, 0x1F30)));
+    }
+
+    #[test]
+    fn transfer_ends_at_end_of_vram() {
+        let mut hdma = Hdma::default();
+        hdma.write_destination_high(0x1F);
+        hdma.write_destination_low(0xE0);
+        assert_eq!(hdma.write_control(0x83), Some(Transfer::HBlank));
+
+        assert_eq!(hdma.next_block(), Some((0x0000, 0x1FE0)));
+        assert_eq!(hdma.next_block(), Some((0x0010, 0x1FF0)));
+        assert_eq!(hdma.next_block(), None);
+        assert_eq!(hdma.read_control(), 0xFF);
+        assert!(!hdma.is_hblank_active());
+    }
+
+    #[test]
+    fn control_reports_progress() {
+        let mut hdma = Hdma::default();
+        assert_eq!(hdma.write_control(0x82), Some(Transfer::HBlank));
+        assert_eq!(hdma.read_control(), 0x02);
+
+        hdma.next_block();
+        assert_eq!(hdma.read_control(), 0x01);
+        hdma.next_block();
+        hdma.next_block();
+        assert_eq!(hdma.read_control(), 0xFF);
+        assert!(!hdma.is_hblank_active());
+    }
+
+    #[test]
+    fn cancel_hblank_transfer() {
+        let mut hdma = Hdma::default();
+        hdma.write_control(0x83);
+        hdma.next_block();
+
+        assert_eq!(hdma.write_control(0x00), None);
+        assert!(!hdma.is_hblank_active());
+        assert_eq!(hdma.read_control(), 0x82);
+    }
+
+    #[test]
+    fn invalid_state() {
+        let mut target = Hdma::default();
+        let outside_vram = Hdma {
+            destination: 0x2000,
+            ..Hdma::default()
+        };
+        assert_eq!(
+            copy_state(&outside_vram, &mut target),
+            Err(LoadStateError::InvalidValue)
+        );
+        let too_long = Hdma {
+            remaining_blocks: 0x81,
+            ..Hdma::default()
+        };
+        assert_eq!(
+            copy_state(&too_long, &mut target),
+            Err(LoadStateError::InvalidValue)
+        );
+        let unaligned = Hdma {
+            source: 0xC001,
+            ..Hdma::default()
+        };
+        assert_eq!(
+            copy_state(&unaligned, &mut target),
+            Err(LoadStateError::InvalidValue)
+        );
+    }
+}
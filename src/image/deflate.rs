@@ -0,0 +1,249 @@
+//! Compresses data in the zlib format PNG uses. The data is compressed with LZ77, which replaces
+//! repeated sequences with references to an earlier occurrence, and then encoded with the fixed
+//! Huffman codes of DEFLATE. Screens consist mostly of repeated pixels and rows, so this gets
+//! close to the size of an optimized encoder without having to build Huffman trees.
+
+/// The first byte of the zlib header: DEFLATE with a window of 32 KiB.
+const ZLIB_METHOD: u8 = 0x78;
+/// The second byte of the zlib header: Chosen so that the header is a multiple of 31, with the
+/// compression level set to "fastest".
+const ZLIB_FLAGS: u8 = 0x01;
+
+/// How far back a match may start.
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Positions are found through a hash of the 3 bytes starting there.
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash are compared at most.
+const MAX_CHAIN: usize = 64;
+const NO_POSITION: usize = usize::MAX;
+
+/// The symbol marking the end of a block.
+const END_OF_BLOCK: u16 = 256;
+
+/// The smallest length each length symbol, starting at 257, stands for.
+pub(super) const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+/// How many extra bits follow each length symbol to select a length within its range.
+pub(super) const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// The smallest distance each distance symbol stands for.
+pub(super) const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(super) const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Compresses data into a zlib stream.
+pub(super) fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut output = BitWriter::default();
+    output.bytes.extend_from_slice(&[ZLIB_METHOD, ZLIB_FLAGS]);
+    compress(data, &mut output);
+    let mut bytes = output.finish();
+    bytes.extend_from_slice(&adler32(data).to_be_bytes());
+    bytes
+}
+
+/// Writes the data as a single final block with fixed Huffman codes.
+fn compress(data: &[u8], output: &mut BitWriter) {
+    // BFINAL, then BTYPE 01 for fixed Huffman codes.
+    output.write_bits(1, 1);
+    output.write_bits(1, 2);
+
+    let mut matches = MatchFinder::new(data);
+    let mut position = 0;
+    while position < data.len() {
+        match matches.find(position) {
+            Some((length, distance)) => {
+                write_length(output, length);
+                write_distance(output, distance);
+                for position in position..position + length {
+                    matches.insert(position);
+                }
+                position += length;
+            }
+            None => {
+                write_symbol(output, data[position] as u16);
+                matches.insert(position);
+                position += 1;
+            }
+        }
+    }
+    write_symbol(output, END_OF_BLOCK);
+}
+
+/// Writes a literal byte, the end of a block or a length with the fixed literal/length code.
+fn write_symbol(output: &mut BitWriter, symbol: u16) {
+    let (code, length) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    };
+    output.write_code(code, length);
+}
+
+fn write_length(output: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASES
+        .iter()
+        .rposition(|base| *base as usize <= length)
+        .unwrap();
+    write_symbol(output, 257 + index as u16);
+    let extra = length - LENGTH_BASES[index] as usize;
+    output.write_bits(extra as u32, LENGTH_EXTRA_BITS[index] as u32);
+}
+
+fn write_distance(output: &mut BitWriter, distance: usize) {
+    let index = DISTANCE_BASES
+        .iter()
+        .rposition(|base| *base as usize <= distance)
+        .unwrap();
+    // All distance symbols have fixed codes of 5 bits.
+    output.write_code(index as u16, 5);
+    let extra = distance - DISTANCE_BASES[index] as usize;
+    output.write_bits(extra as u32, DISTANCE_EXTRA_BITS[index] as u32);
+}
+
+/// Finds earlier occurrences of the bytes at a position. Positions are chained by the hash of
+/// their first 3 bytes, so only candidates that may match are compared.
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    /// The last position inserted for each hash.
+    head: Vec<usize>,
+    /// The previous position with the same hash, for each position within the window.
+    previous: Vec<usize>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![NO_POSITION; 1 << HASH_BITS],
+            previous: vec![NO_POSITION; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let bytes = &self.data[position..position + MIN_MATCH];
+        let hash = (bytes[0] as usize) << 10 ^ (bytes[1] as usize) << 5 ^ bytes[2] as usize;
+        hash & ((1 << HASH_BITS) - 1)
+    }
+
+    fn insert(&mut self, position: usize) {
+        if position + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let hash = self.hash(position);
+        self.previous[position % WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = position;
+    }
+
+    /// Returns the length and distance of the longest match, if there is one.
+    fn find(&self, position: usize) -> Option<(usize, usize)> {
+        if position + MIN_MATCH > self.data.len() {
+            return None;
+        }
+        let max_length = MAX_MATCH.min(self.data.len() - position);
+        let mut best = None;
+        let mut best_length = MIN_MATCH - 1;
+        let mut candidate = self.head[self.hash(position)];
+        for _ in 0..MAX_CHAIN {
+            // Entries of positions that left the window may have been overwritten.
+            if candidate == NO_POSITION || position - candidate > WINDOW_SIZE {
+                break;
+            }
+            let length = self.data[candidate..]
+                .iter()
+                .zip(&self.data[position..position + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best_length {
+                best_length = length;
+                best = Some((length, position - candidate));
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate % WINDOW_SIZE];
+        }
+        best
+    }
+}
+
+/// Packs values into bytes starting at the least significant bit, as DEFLATE requires.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Writes the lowest `count` bits of the value.
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which unlike other values starts with its most significant bit.
+    fn write_code(&mut self, code: u16, length: u32) {
+        let reversed = code.reverse_bits() >> (16 - length);
+        self.write_bits(reversed as u32, length);
+    }
+
+    /// Pads the last byte with zeros.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// The checksum at the end of a zlib stream.
+pub(super) fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1, 0);
+    for byte in data {
+        a = (a + *byte as u32) % MODULUS;
+        b = (b + a) % MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(zlib_compress(&[]), [0x78, 0x01, 0x03, 0x00, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn repeated_bytes() {
+        // A literal, then a match of length 9 at distance 1.
+        let compressed = zlib_compress(&[0xAB; 10]);
+        assert_eq!(
+            compressed[2..compressed.len() - 4],
+            [0x5B, 0x0D, 0x07, 0x00]
+        );
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
@@ -0,0 +1,320 @@
+//! Decompresses zlib streams, for reading PNG files. Unlike the encoder, this supports everything
+//! DEFLATE allows, since other encoders use stored blocks and dynamic Huffman codes as well.
+
+use super::DecodePngError;
+use super::deflate::{
+    DISTANCE_BASES, DISTANCE_EXTRA_BITS, LENGTH_BASES, LENGTH_EXTRA_BITS, adler32,
+};
+
+/// The compression method in the lower 4 bits of the zlib header: DEFLATE.
+const METHOD_DEFLATE: u8 = 8;
+/// Set in the second byte of the zlib header if a preset dictionary is used, which PNG forbids.
+const PRESET_DICTIONARY_BIT: u8 = 0b0010_0000;
+
+const MAX_CODE_LENGTH: usize = 15;
+const END_OF_BLOCK: u16 = 256;
+/// The order in which the lengths of the code length code are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub(super) fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, DecodePngError> {
+    let [method, flags, ..] = *data else {
+        return Err(DecodePngError::UnexpectedEnd);
+    };
+    let header = u16::from_be_bytes([method, flags]);
+    if method & 0x0F != METHOD_DEFLATE
+        || !header.is_multiple_of(31)
+        || flags & PRESET_DICTIONARY_BIT != 0
+    {
+        return Err(DecodePngError::InvalidData);
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let output = inflate(&mut reader)?;
+    let checksum_start = 2 + reader.byte_position();
+    let checksum = data
+        .get(checksum_start..checksum_start + 4)
+        .ok_or(DecodePngError::UnexpectedEnd)?;
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != adler32(&output) {
+        return Err(DecodePngError::InvalidChecksum);
+    }
+    Ok(output)
+}
+
+fn inflate(reader: &mut BitReader) -> Result<Vec<u8>, DecodePngError> {
+    let mut output = Vec::new();
+    loop {
+        let is_final = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => copy_stored_block(reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                decode_block(reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(reader)?;
+                decode_block(reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(DecodePngError::InvalidData),
+        }
+        if is_final {
+            return Ok(output);
+        }
+    }
+}
+
+/// Copies an uncompressed block, which starts at the next byte with its length and the length's
+/// complement.
+fn copy_stored_block(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), DecodePngError> {
+    reader.align_to_byte();
+    let length = reader.read_bits(16)?;
+    let complement = reader.read_bits(16)?;
+    if length != !complement & 0xFFFF {
+        return Err(DecodePngError::InvalidData);
+    }
+    for _ in 0..length {
+        output.push(reader.read_bits(8)? as u8);
+    }
+    Ok(())
+}
+
+fn decode_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), DecodePngError> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(());
+        }
+
+        let index = (symbol - 257) as usize;
+        let (Some(base), Some(extra_bits)) =
+            (LENGTH_BASES.get(index), LENGTH_EXTRA_BITS.get(index))
+        else {
+            return Err(DecodePngError::InvalidData);
+        };
+        let length = *base as usize + reader.read_bits(*extra_bits as u32)? as usize;
+
+        let index = distances.decode(reader)? as usize;
+        let (Some(base), Some(extra_bits)) =
+            (DISTANCE_BASES.get(index), DISTANCE_EXTRA_BITS.get(index))
+        else {
+            return Err(DecodePngError::InvalidData);
+        };
+        let distance = *base as usize + reader.read_bits(*extra_bits as u32)? as usize;
+        if distance > output.len() {
+            return Err(DecodePngError::InvalidData);
+        }
+        // The copy may overlap with itself, repeating the last `distance` bytes.
+        let start = output.len() - distance;
+        for index in start..start + length {
+            output.push(output[index]);
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::new(&lengths).expect("the fixed codes are valid");
+    let distances = Huffman::new(&[5; 30]).expect("the fixed codes are valid");
+    (literals, distances)
+}
+
+/// Reads the codes of a block with dynamic Huffman codes. Their code lengths are themselves
+/// compressed with another Huffman code.
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), DecodePngError> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0; 19];
+    for index in CODE_LENGTH_ORDER.into_iter().take(code_length_count) {
+        code_length_lengths[index] = reader.read_bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    // The lengths of both codes form one sequence, so repetitions may cross from one to the other.
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repetitions) = match code_lengths.decode(reader)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or(DecodePngError::InvalidData)?;
+                (previous, 3 + reader.read_bits(2)?)
+            }
+            17 => (0, 3 + reader.read_bits(3)?),
+            _ => (0, 11 + reader.read_bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repetitions as usize));
+    }
+    if lengths.len() > literal_count + distance_count || lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(DecodePngError::InvalidData);
+    }
+
+    let literals = Huffman::new(&lengths[..literal_count])?;
+    let distances = Huffman::new(&lengths[literal_count..])?;
+    Ok((literals, distances))
+}
+
+/// A canonical Huffman code, where the codes of each length are consecutive numbers assigned to
+/// the symbols in order.
+struct Huffman {
+    /// How many symbols have a code of each length.
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    /// The symbols sorted by the length of their code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Creates the code from the length of each symbol's code, where 0 means the symbol is unused.
+    fn new(lengths: &[u8]) -> Result<Self, DecodePngError> {
+        let mut counts = [0; MAX_CODE_LENGTH + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Each length can have twice as many codes as the previous one, minus the ones that are
+        // prefixes of shorter codes.
+        let mut available = 1;
+        for count in &counts[1..] {
+            available = available * 2 - *count as i32;
+            if available < 0 {
+                return Err(DecodePngError::InvalidData);
+            }
+        }
+
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..=MAX_CODE_LENGTH {
+            for (symbol, _) in lengths
+                .iter()
+                .enumerate()
+                .filter(|(_, symbol_length)| **symbol_length as usize == length)
+            {
+                symbols.push(symbol as u16);
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    /// Reads one code bit by bit, most significant bit first.
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, DecodePngError> {
+        // The first code of the current length, and the index of its symbol.
+        let mut first = 0;
+        let mut index = 0;
+        let mut code = 0;
+        for count in &self.counts[1..] {
+            code |= reader.read_bits(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecodePngError::InvalidData)
+    }
+}
+
+/// Reads values starting at the least significant bit of each byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// The index of the next bit.
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn read_bits(&mut self, count: u32) -> Result<u32, DecodePngError> {
+        let mut value = 0;
+        for bit in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or(DecodePngError::UnexpectedEnd)?;
+            value |= ((*byte as u32 >> (self.position % 8)) & 1) << bit;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.position = self.position.next_multiple_of(8);
+    }
+
+    /// The index of the byte after the last one that was read from.
+    fn byte_position(&self) -> usize {
+        self.position.div_ceil(8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::deflate::zlib_compress;
+
+    #[test]
+    fn fixed_codes_round_trip() {
+        let data: Vec<u8> = (0..5000)
+            .map(|index| (index % 7 * index % 251) as u8)
+            .collect();
+        assert_eq!(zlib_decompress(&zlib_compress(&data)), Ok(data));
+    }
+
+    #[test]
+    fn stored_block() {
+        let data = [0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        let checksum = adler32(b"abc").to_be_bytes();
+        assert_eq!(
+            zlib_decompress(&[&data[..], &checksum].concat()),
+            Ok(b"abc".to_vec())
+        );
+    }
+
+    #[test]
+    fn dynamic_codes() {
+        // Text with a skewed distribution of letters, compressed by zlib at level 9.
+        let data = [
+            0x78, 0xDA, 0x1D, 0x8C, 0x31, 0x0E, 0x00, 0x30, 0x08, 0x02, 0xDF, 0xCA, 0xC0, 0xC0,
+            0x82, 0x43, 0xF9, 0x7F, 0x8A, 0x6A, 0x42, 0xF4, 0x04, 0xC7, 0xDC, 0xAA, 0xAA, 0x22,
+            0xA6, 0x3A, 0x24, 0x54, 0x88, 0xE5, 0x15, 0x26, 0x9D, 0x46, 0xF6, 0xAD, 0x4F, 0xE0,
+            0xA5, 0xE4, 0xDA, 0x7B, 0xC5, 0x26, 0x38, 0xC1, 0xBD, 0x02, 0x4A, 0xDB, 0xBE, 0x58,
+            0xE2, 0x1C, 0x0E, 0x5F, 0x5D, 0x83, 0xB7, 0x31, 0xF0, 0x03, 0x8F, 0xBF, 0x31, 0x18,
+        ];
+        let expected = "oneeeeeneeieneieteneoeeaieeeaeeneaeeetteenoinnneaeesiaeneeeeineteeetaeoeeeot\
+                        aeeeeeaaetetetnetteettnteeeeetesoeeoaseineae";
+        assert_eq!(zlib_decompress(&data), Ok(expected.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn corrupt_data() {
+        let mut data = zlib_compress(b"Hello, World!");
+        assert_eq!(
+            zlib_decompress(&data[..data.len() - 1]),
+            Err(DecodePngError::UnexpectedEnd)
+        );
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert_eq!(zlib_decompress(&data), Err(DecodePngError::InvalidChecksum));
+        assert_eq!(
+            zlib_decompress(&[0x78, 0x02]),
+            Err(DecodePngError::InvalidData)
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Images of the screen, for screenshots. They can be scaled up and recolored with a [`Theme`]
+//! before being saved as PNG files. PNG files can be read as well, to compare the screen to a
+//! reference image.
+
+mod deflate;
+mod inflate;
+mod png;
+
+pub(crate) use png::crc32;
+
+use crate::gameboy::Frame;
+use crate::gpu::palette::{Color, DMG_SHADES};
+use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fmt;
+
+/// Pixels that differ are shown in magenta in a diff image.
+const DIFF_COLOR: Color = Color::new(0xFF, 0x00, 0xFF);
+
+/// An image made up of rows of pixels, from top to bottom.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    /// Creates an image from its pixels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the amount of pixels doesn't match the size.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "wrong amount of pixels");
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn from_frame(frame: &Frame) -> Self {
+        Self::new(SCREEN_WIDTH, SCREEN_HEIGHT, frame.to_vec())
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Replaces the DMG's shades of gray with the theme's colors. Other colors, like the ones
+    /// of CGB and SGB palettes, stay the same.
+    pub fn with_theme(mut self, theme: &Theme) -> Self {
+        for pixel in &mut self.pixels {
+            if let Some(shade) = DMG_SHADES.iter().position(|shade| shade == pixel) {
+                *pixel = theme.shades[shade];
+            }
+        }
+        self
+    }
+
+    /// Enlarges the image by an integer factor, turning every pixel into a square of
+    /// `factor`x`factor` pixels.
+    pub fn scaled(self, factor: usize) -> Self {
+        if factor == 1 {
+            return self;
+        }
+        let mut pixels = Vec::with_capacity(self.pixels.len() * factor * factor);
+        for row in self.pixels.chunks(self.width) {
+            let start = pixels.len();
+            for pixel in row {
+                pixels.extend(std::iter::repeat_n(*pixel, factor));
+            }
+            let end = pixels.len();
+            for _ in 1..factor {
+                pixels.extend_from_within(start..end);
+            }
+        }
+        Self::new(self.width * factor, self.height * factor, pixels)
+    }
+
+    pub fn encode_png(&self) -> Vec<u8> {
+        png::encode(self)
+    }
+
+    /// Reads a PNG file. Transparency is ignored, and interlaced files aren't supported.
+    pub fn decode_png(data: &[u8]) -> Result<Self, DecodePngError> {
+        png::decode(data)
+    }
+
+    /// Compares the image to another one of the same size pixel by pixel. If any pixels differ,
+    /// returns an image showing them in magenta on top of a faded copy of this image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the images have different sizes.
+    pub fn diff(&self, other: &Image) -> Option<Image> {
+        assert_eq!(
+            (self.width, self.height),
+            (other.width, other.height),
+            "images with different sizes can't be compared"
+        );
+        if self.pixels == other.pixels {
+            return None;
+        }
+        let fade = |channel: u8| 0xC0 + channel / 4;
+        let pixels = self
+            .pixels
+            .iter()
+            .zip(&other.pixels)
+            .map(|(pixel, other)| {
+                if pixel == other {
+                    Color::new(fade(pixel.r), fade(pixel.g), fade(pixel.b))
+                } else {
+                    DIFF_COLOR
+                }
+            })
+            .collect();
+        Some(Self::new(self.width, self.height, pixels))
+    }
+}
+
+/// Why a PNG file couldn't be read.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecodePngError {
+    /// The data doesn't start with the PNG signature.
+    NotPng,
+    /// The file ends in the middle of a chunk or of the compressed image data.
+    UnexpectedEnd,
+    /// A chunk or the compressed image data doesn't match its checksum.
+    InvalidChecksum,
+    /// The file is corrupt.
+    InvalidData,
+    /// The file uses a feature that isn't supported, like interlacing.
+    Unsupported,
+}
+
+impl fmt::Display for DecodePngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodePngError::NotPng => write!(f, "not a PNG file"),
+            DecodePngError::UnexpectedEnd => write!(f, "unexpected end of the PNG file"),
+            DecodePngError::InvalidChecksum => write!(f, "checksum mismatch in the PNG file"),
+            DecodePngError::InvalidData => write!(f, "invalid PNG file"),
+            DecodePngError::Unsupported => write!(f, "unsupported PNG file"),
+        }
+    }
+}
+
+impl std::error::Error for DecodePngError {}
+
+/// The colors of a screen that only shows four shades, from lightest to darkest.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Theme {
+    pub shades: [Color; 4],
+}
+
+impl Theme {
+    /// The shades of gray the emulator outputs.
+    pub const GRAY: Self = Self { shades: DMG_SHADES };
+
+    /// The yellowish green of the original DMG's LCD.
+    pub const GREEN: Self = Self {
+        shades: [
+            Color::new(0x9B, 0xBC, 0x0F),
+            Color::new(0x8B, 0xAC, 0x0F),
+            Color::new(0x30, 0x62, 0x30),
+            Color::new(0x0F, 0x38, 0x0F),
+        ],
+    };
+
+    /// The olive grays of the Game Boy Pocket's LCD.
+    pub const POCKET: Self = Self {
+        shades: [
+            Color::new(0xC4, 0xCF, 0xA1),
+            Color::new(0x8B, 0x95, 0x6D),
+            Color::new(0x4D, 0x53, 0x3C),
+            Color::new(0x1F, 0x1F, 0x1F),
+        ],
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color::new(0xFF, 0x00, 0x00);
+
+    #[test]
+    fn scale() {
+        let image = Image::new(2, 1, vec![DMG_SHADES[0], RED]).scaled(3);
+        assert_eq!((image.width(), image.height()), (6, 3));
+        let row = [DMG_SHADES[0], DMG_SHADES[0], DMG_SHADES[0], RED, RED, RED];
+        assert_eq!(image.pixels(), [row, row, row].concat());
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let pixels = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|index| DMG_SHADES[index * 7 / 5 % 4])
+            .collect();
+        let image = Image::new(SCREEN_WIDTH, SCREEN_HEIGHT, pixels).scaled(2);
+        assert_eq!(Image::decode_png(&image.encode_png()), Ok(image));
+    }
+
+    #[test]
+    fn diff() {
+        let image = Image::new(2, 1, vec![DMG_SHADES[0], DMG_SHADES[3]]);
+        assert_eq!(image.diff(&image), None);
+        let other = Image::new(2, 1, vec![DMG_SHADES[0], RED]);
+        let diff = image.diff(&other).unwrap();
+        assert_eq!(diff.pixels(), [Color::new(0xFF, 0xFF, 0xFF), DIFF_COLOR]);
+    }
+
+    #[test]
+    fn theme_only_replaces_shades() {
+        let image = Image::new(3, 1, vec![DMG_SHADES[0], DMG_SHADES[3], RED]);
+        let image = image.with_theme(&Theme::GREEN);
+        assert_eq!(
+            image.pixels(),
+            [Theme::GREEN.shades[0], Theme::GREEN.shades[3], RED]
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Encodes images as PNG files with 8 bits per color channel and no transparency, and decodes
+//! PNG files of any color type and bit depth, as long as they aren't interlaced.
+
+use super::deflate::zlib_compress;
+use super::inflate::zlib_decompress;
+use super::{DecodePngError, Image};
+use crate::gpu::palette::Color;
+
+pub(super) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// The color types, which determine the samples every pixel consists of.
+const COLOR_TYPE_GRAY: u8 = 0;
+const COLOR_TYPE_RGB: u8 = 2;
+/// A single sample that is an index into the palette.
+const COLOR_TYPE_PALETTE: u8 = 3;
+const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+const COLOR_TYPE_RGB_ALPHA: u8 = 6;
+const BIT_DEPTH: u8 = 8;
+
+/// Every row starts with the filter applied to it. The encoder's rows are compressed well enough
+/// without filtering.
+const FILTER_NONE: u8 = 0;
+/// Each byte is stored as the difference to the byte of the previous pixel.
+const FILTER_SUB: u8 = 1;
+/// Each byte is stored as the difference to the byte above it.
+const FILTER_UP: u8 = 2;
+/// Each byte is stored as the difference to the average of the bytes to the left and above.
+const FILTER_AVERAGE: u8 = 3;
+/// Each byte is stored as the difference to whichever of the bytes to the left, above and to the
+/// upper left is closest to a linear prediction from all three.
+const FILTER_PAETH: u8 = 4;
+
+/// The size of the `IHDR` chunk's data.
+const HEADER_SIZE: usize = 13;
+
+pub(super) fn encode(image: &Image) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    // The compression, filter and interlace methods are all the only ones defined.
+    header.extend_from_slice(&[BIT_DEPTH, COLOR_TYPE_RGB, 0, 0, 0]);
+
+    let mut data = Vec::with_capacity((image.width * 3 + 1) * image.height);
+    for row in image.pixels.chunks(image.width) {
+        data.push(FILTER_NONE);
+        for color in row {
+            data.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_compress(&data));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Appends a chunk: Its length, type and data, followed by a checksum of the type and data.
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Image, DecodePngError> {
+    let mut data = data
+        .strip_prefix(&SIGNATURE)
+        .ok_or(DecodePngError::NotPng)?;
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    loop {
+        let (chunk, rest) = read_chunk(data)?;
+        data = rest;
+        match &chunk.kind {
+            b"IHDR" => header = Some(Header::parse(chunk.data)?),
+            b"PLTE" => {
+                palette = chunk
+                    .data
+                    .chunks_exact(3)
+                    .map(|rgb| Color::new(rgb[0], rgb[1], rgb[2]))
+                    .collect();
+            }
+            b"IDAT" => compressed.extend_from_slice(chunk.data),
+            b"IEND" => break,
+            // Other chunks, like the ones with transparency or color space information, don't
+            // affect the colors that are compared.
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or(DecodePngError::InvalidData)?;
+    let mut data = zlib_decompress(&compressed)?;
+    let row_size = (header.width * header.bits_per_pixel()).div_ceil(8);
+    if data.len() != (row_size + 1) * header.height {
+        return Err(DecodePngError::InvalidData);
+    }
+    unfilter(&mut data, row_size, header.bits_per_pixel().div_ceil(8))?;
+
+    let mut pixels = Vec::with_capacity(header.width * header.height);
+    for row in data.chunks_exact(row_size + 1) {
+        let row = &row[1..];
+        for x in 0..header.width {
+            pixels.push(header.pixel(row, x, &palette)?);
+        }
+    }
+    Ok(Image::new(header.width, header.height, pixels))
+}
+
+struct Chunk<'a> {
+    kind: [u8; 4],
+    data: &'a [u8],
+}
+
+/// Splits off the next chunk.
+fn read_chunk(data: &[u8]) -> Result<(Chunk<'_>, &[u8]), DecodePngError> {
+    let (length, rest) = data
+        .split_first_chunk::<4>()
+        .ok_or(DecodePngError::UnexpectedEnd)?;
+    let length = u32::from_be_bytes(*length) as usize;
+    if rest.len() < 4 + length + 4 {
+        return Err(DecodePngError::UnexpectedEnd);
+    }
+    let (checked, rest) = rest.split_at(4 + length);
+    let (crc, rest) = rest.split_at(4);
+    if crc32(checked) != u32::from_be_bytes(crc.try_into().unwrap()) {
+        return Err(DecodePngError::InvalidChecksum);
+    }
+    let (kind, data) = checked.split_at(4);
+    let chunk = Chunk {
+        kind: kind.try_into().unwrap(),
+        data,
+    };
+    Ok((chunk, rest))
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, DecodePngError> {
+        let data: &[u8; HEADER_SIZE] = data.try_into().map_err(|_| DecodePngError::InvalidData)?;
+        let width = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+        let [bit_depth, color_type, compression, filter, interlace] = data[8..] else {
+            unreachable!();
+        };
+        let valid_depths: &[u8] = match color_type {
+            COLOR_TYPE_GRAY => &[1, 2, 4, 8, 16],
+            COLOR_TYPE_PALETTE => &[1, 2, 4, 8],
+            COLOR_TYPE_RGB | COLOR_TYPE_GRAY_ALPHA | COLOR_TYPE_RGB_ALPHA => &[8, 16],
+            _ => &[],
+        };
+        if width == 0 || height == 0 || !valid_depths.contains(&bit_depth) {
+            return Err(DecodePngError::InvalidData);
+        }
+        if compression != 0 || filter != 0 || interlace != 0 {
+            return Err(DecodePngError::Unsupported);
+        }
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+        })
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        let samples = match self.color_type {
+            COLOR_TYPE_RGB => 3,
+            COLOR_TYPE_GRAY_ALPHA => 2,
+            COLOR_TYPE_RGB_ALPHA => 4,
+            _ => 1,
+        };
+        samples * self.bit_depth as usize
+    }
+
+    /// Reads the pixel at the given position of an unfiltered row. Transparency is ignored.
+    fn pixel(&self, row: &[u8], x: usize, palette: &[Color]) -> Result<Color, DecodePngError> {
+        let bit_depth = self.bit_depth as usize;
+        if bit_depth < 8 {
+            let bit = x * bit_depth;
+            let shift = 8 - bit_depth - bit % 8;
+            let value = (row[bit / 8] >> shift) & ((1 << bit_depth) - 1);
+            return match self.color_type {
+                COLOR_TYPE_PALETTE => palette
+                    .get(value as usize)
+                    .copied()
+                    .ok_or(DecodePngError::InvalidData),
+                _ => {
+                    // Scale the value to 8 bits, so that the largest value becomes white.
+                    let gray = (value as usize * 0xFF / ((1 << bit_depth) - 1)) as u8;
+                    Ok(Color::new(gray, gray, gray))
+                }
+            };
+        }
+
+        // Samples with 16 bits are reduced to their more significant byte.
+        let bytes_per_sample = bit_depth / 8;
+        let start = x * self.bits_per_pixel() / 8;
+        let sample = |index: usize| row[start + index * bytes_per_sample];
+        match self.color_type {
+            COLOR_TYPE_GRAY | COLOR_TYPE_GRAY_ALPHA => {
+                Ok(Color::new(sample(0), sample(0), sample(0)))
+            }
+            COLOR_TYPE_PALETTE => palette
+                .get(sample(0) as usize)
+                .copied()
+                .ok_or(DecodePngError::InvalidData),
+            _ => Ok(Color::new(sample(0), sample(1), sample(2))),
+        }
+    }
+}
+
+/// Reverses the filters of all rows in place. Each row starts with its filter type.
+fn unfilter(
+    data: &mut [u8],
+    row_size: usize,
+    bytes_per_pixel: usize,
+) -> Result<(), DecodePngError> {
+    let mut previous = vec![0; row_size];
+    for row in data.chunks_exact_mut(row_size + 1) {
+        let (filter, row) = row.split_first_mut().unwrap();
+        for index in 0..row_size {
+            let left = match index.checked_sub(bytes_per_pixel) {
+                Some(left) => row[left],
+                None => 0,
+            };
+            let above = previous[index];
+            let upper_left = match index.checked_sub(bytes_per_pixel) {
+                Some(left) => previous[left],
+                None => 0,
+            };
+            let prediction = match *filter {
+                FILTER_NONE => 0,
+                FILTER_SUB => left,
+                FILTER_UP => above,
+                FILTER_AVERAGE => ((left as u16 + above as u16) / 2) as u8,
+                FILTER_PAETH => paeth(left, above, upper_left),
+                _ => return Err(DecodePngError::InvalidData),
+            };
+            row[index] = row[index].wrapping_add(prediction);
+        }
+        previous.copy_from_slice(row);
+    }
+    Ok(())
+}
+
+fn paeth(left: u8, above: u8, upper_left: u8) -> u8 {
+    let estimate = left as i16 + above as i16 - upper_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(above) && distance(left) <= distance(upper_left) {
+        left
+    } else if distance(above) <= distance(upper_left) {
+        above
+    } else {
+        upper_left
+    }
+}
+
+/// The CRC of each byte value, for the polynomial used by PNG.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(0xFFFF_FFFF, |crc, byte| {
+        CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn decode_palette_with_filters() {
+        // 3x2 pixels with 2 bits per palette index. The second row uses the "Up" filter.
+        let png = [
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x02, 0x03, 0x00, 0x00,
+            0x00, 0xE0, 0x1A, 0x8E, 0x89, 0x00, 0x00, 0x00, 0x09, 0x50, 0x4C, 0x54, 0x45, 0xFF,
+            0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0x2D, 0x4A, 0xCD, 0x8A, 0x00, 0x00,
+            0x00, 0x0C, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x90, 0x60, 0xEA, 0x01, 0x00,
+            0x00, 0xDC, 0x00, 0xA7, 0x3B, 0xF4, 0xFF, 0x83, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+            0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+        ];
+        let red = Color::new(0xFF, 0, 0);
+        let green = Color::new(0, 0xFF, 0);
+        let blue = Color::new(0, 0, 0xFF);
+        let expected = Image::new(3, 2, vec![red, green, blue, blue, blue, green]);
+        assert_eq!(decode(&png), Ok(expected));
+    }
+
+    #[test]
+    fn decode_16_bit_gray() {
+        // 2x2 pixels, using the "Sub" filter in the first row and "Paeth" in the second.
+        let png = [
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x10, 0x00, 0x00, 0x00,
+            0x00, 0x07, 0x4D, 0x8E, 0xBB, 0x00, 0x00, 0x00, 0x12, 0x49, 0x44, 0x41, 0x54, 0x78,
+            0x9C, 0x63, 0x14, 0x32, 0x99, 0x39, 0x93, 0x85, 0xEF, 0x4C, 0x88, 0x11, 0x00, 0x0D,
+            0xA5, 0x02, 0xDE, 0x73, 0x91, 0x7D, 0x69, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E,
+            0x44, 0xAE, 0x42, 0x60, 0x82,
+        ];
+        let gray = |value| Color::new(value, value, value);
+        let expected = Image::new(2, 2, vec![gray(0x12), gray(0xAB), gray(0x20), gray(0xFF)]);
+        assert_eq!(decode(&png), Ok(expected));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let png = encode(&Image::new(1, 1, vec![Color::default()]));
+        assert_eq!(decode(&png[1..]), Err(DecodePngError::NotPng));
+        assert_eq!(decode(&png[..40]), Err(DecodePngError::UnexpectedEnd));
+        let mut corrupt = png.clone();
+        corrupt[20] ^= 1;
+        assert_eq!(decode(&corrupt), Err(DecodePngError::InvalidChecksum));
+    }
+
+    #[test]
+    fn chunks() {
+        let image = Image::new(2, 1, vec![Color::new(1, 2, 3), Color::new(4, 5, 6)]);
+        let png = encode(&image);
+        assert_eq!(png[..8], SIGNATURE);
+        // IHDR: 13 bytes of data with the width and height.
+        assert_eq!(png[8..16], *b"\0\0\0\x0DIHDR");
+        assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+        assert_eq!(png[29..33], crc32(&png[12..29]).to_be_bytes());
+        // IEND: No data and a fixed checksum.
+        assert_eq!(png[png.len() - 12..], *b"\0\0\0\0IEND\xAE\x42\x60\x82");
+    }
+}
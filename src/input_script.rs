@@ -0,0 +1,141 @@
+//! Scripted input presses buttons at fixed points in time, e.g. to get past a title screen in a
+//! test. A script has one event per line, written as `<frame> press|release <button>`. Empty
+//! lines and everything after a `#` are ignored.
+
+use crate::joypad::Button;
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct InputEvent {
+    /// The frame at whose start the event happens.
+    pub frame: u64,
+    pub button: Button,
+    pub pressed: bool,
+}
+
+/// Button presses and releases, in the order they happen.
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct InputScript {
+    /// Sorted by frame. Events of the same frame keep their order from the script.
+    events: Vec<InputEvent>,
+    /// The index of the first event that hasn't happened yet.
+    next: usize,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, ParseScriptError> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<_> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let event = parse_event(&words).map_err(|message| ParseScriptError {
+                line: index + 1,
+                message,
+            })?;
+            events.push(event);
+        }
+        events.sort_by_key(|event| event.frame);
+        Ok(Self { events, next: 0 })
+    }
+
+    /// Returns the events that are due at the start of the given frame and haven't been returned
+    /// before.
+    pub fn take_due(&mut self, frame: u64) -> &[InputEvent] {
+        let start = self.next;
+        while self
+            .events
+            .get(self.next)
+            .is_some_and(|event| event.frame <= frame)
+        {
+            self.next += 1;
+        }
+        &self.events[start..self.next]
+    }
+}
+
+/// Why a script couldn't be parsed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseScriptError {
+    /// The number of the invalid line, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseScriptError {}
+
+fn parse_event(words: &[&str]) -> Result<InputEvent, String> {
+    let [frame, action, button] = words else {
+        return Err("expected `<frame> press|release <button>`".to_string());
+    };
+    let frame = frame
+        .parse()
+        .map_err(|_| format!("invalid frame: {frame}"))?;
+    let pressed = match *action {
+        "press" => true,
+        "release" => false,
+        _ => return Err(format!("unknown action: {action}")),
+    };
+    let button = parse_button(button)?;
+    Ok(InputEvent {
+        frame,
+        button,
+        pressed,
+    })
+}
+
+fn parse_button(name: &str) -> Result<Button, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "right" => Ok(Button::Right),
+        "left" => Ok(Button::Left),
+        "up" => Ok(Button::Up),
+        "down" => Ok(Button::Down),
+        "a" => Ok(Button::A),
+        "b" => Ok(Button::B),
+        "select" => Ok(Button::Select),
+        "start" => Ok(Button::Start),
+        _ => Err(format!("unknown button: {name}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_in_frame_order() {
+        let mut script = InputScript::parse(
+            "# Skip the title screen.\n\
+             65 release Start\n\
+             \n\
+             60 press start # Hold it for 5 frames.\n\
+             60 press A\n",
+        )
+        .unwrap();
+
+        assert_eq!(script.take_due(59), []);
+        let due = script.take_due(62);
+        assert_eq!(due.len(), 2);
+        assert_eq!((due[0].button, due[0].pressed), (Button::Start, true));
+        assert_eq!((due[1].button, due[1].pressed), (Button::A, true));
+        assert_eq!(script.take_due(62), []);
+        assert_eq!(script.take_due(65)[0].pressed, false);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = InputScript::parse("1 press a\n2 hold b\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: unknown action: hold");
+        assert!(InputScript::parse("1 press turbo").is_err());
+        assert!(InputScript::parse("soon press a").is_err());
+        assert!(InputScript::parse("1 press").is_err());
+    }
+}
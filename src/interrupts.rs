@@ -1,10 +1,12 @@
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter};
+
 #[derive(Default, Copy, Clone)]
 pub(crate) struct InterruptFlags {
-    vblank: bool,
-    lcd: bool,
-    timer: bool,
-    serial: bool,
-    joypad: bool,
+    pub(crate) vblank: bool,
+    pub(crate) lcd: bool,
+    pub(crate) timer: bool,
+    pub(crate) serial: bool,
+    pub(crate) joypad: bool,
 }
 
 const VBLANK_BYTE_POSITION: u8 = 0;
@@ -40,3 +42,15 @@ impl From<InterruptFlags> for u8 {
             | (if flags.joypad { 1 } else { 0 }) << JOYPAD_BYTE_POSITION
     }
 }
+
+impl SaveState for InterruptFlags {
+    fn save(&self, writer: &mut StateWriter) {
+        u8::from(*self).save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        let [byte] = reader.read_bytes()?;
+        *self = byte.into();
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! The eight buttons are read through `P1`, in two groups of four: the directional pad and the
+//! action buttons. The game selects a group by clearing bit 4 or bit 5, after which the lower four
+//! bits report the buttons of that group, with a cleared bit meaning the button is pressed.
+
+use crate::save_state::impl_save_state;
+
+/// Bit 4 of `P1`: Cleared to select the directional pad.
+pub(crate) const SELECT_DIRECTIONS_BIT: u8 = 0b0001_0000;
+/// Bit 5 of `P1`: Cleared to select the action buttons.
+pub(crate) const SELECT_BUTTONS_BIT: u8 = 0b0010_0000;
+/// The bits of `P1` that can be written.
+pub(crate) const SELECT_MASK: u8 = SELECT_DIRECTIONS_BIT | SELECT_BUTTONS_BIT;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    /// The bit of this button in its group, as reported in the lower bits of `P1`.
+    fn bit(self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0b0001,
+            Button::Left | Button::B => 0b0010,
+            Button::Up | Button::Select => 0b0100,
+            Button::Down | Button::Start => 0b1000,
+        }
+    }
+
+    fn is_direction(self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Joypad {
+    /// The pressed directions, one bit per button. Unlike in `P1`, a set bit means pressed.
+    directions: u8,
+    /// The pressed action buttons, one bit per button.
+    buttons: u8,
+    /// The group selection bits written to `P1`.
+    selection: u8,
+}
+
+impl_save_state!(Joypad {
+    directions,
+    buttons,
+    selection,
+});
+
+impl Joypad {
+    pub(crate) fn read(&self) -> u8 {
+        let mut pressed = 0;
+        if self.selection & SELECT_DIRECTIONS_BIT == 0 {
+            pressed |= self.directions;
+        }
+        if self.selection & SELECT_BUTTONS_BIT == 0 {
+            pressed |= self.buttons;
+        }
+        // Bits 6 and 7 are unused and always read as set.
+        0b1100_0000 | self.selection | (!pressed & 0x0F)
+    }
+
+    pub(crate) fn write(&mut self, value: u8) {
+        self.selection = value & SELECT_MASK;
+    }
+
+    /// The group selection bits last written to `P1`.
+    pub(crate) fn selection(&self) -> u8 {
+        self.selection
+    }
+
+    pub(crate) fn is_pressed(&self, button: Button) -> bool {
+        let group = if button.is_direction() {
+            self.directions
+        } else {
+            self.buttons
+        };
+        group & button.bit() != 0
+    }
+
+    /// Presses or releases a button. Returns whether the joypad interrupt is requested, which
+    /// happens when a button of a selected group is pressed.
+    pub(crate) fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let before = self.read();
+        let group = if button.is_direction() {
+            &mut self.directions
+        } else {
+            &mut self.buttons
+        };
+        if pressed {
+            *group |= button.bit();
+        } else {
+            *group &= !button.bit();
+        }
+        // The interrupt fires when any of the lower bits goes from high to low.
+        before & !self.read() & 0x0F != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_selected() {
+        let mut joypad = Joypad::default();
+        joypad.write(SELECT_MASK);
+        joypad.set_button(Button::A, true);
+
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[test]
+    fn select_group() {
+        let mut joypad = Joypad::default();
+        joypad.set_button(Button::Start, true);
+        joypad.set_button(Button::Left, true);
+
+        joypad.write(SELECT_DIRECTIONS_BIT);
+        assert_eq!(joypad.read(), 0b1101_0111);
+        joypad.write(SELECT_BUTTONS_BIT);
+        assert_eq!(joypad.read(), 0b1110_1101);
+    }
+
+    #[test]
+    fn interrupt_only_for_selected_group() {
+        let mut joypad = Joypad::default();
+        joypad.write(SELECT_DIRECTIONS_BIT);
+
+        assert!(!joypad.set_button(Button::Up, true));
+        assert!(joypad.set_button(Button::B, true));
+        assert!(!joypad.set_button(Button::B, false));
+    }
+}
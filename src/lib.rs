@@ -0,0 +1,57 @@
+//! An emulator for the Nintendo Game Boy, Game Boy Color and Super Game Boy.
+//!
+//! [`GameBoy`] is the entry point: It is created from a cartridge's ROM and then run frame by
+//! frame, producing the picture and sound of each frame. Frontends feed in the buttons that are
+//! pressed and can save and restore the cartridge RAM as well as the state of the whole console.
+//! Frames can be saved as PNG screenshots through [`Image`].
+//!
+//! The CPU can also be used on its own: [`Cpu`] runs on any [`Bus`], like plain RAM for testing
+//! instructions or a bus that traces every access.
+
+mod apu;
+mod assembler;
+mod cartridge;
+mod cgb;
+mod cpu;
+mod debug_message;
+mod gameboy;
+mod gpu;
+mod hdma;
+mod image;
+mod input_script;
+mod interrupts;
+mod joypad;
+mod mbc;
+mod memory_bus;
+mod memory_map;
+mod model;
+mod movie;
+mod oam_dma;
+mod rewind;
+mod rom_disassembly;
+mod save_state;
+mod serial;
+mod sgb;
+mod symbols;
+mod timer;
+mod watchpoint;
+
+pub use apu::DEFAULT_SAMPLE_RATE;
+pub use assembler::{AssembleError, assemble};
+pub use cartridge::LoadRomError;
+pub use cgb::compatibility::ManualPalette;
+pub use cpu::{Bus, Cpu, CpuRegisters, Disassembly, disassemble};
+pub use gameboy::{CYCLES_PER_FRAME, Frame, GameBoy, Output};
+pub use gpu::palette::Color;
+pub use gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use image::{DecodePngError, Image, Theme};
+pub use input_script::{InputEvent, InputScript, ParseScriptError};
+pub use joypad::Button;
+pub use model::Model;
+pub use movie::{Movie, MovieError};
+pub use rewind::Rewind;
+pub use rom_disassembly::{AsmFile, disassemble_rom};
+pub use save_state::LoadStateError;
+pub use sgb::{BORDER_HEIGHT, BORDER_WIDTH};
+pub use symbols::{ParseSymbolsError, Symbols};
+pub use watchpoint::{AccessKind, Watchpoint, WatchpointHit};
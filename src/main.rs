@@ -1,9 +0,0 @@
-mod cpu;
-mod gpu;
-mod interrupts;
-mod memory_bus;
-mod memory_map;
-
-fn main() {
-    println!("Hello, world!");
-}
@@ -0,0 +1,477 @@
+//! Cartridges with more than 32 KiB of ROM or 8 KiB of RAM contain a memory bank controller
+//! (MBC), which maps one bank of ROM into `0x4000`-`0x7FFF` and one bank of RAM into
+//! `0xA000`-`0xBFFF` at a time. Games select the banks by writing to the ROM, which the MBC
+//! intercepts. The MBC3 can also contain a real-time clock, whose registers are mapped in place of
+//! the RAM.
+//! Like [`crate::hdma`], this only keeps track of the registers; the ROM and RAM are owned by
+//! [`crate::memory_bus::MemoryBus`].
+
+use crate::cartridge::{Header, LoadRomError, Mapper};
+use crate::memory_map::{CARTRIDGE_RAM_SIZE, GAME_ROM_BANK_N_SIZE};
+use crate::model::Model;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter, impl_save_state};
+
+/// Writing a value with this lower nibble to `0x0000`-`0x1FFF` enables the RAM, anything else
+/// disables it.
+const RAM_ENABLE: u8 = 0x0A;
+/// The MBC3 maps the clock's registers instead of RAM for these values of the RAM bank register.
+const RTC_SECONDS: u8 = 0x08;
+const RTC_DAYS_HIGH: u8 = 0x0C;
+/// Bit 0 of the upper day register: Bit 8 of the day counter.
+const DAYS_HIGH_BIT: u8 = 0b0000_0001;
+/// Bit 6 of the upper day register: Stops the clock.
+const HALT_BIT: u8 = 0b0100_0000;
+/// Bit 7 of the upper day register: Set when the day counter overflows, until it's cleared.
+const DAY_CARRY_BIT: u8 = 0b1000_0000;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub(super) struct Mbc {
+    /// Which controller the cartridge contains. Like the ROM, it isn't part of the state.
+    mapper: Mapper,
+    has_rtc: bool,
+    /// The amount of ROM banks, which is always a power of two.
+    rom_bank_count: usize,
+    /// The dots that make up a second of the clock. The SGB runs faster than the clock.
+    clock_rate: u32,
+    /// Whether the RAM and clock can be accessed.
+    ram_enabled: bool,
+    /// The ROM bank mapped to `0x4000`-`0x7FFF`. The MBC1 only uses the lower 5 bits, the MBC3 7
+    /// and the MBC5 9.
+    rom_bank: u16,
+    /// The RAM bank mapped to `0xA000`-`0xBFFF`. On the MBC1, these two bits also select the
+    /// upper bits of the ROM bank. On the MBC3, this selects a register of the clock instead if
+    /// it's at least `0x08`.
+    ram_bank: u8,
+    /// Set through `0x6000`-`0x7FFF` on the MBC1: The upper two bits of the ROM bank also apply to
+    /// `0x0000`-`0x3FFF`, and select the RAM bank.
+    advanced_banking: bool,
+    rtc: Rtc,
+}
+
+/// A cartridge without an MBC and with 32 KiB of ROM.
+impl Default for Mbc {
+    fn default() -> Self {
+        Self {
+            mapper: Mapper::None,
+            has_rtc: false,
+            rom_bank_count: 2,
+            clock_rate: Model::default().clock_rate(),
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            advanced_banking: false,
+            rtc: Rtc::default(),
+        }
+    }
+}
+
+impl Mbc {
+    /// Sets up the MBC for a cartridge with the given amount of ROM banks, a power of two,
+    /// inserted into the given model. Fails if the cartridge contains an MBC that isn't emulated.
+    pub(super) fn new(
+        header: Option<&Header>,
+        rom_bank_count: usize,
+        model: Model,
+    ) -> Result<Self, LoadRomError> {
+        Ok(Self {
+            mapper: header.map_or(Ok(Mapper::None), Header::mapper)?,
+            has_rtc: header.is_some_and(Header::has_rtc),
+            rom_bank_count,
+            clock_rate: model.clock_rate(),
+            ..Self::default()
+        })
+    }
+
+    /// Writes to one of the registers in `0x0000`-`0x7FFF`.
+    pub(super) fn write_register(&mut self, address: u16, value: u8) {
+        match (self.mapper, address) {
+            (Mapper::None, _) => (),
+            (Mapper::Mbc5, 0x0000..=0x1FFF) => self.ram_enabled = value == RAM_ENABLE,
+            (_, 0x0000..=0x1FFF) => self.ram_enabled = value & 0x0F == RAM_ENABLE,
+            // Bank 0 can't be mapped there, as it's always mapped to `0x0000`-`0x3FFF` anyway.
+            // The MBC1 only checks the lower 5 bits for that.
+            (Mapper::Mbc1, 0x2000..=0x3FFF) => self.rom_bank = (value & 0x1F).max(1) as u16,
+            (Mapper::Mbc3, 0x2000..=0x3FFF) => self.rom_bank = (value & 0x7F).max(1) as u16,
+            (Mapper::Mbc5, 0x2000..=0x2FFF) => self.rom_bank = self.rom_bank & 0x100 | value as u16,
+            (Mapper::Mbc5, 0x3000..=0x3FFF) => {
+                self.rom_bank = ((value & 0x01) as u16) << 8 | self.rom_bank & 0xFF
+            }
+            (Mapper::Mbc1, 0x4000..=0x5FFF) => self.ram_bank = value & 0x03,
+            (Mapper::Mbc3, 0x4000..=0x5FFF) => self.ram_bank = value & 0x0F,
+            (Mapper::Mbc5, 0x4000..=0x5FFF) => self.ram_bank = value & 0x0F,
+            (Mapper::Mbc1, 0x6000..=0x7FFF) => self.advanced_banking = value & 0x01 != 0,
+            (Mapper::Mbc3, 0x6000..=0x7FFF) if self.has_rtc => self.rtc.write_latch(value),
+            _ => (),
+        }
+    }
+
+    /// The ROM bank mapped to `0x0000`-`0x3FFF`.
+    pub(super) fn rom_bank_0(&self) -> usize {
+        match self.mapper {
+            Mapper::Mbc1 if self.advanced_banking => {
+                ((self.ram_bank as usize) << 5) & (self.rom_bank_count - 1)
+            }
+            _ => 0,
+        }
+    }
+
+    /// The ROM bank mapped to `0x4000`-`0x7FFF`.
+    pub(super) fn rom_bank_n(&self) -> usize {
+        let bank = match self.mapper {
+            Mapper::None => 1,
+            Mapper::Mbc1 => (self.ram_bank as usize) << 5 | self.rom_bank as usize,
+            Mapper::Mbc3 | Mapper::Mbc5 => self.rom_bank as usize,
+        };
+        bank & (self.rom_bank_count - 1)
+    }
+
+    /// The RAM bank mapped to `0xA000`-`0xBFFF`, unless it's replaced by a clock register.
+    fn ram_bank(&self) -> usize {
+        match self.mapper {
+            Mapper::None => 0,
+            Mapper::Mbc1 if self.advanced_banking => self.ram_bank as usize,
+            Mapper::Mbc1 => 0,
+            Mapper::Mbc3 | Mapper::Mbc5 => self.ram_bank as usize,
+        }
+    }
+
+    /// The offset into the cartridge RAM an address in `0xA000`-`0xBFFF` maps to, or [`None`] if
+    /// it isn't mapped to the RAM. RAM smaller than a bank is mirrored.
+    fn ram_offset(&self, ram: &[u8], offset: usize) -> Option<usize> {
+        let disabled = self.mapper != Mapper::None && !self.ram_enabled;
+        if disabled || ram.is_empty() || self.rtc_register().is_some() {
+            return None;
+        }
+        Some((self.ram_bank() * CARTRIDGE_RAM_SIZE + offset) % ram.len())
+    }
+
+    /// The clock register mapped to `0xA000`-`0xBFFF`, if any.
+    fn rtc_register(&self) -> Option<usize> {
+        let is_rtc = self.mapper == Mapper::Mbc3 && self.ram_bank >= RTC_SECONDS;
+        is_rtc.then(|| (self.ram_bank - RTC_SECONDS) as usize)
+    }
+
+    /// Reads from `0xA000`-`0xBFFF`. Disabled or missing RAM reads as `0xFF`.
+    pub(super) fn read_ram(&self, ram: &[u8], offset: usize) -> u8 {
+        match self.rtc_register() {
+            Some(register) if self.has_rtc && self.ram_enabled => self.rtc.read(register),
+            _ => self
+                .ram_offset(ram, offset)
+                .map_or(0xFF, |offset| ram[offset]),
+        }
+    }
+
+    /// Writes to `0xA000`-`0xBFFF`. Writes to disabled or missing RAM are ignored.
+    pub(super) fn write_ram(&mut self, ram: &mut [u8], offset: usize, value: u8) {
+        match self.rtc_register() {
+            Some(register) if self.has_rtc && self.ram_enabled => self.rtc.write(register, value),
+            _ => {
+                if let Some(offset) = self.ram_offset(ram, offset) {
+                    ram[offset] = value;
+                }
+            }
+        }
+    }
+
+    /// Sets the clock to the given amount of seconds, and latches it.
+    pub(super) fn set_rtc_seconds(&mut self, seconds: u64) {
+        self.rtc.set_seconds(seconds);
+    }
+
+    /// The time the clock shows, in seconds.
+    pub(super) fn rtc_seconds(&self) -> u64 {
+        self.rtc.seconds()
+    }
+
+    /// Advances the clock by the given amount of dots. The clock runs on its own crystal, so
+    /// it isn't affected by double speed mode.
+    pub(super) fn step(&mut self, dots: u32) {
+        if self.has_rtc {
+            self.rtc.step(dots, self.clock_rate);
+        }
+    }
+}
+
+/// The type of controller isn't part of the state, as it's given by the cartridge.
+impl SaveState for Mbc {
+    fn save(&self, writer: &mut StateWriter) {
+        self.ram_enabled.save(writer);
+        self.rom_bank.save(writer);
+        self.ram_bank.save(writer);
+        self.advanced_banking.save(writer);
+        self.rtc.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.ram_enabled.load(reader)?;
+        self.rom_bank.load(reader)?;
+        self.ram_bank.load(reader)?;
+        self.advanced_banking.load(reader)?;
+        self.rtc.load(reader)?;
+        let registers_valid = [self.rtc.registers, self.rtc.latched]
+            .iter()
+            .flatten()
+            .zip(RTC_MASKS.iter().cycle())
+            .all(|(value, mask)| value & mask == *value);
+        if !registers_valid || self.rtc.dots >= self.clock_rate {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+/// The real-time clock of an MBC3. It only advances with the emulated time from where it was set,
+/// so a game always runs the same way no matter when it's played.
+#[derive(Default)]
+struct Rtc {
+    /// The seconds, minutes, hours, lower 8 bits of the day counter, and the upper day register.
+    registers: [u8; 5],
+    /// The registers as they were when they were last latched. Reads return these, so all
+    /// registers can be read without them changing in between.
+    latched: [u8; 5],
+    /// The dots that have passed since the seconds were last incremented.
+    dots: u32,
+    /// Whether the last value written to the latch register was 0. Writing 1 afterwards latches
+    /// the registers.
+    latch_armed: bool,
+}
+
+impl_save_state!(Rtc {
+    registers,
+    latched,
+    dots,
+    latch_armed,
+});
+
+/// The bits of each register that exist in hardware.
+const RTC_MASKS: [u8; 5] = [
+    0x3F,
+    0x3F,
+    0x1F,
+    0xFF,
+    DAY_CARRY_BIT | HALT_BIT | DAYS_HIGH_BIT,
+];
+
+impl Rtc {
+    fn read(&self, register: usize) -> u8 {
+        // Registers past the upper day register don't exist.
+        self.latched.get(register).copied().unwrap_or(0xFF)
+    }
+
+    fn write(&mut self, register: usize, value: u8) {
+        if register > (RTC_DAYS_HIGH - RTC_SECONDS) as usize {
+            return;
+        }
+        self.registers[register] = value & RTC_MASKS[register];
+        // Writing the seconds restarts the current second.
+        if register == 0 {
+            self.dots = 0;
+        }
+    }
+
+    fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 1 {
+            self.latched = self.registers;
+        }
+        self.latch_armed = value == 0;
+    }
+
+    /// Sets the registers to the given amount of seconds. Days past the range of the day counter
+    /// wrap around and set the carry bit, like they do when the clock counts up to them.
+    fn set_seconds(&mut self, seconds: u64) {
+        let days = seconds / SECONDS_PER_DAY;
+        let carry = if days > 0x1FF { DAY_CARRY_BIT } else { 0 };
+        self.registers = [
+            (seconds % 60) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds / 3600 % 24) as u8,
+            days as u8,
+            carry | (days >> 8) as u8 & DAYS_HIGH_BIT,
+        ];
+        self.latched = self.registers;
+        self.dots = 0;
+    }
+
+    /// The time the registers show, in seconds. The carry bit counts as another 512 days.
+    fn seconds(&self) -> u64 {
+        let [seconds, minutes, hours, days_low, days_high] = self.registers.map(u64::from);
+        let carry = if days_high & DAY_CARRY_BIT as u64 != 0 {
+            0x200
+        } else {
+            0
+        };
+        let days = carry | (days_high & DAYS_HIGH_BIT as u64) << 8 | days_low;
+        days * SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds
+    }
+
+    fn step(&mut self, dots: u32, clock_rate: u32) {
+        if self.registers[4] & HALT_BIT != 0 {
+            return;
+        }
+        self.dots += dots;
+        while self.dots >= clock_rate {
+            self.dots -= clock_rate;
+            self.tick_second();
+        }
+    }
+
+    /// Advances the clock by a second. Each counter only carries into the next one once it
+    /// reaches its limit. Values above the limit, which can only be written, count up until the
+    /// register overflows instead, without carrying.
+    fn tick_second(&mut self) {
+        const LIMITS: [u8; 3] = [60, 60, 24];
+        for (register, limit) in LIMITS.into_iter().enumerate() {
+            let value = self.registers[register] + 1;
+            if value == limit {
+                self.registers[register] = 0;
+            } else {
+                self.registers[register] = value & RTC_MASKS[register];
+                return;
+            }
+        }
+
+        let days = ((self.registers[4] & DAYS_HIGH_BIT) as u16) << 8 | self.registers[3] as u16;
+        let days = days + 1;
+        self.registers[3] = days as u8;
+        self.registers[4] &= !DAYS_HIGH_BIT;
+        if days > 0x1FF {
+            self.registers[4] |= DAY_CARRY_BIT;
+        } else {
+            self.registers[4] |= (days >> 8) as u8;
+        }
+    }
+}
+
+/// The amount of ROM banks a ROM of the given size occupies. Partial banks are padded, and the
+/// amount is rounded up to a power of two, with at least the two banks that are always mapped.
+pub(super) fn rom_bank_count(rom_size: usize) -> usize {
+    rom_size
+        .div_ceil(GAME_ROM_BANK_N_SIZE)
+        .next_power_of_two()
+        .max(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save_state::copy_state;
+
+    fn header(cartridge_type: u8, ram_size: u8) -> Header {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = cartridge_type;
+        rom[0x0149] = ram_size;
+        Header::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn mbc1_rom_banks() {
+        let mut mbc = Mbc::new(Some(&header(0x01, 0x00)), 128, Model::Dmg).unwrap();
+        assert_eq!(mbc.rom_bank_n(), 1);
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.rom_bank_n(), 1);
+        // Only the lower 5 bits are checked for 0, so bank 0x20 maps 0x21 instead.
+        mbc.write_register(0x2000, 0x20);
+        mbc.write_register(0x4000, 0x01);
+        assert_eq!(mbc.rom_bank_n(), 0x21);
+        assert_eq!(mbc.rom_bank_0(), 0);
+
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.rom_bank_0(), 0x20);
+    }
+
+    #[test]
+    fn rom_banks_are_masked() {
+        let mut mbc = Mbc::new(Some(&header(0x19, 0x00)), 4, Model::Dmg).unwrap();
+        mbc.write_register(0x2000, 0x07);
+        assert_eq!(mbc.rom_bank_n(), 3);
+        // Unlike the other MBCs, the MBC5 can map bank 0.
+        mbc.write_register(0x2000, 0x00);
+        assert_eq!(mbc.rom_bank_n(), 0);
+    }
+
+    #[test]
+    fn mbc5_ninth_bank_bit() {
+        let mut mbc = Mbc::new(Some(&header(0x19, 0x00)), 512, Model::Dmg).unwrap();
+        mbc.write_register(0x2000, 0x34);
+        mbc.write_register(0x3000, 0x01);
+        assert_eq!(mbc.rom_bank_n(), 0x134);
+    }
+
+    #[test]
+    fn ram_needs_to_be_enabled() {
+        let mut mbc = Mbc::new(Some(&header(0x1A, 0x03)), 2, Model::Dmg).unwrap();
+        let mut ram = vec![0; 0x8000];
+        mbc.write_ram(&mut ram, 0x10, 1);
+        assert_eq!(mbc.read_ram(&ram, 0x10), 0xFF);
+
+        mbc.write_register(0x0000, RAM_ENABLE);
+        mbc.write_register(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0x10, 1);
+        assert_eq!(mbc.read_ram(&ram, 0x10), 1);
+        assert_eq!(ram[0x4010], 1);
+    }
+
+    #[test]
+    fn rtc_counts_emulated_time() {
+        let mut mbc = Mbc::new(Some(&header(0x10, 0x03)), 2, Model::Dmg).unwrap();
+        mbc.clock_rate = 100;
+        mbc.write_register(0x0000, RAM_ENABLE);
+        mbc.step(59 * 100 + 99);
+        mbc.write_register(0x6000, 0x00);
+        mbc.write_register(0x6000, 0x01);
+        mbc.write_register(0x4000, RTC_SECONDS);
+        assert_eq!(mbc.read_ram(&[], 0), 59);
+
+        // The latched value stays until the registers are latched again.
+        mbc.step(1);
+        assert_eq!(mbc.read_ram(&[], 0), 59);
+        mbc.write_register(0x6000, 0x00);
+        mbc.write_register(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&[], 0), 0);
+        mbc.write_register(0x4000, RTC_SECONDS + 1);
+        assert_eq!(mbc.read_ram(&[], 0), 1);
+    }
+
+    #[test]
+    fn rtc_day_carry() {
+        let mut rtc = Rtc {
+            registers: [59, 59, 23, 0xFF, DAYS_HIGH_BIT],
+            ..Rtc::default()
+        };
+        rtc.tick_second();
+        assert_eq!(rtc.registers, [0, 0, 0, 0, DAY_CARRY_BIT]);
+
+        rtc.registers[4] |= HALT_BIT;
+        rtc.step(100, 100);
+        assert_eq!(rtc.registers[0], 0);
+    }
+
+    #[test]
+    fn rtc_seconds() {
+        let mut rtc = Rtc::default();
+        rtc.set_seconds(3 * SECONDS_PER_DAY + 4 * 3600 + 5 * 60 + 6);
+        assert_eq!(rtc.latched, [6, 5, 4, 3, 0]);
+
+        let seconds = 0x1FE * SECONDS_PER_DAY + 59;
+        rtc.set_seconds(seconds);
+        assert_eq!(rtc.registers, [59, 0, 0, 0xFE, DAYS_HIGH_BIT]);
+        assert_eq!(rtc.seconds(), seconds);
+
+        // Too many days set the carry bit, which counts as 512 days.
+        rtc.set_seconds(0x401 * SECONDS_PER_DAY);
+        assert_eq!(rtc.registers, [0, 0, 0, 0x01, DAY_CARRY_BIT]);
+        assert_eq!(rtc.seconds(), 0x201 * SECONDS_PER_DAY);
+    }
+
+    #[test]
+    fn invalid_rtc_state() {
+        let header = header(0x10, 0x03);
+        let mut mbc = Mbc::new(Some(&header), 2, Model::Dmg).unwrap();
+        mbc.rtc.registers[2] = 0x20;
+        let mut target = Mbc::new(Some(&header), 2, Model::Dmg).unwrap();
+        assert_eq!(
+            copy_state(&mbc, &mut target),
+            Err(LoadStateError::InvalidValue)
+        );
+    }
+}
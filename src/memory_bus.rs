@@ -1,39 +1,164 @@
-use super::gpu::{GPU, VRAM_BEGIN, VRAM_END};
+use super::gpu::palette::Color;
+use super::gpu::{GPU, Mode, SCREEN_HEIGHT, SCREEN_WIDTH, VRAM_BEGIN, VRAM_END};
+use super::hdma::{BLOCK_SIZE, Hdma, Transfer};
 use super::interrupts::InterruptFlags;
+use super::oam_dma::OamDma;
+use crate::apu::Apu;
+use crate::cartridge::{CgbSupport, Header, LoadRomError};
+use crate::cgb::CgbMode;
+use crate::cgb::compatibility::{PaletteSelection, select_palettes};
+use crate::joypad::{Button, Joypad};
+use crate::mbc::{self, Mbc};
 use crate::memory_map::*;
+use crate::model::Model;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter};
+use crate::serial::Serial;
+use crate::sgb::Sgb;
+use crate::timer::Timer;
+use crate::watchpoint::{AccessKind, Watchpoint, WatchpointHit};
 
 pub(super) struct MemoryBus {
     /// The boot ROM of the emulator. Gets unloaded after the code from the cartridge has been loaded.
     boot_rom: Option<[u8; BOOT_ROM_SIZE]>,
-    rom_bank_0: [u8; GAME_ROM_BANK_0_SIZE],
-    rom_bank_n: [u8; GAME_ROM_BANK_N_SIZE],
-    cartridge_ram: [u8; CARTRIDGE_RAM_SIZE],
-    working_ram: [u8; WORKING_RAM_SIZE],
+    /// The cartridge's ROM, padded to a power of two of banks.
+    rom: Vec<u8>,
+    /// The cartridge's RAM, which is empty if it has none.
+    cartridge_ram: Vec<u8>,
+    mbc: Mbc,
+    /// All working RAM banks. The DMG only uses the first two.
+    working_ram: [[u8; WORKING_RAM_BANK_SIZE]; WORKING_RAM_BANK_COUNT],
+    /// The bank mapped to `0xD000`-`0xDFFF`, selected by `SVBK`.
+    working_ram_bank: usize,
     high_ram: [u8; HIGH_RAM_SIZE],
-    /// The interrupt master enable flag. Controls whether _any_ type of interrupt is handled.
-    /// Can only be written to, not read from. Set by `EI`, `DI` and `RETI` instructions.
-    ime: bool,
     /// Controls whether the corresponding interrupt handler may be called.
     interrupt_enable: InterruptFlags,
     /// Controls whether the corresponding interrupt handler is being requested.
-    /// The execution of an interrupt only happens if both [`Self.ime`] and [`Self.interrupt_enable`] are true.
+    /// The execution of an interrupt only happens if both the CPU's IME flag and
+    /// [`Self.interrupt_enable`] are set.
     interrupt_flag: InterruptFlags,
     gpu: GPU,
+    apu: Apu,
+    timer: Timer,
+    serial: Serial,
+    joypad: Joypad,
+    /// Only present on an SGB running a game that supports it.
+    sgb: Option<Sgb>,
+    /// Backing storage for the I/O registers that aren't handled by any component yet.
+    io_registers: [u8; IO_REGISTER_SIZE],
+    /// The hardware being emulated.
+    model: Model,
+    /// Whether CGB-only features like VRAM banking, VRAM DMA and double speed mode are available.
+    cgb_mode: CgbMode,
+    /// Whether the CPU runs at twice its normal clock speed. CGB only.
+    double_speed: bool,
+    /// Set through `KEY1`. The next `STOP` instruction switches the speed if this is set.
+    speed_switch_armed: bool,
+    hdma: Hdma,
+    /// The amount of CPU cycles the CPU can't execute anything because a VRAM DMA is running.
+    dma_stall_cycles: u32,
+    oam_dma: OamDma,
+    /// Set when the PPU finishes a frame by entering VBlank.
+    frame_finished: bool,
+    /// Makes LY always read as 0x90, like Gameboy Doctor expects. Not part of the state, as it's
+    /// only used for debugging.
+    stub_ly: bool,
+    /// The CPU's accesses that are recorded in `watchpoint_hits`. Not part of the state either.
+    watchpoints: Vec<Watchpoint>,
+    watchpoint_hits: Vec<WatchpointHit>,
 }
 
+/// The cartridge's ROM isn't part of the state, as it can't change.
+impl SaveState for MemoryBus {
+    fn save(&self, writer: &mut StateWriter) {
+        self.cartridge_ram.save(writer);
+        self.mbc.save(writer);
+        self.working_ram.save(writer);
+        self.working_ram_bank.save(writer);
+        self.high_ram.save(writer);
+        self.interrupt_enable.save(writer);
+        self.interrupt_flag.save(writer);
+        self.gpu.save(writer);
+        self.apu.save(writer);
+        self.timer.save(writer);
+        self.serial.save(writer);
+        self.joypad.save(writer);
+        self.sgb.save(writer);
+        self.io_registers.save(writer);
+        self.model.save(writer);
+        self.cgb_mode.save(writer);
+        self.double_speed.save(writer);
+        self.speed_switch_armed.save(writer);
+        self.hdma.save(writer);
+        self.dma_stall_cycles.save(writer);
+        self.oam_dma.save(writer);
+        self.frame_finished.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        let cartridge_ram_size = self.cartridge_ram.len();
+        self.cartridge_ram.load(reader)?;
+        self.mbc.load(reader)?;
+        self.working_ram.load(reader)?;
+        self.working_ram_bank.load(reader)?;
+        self.high_ram.load(reader)?;
+        self.interrupt_enable.load(reader)?;
+        self.interrupt_flag.load(reader)?;
+        self.gpu.load(reader)?;
+        self.apu.load(reader)?;
+        self.timer.load(reader)?;
+        self.serial.load(reader)?;
+        self.joypad.load(reader)?;
+        self.sgb.load(reader)?;
+        self.io_registers.load(reader)?;
+        self.model.load(reader)?;
+        self.cgb_mode.load(reader)?;
+        self.double_speed.load(reader)?;
+        self.speed_switch_armed.load(reader)?;
+        self.hdma.load(reader)?;
+        self.dma_stall_cycles.load(reader)?;
+        self.oam_dma.load(reader)?;
+        self.frame_finished.load(reader)?;
+        let working_ram_bank_valid = (1..WORKING_RAM_BANK_COUNT).contains(&self.working_ram_bank);
+        if !working_ram_bank_valid || self.cartridge_ram.len() != cartridge_ram_size {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+/// The boot ROM leaves the VBlank interrupt requested.
+const INTERRUPT_FLAG_AFTER_BOOT: u8 = 0x01;
+
 impl Default for MemoryBus {
     fn default() -> Self {
         Self {
             boot_rom: Some([0; BOOT_ROM_SIZE]),
-            rom_bank_0: [0; GAME_ROM_BANK_0_SIZE],
-            rom_bank_n: [0; GAME_ROM_BANK_N_SIZE],
-            cartridge_ram: [0; CARTRIDGE_RAM_SIZE],
-            working_ram: [0; WORKING_RAM_SIZE],
+            rom: vec![0; GAME_ROM_BANK_0_SIZE + GAME_ROM_BANK_N_SIZE],
+            cartridge_ram: Vec::new(),
+            mbc: Mbc::default(),
+            working_ram: [[0; WORKING_RAM_BANK_SIZE]; WORKING_RAM_BANK_COUNT],
+            working_ram_bank: 1,
             high_ram: [0; HIGH_RAM_SIZE],
-            ime: false,
             interrupt_enable: InterruptFlags::default(),
             interrupt_flag: InterruptFlags::default(),
             gpu: GPU::default(),
+            apu: Apu::default(),
+            timer: Timer::default(),
+            serial: Serial::default(),
+            joypad: Joypad::default(),
+            sgb: None,
+            io_registers: [0; IO_REGISTER_SIZE],
+            model: Model::default(),
+            cgb_mode: CgbMode::default(),
+            double_speed: false,
+            speed_switch_armed: false,
+            hdma: Hdma::default(),
+            dma_stall_cycles: 0,
+            oam_dma: OamDma::default(),
+            frame_finished: false,
+            stub_ly: false,
+            watchpoints: Vec::new(),
+            watchpoint_hits: Vec::new(),
         }
     }
 }
@@ -43,25 +168,30 @@ impl MemoryBus {
     pub(super) fn read_byte(&self, address: u16) -> u8 {
         let address = address as usize;
         match address {
-            BOOT_ROM_START..=BOOT_ROM_END => {
-                if let Some(boot_rom) = self.boot_rom {
-                    boot_rom[address]
-                } else {
-                    self.rom_bank_0[address]
-                }
+            GAME_ROM_BANK_0_START..=GAME_ROM_BANK_0_END => match self.boot_rom {
+                Some(boot_rom) if address <= BOOT_ROM_END => boot_rom[address - BOOT_ROM_START],
+                _ => self.rom[self.mbc.rom_bank_0() * GAME_ROM_BANK_0_SIZE + address],
+            },
+            GAME_ROM_BANK_N_START..=GAME_ROM_BANK_N_END => {
+                self.rom
+                    [self.mbc.rom_bank_n() * GAME_ROM_BANK_N_SIZE + address - GAME_ROM_BANK_N_START]
+            }
+            VRAM_BEGIN..=VRAM_END => self.gpu.read_vram(address - VRAM_BEGIN),
+            CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => self
+                .mbc
+                .read_ram(&self.cartridge_ram, address - CARTRIDGE_RAM_START),
+            WORKING_RAM_START..=WORKING_RAM_END => {
+                let (bank, offset) = self.working_ram_location(address - WORKING_RAM_START);
+                self.working_ram[bank][offset]
             }
-            GAME_ROM_BANK_0_START..=GAME_ROM_BANK_0_END => self.rom_bank_0[address],
-            GAME_ROM_BANK_N_START..=GAME_ROM_BANK_N_END => self.rom_bank_n[address],
-            VRAM_BEGIN..=VRAM_END => self.gpu.read_vram(address),
-            CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => {
-                self.cartridge_ram[address - CARTRIDGE_RAM_START]
+            ECHO_RAM_START..=ECHO_RAM_END => {
+                let (bank, offset) = self.working_ram_location(address - ECHO_RAM_START);
+                self.working_ram[bank][offset]
             }
-            WORKING_RAM_START..=WORKING_RAM_END => self.working_ram[address - WORKING_RAM_START],
-            ECHO_RAM_START..=ECHO_RAM_END => self.working_ram[address - ECHO_RAM_START],
+            // OAM can't be accessed while an OAM DMA is writing to it.
+            OAM_START..=OAM_END if self.oam_dma.is_active() => 0xFF,
             OAM_START..=OAM_END => self.gpu.read_oam(address - OAM_START),
-            IO_REGISTER_START..=IO_REGISTER_END => {
-                self.read_io_register(address - IO_REGISTER_START)
-            }
+            IO_REGISTER_START..=IO_REGISTER_END => self.read_io_register(address),
             UNUSED_MEMORY_START..=UNUSED_MEMORY_END => 0,
             HIGH_RAM_START..=HIGH_RAM_END => self.high_ram[address - HIGH_RAM_START],
             INTERRUPT_ENABLE_REGISTER => self.interrupt_enable.into(),
@@ -73,18 +203,27 @@ impl MemoryBus {
     pub(super) fn write_byte(&mut self, address: u16, value: u8) {
         let address = address as usize;
         match address {
-            GAME_ROM_BANK_0_START..=GAME_ROM_BANK_0_END => self.rom_bank_0[address] = value,
-            VRAM_BEGIN..=VRAM_END => self.gpu.write_vram(address - VRAM_BEGIN, value),
-            CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => {
-                self.cartridge_ram[address - CARTRIDGE_RAM_START] = value
+            // The ROM can't be written to, but the MBC intercepts the writes.
+            GAME_ROM_BANK_0_START..=GAME_ROM_BANK_N_END => {
+                self.mbc.write_register(address as u16, value)
             }
+            VRAM_BEGIN..=VRAM_END => self.gpu.write_vram(address - VRAM_BEGIN, value),
+            CARTRIDGE_RAM_START..=CARTRIDGE_RAM_END => self.mbc.write_ram(
+                &mut self.cartridge_ram,
+                address - CARTRIDGE_RAM_START,
+                value,
+            ),
             WORKING_RAM_START..=WORKING_RAM_END => {
-                self.working_ram[address - WORKING_RAM_START] = value
+                let (bank, offset) = self.working_ram_location(address - WORKING_RAM_START);
+                self.working_ram[bank][offset] = value
             }
-            OAM_START..=OAM_END => self.gpu.write_oam(address - OAM_START, value),
-            IO_REGISTER_START..=IO_REGISTER_END => {
-                self.write_io_register(address - IO_REGISTER_START, value)
+            ECHO_RAM_START..=ECHO_RAM_END => {
+                let (bank, offset) = self.working_ram_location(address - ECHO_RAM_START);
+                self.working_ram[bank][offset] = value
             }
+            OAM_START..=OAM_END if self.oam_dma.is_active() => (),
+            OAM_START..=OAM_END => self.gpu.write_oam(address - OAM_START, value),
+            IO_REGISTER_START..=IO_REGISTER_END => self.write_io_register(address, value),
             UNUSED_MEMORY_START..=UNUSED_MEMORY_END => (),
             HIGH_RAM_START..=HIGH_RAM_END => self.high_ram[address - HIGH_RAM_START] = value,
             INTERRUPT_ENABLE_REGISTER => self.interrupt_enable = value.into(),
@@ -92,11 +231,698 @@ impl MemoryBus {
         }
     }
 
+    /// Maps an offset into the working RAM to the bank and offset inside of that bank.
+    fn working_ram_location(&self, offset: usize) -> (usize, usize) {
+        if offset < WORKING_RAM_BANK_SIZE {
+            (0, offset)
+        } else {
+            (self.working_ram_bank, offset - WORKING_RAM_BANK_SIZE)
+        }
+    }
+
     fn read_io_register(&self, address: usize) -> u8 {
-        todo!()
+        let has_cgb_features = self.cgb_mode.has_cgb_features();
+        match address {
+            JOYPAD_REGISTER => match &self.sgb {
+                Some(sgb) => sgb.read_joypad(&self.joypad),
+                None => self.joypad.read(),
+            },
+            SERIAL_DATA_REGISTER | SERIAL_CONTROL_REGISTER => {
+                self.serial.read_register(address, has_cgb_features)
+            }
+            DIVIDER_REGISTER..=TIMER_CONTROL_REGISTER => self.timer.read_register(address),
+            INTERRUPT_FLAG_REGISTER => 0b1110_0000 | u8::from(self.interrupt_flag),
+            AUDIO_REGISTER_START..=AUDIO_REGISTER_END | WAVE_RAM_START..=WAVE_RAM_END => {
+                self.apu.read_register(address)
+            }
+            OAM_DMA_REGISTER => self.oam_dma.read_register(),
+            LCD_Y_REGISTER if self.stub_ly => 0x90,
+            LCD_CONTROL_REGISTER..=LCD_Y_COMPARE_REGISTER
+            | BACKGROUND_PALETTE_REGISTER..=WINDOW_X_REGISTER
+            | VRAM_BANK_REGISTER
+            | BACKGROUND_PALETTE_INDEX_REGISTER..=OBJECT_PRIORITY_MODE_REGISTER => {
+                self.gpu.read_register(address)
+            }
+            SPEED_SWITCH_REGISTER if has_cgb_features => {
+                let current_speed = if self.double_speed { 0b1000_0000 } else { 0 };
+                let armed = if self.speed_switch_armed { 0b1 } else { 0 };
+                current_speed | 0b0111_1110 | armed
+            }
+            // The source and destination registers can only be written to.
+            HDMA_SOURCE_HIGH_REGISTER..=HDMA_DESTINATION_LOW_REGISTER if has_cgb_features => 0xFF,
+            HDMA_CONTROL_REGISTER if has_cgb_features => self.hdma.read_control(),
+            // Only bits 0-2 are used, all others read as set.
+            WRAM_BANK_REGISTER if has_cgb_features => 0b1111_1000 | self.working_ram_bank as u8,
+            SPEED_SWITCH_REGISTER | WRAM_BANK_REGISTER => 0xFF,
+            HDMA_SOURCE_HIGH_REGISTER..=HDMA_CONTROL_REGISTER => 0xFF,
+            _ => self.io_registers[address - IO_REGISTER_START],
+        }
     }
 
     fn write_io_register(&mut self, address: usize, value: u8) {
-        todo!()
+        let has_cgb_features = self.cgb_mode.has_cgb_features();
+        match address {
+            JOYPAD_REGISTER => {
+                let previous_selection = self.joypad.selection();
+                self.joypad.write(value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value, previous_selection);
+                }
+            }
+            SERIAL_DATA_REGISTER | SERIAL_CONTROL_REGISTER => {
+                self.serial.write_register(address, value, has_cgb_features)
+            }
+            DIVIDER_REGISTER..=TIMER_CONTROL_REGISTER => {
+                let apu_clocks = self.timer.write_register(address, value, self.double_speed);
+                for _ in 0..apu_clocks {
+                    self.apu.clock_frame_sequencer();
+                }
+            }
+            INTERRUPT_FLAG_REGISTER => self.interrupt_flag = value.into(),
+            AUDIO_REGISTER_START..=AUDIO_REGISTER_END | WAVE_RAM_START..=WAVE_RAM_END => {
+                self.apu.write_register(address, value)
+            }
+            OAM_DMA_REGISTER => self.oam_dma.write_register(value),
+            LCD_CONTROL_REGISTER..=LCD_Y_COMPARE_REGISTER
+            | BACKGROUND_PALETTE_REGISTER..=WINDOW_X_REGISTER
+            | VRAM_BANK_REGISTER
+            | BACKGROUND_PALETTE_INDEX_REGISTER..=OBJECT_PRIORITY_MODE_REGISTER => {
+                self.gpu.write_register(address, value)
+            }
+            SPEED_SWITCH_REGISTER if has_cgb_features => self.speed_switch_armed = value & 0b1 != 0,
+            HDMA_SOURCE_HIGH_REGISTER if has_cgb_features => self.hdma.write_source_high(value),
+            HDMA_SOURCE_LOW_REGISTER if has_cgb_features => self.hdma.write_source_low(value),
+            HDMA_DESTINATION_HIGH_REGISTER if has_cgb_features => {
+                self.hdma.write_destination_high(value)
+            }
+            HDMA_DESTINATION_LOW_REGISTER if has_cgb_features => {
+                self.hdma.write_destination_low(value)
+            }
+            HDMA_CONTROL_REGISTER if has_cgb_features => self.start_hdma(value),
+            // Selecting bank 0 selects bank 1 instead.
+            WRAM_BANK_REGISTER if has_cgb_features => {
+                self.working_ram_bank = ((value & 0b111) as usize).max(1)
+            }
+            SPEED_SWITCH_REGISTER | WRAM_BANK_REGISTER => (),
+            HDMA_SOURCE_HIGH_REGISTER..=HDMA_CONTROL_REGISTER => (),
+            _ => self.io_registers[address - IO_REGISTER_START] = value,
+        }
+    }
+
+    /// Loads a cartridge's ROM and sets up the system the way the boot ROM would leave it.
+    /// On a CGB, this decides whether the game runs in CGB or DMG compatibility mode. In the
+    /// latter case, `palette_selection` decides how the game gets colorized. On an SGB, the
+    /// SGB's functions are only enabled for games that declare support in their header.
+    /// Fails if the cartridge contains hardware that isn't emulated, leaving the bus unchanged.
+    pub(super) fn load_rom(
+        &mut self,
+        rom: &[u8],
+        model: Model,
+        palette_selection: PaletteSelection,
+    ) -> Result<(), LoadRomError> {
+        let header = Header::parse(rom);
+        let rom_bank_count = mbc::rom_bank_count(rom.len());
+        self.mbc = Mbc::new(header.as_ref(), rom_bank_count, model)?;
+        self.rom = rom.to_vec();
+        self.rom.resize(rom_bank_count * GAME_ROM_BANK_N_SIZE, 0);
+        self.cartridge_ram = vec![0; header.as_ref().map_or(0, Header::ram_size)];
+        // The boot ROM isn't emulated, so the cartridge is mapped right away.
+        self.boot_rom = None;
+
+        self.model = model;
+        self.cgb_mode = match &header {
+            _ if !model.is_cgb() => CgbMode::Dmg,
+            Some(header) if header.cgb_support() != CgbSupport::None => CgbMode::Cgb,
+            _ => CgbMode::DmgCompatibility,
+        };
+        self.gpu.set_cgb_mode(self.cgb_mode);
+        if let (CgbMode::DmgCompatibility, Some(header)) = (self.cgb_mode, &header) {
+            self.gpu
+                .load_compatibility_palettes(select_palettes(header, palette_selection));
+        }
+        if self.cgb_mode == CgbMode::Cgb {
+            self.gpu.clear_background_color_palettes();
+        }
+        let sgb_enabled = model.is_sgb() && header.is_some_and(|header| header.supports_sgb());
+        self.sgb = sgb_enabled.then(Sgb::default);
+        self.timer = Timer::after_boot(model);
+        self.apu = Apu::after_boot(model, self.apu.sample_rate());
+        self.serial = Serial::default();
+
+        // The boot ROM leaves the LCD turned on, showing the background.
+        self.interrupt_flag = INTERRUPT_FLAG_AFTER_BOOT.into();
+        self.write_io_register(LCD_CONTROL_REGISTER, 0x91);
+        self.write_io_register(BACKGROUND_PALETTE_REGISTER, 0xFC);
+        // The SGB's boot ROM talks to the SNES through `P1`, leaving both groups deselected.
+        if model.is_sgb() {
+            self.joypad.write(0x30);
+        }
+        Ok(())
+    }
+
+    /// Triggers the OAM corruption bug if the CPU puts an address inside of OAM on the address
+    /// bus while the PPU is scanning it, e.g. when incrementing or decrementing a 16-bit register.
+    pub(super) fn trigger_oam_bug(&mut self, address: u16) {
+        let in_oam = (OAM_START..=OAM_END).contains(&(address as usize));
+        if self.model.has_oam_bug() && in_oam && self.gpu.mode() == Mode::OamScan {
+            self.gpu.corrupt_oam();
+        }
+    }
+
+    /// Makes LY always read as 0x90, the first line of VBlank, or lets it work normally again.
+    pub(super) fn set_stub_ly(&mut self, stub_ly: bool) {
+        self.stub_ly = stub_ly;
+    }
+
+    /// Which ROM bank is mapped at the address, or [`None`] if it isn't in the ROM.
+    pub(super) fn rom_bank(&self, address: u16) -> Option<u16> {
+        match address as usize {
+            GAME_ROM_BANK_0_START..=GAME_ROM_BANK_0_END => Some(self.mbc.rom_bank_0() as u16),
+            GAME_ROM_BANK_N_START..=GAME_ROM_BANK_N_END => Some(self.mbc.rom_bank_n() as u16),
+            _ => None,
+        }
+    }
+
+    pub(super) fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.watchpoints = watchpoints;
+    }
+
+    pub(super) fn take_watchpoint_hits(&mut self) -> Vec<WatchpointHit> {
+        std::mem::take(&mut self.watchpoint_hits)
+    }
+
+    /// Records an access of the CPU if it's watched.
+    pub(super) fn watch(&mut self, address: u16, value: u8, kind: AccessKind) {
+        if self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(address, kind))
+        {
+            self.watchpoint_hits.push(WatchpointHit {
+                address,
+                value,
+                kind,
+            });
+        }
+    }
+
+    /// Whether the CPU runs in double speed mode.
+    pub(super) fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// The SGB's state, if its functions are enabled.
+    pub(super) fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_ref()
+    }
+
+    /// The interrupts that are both requested and enabled, in the same layout as `IF` and `IE`.
+    pub(super) fn pending_interrupts(&self) -> u8 {
+        u8::from(self.interrupt_flag) & u8::from(self.interrupt_enable)
+    }
+
+    /// Clears the request of the interrupt with the given bit once its handler is called.
+    pub(super) fn acknowledge_interrupt(&mut self, bit: u8) {
+        let flags = u8::from(self.interrupt_flag) & !(1 << bit);
+        self.interrupt_flag = flags.into();
+    }
+
+    /// The last frame drawn by the PPU.
+    pub(super) fn framebuffer(&self) -> &[Color; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.gpu.framebuffer()
+    }
+
+    /// Returns whether the PPU finished a frame since the last call.
+    pub(super) fn take_frame_finished(&mut self) -> bool {
+        std::mem::take(&mut self.frame_finished)
+    }
+
+    /// Returns the audio samples generated since the last call, interleaved as left and right.
+    pub(super) fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.apu.take_samples()
+    }
+
+    /// Changes the rate at which audio samples are generated.
+    pub(super) fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    /// The RAM inside the cartridge, which keeps its contents on cartridges with a battery.
+    pub(super) fn cartridge_ram(&self) -> &[u8] {
+        &self.cartridge_ram
+    }
+
+    pub(super) fn cartridge_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge_ram
+    }
+
+    pub(super) fn set_rtc_seconds(&mut self, seconds: u64) {
+        self.mbc.set_rtc_seconds(seconds);
+    }
+
+    pub(super) fn rtc_seconds(&self) -> u64 {
+        self.mbc.rtc_seconds()
+    }
+
+    /// Everything that has been sent through the serial port.
+    pub(super) fn serial_output(&self) -> &[u8] {
+        self.serial.output()
+    }
+
+    /// Presses or releases a button, requesting the joypad interrupt if necessary.
+    pub(super) fn is_pressed(&self, button: Button) -> bool {
+        self.joypad.is_pressed(button)
+    }
+
+    pub(super) fn set_button(&mut self, button: Button, pressed: bool) {
+        self.interrupt_flag.joypad |= self.joypad.set_button(button, pressed);
+    }
+
+    /// Advance every component on the bus by the given amount of CPU cycles.
+    /// The amount is expected to be a multiple of a machine cycle, i.e. of 4.
+    pub(super) fn step(&mut self, cycles: u32) {
+        // The PPU isn't affected by double speed mode, so it only sees half of the CPU's cycles.
+        let dots = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+
+        for _ in 0..cycles / 4 {
+            if let Some((source, offset)) = self.oam_dma.step() {
+                // Everything above the working RAM is mirrored from it.
+                let source = match source as usize {
+                    ECHO_RAM_START.. => source - 0x2000,
+                    _ => source,
+                };
+                let value = self.read_byte(source);
+                self.gpu.write_oam(offset, value);
+            }
+        }
+
+        let timer_events = self.timer.step(cycles, self.double_speed);
+        self.interrupt_flag.timer |= timer_events.interrupt;
+        for _ in 0..timer_events.apu_clocks {
+            self.apu.clock_frame_sequencer();
+        }
+        self.apu.step(dots);
+        self.mbc.step(dots);
+        self.interrupt_flag.serial |= self.serial.step(cycles, self.cgb_mode.has_cgb_features());
+
+        let events = self.gpu.step(dots);
+        self.interrupt_flag.vblank |= events.vblank_interrupt;
+        self.frame_finished |= events.vblank_interrupt;
+        self.interrupt_flag.lcd |= events.stat_interrupt;
+        if let (true, Some(sgb)) = (events.vblank_interrupt, &mut self.sgb) {
+            sgb.finish_frame(self.gpu.shades());
+        }
+        if events.entered_hblank && self.hdma.is_hblank_active() {
+            self.transfer_hdma_block();
+        }
+    }
+
+    /// Executes the speed switch prepared through `KEY1`, as done by the `STOP` instruction.
+    /// Returns whether the speed was switched.
+    pub(super) fn switch_speed(&mut self) -> bool {
+        if !self.cgb_mode.has_cgb_features() || !self.speed_switch_armed {
+            return false;
+        }
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+        true
+    }
+
+    /// Returns how many CPU cycles the CPU has to sit idle because of VRAM DMA transfers,
+    /// and resets the counter.
+    pub(super) fn take_dma_stall_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.dma_stall_cycles)
+    }
+
+    /// Handles a write to `HDMA5`.
+    fn start_hdma(&mut self, value: u8) {
+        match self.hdma.write_control(value) {
+            Some(Transfer::GeneralPurpose) => while self.transfer_hdma_block() {},
+            // If the PPU is already in HBlank, or won't get there because the LCD is off,
+            // the first block is copied immediately.
+            Some(Transfer::HBlank)
+                if !self.gpu.is_lcd_enabled() || self.gpu.mode() == Mode::HBlank =>
+            {
+                self.transfer_hdma_block();
+            }
+            Some(Transfer::HBlank) | None => (),
+        }
+    }
+
+    /// Copies the next block of a VRAM DMA transfer. Returns `false` if there was nothing left.
+    fn transfer_hdma_block(&mut self) -> bool {
+        let Some((source, destination)) = self.hdma.next_block() else {
+            return false;
+        };
+
+        for offset in 0..BLOCK_SIZE {
+            let source = source.wrapping_add(offset);
+            // VRAM itself and everything above the working RAM can't be used as a source.
+            let value = match source as usize {
+                VRAM_BEGIN..=VRAM_END | ECHO_RAM_START.. => 0xFF,
+                _ => self.read_byte(source),
+            };
+            self.gpu.write_vram((destination + offset) as usize, value);
+        }
+        self.dma_stall_cycles += Hdma::block_cycles(self.double_speed);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgb::compatibility::ManualPalette;
+    use crate::gpu::palette::{Color, DMG_SHADES};
+    use crate::save_state::copy_state;
+
+    /// A bus in CGB mode with the LCD turned on.
+    fn cgb_bus() -> MemoryBus {
+        let mut bus = MemoryBus {
+            cgb_mode: CgbMode::Cgb,
+            ..Default::default()
+        };
+        bus.gpu.set_cgb_mode(CgbMode::Cgb);
+        bus.write_byte(LCD_CONTROL_REGISTER as u16, 0b1000_0000);
+        bus
+    }
+
+    /// Fills `0xC000` onwards with increasing values and points the HDMA source there.
+    fn prepare_transfer(bus: &mut MemoryBus, destination: u16) {
+        for offset in 0..0x100 {
+            bus.write_byte(WORKING_RAM_START as u16 + offset, offset as u8);
+        }
+        bus.write_byte(HDMA_SOURCE_HIGH_REGISTER as u16, 0xC0);
+        bus.write_byte(HDMA_SOURCE_LOW_REGISTER as u16, 0x00);
+        bus.write_byte(
+            HDMA_DESTINATION_HIGH_REGISTER as u16,
+            (destination >> 8) as u8,
+        );
+        bus.write_byte(HDMA_DESTINATION_LOW_REGISTER as u16, destination as u8);
+    }
+
+    #[test]
+    fn general_purpose_dma() {
+        let mut bus = cgb_bus();
+        prepare_transfer(&mut bus, 0x8800);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x01);
+
+        assert_eq!(bus.read_byte(0x8800), 0x00);
+        assert_eq!(bus.read_byte(0x881F), 0x1F);
+        assert_eq!(bus.read_byte(0x8820), 0x00);
+        assert_eq!(bus.read_byte(HDMA_CONTROL_REGISTER as u16), 0xFF);
+        assert_eq!(bus.take_dma_stall_cycles(), 64);
+        assert_eq!(bus.take_dma_stall_cycles(), 0);
+    }
+
+    #[test]
+    fn general_purpose_dma_double_speed() {
+        let mut bus = cgb_bus();
+        bus.write_byte(SPEED_SWITCH_REGISTER as u16, 0b1);
+        assert!(bus.switch_speed());
+        assert_eq!(bus.read_byte(SPEED_SWITCH_REGISTER as u16), 0xFE);
+
+        prepare_transfer(&mut bus, 0x8000);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x01);
+        assert_eq!(bus.take_dma_stall_cycles(), 128);
+    }
+
+    #[test]
+    fn dma_into_selected_vram_bank() {
+        let mut bus = cgb_bus();
+        bus.write_byte(VRAM_BANK_REGISTER as u16, 1);
+        prepare_transfer(&mut bus, 0x9000);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x00);
+
+        assert_eq!(bus.read_byte(0x900F), 0x0F);
+        bus.write_byte(VRAM_BANK_REGISTER as u16, 0);
+        assert_eq!(bus.read_byte(0x900F), 0x00);
+    }
+
+    #[test]
+    fn hblank_dma_copies_a_block_per_hblank() {
+        let mut bus = cgb_bus();
+        prepare_transfer(&mut bus, 0x8000);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x81);
+        assert_eq!(bus.read_byte(HDMA_CONTROL_REGISTER as u16), 0x01);
+        assert_eq!(bus.read_byte(0x8000), 0x00);
+        assert_eq!(bus.read_byte(0x8001), 0x00);
+
+        // OAM scan and drawing of the first scanline.
+        bus.step(252);
+        assert_eq!(bus.read_byte(0x800F), 0x0F);
+        assert_eq!(bus.read_byte(0x8010), 0x00);
+        assert_eq!(bus.read_byte(HDMA_CONTROL_REGISTER as u16), 0x00);
+        assert_eq!(bus.take_dma_stall_cycles(), 32);
+
+        bus.step(456);
+        assert_eq!(bus.read_byte(0x801F), 0x1F);
+        assert_eq!(bus.read_byte(HDMA_CONTROL_REGISTER as u16), 0xFF);
+    }
+
+    #[test]
+    fn hblank_dma_starts_immediately_with_lcd_off() {
+        let mut bus = cgb_bus();
+        bus.write_byte(LCD_CONTROL_REGISTER as u16, 0);
+        prepare_transfer(&mut bus, 0x8000);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x81);
+
+        assert_eq!(bus.read_byte(0x800F), 0x0F);
+        assert_eq!(bus.read_byte(HDMA_CONTROL_REGISTER as u16), 0x00);
+    }
+
+    #[test]
+    fn cancel_hblank_dma() {
+        let mut bus = cgb_bus();
+        prepare_transfer(&mut bus, 0x8000);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x82);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x00);
+        assert_eq!(bus.read_byte(HDMA_CONTROL_REGISTER as u16), 0x82);
+
+        bus.step(252);
+        assert_eq!(bus.read_byte(0x800F), 0x00);
+    }
+
+    #[test]
+    fn invalid_source_reads_ones() {
+        let mut bus = cgb_bus();
+        bus.write_byte(HDMA_SOURCE_HIGH_REGISTER as u16, 0xE0);
+        bus.write_byte(HDMA_SOURCE_LOW_REGISTER as u16, 0x00);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x00);
+
+        assert_eq!(bus.read_byte(0x8000), 0xFF);
+    }
+
+    /// A DMG-only Nintendo game called "TETRIS", with the first tile filled with color index 1.
+    fn dmg_rom() -> Vec<u8> {
+        let mut rom = vec![0; GAME_ROM_BANK_0_SIZE + GAME_ROM_BANK_N_SIZE];
+        rom[0x0134..0x013A].copy_from_slice(b"TETRIS");
+        rom[0x014B] = 0x01;
+        rom
+    }
+
+    /// Draws the first scanline with every pixel set to color index 1, and returns its first pixel.
+    fn render_color_index_1(bus: &mut MemoryBus) -> Color {
+        for row in 0..8 {
+            bus.write_byte(0x8000 + row * 2, 0xFF);
+        }
+        bus.write_byte(BACKGROUND_PALETTE_REGISTER as u16, 0b1110_0100);
+        bus.write_byte(LCD_CONTROL_REGISTER as u16, 0b1001_0001);
+        bus.step(456);
+        bus.gpu.framebuffer()[0]
+    }
+
+    #[test]
+    fn dmg_uses_shades_of_gray() {
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&dmg_rom(), Model::Dmg, PaletteSelection::Automatic)
+            .unwrap();
+
+        assert_eq!(bus.cgb_mode, CgbMode::Dmg);
+        assert_eq!(render_color_index_1(&mut bus), DMG_SHADES[1]);
+    }
+
+    #[test]
+    fn dmg_game_on_cgb_is_colorized() {
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&dmg_rom(), Model::Cgb, PaletteSelection::Automatic)
+            .unwrap();
+
+        assert_eq!(bus.cgb_mode, CgbMode::DmgCompatibility);
+        // Tetris' palettes go from white over yellow and red to black.
+        assert_eq!(render_color_index_1(&mut bus), Color::new(0xFF, 0xFF, 0x00));
+    }
+
+    #[test]
+    fn dmg_game_on_cgb_with_manual_palette() {
+        let mut bus = MemoryBus::default();
+        let selection = PaletteSelection::Manual(ManualPalette::LeftB);
+        bus.load_rom(&dmg_rom(), Model::Cgb, selection).unwrap();
+
+        assert_eq!(render_color_index_1(&mut bus), Color::new(0xA5, 0xA5, 0xA5));
+    }
+
+    #[test]
+    fn dmg_compatibility_locks_cgb_registers() {
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&dmg_rom(), Model::Cgb, PaletteSelection::Automatic)
+            .unwrap();
+
+        bus.write_byte(VRAM_BANK_REGISTER as u16, 1);
+        bus.write_byte(WRAM_BANK_REGISTER as u16, 2);
+        bus.write_byte(0x8000, 0x12);
+        bus.write_byte(0xD000, 0x34);
+        bus.write_byte(BACKGROUND_PALETTE_INDEX_REGISTER as u16, 0);
+        bus.write_byte(BACKGROUND_PALETTE_DATA_REGISTER as u16, 0);
+
+        assert_eq!(bus.read_byte(VRAM_BANK_REGISTER as u16), 0xFF);
+        assert_eq!(bus.read_byte(WRAM_BANK_REGISTER as u16), 0xFF);
+        assert_eq!(bus.read_byte(BACKGROUND_PALETTE_DATA_REGISTER as u16), 0xFF);
+        assert_eq!(bus.working_ram[1][0], 0x34);
+        assert_eq!(bus.gpu.read_vram(0), 0x12);
+        assert_eq!(render_color_index_1(&mut bus), Color::new(0xFF, 0xFF, 0x00));
+    }
+
+    #[test]
+    fn cgb_game_on_cgb() {
+        let mut rom = dmg_rom();
+        rom[0x0143] = 0x80;
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&rom, Model::Cgb, PaletteSelection::Automatic)
+            .unwrap();
+        assert_eq!(bus.cgb_mode, CgbMode::Cgb);
+
+        bus.write_byte(WRAM_BANK_REGISTER as u16, 0);
+        assert_eq!(bus.read_byte(WRAM_BANK_REGISTER as u16), 0xF9);
+        bus.write_byte(WRAM_BANK_REGISTER as u16, 3);
+        bus.write_byte(0xD000, 0x56);
+        assert_eq!(bus.working_ram[3][0], 0x56);
+        assert_eq!(bus.read_byte(0xF000), 0x56);
+    }
+
+    #[test]
+    fn dmg_has_no_hdma() {
+        let mut bus = MemoryBus::default();
+        prepare_transfer(&mut bus, 0x8000);
+        bus.write_byte(HDMA_CONTROL_REGISTER as u16, 0x00);
+
+        assert_eq!(bus.read_byte(0x8000), 0x00);
+        assert_eq!(bus.read_byte(HDMA_CONTROL_REGISTER as u16), 0xFF);
+        assert!(!bus.switch_speed());
+    }
+
+    #[test]
+    fn joypad() {
+        let mut bus = MemoryBus::default();
+        bus.write_byte(JOYPAD_REGISTER as u16, 0x10);
+        bus.set_button(Button::Start, true);
+
+        assert_eq!(bus.read_byte(JOYPAD_REGISTER as u16), 0xD7);
+        assert!(bus.interrupt_flag.joypad);
+    }
+
+    #[test]
+    fn sgb_game_on_sgb() {
+        let mut rom = dmg_rom();
+        rom[0x0146] = 0x03;
+        rom[0x014B] = 0x33;
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&rom, Model::Sgb, PaletteSelection::Automatic)
+            .unwrap();
+
+        assert!(bus.sgb().is_some());
+    }
+
+    #[test]
+    fn dmg_game_on_sgb() {
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&dmg_rom(), Model::Sgb, PaletteSelection::Automatic)
+            .unwrap();
+
+        assert!(bus.sgb().is_none());
+        assert_eq!(bus.cgb_mode, CgbMode::Dmg);
+    }
+
+    #[test]
+    fn state_after_boot() {
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&dmg_rom(), Model::Dmg, PaletteSelection::Automatic)
+            .unwrap();
+
+        assert_eq!(bus.read_byte(LCD_CONTROL_REGISTER as u16), 0x91);
+        assert_eq!(bus.read_byte(BACKGROUND_PALETTE_REGISTER as u16), 0xFC);
+        assert_eq!(bus.read_byte(INTERRUPT_FLAG_REGISTER as u16), 0xE1);
+        assert_eq!(bus.read_byte(JOYPAD_REGISTER as u16), 0xCF);
+        assert!(bus.boot_rom.is_none());
+    }
+
+    #[test]
+    fn forced_model_ignores_header() {
+        let mut rom = dmg_rom();
+        rom[0x0143] = 0xC0;
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&rom, Model::Mgb, PaletteSelection::Automatic)
+            .unwrap();
+
+        assert_eq!(bus.cgb_mode, CgbMode::Dmg);
+        assert_eq!(bus.read_byte(VRAM_BANK_REGISTER as u16), 0xFF);
+    }
+
+    #[test]
+    fn agb_runs_cgb_games() {
+        let mut rom = dmg_rom();
+        rom[0x0143] = 0x80;
+        let mut bus = MemoryBus::default();
+        bus.load_rom(&rom, Model::Agb, PaletteSelection::Automatic)
+            .unwrap();
+
+        assert_eq!(bus.cgb_mode, CgbMode::Cgb);
+    }
+
+    #[test]
+    fn oam_bug_depends_on_model() {
+        for (model, corrupted) in [(Model::Dmg, true), (Model::Sgb2, true), (Model::Cgb, false)] {
+            let mut bus = MemoryBus::default();
+            bus.load_rom(&dmg_rom(), model, PaletteSelection::Automatic)
+                .unwrap();
+            bus.write_byte(0xFE0A, 0x12);
+            // Move to the middle of OAM scan on the next scanline.
+            while bus.gpu.mode() != Mode::HBlank {
+                bus.step(4);
+            }
+            while bus.gpu.mode() != Mode::OamScan {
+                bus.step(4);
+            }
+            bus.step(8);
+            bus.trigger_oam_bug(0xFE00);
+
+            // The second row gets copied into the third row.
+            assert_eq!(bus.read_byte(0xFE12) == 0x12, corrupted, "{model:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_state() {
+        let load = |bus: &MemoryBus| copy_state(bus, &mut cgb_bus());
+        let bank_0 = MemoryBus {
+            working_ram_bank: 0,
+            ..cgb_bus()
+        };
+        assert_eq!(load(&bank_0), Err(LoadStateError::InvalidValue));
+        let bank_8 = MemoryBus {
+            working_ram_bank: WORKING_RAM_BANK_COUNT,
+            ..cgb_bus()
+        };
+        assert_eq!(load(&bank_8), Err(LoadStateError::InvalidValue));
+        let other_ram = MemoryBus {
+            cartridge_ram: vec![0; CARTRIDGE_RAM_SIZE],
+            ..cgb_bus()
+        };
+        assert_eq!(load(&other_ram), Err(LoadStateError::InvalidValue));
+        assert_eq!(load(&cgb_bus()), Ok(()));
     }
 }
@@ -4,11 +4,11 @@ pub const BOOT_ROM_SIZE: usize = BOOT_ROM_END - BOOT_ROM_START + 1;
 
 pub const GAME_ROM_BANK_0_START: usize = 0x0000;
 pub const GAME_ROM_BANK_0_END: usize = 0x03FFF;
-pub const GAME_ROM_BANK_0_SIZE: usize = GAME_ROM_BANK_0_END - GAME_ROM_BANK_0_START;
+pub const GAME_ROM_BANK_0_SIZE: usize = GAME_ROM_BANK_0_END - GAME_ROM_BANK_0_START + 1;
 
 pub const GAME_ROM_BANK_N_START: usize = 0x4000;
 pub const GAME_ROM_BANK_N_END: usize = 0x7FFF;
-pub const GAME_ROM_BANK_N_SIZE: usize = GAME_ROM_BANK_N_END - GAME_ROM_BANK_N_START;
+pub const GAME_ROM_BANK_N_SIZE: usize = GAME_ROM_BANK_N_END - GAME_ROM_BANK_N_START + 1;
 
 pub const TILE_RAM_START: usize = 0x8000;
 pub const TILE_RAM_END: usize = 0x97FF;
@@ -25,6 +25,10 @@ pub const CARTRIDGE_RAM_SIZE: usize = CARTRIDGE_RAM_END - CARTRIDGE_RAM_START +
 pub const WORKING_RAM_START: usize = 0xC000;
 pub const WORKING_RAM_END: usize = 0xDFFF;
 pub const WORKING_RAM_SIZE: usize = WORKING_RAM_END - WORKING_RAM_START + 1;
+/// The working RAM is split in two halves. The first is fixed to bank 0, the second one is
+/// switchable between banks 1-7 on the CGB.
+pub const WORKING_RAM_BANK_SIZE: usize = WORKING_RAM_SIZE / 2;
+pub const WORKING_RAM_BANK_COUNT: usize = 8;
 
 pub const ECHO_RAM_START: usize = 0xE000;
 pub const ECHO_RAM_END: usize = 0xFDFF;
@@ -38,9 +42,100 @@ pub const UNUSED_MEMORY_END: usize = 0xFEFF;
 
 pub const IO_REGISTER_START: usize = 0xFF00;
 pub const IO_REGISTER_END: usize = 0xFF7F;
+pub const IO_REGISTER_SIZE: usize = IO_REGISTER_END - IO_REGISTER_START + 1;
 
 pub const HIGH_RAM_START: usize = 0xFF80;
 pub const HIGH_RAM_END: usize = 0xFFFE;
 pub const HIGH_RAM_SIZE: usize = HIGH_RAM_END - HIGH_RAM_START + 1;
 
 pub const INTERRUPT_ENABLE_REGISTER: usize = 0xFFFF;
+
+// Individual I/O registers.
+
+/// `P1`/`JOYP`: Selects a button group and reads the buttons that are pressed.
+pub const JOYPAD_REGISTER: usize = 0xFF00;
+
+/// `SB`: The byte that is sent and received through the serial port.
+pub const SERIAL_DATA_REGISTER: usize = 0xFF01;
+/// `SC`: Starts a serial transfer and selects its clock.
+pub const SERIAL_CONTROL_REGISTER: usize = 0xFF02;
+
+/// `DIV`: Upper byte of the timer's internal counter. Writing any value resets it.
+pub const DIVIDER_REGISTER: usize = 0xFF04;
+/// `TIMA`: Incremented at the rate selected by `TAC`. Requests an interrupt when it overflows.
+pub const TIMER_COUNTER_REGISTER: usize = 0xFF05;
+/// `TMA`: Loaded into `TIMA` after it overflows.
+pub const TIMER_MODULO_REGISTER: usize = 0xFF06;
+/// `TAC`: Enables `TIMA` and selects how fast it is incremented.
+pub const TIMER_CONTROL_REGISTER: usize = 0xFF07;
+
+/// `IF`: Which interrupts are currently being requested.
+pub const INTERRUPT_FLAG_REGISTER: usize = 0xFF0F;
+
+/// `NR10`: The first of the sound channel registers, which are 5 bytes per channel.
+pub const AUDIO_REGISTER_START: usize = 0xFF10;
+/// `NR50`: Volume of the left and right output.
+pub const MASTER_VOLUME_REGISTER: usize = 0xFF24;
+/// `NR51`: Which channels are output on the left and right.
+pub const SOUND_PANNING_REGISTER: usize = 0xFF25;
+/// `NR52`: Turns the APU on or off and reports which channels are playing.
+pub const SOUND_CONTROL_REGISTER: usize = 0xFF26;
+/// `0xFF27`-`0xFF2F` are unused and read as `0xFF`.
+pub const AUDIO_REGISTER_END: usize = 0xFF2F;
+/// The samples played by the wave channel.
+pub const WAVE_RAM_START: usize = 0xFF30;
+pub const WAVE_RAM_END: usize = 0xFF3F;
+
+/// `LCDC`: LCD control.
+pub const LCD_CONTROL_REGISTER: usize = 0xFF40;
+/// `STAT`: LCD status.
+pub const LCD_STATUS_REGISTER: usize = 0xFF41;
+/// `SCY`: Vertical scroll position of the background.
+pub const SCROLL_Y_REGISTER: usize = 0xFF42;
+/// `SCX`: Horizontal scroll position of the background.
+pub const SCROLL_X_REGISTER: usize = 0xFF43;
+/// `LY`: The scanline that is currently being drawn.
+pub const LCD_Y_REGISTER: usize = 0xFF44;
+/// `LYC`: Compared against `LY` to request STAT interrupts.
+pub const LCD_Y_COMPARE_REGISTER: usize = 0xFF45;
+/// `DMA`: Starts copying 160 bytes from `XX00` into OAM.
+pub const OAM_DMA_REGISTER: usize = 0xFF46;
+/// `BGP`: Shades of the background and window color indices (DMG palette).
+pub const BACKGROUND_PALETTE_REGISTER: usize = 0xFF47;
+/// `OBP0`: Shades of the color indices of objects using palette 0 (DMG palette).
+pub const OBJECT_PALETTE_0_REGISTER: usize = 0xFF48;
+/// `OBP1`: Shades of the color indices of objects using palette 1 (DMG palette).
+pub const OBJECT_PALETTE_1_REGISTER: usize = 0xFF49;
+/// `WY`: Vertical position of the window.
+pub const WINDOW_Y_REGISTER: usize = 0xFF4A;
+/// `WX`: Horizontal position of the window, plus 7.
+pub const WINDOW_X_REGISTER: usize = 0xFF4B;
+
+/// `KEY1`: Prepare a switch between normal and double speed mode (CGB only).
+pub const SPEED_SWITCH_REGISTER: usize = 0xFF4D;
+/// `VBK`: Selects the VRAM bank mapped to `0x8000`-`0x9FFF` (CGB only).
+pub const VRAM_BANK_REGISTER: usize = 0xFF4F;
+
+/// `HDMA1`: High byte of the VRAM DMA source address (CGB only).
+pub const HDMA_SOURCE_HIGH_REGISTER: usize = 0xFF51;
+/// `HDMA2`: Low byte of the VRAM DMA source address (CGB only).
+pub const HDMA_SOURCE_LOW_REGISTER: usize = 0xFF52;
+/// `HDMA3`: High byte of the VRAM DMA destination address (CGB only).
+pub const HDMA_DESTINATION_HIGH_REGISTER: usize = 0xFF53;
+/// `HDMA4`: Low byte of the VRAM DMA destination address (CGB only).
+pub const HDMA_DESTINATION_LOW_REGISTER: usize = 0xFF54;
+/// `HDMA5`: Length, mode and start of a VRAM DMA transfer (CGB only).
+pub const HDMA_CONTROL_REGISTER: usize = 0xFF55;
+
+/// `BCPS`: Index into the background color palette memory (CGB only).
+pub const BACKGROUND_PALETTE_INDEX_REGISTER: usize = 0xFF68;
+/// `BCPD`: Accesses the background color palette memory at `BCPS` (CGB only).
+pub const BACKGROUND_PALETTE_DATA_REGISTER: usize = 0xFF69;
+/// `OCPS`: Index into the object color palette memory (CGB only).
+pub const OBJECT_PALETTE_INDEX_REGISTER: usize = 0xFF6A;
+/// `OCPD`: Accesses the object color palette memory at `OCPS` (CGB only).
+pub const OBJECT_PALETTE_DATA_REGISTER: usize = 0xFF6B;
+/// `OPRI`: Whether objects are prioritized by OAM position or by X coordinate (CGB only).
+pub const OBJECT_PRIORITY_MODE_REGISTER: usize = 0xFF6C;
+/// `SVBK`: Selects the WRAM bank mapped to `0xD000`-`0xDFFF` (CGB only).
+pub const WRAM_BANK_REGISTER: usize = 0xFF70;
@@ -0,0 +1,99 @@
+//! The hardware being emulated. Games behave differently depending on the console they run on,
+//! e.g. DMG games are colorized on a CGB and can use additional features on an SGB. Even revisions
+//! of the same console differ in the state their boot ROM leaves behind, which some games use to
+//! detect the hardware they run on.
+
+use crate::cartridge::{CgbSupport, Header};
+use crate::save_state::impl_save_state_enum;
+
+/// The clock rate of the CPU in Hz, shared by most models.
+const CLOCK_RATE: u32 = 4_194_304;
+/// The SGB derives its clock from the SNES, running about 2.4% faster than a DMG.
+const SGB_CLOCK_RATE: u32 = 4_295_454;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Model {
+    /// The earliest revision of the original Game Boy, only sold in Japan.
+    Dmg0,
+    /// The original Game Boy.
+    #[default]
+    Dmg,
+    /// The Game Boy Pocket.
+    Mgb,
+    /// The Super Game Boy, a SNES cartridge containing a DMG.
+    Sgb,
+    /// The Super Game Boy 2, which fixes the SGB's clock rate.
+    Sgb2,
+    /// The Game Boy Color.
+    Cgb,
+    /// The Game Boy Advance running a Game Boy game. Behaves like a CGB, but games can tell the
+    /// difference through the `B` register and offer different colors for its darker screen.
+    Agb,
+}
+
+impl_save_state_enum!(Model {
+    Dmg0,
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    Agb,
+});
+
+impl Model {
+    /// Picks the model a game is meant for: A CGB for games that use its features, an SGB for
+    /// games that support it, and a DMG for everything else.
+    pub(crate) fn detect(header: Option<&Header>) -> Self {
+        match header {
+            Some(header) if header.cgb_support() != CgbSupport::None => Model::Cgb,
+            Some(header) if header.supports_sgb() => Model::Sgb,
+            _ => Model::Dmg,
+        }
+    }
+
+    /// Whether the CGB's features and palettes are available.
+    pub(crate) fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub(crate) fn is_sgb(self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    /// Whether OAM gets corrupted when the CPU puts an address inside of it on the address bus
+    /// while the PPU is scanning it. Fixed on the CGB.
+    pub(crate) fn has_oam_bug(self) -> bool {
+        !self.is_cgb()
+    }
+
+    /// How many CPU cycles pass per second in normal speed mode.
+    pub fn clock_rate(self) -> u32 {
+        match self {
+            Model::Sgb => SGB_CLOCK_RATE,
+            _ => CLOCK_RATE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(cgb_flag: u8, sgb_flag: u8) -> Header {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0143] = cgb_flag;
+        rom[0x0146] = sgb_flag;
+        rom[0x014B] = 0x33;
+        Header::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(Model::detect(None), Model::Dmg);
+        assert_eq!(Model::detect(Some(&header(0x00, 0x00))), Model::Dmg);
+        assert_eq!(Model::detect(Some(&header(0x00, 0x03))), Model::Sgb);
+        assert_eq!(Model::detect(Some(&header(0x80, 0x03))), Model::Cgb);
+        assert_eq!(Model::detect(Some(&header(0xC0, 0x00))), Model::Cgb);
+    }
+}
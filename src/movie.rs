@@ -0,0 +1,386 @@
+//! Movies record the buttons held during every frame, so a session can be played back exactly,
+//! for example to reproduce a bug. Emulation only depends on the ROM, the model, the palettes a
+//! DMG game is colorized with on a CGB, the time the cartridge's real-time clock is set to, the
+//! state it starts from and the buttons: Nothing is random, and no emulated hardware reads the
+//! host's clock. A checksum of the console's state at the end of the recording tells whether the
+//! playback ended up in the same state.
+//!
+//! The state of the audio filter depends on the sample rate, so it has to be the same when
+//! recording and playing back.
+//!
+//! The file starts with a signature and the version of the format, followed by the fields of
+//! [`Movie`] in the encoding of save states.
+
+use crate::cgb::compatibility::PaletteSelection;
+use crate::gameboy::GameBoy;
+use crate::image::crc32;
+use crate::model::Model;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter, impl_save_state};
+use std::fmt;
+
+/// The start of every movie.
+const SIGNATURE: [u8; 8] = *b"GBEMU-MV";
+/// Increased whenever the layout of a movie changes. Movies that start from a save state also
+/// depend on the version of the save state format.
+const FORMAT_VERSION: u16 = 3;
+
+/// The buttons pressed in each frame since power-on or a save state.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Movie {
+    model: Model,
+    /// How a DMG game was colorized at power-on on a CGB.
+    palette_selection: PaletteSelection,
+    /// The time the cartridge's real-time clock was set to at power-on, in seconds.
+    rtc_seed: u64,
+    rom_checksum: u32,
+    /// The save state the movie starts from, or [`None`] if it starts at power-on.
+    start_state: Option<Vec<u8>>,
+    /// The buttons held during each frame, one bit per button in the order of
+    /// [`crate::Button::ALL`].
+    frames: Vec<u8>,
+    /// The checksum of the console's state after the last frame, once the recording is finished.
+    end_checksum: Option<u32>,
+}
+
+impl_save_state!(Movie {
+    model,
+    palette_selection,
+    rtc_seed,
+    rom_checksum,
+    start_state,
+    frames,
+    end_checksum,
+});
+
+/// Why a movie couldn't be loaded or played back.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MovieError {
+    /// The data doesn't start with the signature of a movie.
+    NotAMovie,
+    /// The movie was written in another version of the format.
+    IncompatibleVersion(u16),
+    /// The data ended early, or contains invalid values.
+    Corrupt,
+    /// The movie was recorded while playing a ROM with another checksum.
+    WrongRom { expected: u32, found: u32 },
+    /// The save state the movie starts from couldn't be loaded.
+    StartState(LoadStateError),
+    /// The playback ended in another state than the recording.
+    Desync { expected: u32, found: u32 },
+    /// A movie was to start at power-on, but the console already ran or was changed.
+    NotAtPowerOn,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "the file is not a movie"),
+            MovieError::IncompatibleVersion(version) => write!(
+                f,
+                "the movie was recorded in version {version} of the format, but only version \
+                 {FORMAT_VERSION} is supported"
+            ),
+            MovieError::Corrupt => write!(f, "the movie is corrupt"),
+            MovieError::WrongRom { expected, found } => write!(
+                f,
+                "the movie was recorded with another ROM (checksum {found:08X}, expected \
+                 {expected:08X})"
+            ),
+            MovieError::StartState(error) => {
+                write!(f, "the movie's save state can't be loaded: {error}")
+            }
+            MovieError::Desync { expected, found } => write!(
+                f,
+                "the playback ended in another state than the recording (checksum {found:08X}, \
+                 expected {expected:08X})"
+            ),
+            MovieError::NotAtPowerOn => write!(
+                f,
+                "the console isn't at power-on anymore, so the movie has to start from a save state"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl Movie {
+    /// Starts recording at power-on. Fails if the console already ran, or its memory, registers
+    /// or state were changed, as the movie couldn't be played back from power-on then. Use
+    /// [`Self::from_state`] instead.
+    pub fn from_power_on(gameboy: &GameBoy) -> Result<Self, MovieError> {
+        if !gameboy.is_at_power_on() {
+            return Err(MovieError::NotAtPowerOn);
+        }
+        Ok(Self::new(gameboy))
+    }
+
+    /// Starts recording from the console's current state, which is saved in the movie.
+    pub fn from_state(gameboy: &GameBoy) -> Self {
+        Self {
+            start_state: Some(gameboy.save_state()),
+            ..Self::new(gameboy)
+        }
+    }
+
+    fn new(gameboy: &GameBoy) -> Self {
+        Self {
+            model: gameboy.model(),
+            palette_selection: gameboy.palette_selection(),
+            rtc_seed: gameboy.rtc_seconds(),
+            rom_checksum: gameboy.rom_checksum(),
+            ..Self::default()
+        }
+    }
+
+    /// Records the buttons that are currently held as those of the next frame. Call this once
+    /// per frame while the buttons are held.
+    pub fn record_frame(&mut self, gameboy: &GameBoy) {
+        self.frames.push(gameboy.pressed_buttons());
+        self.end_checksum = None;
+    }
+
+    /// Finishes the recording by saving the checksum of the console's state, which has to be
+    /// at the end of the last recorded frame. Recording can go on afterwards.
+    pub fn finish(&mut self, gameboy: &GameBoy) {
+        self.end_checksum = Some(gameboy.state_checksum());
+    }
+
+    /// Forgets the frames after the first `frames`, for example when the console was rewound.
+    pub fn truncate(&mut self, frames: usize) {
+        if frames < self.frames.len() {
+            self.frames.truncate(frames);
+            self.end_checksum = None;
+        }
+    }
+
+    /// The amount of recorded frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The model the movie was recorded on.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Whether [`Self::finish`] was called after the last frame was recorded, so the playback
+    /// can be verified.
+    pub fn is_finished(&self) -> bool {
+        self.end_checksum.is_some()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        writer.write_bytes(&SIGNATURE);
+        FORMAT_VERSION.save(&mut writer);
+        self.save(&mut writer);
+        writer.into_bytes()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = StateReader::new(data);
+        if reader.read_bytes() != Ok(SIGNATURE) {
+            return Err(MovieError::NotAMovie);
+        }
+        let mut version = 0u16;
+        version.load(&mut reader).map_err(|_| MovieError::Corrupt)?;
+        if version != FORMAT_VERSION {
+            return Err(MovieError::IncompatibleVersion(version));
+        }
+        let mut movie = Self::default();
+        movie
+            .load(&mut reader)
+            .and_then(|()| reader.finish())
+            .map_err(|_| MovieError::Corrupt)?;
+        Ok(movie)
+    }
+
+    /// Creates the console the movie starts with, playing the given ROM.
+    pub fn start(&self, rom: &[u8]) -> Result<GameBoy, MovieError> {
+        let found = crc32(rom);
+        if found != self.rom_checksum {
+            return Err(MovieError::WrongRom {
+                expected: self.rom_checksum,
+                found,
+            });
+        }
+        let mut gameboy = GameBoy::with_palette_selection(rom, self.model, self.palette_selection)
+            .expect("the movie was recorded with the same ROM, so it can be loaded");
+        gameboy.set_rtc_seconds(self.rtc_seed);
+        if let Some(state) = &self.start_state {
+            gameboy.load_state(state).map_err(MovieError::StartState)?;
+        }
+        Ok(gameboy)
+    }
+
+    /// Runs the recorded frames on a console created with [`Self::start`], then checks that it
+    /// ended up in the same state as when recording, if the recording was finished.
+    pub fn play(&self, gameboy: &mut GameBoy) -> Result<(), MovieError> {
+        for buttons in &self.frames {
+            gameboy.set_pressed_buttons(*buttons);
+            gameboy.run_frame();
+        }
+        match self.end_checksum {
+            Some(expected) if gameboy.state_checksum() != expected => Err(MovieError::Desync {
+                expected,
+                found: gameboy.state_checksum(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::joypad_accumulator_rom;
+    use crate::cgb::compatibility::ManualPalette;
+    use crate::joypad::Button;
+
+    /// Runs 20 frames, holding Right for some of them.
+    fn record(gameboy: &mut GameBoy, movie: &mut Movie) {
+        for frame in 0..20 {
+            gameboy.set_button(Button::Right, (3..8).contains(&frame) || frame == 15);
+            movie.record_frame(gameboy);
+            gameboy.run_frame();
+        }
+        movie.finish(gameboy);
+    }
+
+    #[test]
+    fn plays_back_exactly() {
+        let rom = joypad_accumulator_rom();
+        let mut gameboy = GameBoy::with_model(&rom, Model::Cgb).unwrap();
+        let mut movie = Movie::from_power_on(&gameboy).unwrap();
+        record(&mut gameboy, &mut movie);
+
+        let movie = Movie::decode(&movie.encode()).unwrap();
+        assert_eq!(movie.len(), 20);
+        assert_eq!(movie.model(), Model::Cgb);
+        let mut played = movie.start(&rom).unwrap();
+        assert_eq!(movie.play(&mut played), Ok(()));
+        assert_eq!(played.save_state(), gameboy.save_state());
+    }
+
+    #[test]
+    fn starts_from_save_state() {
+        let rom = joypad_accumulator_rom();
+        let mut gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        gameboy.set_button(Button::Left, true);
+        for _ in 0..5 {
+            gameboy.run_frame();
+        }
+        let mut movie = Movie::from_state(&gameboy);
+        record(&mut gameboy, &mut movie);
+
+        let mut played = movie.start(&rom).unwrap();
+        assert_eq!(played.frame_count(), 5);
+        assert_eq!(movie.play(&mut played), Ok(()));
+        assert_eq!(played.save_state(), gameboy.save_state());
+    }
+
+    #[test]
+    fn desync() {
+        let rom = joypad_accumulator_rom();
+        let mut gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        let mut movie = Movie::from_power_on(&gameboy).unwrap();
+        record(&mut gameboy, &mut movie);
+        movie.frames[10] = 1 << Button::ALL.iter().position(|b| *b == Button::Up).unwrap();
+
+        let mut played = movie.start(&rom).unwrap();
+        assert!(matches!(
+            movie.play(&mut played),
+            Err(MovieError::Desync { expected, .. }) if expected == gameboy.state_checksum()
+        ));
+
+        // Without the checksum, the playback can't be verified.
+        movie.truncate(10);
+        assert!(!movie.is_finished());
+        assert_eq!(movie.play(&mut movie.start(&rom).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn keeps_rtc_seed() {
+        let mut rom = joypad_accumulator_rom();
+        // MBC3 with a clock
+        rom[0x0147] = 0x0F;
+        let mut gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        gameboy.set_rtc_seconds(123_456);
+        let mut movie = Movie::from_power_on(&gameboy).unwrap();
+        record(&mut gameboy, &mut movie);
+
+        let movie = Movie::decode(&movie.encode()).unwrap();
+        let mut played = movie.start(&rom).unwrap();
+        assert_eq!(played.rtc_seconds(), 123_456);
+        assert_eq!(movie.play(&mut played), Ok(()));
+        assert_eq!(played.save_state(), gameboy.save_state());
+    }
+
+    #[test]
+    fn not_at_power_on() {
+        let rom = joypad_accumulator_rom();
+        let state = GameBoy::with_model(&rom, Model::Dmg).unwrap().save_state();
+        let changes: [&dyn Fn(&mut GameBoy); 3] = [
+            &|gameboy| {
+                gameboy.step_instruction();
+            },
+            &|gameboy| gameboy.poke(0xC000, 1),
+            &|gameboy| gameboy.load_state(&state).unwrap(),
+        ];
+        for change in changes {
+            let mut gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+            change(&mut gameboy);
+            assert_eq!(
+                Movie::from_power_on(&gameboy),
+                Err(MovieError::NotAtPowerOn)
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_manual_palette() {
+        let rom = joypad_accumulator_rom();
+        let gameboy = GameBoy::with_palette(&rom, Model::Cgb, ManualPalette::DownA).unwrap();
+        let movie = Movie::decode(&Movie::from_power_on(&gameboy).unwrap().encode()).unwrap();
+        assert_eq!(
+            movie.start(&rom).unwrap().palette_selection(),
+            PaletteSelection::Manual(ManualPalette::DownA)
+        );
+    }
+
+    #[test]
+    fn invalid_movies() {
+        let rom = joypad_accumulator_rom();
+        let gameboy = GameBoy::with_model(&rom, Model::Dmg).unwrap();
+        let data = Movie::from_power_on(&gameboy).unwrap().encode();
+        assert_eq!(
+            Movie::decode(b"GBEMU-SS\x01\x00"),
+            Err(MovieError::NotAMovie)
+        );
+        assert_eq!(
+            Movie::decode(&[&data[..8], &(FORMAT_VERSION + 1).to_le_bytes()].concat()),
+            Err(MovieError::IncompatibleVersion(FORMAT_VERSION + 1))
+        );
+        assert_eq!(
+            Movie::decode(&data[..data.len() - 1]),
+            Err(MovieError::Corrupt)
+        );
+        assert_eq!(
+            Movie::decode(&[&data[..], &[0]].concat()),
+            Err(MovieError::Corrupt)
+        );
+
+        let mut other_rom = rom.clone();
+        other_rom[0x0200] = 1;
+        let movie = Movie::decode(&data).unwrap();
+        assert!(matches!(
+            movie.start(&other_rom),
+            Err(MovieError::WrongRom { expected, .. }) if expected == gameboy.rom_checksum()
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+//! Writing to `DMA` copies 160 bytes from `XX00`-`XX9F` into OAM, where `XX` is the written
+//! value. One byte is copied per machine cycle, starting a machine cycle after the write. While
+//! the transfer is running, OAM can't be accessed by the CPU.
+//! Like [`crate::hdma`], this only keeps track of the transfer; the copying is done by
+//! [`crate::memory_bus::MemoryBus`].
+
+use crate::memory_map::OAM_SIZE;
+use crate::save_state::{LoadStateError, SaveState, StateReader, StateWriter};
+
+#[derive(Default)]
+pub(super) struct OamDma {
+    /// The value last written to `DMA`, which is the upper byte of the source address.
+    source: u8,
+    /// The machine cycles until the transfer starts, if it was just requested.
+    start_delay: Option<u8>,
+    /// The offset of the next byte to copy, if a transfer is running.
+    progress: Option<usize>,
+}
+
+impl SaveState for OamDma {
+    fn save(&self, writer: &mut StateWriter) {
+        self.source.save(writer);
+        self.start_delay.save(writer);
+        self.progress.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.source.load(reader)?;
+        self.start_delay.load(reader)?;
+        self.progress.load(reader)?;
+        if self.progress.is_some_and(|offset| offset >= OAM_SIZE) {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl OamDma {
+    pub(super) fn read_register(&self) -> u8 {
+        self.source
+    }
+
+    /// Requests a new transfer. A running transfer keeps going until the new one starts.
+    pub(super) fn write_register(&mut self, value: u8) {
+        self.source = value;
+        self.start_delay = Some(1);
+    }
+
+    /// Whether OAM is blocked for the CPU.
+    pub(super) fn is_active(&self) -> bool {
+        self.progress.is_some()
+    }
+
+    /// Advances the transfer by a machine cycle. Returns the source address and the OAM offset of
+    /// the byte that has to be copied in this cycle, if any.
+    pub(super) fn step(&mut self) -> Option<(u16, usize)> {
+        let transfer = self.progress.map(|offset| {
+            self.progress = (offset + 1 < OAM_SIZE).then_some(offset + 1);
+            ((self.source as u16) << 8 | offset as u16, offset)
+        });
+
+        match self.start_delay {
+            Some(0) => {
+                self.start_delay = None;
+                self.progress = Some(0);
+            }
+            Some(delay) => self.start_delay = Some(delay - 1),
+            None => (),
+        }
+        transfer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer() {
+        let mut dma = OamDma::default();
+        dma.write_register(0xC1);
+        assert_eq!(dma.read_register(), 0xC1);
+
+        assert_eq!(dma.step(), None);
+        assert_eq!(dma.step(), None);
+        assert!(dma.is_active());
+        assert_eq!(dma.step(), Some((0xC100, 0)));
+        for offset in 1..OAM_SIZE {
+            assert_eq!(dma.step(), Some((0xC100 + offset as u16, offset)));
+        }
+        assert!(!dma.is_active());
+        assert_eq!(dma.step(), None);
+    }
+}
@@ -0,0 +1,321 @@
+//! Rewinding steps back in time by restoring an earlier save state. Snapshots are taken every few
+//! frames and the buttons are recorded for every frame, so any frame since the oldest snapshot can
+//! be reached exactly by loading the snapshot before it and running the frames in between again.
+//!
+//! Only the newest snapshot is kept whole. Each older one is stored as the difference to the
+//! snapshot after it: The bytes of both are XORed, which leaves mostly zeros, and runs of zeros are
+//! replaced by their length. Older snapshots are dropped once the memory budget is used up.
+
+use crate::gameboy::GameBoy;
+use std::collections::VecDeque;
+
+/// How often [`Rewind::default`] takes a snapshot, in frames.
+const DEFAULT_INTERVAL: u32 = 10;
+/// The memory budget of [`Rewind::default`], in bytes.
+const DEFAULT_MEMORY_BUDGET: usize = 64 << 20;
+
+struct Snapshot {
+    /// The frame count of the console when the snapshot was taken.
+    frame: u64,
+    /// The save state if this is the newest snapshot, otherwise the delta to the next one.
+    data: Vec<u8>,
+}
+
+/// A history of the console's states to rewind through.
+pub struct Rewind {
+    /// The frames between two snapshots.
+    interval: u64,
+    /// The bytes the snapshots and recorded buttons may take up.
+    memory_budget: usize,
+    /// Oldest first.
+    snapshots: VecDeque<Snapshot>,
+    /// The buttons held during each frame since the oldest snapshot, one bit per button in the
+    /// order of [`crate::Button::ALL`].
+    inputs: VecDeque<u8>,
+    memory_used: usize,
+}
+
+/// The interval and memory budget the frontends use, which keep a few minutes of most games.
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL, DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl Rewind {
+    /// Takes a snapshot every `interval` frames, keeping as many as fit into `memory_budget`
+    /// bytes. The newest snapshot is always kept, even if it's larger than the budget.
+    pub fn new(interval: u32, memory_budget: usize) -> Self {
+        Self {
+            interval: interval.max(1) as u64,
+            memory_budget,
+            snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+            memory_used: 0,
+        }
+    }
+
+    /// Records the frame that is about to run. Call this before every frame, after the buttons
+    /// have been set for it. Recording a frame out of order starts over, and so should loading a
+    /// save state, by calling [`Self::clear`].
+    pub fn record(&mut self, gameboy: &GameBoy) {
+        let frame = gameboy.frame_count();
+        if !self.snapshots.is_empty() && frame != self.next_frame() {
+            self.clear();
+        }
+        let due = self
+            .snapshots
+            .back()
+            .is_none_or(|newest| frame - newest.frame >= self.interval);
+        if due {
+            self.push_snapshot(frame, gameboy.save_state());
+        }
+        self.inputs.push_back(gameboy.pressed_buttons());
+        self.memory_used += 1;
+        self.drop_old_snapshots();
+    }
+
+    /// Rewinds the console by the given amount of frames, or as far as the history goes. Returns
+    /// the amount of frames it went back, which is 0 if the current frame wasn't recorded. The
+    /// frames after it are forgotten.
+    pub fn rewind(&mut self, gameboy: &mut GameBoy, frames: u64) -> u64 {
+        let current = gameboy.frame_count();
+        let Some(oldest) = self.snapshots.front() else {
+            return 0;
+        };
+        if current < oldest.frame || current > self.next_frame() {
+            return 0;
+        }
+        let target = current.saturating_sub(frames).max(oldest.frame);
+        let first_frame = oldest.frame;
+
+        self.truncate(target);
+        let newest = self.snapshots.back().unwrap();
+        gameboy
+            .load_state(&newest.data)
+            .expect("snapshots are states of the same console");
+        for frame in newest.frame..target {
+            gameboy.set_pressed_buttons(self.inputs[(frame - first_frame) as usize]);
+            gameboy.run_frame();
+        }
+        current - target
+    }
+
+    /// Forgets the whole history.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.inputs.clear();
+        self.memory_used = 0;
+    }
+
+    /// The bytes the snapshots and recorded buttons take up.
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    /// The earliest frame that can be rewound to, if anything was recorded.
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.snapshots.front().map(|snapshot| snapshot.frame)
+    }
+
+    /// The frame after the last one that was recorded.
+    fn next_frame(&self) -> u64 {
+        self.snapshots.front().map_or(0, |oldest| oldest.frame) + self.inputs.len() as u64
+    }
+
+    fn push_snapshot(&mut self, frame: u64, state: Vec<u8>) {
+        if let Some(previous) = self.snapshots.back_mut() {
+            let delta = encode_delta(&previous.data, &state);
+            self.memory_used -= previous.data.len();
+            self.memory_used += delta.len();
+            previous.data = delta;
+        }
+        self.memory_used += state.len();
+        self.snapshots.push_back(Snapshot { frame, data: state });
+    }
+
+    /// Forgets the recorded frames from `frame` on, and the snapshots taken after it.
+    fn truncate(&mut self, frame: u64) {
+        while self.snapshots.len() > 1 && self.snapshots.back().unwrap().frame > frame {
+            let newest = self.snapshots.pop_back().unwrap();
+            let previous = self.snapshots.back_mut().unwrap();
+            let state = decode_delta(&previous.data, &newest.data);
+            self.memory_used -= newest.data.len() + previous.data.len();
+            self.memory_used += state.len();
+            previous.data = state;
+        }
+        let first_frame = self.snapshots.front().map_or(frame, |oldest| oldest.frame);
+        let length = frame.saturating_sub(first_frame) as usize;
+        if length < self.inputs.len() {
+            self.memory_used -= self.inputs.len() - length;
+            self.inputs.truncate(length);
+        }
+    }
+
+    fn drop_old_snapshots(&mut self) {
+        while self.memory_used > self.memory_budget && self.snapshots.len() > 1 {
+            let oldest = self.snapshots.pop_front().unwrap();
+            let frames = self.snapshots.front().unwrap().frame - oldest.frame;
+            let frames = (frames as usize).min(self.inputs.len());
+            self.inputs.drain(..frames);
+            self.memory_used -= oldest.data.len() + frames;
+        }
+    }
+}
+
+/// Encodes `target` as the difference to `base`: Its length, followed by pairs of a run of
+/// unchanged bytes and a run of changed bytes, each run starting with its length.
+fn encode_delta(target: &[u8], base: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = target
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| byte ^ base.get(index).copied().unwrap_or(0))
+        .collect();
+    let mut delta = Vec::new();
+    write_length(&mut delta, target.len());
+    let mut rest = &xor[..];
+    while !rest.is_empty() {
+        let unchanged = rest.iter().take_while(|byte| **byte == 0).count();
+        rest = &rest[unchanged..];
+        let changed = rest.iter().take_while(|byte| **byte != 0).count();
+        write_length(&mut delta, unchanged);
+        write_length(&mut delta, changed);
+        delta.extend_from_slice(&rest[..changed]);
+        rest = &rest[changed..];
+    }
+    delta
+}
+
+/// Restores the data that [`encode_delta`] encoded against `base`.
+fn decode_delta(delta: &[u8], base: &[u8]) -> Vec<u8> {
+    let mut rest = delta;
+    let length = read_length(&mut rest);
+    let mut target = Vec::with_capacity(length);
+    let base_byte = |index: usize| base.get(index).copied().unwrap_or(0);
+    while !rest.is_empty() {
+        let unchanged = read_length(&mut rest);
+        let changed = read_length(&mut rest);
+        for _ in 0..unchanged {
+            target.push(base_byte(target.len()));
+        }
+        for byte in &rest[..changed] {
+            target.push(byte ^ base_byte(target.len()));
+        }
+        rest = &rest[changed..];
+    }
+    target
+}
+
+/// Writes a length with 7 bits per byte, least significant first. The top bit is set on every
+/// byte but the last.
+fn write_length(data: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        data.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    data.push(length as u8);
+}
+
+fn read_length(data: &mut &[u8]) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    while let Some((byte, rest)) = data.split_first() {
+        *data = rest;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::joypad_accumulator_rom;
+    use crate::joypad::Button;
+    use crate::model::Model;
+
+    fn console() -> GameBoy {
+        GameBoy::with_model(&joypad_accumulator_rom(), Model::Dmg).unwrap()
+    }
+
+    /// Runs 30 frames, holding Right for some of them. Returns the state before each frame.
+    fn record(gameboy: &mut GameBoy, rewind: &mut Rewind) -> Vec<Vec<u8>> {
+        let mut states = Vec::new();
+        for frame in 0..30 {
+            gameboy.set_button(Button::Right, (5..12).contains(&frame) || frame == 21);
+            states.push(gameboy.save_state());
+            rewind.record(gameboy);
+            gameboy.run_frame();
+        }
+        states
+    }
+
+    #[test]
+    fn rewinds_exactly() {
+        let mut gameboy = console();
+        let mut rewind = Rewind::new(8, usize::MAX);
+        let states = record(&mut gameboy, &mut rewind);
+        assert_eq!(rewind.oldest_frame(), Some(0));
+
+        assert_eq!(rewind.rewind(&mut gameboy, 7), 7);
+        assert_eq!(gameboy.frame_count(), 23);
+        assert_eq!(gameboy.save_state(), states[23]);
+        assert_eq!(rewind.rewind(&mut gameboy, 14), 14);
+        assert_eq!(gameboy.save_state(), states[9]);
+        assert_eq!(rewind.rewind(&mut gameboy, 100), 9);
+        assert_eq!(gameboy.save_state(), states[0]);
+    }
+
+    #[test]
+    fn recording_again_after_rewinding() {
+        let mut gameboy = console();
+        let mut rewind = Rewind::new(4, usize::MAX);
+        let states = record(&mut gameboy, &mut rewind);
+        rewind.rewind(&mut gameboy, 20);
+
+        // Playing the same frames again leads to the same states.
+        let mut replayed = console();
+        replayed.load_state(&states[10]).unwrap();
+        for frame in 10..20 {
+            gameboy.set_button(Button::Right, frame == 15);
+            replayed.set_button(Button::Right, frame == 15);
+            rewind.record(&gameboy);
+            gameboy.run_frame();
+            replayed.run_frame();
+        }
+        assert_eq!(gameboy.save_state(), replayed.save_state());
+        let replayed_state = gameboy.save_state();
+        rewind.record(&gameboy);
+        gameboy.run_frame();
+        assert_eq!(rewind.rewind(&mut gameboy, 1), 1);
+        assert_eq!(gameboy.save_state(), replayed_state);
+    }
+
+    #[test]
+    fn memory_budget() {
+        let mut gameboy = console();
+        let state_size = gameboy.save_state().len();
+        let mut rewind = Rewind::new(4, state_size + 2000);
+        let states = record(&mut gameboy, &mut rewind);
+        assert!(rewind.memory_used() <= state_size + 2000);
+        let oldest = rewind.oldest_frame().unwrap();
+        assert!(oldest > 0 && oldest < 28, "{oldest}");
+
+        let frames = rewind.rewind(&mut gameboy, 100);
+        assert_eq!(frames, 30 - oldest);
+        assert_eq!(gameboy.save_state(), states[oldest as usize]);
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let base = [1, 2, 3, 4, 5, 6, 7, 8];
+        for target in [&[1, 2, 0, 4, 5, 9, 9, 8][..], &[1, 2, 3], &[1; 300], &[]] {
+            let delta = encode_delta(target, &base);
+            assert_eq!(decode_delta(&delta, &base), target);
+        }
+        assert_eq!(encode_delta(&base, &base), [8, 8, 0]);
+    }
+}
@@ -0,0 +1,480 @@
+//! Disassembles a whole ROM into RGBDS source code, which assembles back into the same ROM.
+//!
+//! Code is found by following every path of execution from the entry point and the `RST` and
+//! interrupt vectors. Everything that isn't reached is kept as data. Jumps into the switchable
+//! bank are only followed if it's known which bank is mapped there: Either the jump comes from
+//! that bank itself, or the code selected the bank right before with `LD A, n8` followed by
+//! a write of A to the MBC. ROMs with only two banks always have bank 1 mapped.
+//!
+//! Labels are named after the game's symbols where they are known, so a disassembly made with
+//! a symbol file reads like the original source.
+
+use crate::cpu::{Disassembly, Flow, disassemble};
+use crate::symbols::Symbols;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+/// A source file of the disassembly.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AsmFile {
+    pub name: String,
+    pub text: String,
+}
+
+const BANK_SIZE: usize = 0x4000;
+const SWITCHABLE_BANK_START: u16 = 0x4000;
+const SWITCHABLE_BANK_END: u16 = 0x7FFF;
+/// Writing the bank number to this range selects the ROM bank on all common MBCs.
+const BANK_SELECT: RangeInclusive<u16> = 0x2000..=0x3FFF;
+/// The opcodes of `LD A, n8` and `LD [n16], A`, which select a bank.
+const LOAD_A_IMMEDIATE: u8 = 0x3E;
+const STORE_A_INDIRECT: u8 = 0xEA;
+/// The opcode of `RST $38`. Unused vectors are usually filled with it, so they aren't followed.
+const PADDING_OPCODE: u8 = 0xFF;
+const STOP_OPCODE: u8 = 0x10;
+/// Where execution can start without being jumped to.
+const VECTORS: [(u16, &str); 14] = [
+    (0x0000, "Rst_00"),
+    (0x0008, "Rst_08"),
+    (0x0010, "Rst_10"),
+    (0x0018, "Rst_18"),
+    (0x0020, "Rst_20"),
+    (0x0028, "Rst_28"),
+    (0x0030, "Rst_30"),
+    (0x0038, "Rst_38"),
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LCDInterrupt"),
+    (0x0050, "TimerInterrupt"),
+    (0x0058, "SerialInterrupt"),
+    (0x0060, "JoypadInterrupt"),
+    (0x0100, "EntryPoint"),
+];
+const BYTES_PER_DATA_LINE: usize = 8;
+/// Runs of the same byte at least this long are written with `DS` instead of `DB`.
+const MIN_FILL_LENGTH: usize = 16;
+
+/// What a byte of the ROM was found to be.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Byte {
+    Data,
+    /// The first byte of an instruction.
+    Opcode,
+    /// One of the following bytes of an instruction.
+    Operand,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum LabelKind {
+    Jump,
+    Call,
+    Vector(&'static str),
+}
+
+/// A place in the ROM to start tracing code from, together with the switchable bank known to be
+/// mapped there.
+#[derive(Copy, Clone, Debug)]
+struct Entry {
+    offset: usize,
+    mapped_bank: Option<usize>,
+}
+
+struct Analysis<'a> {
+    rom: &'a [u8],
+    bytes: Vec<Byte>,
+    /// The decoded instructions by their offset in the ROM.
+    instructions: HashMap<usize, Disassembly>,
+    /// The offsets that jumps and calls of the instructions lead to.
+    targets: HashMap<usize, usize>,
+    labels: BTreeMap<usize, LabelKind>,
+    queue: Vec<Entry>,
+    symbols: &'a Symbols,
+}
+
+/// Disassembles the ROM into a main file `game.asm`, which includes a file for each bank. Code
+/// at the addresses of symbols is labeled with their names; local labels are left out, because
+/// they'd need their parent label in front of them.
+pub fn disassemble_rom(rom: &[u8], symbols: &Symbols) -> Vec<AsmFile> {
+    let analysis = Analysis::run(rom, symbols);
+    let bank_count = rom.len().div_ceil(BANK_SIZE);
+    let mut main = String::from(
+        "; Build the ROM with:\n\
+         ;     rgbasm -o game.o game.asm\n\
+         ;     rgblink -o game.gb game.o\n\
+         ; The header is included in bank 0, so it doesn't need to be fixed with rgbfix.\n\n",
+    );
+    let mut files = Vec::new();
+    for bank in 0..bank_count {
+        let name = format!("bank_{bank:03X}.asm");
+        writeln!(main, "INCLUDE \"{name}\"").unwrap();
+        files.push(AsmFile {
+            name,
+            text: analysis.bank_source(bank),
+        });
+    }
+    files.insert(
+        0,
+        AsmFile {
+            name: "game.asm".to_string(),
+            text: main,
+        },
+    );
+    files
+}
+
+/// The address a byte of the ROM is mapped to while its bank is selected.
+fn address(offset: usize) -> u16 {
+    if offset < BANK_SIZE {
+        offset as u16
+    } else {
+        SWITCHABLE_BANK_START + (offset % BANK_SIZE) as u16
+    }
+}
+
+impl<'a> Analysis<'a> {
+    /// Finds the code reachable from the vectors.
+    fn run(rom: &'a [u8], symbols: &'a Symbols) -> Self {
+        let mut analysis = Analysis {
+            rom,
+            bytes: vec![Byte::Data; rom.len()],
+            instructions: HashMap::new(),
+            targets: HashMap::new(),
+            labels: BTreeMap::new(),
+            queue: Vec::new(),
+            symbols,
+        };
+        for (address, name) in VECTORS {
+            let offset = address as usize;
+            if rom.get(offset).is_some_and(|byte| *byte != PADDING_OPCODE) {
+                analysis.labels.insert(offset, LabelKind::Vector(name));
+                analysis.queue.push(Entry {
+                    offset,
+                    mapped_bank: None,
+                });
+            }
+        }
+        while let Some(entry) = analysis.queue.pop() {
+            analysis.trace(entry);
+        }
+        analysis
+    }
+
+    fn bank_count(&self) -> usize {
+        self.rom.len().div_ceil(BANK_SIZE)
+    }
+
+    /// Finds the byte of the ROM that an address refers to when reached from the given bank.
+    fn resolve(&self, address: u16, bank: usize, mapped_bank: Option<usize>) -> Option<usize> {
+        let offset = match address {
+            0..SWITCHABLE_BANK_START => address as usize,
+            SWITCHABLE_BANK_START..=SWITCHABLE_BANK_END => {
+                let bank = match bank {
+                    0 if self.bank_count() == 2 => 1,
+                    0 => mapped_bank?,
+                    _ => bank,
+                };
+                bank * BANK_SIZE + (address - SWITCHABLE_BANK_START) as usize
+            }
+            // Code in RAM isn't part of the ROM.
+            _ => return None,
+        };
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    /// Decodes instructions from the entry until execution can't continue, or until it reaches
+    /// code that was already found.
+    fn trace(&mut self, entry: Entry) {
+        let Entry {
+            mut offset,
+            mut mapped_bank,
+        } = entry;
+        let bank = offset / BANK_SIZE;
+        let bank_end = ((bank + 1) * BANK_SIZE).min(self.rom.len());
+        // The value of A, as long as it was just loaded.
+        let mut loaded_value = None;
+        while self.bytes.get(offset) == Some(&Byte::Data) {
+            let start = offset;
+            let instruction = disassemble(address(offset), |address| {
+                let at = start + address.wrapping_sub(self::address(start)) as usize;
+                self.rom.get(at).copied().unwrap_or_default()
+            });
+            let end = offset + instruction.bytes.len();
+            // Instructions can't reach into the next bank or overlap others. `STOP` is always
+            // assembled with a 0 after it, so anything else has to stay data.
+            if end > bank_end
+                || self.bytes[offset..end]
+                    .iter()
+                    .any(|byte| *byte != Byte::Data)
+                || matches!(instruction.bytes[..], [STOP_OPCODE, second] if second != 0)
+            {
+                return;
+            }
+            self.bytes[offset] = Byte::Opcode;
+            self.bytes[offset + 1..end].fill(Byte::Operand);
+
+            match instruction.bytes[..] {
+                [LOAD_A_IMMEDIATE, value] => loaded_value = Some(value),
+                [STORE_A_INDIRECT, low, high]
+                    if BANK_SELECT.contains(&u16::from_le_bytes([low, high])) =>
+                {
+                    // Selecting bank 0 selects bank 1 instead on most MBCs.
+                    mapped_bank = loaded_value.map(|value| (value as usize).max(1));
+                }
+                _ => loaded_value = None,
+            }
+
+            let flow = instruction.flow();
+            self.instructions.insert(offset, instruction);
+            let (target, kind, continues) = match flow {
+                Flow::Next => (None, None, true),
+                Flow::Jump {
+                    target,
+                    conditional,
+                } => (Some(target), Some(LabelKind::Jump), conditional),
+                Flow::Call { target } => (Some(target), Some(LabelKind::Call), true),
+                Flow::Unknown => (None, None, false),
+            };
+            if let Some(target) = target.and_then(|target| self.resolve(target, bank, mapped_bank))
+            {
+                self.targets.insert(offset, target);
+                let label = self.labels.entry(target).or_insert(LabelKind::Jump);
+                *label = (*label).max(kind.unwrap());
+                self.queue.push(Entry {
+                    offset: target,
+                    mapped_bank,
+                });
+            }
+            if !continues {
+                return;
+            }
+            offset = end;
+        }
+    }
+
+    /// The name of the label at an offset, if there is one and it's placed at an instruction.
+    fn label(&self, offset: usize) -> Option<String> {
+        if self.bytes[offset] != Byte::Opcode {
+            return None;
+        }
+        let bank = (offset / BANK_SIZE) as u16;
+        if let Some(name) = self
+            .symbols
+            .names_at(address(offset), Some(bank))
+            .find(|name| !name.contains('.'))
+        {
+            return Some(name.to_string());
+        }
+        let prefix = match self.labels.get(&offset)? {
+            LabelKind::Vector(name) => return Some(name.to_string()),
+            LabelKind::Jump => "Jump",
+            LabelKind::Call => "Call",
+        };
+        Some(format!(
+            "{prefix}_{:03X}_{:04X}",
+            offset / BANK_SIZE,
+            address(offset)
+        ))
+    }
+
+    /// The instruction at an offset, with the address it jumps to replaced by its label.
+    fn instruction_source(&self, offset: usize) -> String {
+        let instruction = &self.instructions[&offset];
+        let text = &instruction.text;
+        let Some(target) = self.targets.get(&offset) else {
+            return text.clone();
+        };
+        let target_text = format!("${:04X}", address(*target));
+        match (text.strip_suffix(&target_text), self.label(*target)) {
+            (Some(start), Some(label)) => format!("{start}{label}"),
+            _ => text.clone(),
+        }
+    }
+
+    fn bank_source(&self, bank: usize) -> String {
+        let start = bank * BANK_SIZE;
+        let end = ((bank + 1) * BANK_SIZE).min(self.rom.len());
+        let mut text = if bank == 0 {
+            "SECTION \"ROM Bank $000\", ROM0[$0000]\n".to_string()
+        } else {
+            format!("SECTION \"ROM Bank ${bank:03X}\", ROMX[$4000], BANK[${bank:X}]\n")
+        };
+
+        let mut offset = start;
+        while offset < end {
+            if self.bytes[offset] == Byte::Opcode {
+                if let Some(label) = self.label(offset) {
+                    writeln!(text, "\n{label}:").unwrap();
+                }
+                writeln!(text, "    {}", self.instruction_source(offset)).unwrap();
+                offset += self.instructions[&offset].bytes.len();
+                continue;
+            }
+
+            let data_end = (offset..end)
+                .find(|offset| self.bytes[*offset] != Byte::Data)
+                .unwrap_or(end);
+            let data = &self.rom[offset..data_end];
+            let fill_length = data.iter().take_while(|byte| **byte == data[0]).count();
+            if fill_length >= MIN_FILL_LENGTH {
+                writeln!(text, "    DS {fill_length}, ${:02X}", data[0]).unwrap();
+                offset += fill_length;
+                continue;
+            }
+            // Stop before the next run that is written with `DS`.
+            let line_length = (1..data.len().min(BYTES_PER_DATA_LINE))
+                .find(|start| {
+                    let rest = &data[*start..];
+                    rest.len() >= MIN_FILL_LENGTH
+                        && rest[..MIN_FILL_LENGTH].iter().all(|byte| *byte == rest[0])
+                })
+                .unwrap_or(data.len().min(BYTES_PER_DATA_LINE));
+            let bytes: Vec<_> = data[..line_length]
+                .iter()
+                .map(|byte| format!("${byte:02X}"))
+                .collect();
+            writeln!(text, "    DB {}", bytes.join(", ")).unwrap();
+            offset += line_length;
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four banks filled with `RST $38`. The entry point calls a function in bank 0, which selects
+    /// bank 2 and calls into it.
+    fn test_rom() -> Vec<u8> {
+        let mut rom = vec![PADDING_OPCODE; 4 * BANK_SIZE];
+        let mut write = |offset: usize, bytes: &[u8]| {
+            rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        write(0x0100, &[0x00, 0xC3, 0x50, 0x01]); // NOP, JP $0150
+        write(0x0104, &[0xCE, 0xED, 0x66, 0x66]); // The start of the logo
+        write(0x0150, &[0xCD, 0x60, 0x01]); // CALL $0160
+        write(0x0153, &[0x18, 0xFE]); // JR $0153
+        write(0x0160, &[0x3E, 0x02]); // LD A, 2
+        write(0x0162, &[0xEA, 0x00, 0x20]); // LD [$2000], A
+        write(0x0165, &[0xCD, 0x00, 0x40]); // CALL $4000
+        write(0x0168, &[0xC9]); // RET
+        write(0x8000, &[0x40, 0x20, 0xFD, 0xC9]); // LD B, B; JR NZ, $4000; RET
+        rom
+    }
+
+    #[test]
+    fn files() {
+        let files = disassemble_rom(&test_rom(), &Symbols::default());
+        let names: Vec<_> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "game.asm",
+                "bank_000.asm",
+                "bank_001.asm",
+                "bank_002.asm",
+                "bank_003.asm"
+            ]
+        );
+        assert!(files[0].text.ends_with(
+            "INCLUDE \"bank_000.asm\"\nINCLUDE \"bank_001.asm\"\n\
+             INCLUDE \"bank_002.asm\"\nINCLUDE \"bank_003.asm\"\n"
+        ));
+    }
+
+    #[test]
+    fn code_and_labels() {
+        let files = disassemble_rom(&test_rom(), &Symbols::default());
+        let bank_0 = &files[1].text;
+        assert!(bank_0.starts_with("SECTION \"ROM Bank $000\", ROM0[$0000]\n    DS 256, $FF\n"));
+        assert!(bank_0.contains(
+            "\nEntryPoint:\n    NOP\n    JP Jump_000_0150\n    DB $CE, $ED, $66, $66\n    DS 72, $FF\n"
+        ));
+        assert!(bank_0.contains(
+            "\nJump_000_0150:\n    CALL Call_000_0160\n\nJump_000_0153:\n    JR Jump_000_0153\n"
+        ));
+        assert!(bank_0.contains(
+            "\nCall_000_0160:\n    LD A, $02\n    LD [$2000], A\n    CALL Call_002_4000\n    RET\n"
+        ));
+
+        let bank_2 = &files[3].text;
+        assert!(bank_2.starts_with(
+            "SECTION \"ROM Bank $002\", ROMX[$4000], BANK[$2]\n\n\
+             Call_002_4000:\n    LD B, B\n    JR NZ, Call_002_4000\n    RET\n    DS 16380, $FF\n"
+        ));
+        // Bank 1 is never selected, so it's all data.
+        assert!(files[2].text.ends_with("BANK[$1]\n    DS 16384, $FF\n"));
+    }
+
+    #[test]
+    fn unknown_bank() {
+        let mut rom = test_rom();
+        // Without the bank being selected, the call can't be followed.
+        rom[0x0160..0x0162].copy_from_slice(&[0x00, 0x00]);
+        let files = disassemble_rom(&rom, &Symbols::default());
+        assert!(files[1].text.contains("    CALL $4000\n"));
+        assert!(
+            files[3]
+                .text
+                .contains("BANK[$2]\n    DB $40, $20, $FD, $C9\n")
+        );
+    }
+
+    /// Every byte of the ROM is written exactly once, either as data or as part of an instruction.
+    #[test]
+    fn covers_every_byte() {
+        let rom = test_rom();
+        let symbols = Symbols::default();
+        let analysis = Analysis::run(&rom, &symbols);
+        let code_length: usize = analysis
+            .instructions
+            .values()
+            .map(|instruction| instruction.bytes.len())
+            .sum();
+        let data_length = analysis
+            .bytes
+            .iter()
+            .filter(|byte| **byte == Byte::Data)
+            .count();
+        assert_eq!(code_length + data_length, rom.len());
+    }
+
+    #[test]
+    fn data_lines() {
+        let mut rom = vec![PADDING_OPCODE; 0x40];
+        rom[0] = 0xC9; // RET
+        rom[1..11].copy_from_slice(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0xFF, 0xFF, 0x0A]);
+        let files = disassemble_rom(&rom, &Symbols::default());
+        assert_eq!(
+            files[1].text,
+            "SECTION \"ROM Bank $000\", ROM0[$0000]\n\nRst_00:\n    RET\n    \
+             DB $01, $02, $03, $04, $05, $06, $07, $FF\n    DB $FF, $0A\n    DS 53, $FF\n"
+        );
+    }
+
+    #[test]
+    fn symbol_labels() {
+        let mut symbols = Symbols::default();
+        symbols.insert(0, 0x0150, "Main");
+        symbols.insert(0, 0x0153, "Main.loop");
+        symbols.insert(0, 0x0162, "SelectBank");
+        symbols.insert(2, 0x4000, "PlayMusic");
+        // A symbol in another bank doesn't name the code.
+        symbols.insert(1, 0x4000, "Graphics");
+        let files = disassemble_rom(&test_rom(), &symbols);
+        let bank_0 = &files[1].text;
+        assert!(bank_0.contains("    JP Main\n"));
+        assert!(
+            bank_0.contains(
+                "\nMain:\n    CALL Call_000_0160\n\nJump_000_0153:\n    JR Jump_000_0153\n"
+            )
+        );
+        assert!(bank_0.contains("    LD A, $02\n\nSelectBank:\n    LD [$2000], A\n"));
+        assert!(bank_0.contains("    CALL PlayMusic\n"));
+        assert!(
+            files[3]
+                .text
+                .contains("\nPlayMusic:\n    LD B, B\n    JR NZ, PlayMusic\n")
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Save states capture everything needed to continue emulation later: The state of every
+//! component, but not the cartridge's ROM or settings of the frontend like the sample rate.
+//! Every component writes its fields one after another in a fixed order, without any names or
+//! separators in between, and reads them back in the same order.
+//!
+//! The state of the console is preceded by a header: A signature, the version of the format,
+//! the version of the emulator that wrote the state and a checksum of the ROM. States of other
+//! formats or ROMs are rejected before anything is loaded, as their fields can't be made sense of.
+
+use std::fmt;
+
+/// The start of every save state.
+const SIGNATURE: [u8; 8] = *b"GBEMU-SS";
+/// Increased whenever the layout of a component's state changes.
+const FORMAT_VERSION: u16 = 2;
+/// The emulator's version, which is only recorded to tell the user which version made a state.
+const EMULATOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Why a save state couldn't be loaded. The emulator keeps its previous state in that case.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LoadStateError {
+    /// The data doesn't start with the signature of a save state.
+    NotASaveState,
+    /// The state was written in another version of the format, by the given emulator version.
+    IncompatibleVersion {
+        format_version: u16,
+        emulator_version: String,
+    },
+    /// The state was saved while playing a ROM with another checksum.
+    WrongRom { expected: u32, found: u32 },
+    /// The data ended before the state was complete.
+    UnexpectedEnd,
+    /// There is more data after the state.
+    TrailingData,
+    /// A value is out of the range the field allows.
+    InvalidValue,
+}
+
+impl fmt::Display for LoadStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadStateError::NotASaveState => write!(f, "the file is not a save state"),
+            LoadStateError::IncompatibleVersion {
+                format_version,
+                emulator_version,
+            } => write!(
+                f,
+                "the save state was made by version {emulator_version} of the emulator, whose \
+                 format {format_version} can't be read by version {EMULATOR_VERSION} (format \
+                 {FORMAT_VERSION})"
+            ),
+            LoadStateError::WrongRom { expected, found } => write!(
+                f,
+                "the save state belongs to another ROM (checksum {found:08X} instead of \
+                 {expected:08X})"
+            ),
+            LoadStateError::UnexpectedEnd => write!(f, "the save state is truncated"),
+            LoadStateError::TrailingData => write!(f, "the save state contains unexpected data"),
+            LoadStateError::InvalidValue => write!(f, "the save state contains an invalid value"),
+        }
+    }
+}
+
+impl std::error::Error for LoadStateError {}
+
+#[derive(Default)]
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub(crate) fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], LoadStateError> {
+        let (bytes, rest) = self
+            .data
+            .split_first_chunk()
+            .ok_or(LoadStateError::UnexpectedEnd)?;
+        self.data = rest;
+        Ok(*bytes)
+    }
+
+    pub(crate) fn read_slice(&mut self, length: usize) -> Result<&'a [u8], LoadStateError> {
+        if length > self.data.len() {
+            return Err(LoadStateError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Makes sure the whole state has been read.
+    pub(crate) fn finish(self) -> Result<(), LoadStateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(LoadStateError::TrailingData)
+        }
+    }
+}
+
+/// Writes the header for a state of the console playing the ROM with the given checksum.
+pub(crate) fn write_header(writer: &mut StateWriter, rom_checksum: u32) {
+    writer.write_bytes(&SIGNATURE);
+    FORMAT_VERSION.save(writer);
+    (EMULATOR_VERSION.len() as u8).save(writer);
+    writer.write_bytes(EMULATOR_VERSION.as_bytes());
+    rom_checksum.save(writer);
+}
+
+/// Reads the header and checks that the state can be loaded for the ROM with the given checksum.
+pub(crate) fn read_header(
+    reader: &mut StateReader,
+    rom_checksum: u32,
+) -> Result<(), LoadStateError> {
+    if reader.read_bytes() != Ok(SIGNATURE) {
+        return Err(LoadStateError::NotASaveState);
+    }
+    let mut format_version = 0u16;
+    format_version.load(reader)?;
+    let mut length = 0u8;
+    length.load(reader)?;
+    let emulator_version = String::from_utf8_lossy(reader.read_slice(length as usize)?);
+    if format_version != FORMAT_VERSION {
+        return Err(LoadStateError::IncompatibleVersion {
+            format_version,
+            emulator_version: emulator_version.into_owned(),
+        });
+    }
+    let mut found = 0u32;
+    found.load(reader)?;
+    if found != rom_checksum {
+        return Err(LoadStateError::WrongRom {
+            expected: rom_checksum,
+            found,
+        });
+    }
+    Ok(())
+}
+
+/// A part of the emulator's state that can be saved and restored. Loading overwrites the fields
+/// in place, so anything that isn't part of the state keeps its current value.
+pub(crate) trait SaveState {
+    fn save(&self, writer: &mut StateWriter);
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError>;
+}
+
+/// Implements [`SaveState`] for a struct by saving the listed fields in order.
+macro_rules! impl_save_state {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::save_state::SaveState for $type {
+            fn save(&self, writer: &mut $crate::save_state::StateWriter) {
+                $($crate::save_state::SaveState::save(&self.$field, writer);)*
+            }
+
+            fn load(
+                &mut self,
+                reader: &mut $crate::save_state::StateReader,
+            ) -> Result<(), $crate::save_state::LoadStateError> {
+                $($crate::save_state::SaveState::load(&mut self.$field, reader)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Implements [`SaveState`] for an enum without fields by saving the index of its variant.
+macro_rules! impl_save_state_enum {
+    ($type:ident { $($variant:ident),* $(,)? }) => {
+        impl $crate::save_state::SaveState for $type {
+            fn save(&self, writer: &mut $crate::save_state::StateWriter) {
+                const VARIANTS: &[$type] = &[$($type::$variant),*];
+                let index = VARIANTS.iter().position(|variant| variant == self).unwrap();
+                writer.write_bytes(&[index as u8]);
+            }
+
+            fn load(
+                &mut self,
+                reader: &mut $crate::save_state::StateReader,
+            ) -> Result<(), $crate::save_state::LoadStateError> {
+                const VARIANTS: &[$type] = &[$($type::$variant),*];
+                let [index] = reader.read_bytes()?;
+                *self = *VARIANTS
+                    .get(index as usize)
+                    .ok_or($crate::save_state::LoadStateError::InvalidValue)?;
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use {impl_save_state, impl_save_state_enum};
+
+/// Saves `value` and loads the result into `target`, e.g. to check that a corrupted value is
+/// rejected when loading.
+#[cfg(test)]
+pub(crate) fn copy_state<T: SaveState>(value: &T, target: &mut T) -> Result<(), LoadStateError> {
+    let mut writer = StateWriter::default();
+    value.save(&mut writer);
+    let data = writer.into_bytes();
+    let mut reader = StateReader::new(&data);
+    target.load(&mut reader)?;
+    reader.finish()
+}
+
+macro_rules! impl_save_state_for_number {
+    ($($type:ty),*) => {
+        $(
+            impl SaveState for $type {
+                fn save(&self, writer: &mut StateWriter) {
+                    writer.write_bytes(&self.to_le_bytes());
+                }
+
+                fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+                    *self = <$type>::from_le_bytes(reader.read_bytes()?);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_save_state_for_number!(u8, u16, u32, u64, f32);
+
+/// Sizes and offsets are saved as 32 bits, so states don't depend on the platform.
+impl SaveState for usize {
+    fn save(&self, writer: &mut StateWriter) {
+        (*self as u32).save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        *self = u32::from_le_bytes(reader.read_bytes()?) as usize;
+        Ok(())
+    }
+}
+
+impl SaveState for bool {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&[*self as u8]);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        *self = match reader.read_bytes()? {
+            [0] => false,
+            [1] => true,
+            _ => return Err(LoadStateError::InvalidValue),
+        };
+        Ok(())
+    }
+}
+
+impl<T: SaveState, const N: usize> SaveState for [T; N] {
+    fn save(&self, writer: &mut StateWriter) {
+        for element in self {
+            element.save(writer);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        for element in self {
+            element.load(reader)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: SaveState> SaveState for Box<T> {
+    fn save(&self, writer: &mut StateWriter) {
+        self.as_ref().save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.as_mut().load(reader)
+    }
+}
+
+/// Saved as a flag followed by the value, if present. A value that is missing before loading
+/// starts out with its default.
+impl<T: SaveState + Default> SaveState for Option<T> {
+    fn save(&self, writer: &mut StateWriter) {
+        self.is_some().save(writer);
+        if let Some(value) = self {
+            value.save(writer);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        let mut is_some = false;
+        is_some.load(reader)?;
+        if !is_some {
+            *self = None;
+            return Ok(());
+        }
+        self.get_or_insert_with(T::default).load(reader)
+    }
+}
+
+/// Saved as the length followed by the elements.
+impl<T: SaveState + Default> SaveState for Vec<T> {
+    fn save(&self, writer: &mut StateWriter) {
+        self.len().save(writer);
+        for element in self {
+            element.save(writer);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        let mut length = 0usize;
+        length.load(reader)?;
+        // Every element takes up at least a byte, which rules out absurd lengths early.
+        if length > reader.data.len() {
+            return Err(LoadStateError::UnexpectedEnd);
+        }
+        self.clear();
+        for _ in 0..length {
+            let mut element = T::default();
+            element.load(reader)?;
+            self.push(element);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, PartialEq, Debug)]
+    struct Example {
+        number: u16,
+        flag: bool,
+        optional: Option<u32>,
+        list: Vec<u8>,
+        array: [u8; 3],
+    }
+
+    impl_save_state!(Example {
+        number,
+        flag,
+        optional,
+        list,
+        array,
+    });
+
+    fn save(value: &impl SaveState) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        value.save(&mut writer);
+        writer.into_bytes()
+    }
+
+    #[test]
+    fn round_trip() {
+        let example = Example {
+            number: 0x1234,
+            flag: true,
+            optional: Some(7),
+            list: vec![1, 2, 3],
+            array: [4, 5, 6],
+        };
+        let data = save(&example);
+
+        let mut loaded = Example::default();
+        let mut reader = StateReader::new(&data);
+        loaded.load(&mut reader).unwrap();
+        reader.finish().unwrap();
+        assert_eq!(loaded, example);
+    }
+
+    #[test]
+    fn truncated_data() {
+        let data = save(&Example::default());
+        let mut reader = StateReader::new(&data[..data.len() - 1]);
+        let result = Example::default().load(&mut reader);
+        assert_eq!(result, Err(LoadStateError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn trailing_data() {
+        let mut data = save(&Example::default());
+        data.push(0);
+        let mut reader = StateReader::new(&data);
+        Example::default().load(&mut reader).unwrap();
+        assert_eq!(reader.finish(), Err(LoadStateError::TrailingData));
+    }
+
+    #[test]
+    fn header() {
+        let mut writer = StateWriter::default();
+        write_header(&mut writer, 0x1234_5678);
+        let data = writer.into_bytes();
+        let mut reader = StateReader::new(&data);
+        assert_eq!(read_header(&mut reader, 0x1234_5678), Ok(()));
+        assert_eq!(reader.finish(), Ok(()));
+
+        assert_eq!(
+            read_header(&mut StateReader::new(&data), 0x1234_5679),
+            Err(LoadStateError::WrongRom {
+                expected: 0x1234_5679,
+                found: 0x1234_5678
+            })
+        );
+        assert_eq!(
+            read_header(&mut StateReader::new(&data[..4]), 0x1234_5678),
+            Err(LoadStateError::NotASaveState)
+        );
+
+        let mut newer = data.clone();
+        newer[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = read_header(&mut StateReader::new(&newer), 0x1234_5678).unwrap_err();
+        assert_eq!(
+            error,
+            LoadStateError::IncompatibleVersion {
+                format_version: FORMAT_VERSION + 1,
+                emulator_version: EMULATOR_VERSION.to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_bool() {
+        let mut flag = false;
+        let result = flag.load(&mut StateReader::new(&[2]));
+        assert_eq!(result, Err(LoadStateError::InvalidValue));
+    }
+}
@@ -0,0 +1,131 @@
+//! The serial port shifts out the byte in `SB` one bit at a time while shifting in the bits of the
+//! other Game Boy. Without a link cable, every received bit is a 1. Test ROMs commonly print their
+//! results through it, so everything that is sent is recorded.
+
+use crate::memory_map::*;
+use crate::save_state::impl_save_state;
+
+/// Bit 7 of `SC`: Set to start a transfer, cleared by the hardware once it is done.
+const TRANSFER_ENABLE_BIT: u8 = 0b1000_0000;
+/// Bit 1 of `SC`: Use the fast clock (CGB only).
+const CLOCK_SPEED_BIT: u8 = 0b10;
+/// Bit 0 of `SC`: Use the internal clock. Otherwise, the other Game Boy provides the clock.
+const CLOCK_SELECT_BIT: u8 = 0b1;
+
+/// How many CPU cycles it takes to transfer a single bit with the internal clock at 8192 Hz.
+const BIT_CYCLES: u32 = 512;
+/// How many CPU cycles it takes to transfer a single bit with the fast clock at 262144 Hz.
+const FAST_BIT_CYCLES: u32 = 16;
+
+#[derive(Default)]
+pub(crate) struct Serial {
+    /// `SB`
+    data: u8,
+    /// `SC`
+    control: u8,
+    /// How many bits of the current transfer are left.
+    remaining_bits: u8,
+    /// The cycles that have passed since the last bit was transferred.
+    cycles: u32,
+    /// Every byte that has been sent.
+    output: Vec<u8>,
+}
+
+// The output isn't part of the state, it is only recorded for the frontend.
+impl_save_state!(Serial {
+    data,
+    control,
+    remaining_bits,
+    cycles,
+});
+
+impl Serial {
+    /// Advances an ongoing transfer by the given amount of CPU cycles. Returns whether the
+    /// serial interrupt is requested because the transfer finished.
+    pub(crate) fn step(&mut self, cycles: u32, cgb_features: bool) -> bool {
+        if self.remaining_bits == 0 {
+            return false;
+        }
+
+        let bit_cycles = if cgb_features && self.control & CLOCK_SPEED_BIT != 0 {
+            FAST_BIT_CYCLES
+        } else {
+            BIT_CYCLES
+        };
+        self.cycles += cycles;
+        while self.cycles >= bit_cycles && self.remaining_bits > 0 {
+            self.cycles -= bit_cycles;
+            // No other Game Boy is connected, so only ones are received.
+            self.data = self.data << 1 | 1;
+            self.remaining_bits -= 1;
+        }
+
+        if self.remaining_bits == 0 {
+            self.control &= !TRANSFER_ENABLE_BIT;
+            return true;
+        }
+        false
+    }
+
+    /// Everything that has been sent since the cartridge was loaded.
+    pub(crate) fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub(crate) fn read_register(&self, address: usize, cgb_features: bool) -> u8 {
+        match address {
+            SERIAL_DATA_REGISTER => self.data,
+            // Unused bits read as set. The clock speed bit only exists on the CGB.
+            SERIAL_CONTROL_REGISTER if cgb_features => 0b0111_1100 | self.control,
+            SERIAL_CONTROL_REGISTER => 0b0111_1110 | self.control,
+            _ => 0xFF,
+        }
+    }
+
+    pub(crate) fn write_register(&mut self, address: usize, value: u8, cgb_features: bool) {
+        match address {
+            SERIAL_DATA_REGISTER => self.data = value,
+            SERIAL_CONTROL_REGISTER => {
+                let speed_mask = if cgb_features { CLOCK_SPEED_BIT } else { 0 };
+                self.control = value & (TRANSFER_ENABLE_BIT | speed_mask | CLOCK_SELECT_BIT);
+                // With the external clock, nothing happens as no other Game Boy provides it.
+                if value & TRANSFER_ENABLE_BIT != 0 && value & CLOCK_SELECT_BIT != 0 {
+                    self.output.push(self.data);
+                    self.remaining_bits = 8;
+                    self.cycles = 0;
+                } else {
+                    self.remaining_bits = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_with_internal_clock() {
+        let mut serial = Serial::default();
+        serial.write_register(SERIAL_DATA_REGISTER, b'A', false);
+        serial.write_register(SERIAL_CONTROL_REGISTER, 0x81, false);
+
+        assert!(!serial.step(BIT_CYCLES * 8 - 4, false));
+        assert_eq!(serial.read_register(SERIAL_CONTROL_REGISTER, false), 0xFF);
+        assert!(serial.step(4, false));
+        assert_eq!(serial.read_register(SERIAL_CONTROL_REGISTER, false), 0x7F);
+        assert_eq!(serial.read_register(SERIAL_DATA_REGISTER, false), 0xFF);
+        assert_eq!(serial.output(), b"A");
+    }
+
+    #[test]
+    fn external_clock_never_finishes() {
+        let mut serial = Serial::default();
+        serial.write_register(SERIAL_CONTROL_REGISTER, 0x80, false);
+
+        assert!(!serial.step(BIT_CYCLES * 16, false));
+        assert!(serial.output().is_empty());
+    }
+}
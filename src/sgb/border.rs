@@ -0,0 +1,159 @@
+//! The SGB surrounds the game screen with a 256x224 border, drawn by the SNES from its own tiles.
+//! Games upload the tiles with `CHR_TRN` and the tile map together with the border's palettes with
+//! `PCT_TRN`. Transparent pixels show the game screen or, outside of it, the backdrop color.
+
+use super::TRANSFER_SIZE;
+use crate::gpu::palette::Color;
+use crate::save_state::impl_save_state;
+
+/// The width of the SNES screen, and therefore of the border, in pixels.
+pub const BORDER_WIDTH: usize = 256;
+/// The height of the SNES screen, and therefore of the border, in pixels.
+pub const BORDER_HEIGHT: usize = 224;
+
+/// The SNES uses 4 bits per pixel, so each 8x8 tile takes up 32 bytes.
+const TILE_SIZE: usize = 32;
+const TILE_COUNT: usize = 256;
+/// The tile map is 32x32 tiles large, of which only the upper 28 rows are visible.
+const MAP_SIZE: usize = 32;
+/// Each `CHR_TRN` only transfers half of the tiles.
+const TILES_PER_TRANSFER: usize = TRANSFER_SIZE / TILE_SIZE;
+/// The border uses the SNES palettes 4-7 with 16 colors each.
+const PALETTE_COUNT: usize = 4;
+const PALETTE_SIZE: usize = 16;
+/// The palettes follow the tile map in the data sent by `PCT_TRN`.
+const PALETTES_OFFSET: usize = MAP_SIZE * MAP_SIZE * 2;
+
+// Bits of a tile map entry.
+const TILE_MASK: u16 = 0x00FF;
+const PALETTE_SHIFT: u16 = 10;
+const X_FLIP_BIT: u16 = 0x4000;
+const Y_FLIP_BIT: u16 = 0x8000;
+
+pub(super) struct Border {
+    tiles: Box<[u8; TILE_COUNT * TILE_SIZE]>,
+    map: [u16; MAP_SIZE * MAP_SIZE],
+    palettes: [[u16; PALETTE_SIZE]; PALETTE_COUNT],
+}
+
+impl_save_state!(Border {
+    tiles,
+    map,
+    palettes,
+});
+
+impl Default for Border {
+    fn default() -> Self {
+        Self {
+            tiles: Box::new([0; TILE_COUNT * TILE_SIZE]),
+            map: [0; MAP_SIZE * MAP_SIZE],
+            palettes: [[0; PALETTE_SIZE]; PALETTE_COUNT],
+        }
+    }
+}
+
+impl Border {
+    /// Handles the data of `CHR_TRN`, which contains either the lower or the upper 128 tiles.
+    pub(super) fn load_tiles(&mut self, upper_half: bool, data: &[u8; TRANSFER_SIZE]) {
+        let start = if upper_half {
+            TILES_PER_TRANSFER * TILE_SIZE
+        } else {
+            0
+        };
+        self.tiles[start..start + TRANSFER_SIZE].copy_from_slice(data);
+    }
+
+    /// Handles the data of `PCT_TRN`, which contains the tile map followed by the palettes.
+    pub(super) fn load_map(&mut self, data: &[u8; TRANSFER_SIZE]) {
+        for (entry, bytes) in self.map.iter_mut().zip(data.chunks_exact(2)) {
+            *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        let palette_data = data[PALETTES_OFFSET..].chunks_exact(2);
+        for (color, bytes) in self
+            .palettes
+            .as_flattened_mut()
+            .iter_mut()
+            .zip(palette_data)
+        {
+            *color = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+    }
+
+    /// The color of the border at the given position, or [`None`] if it is transparent.
+    pub(super) fn pixel(&self, x: usize, y: usize) -> Option<Color> {
+        let entry = self.map[(y / 8) * MAP_SIZE + x / 8];
+        let mut row = y % 8;
+        let mut column = x % 8;
+        if entry & X_FLIP_BIT != 0 {
+            column = 7 - column;
+        }
+        if entry & Y_FLIP_BIT != 0 {
+            row = 7 - row;
+        }
+
+        // Every row is stored in two pairs of bit planes: planes 0 and 1 in the first 16 bytes of
+        // the tile, planes 2 and 3 in the second 16 bytes.
+        let tile = &self.tiles[(entry & TILE_MASK) as usize * TILE_SIZE..][..TILE_SIZE];
+        let bit = 7 - column;
+        let color_index = [
+            tile[row * 2],
+            tile[row * 2 + 1],
+            tile[16 + row * 2],
+            tile[17 + row * 2],
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |index, (plane, byte)| {
+            index | ((byte >> bit) & 1) << plane
+        });
+        if color_index == 0 {
+            return None;
+        }
+
+        // Only palettes 4-7 are meant to be used by the border.
+        let palette = (entry >> PALETTE_SHIFT) as usize % PALETTE_COUNT;
+        Some(Color::from_rgb555(
+            self.palettes[palette][color_index as usize],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_border_is_transparent() {
+        let border = Border::default();
+
+        assert_eq!(border.pixel(0, 0), None);
+        assert_eq!(border.pixel(BORDER_WIDTH - 1, BORDER_HEIGHT - 1), None);
+    }
+
+    #[test]
+    fn draw_tile() {
+        let mut border = Border::default();
+        let mut tiles = [0; TRANSFER_SIZE];
+        // Tile 0x81 has color 15 in the leftmost column of its first row and color 2 in the
+        // second column.
+        let tile = TILE_SIZE;
+        tiles[tile] = 0b1000_0000;
+        tiles[tile + 1] = 0b1100_0000;
+        tiles[tile + 16] = 0b1000_0000;
+        tiles[tile + 17] = 0b1000_0000;
+        border.load_tiles(true, &tiles);
+
+        let mut map = [0; TRANSFER_SIZE];
+        // The second tile of the map uses tile 0x81 with palette 5, flipped horizontally.
+        map[2..4].copy_from_slice(&(0x81 | 5 << PALETTE_SHIFT | X_FLIP_BIT).to_le_bytes());
+        let palette_5 = PALETTES_OFFSET + PALETTE_SIZE * 2;
+        map[palette_5 + 2 * 2..][..2].copy_from_slice(&0x001Fu16.to_le_bytes());
+        map[palette_5 + 15 * 2..][..2].copy_from_slice(&0x7FFFu16.to_le_bytes());
+        border.load_map(&map);
+
+        assert_eq!(border.pixel(15, 0), Some(Color::new(0xFF, 0xFF, 0xFF)));
+        assert_eq!(border.pixel(14, 0), Some(Color::new(0xFF, 0x00, 0x00)));
+        assert_eq!(border.pixel(8, 0), None);
+        assert_eq!(border.pixel(15, 1), None);
+    }
+}
@@ -0,0 +1,570 @@
+//! The Super Game Boy (SGB) runs DMG games on a SNES. It receives the shades the DMG's LCD would
+//! show and colorizes them with four palettes, one of which is assigned to each 8x8 area of the
+//! screen. Games that support the SGB control this, and the border around the screen, by sending
+//! commands as packets through `P1`. Larger amounts of data, like the tiles of the border, are
+//! instead transferred by displaying them on the screen for a frame.
+
+mod border;
+mod packet;
+
+use crate::gpu::palette::Color;
+use crate::gpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::joypad::{Joypad, SELECT_BUTTONS_BIT, SELECT_MASK};
+use crate::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter, impl_save_state_enum,
+};
+use border::Border;
+pub use border::{BORDER_HEIGHT, BORDER_WIDTH};
+use packet::{PACKET_SIZE, Packet, PacketReceiver};
+
+/// A VRAM transfer always sends 4 KiB, the amount of data in the first 256 tiles on screen.
+const TRANSFER_SIZE: usize = 0x1000;
+
+/// Palettes are assigned to areas of 8x8 pixels.
+const SCREEN_TILES_X: usize = SCREEN_WIDTH / 8;
+const SCREEN_TILES_Y: usize = SCREEN_HEIGHT / 8;
+/// An attribute file assigns a palette to every area of the screen, with 2 bits per area.
+const ATTRIBUTE_FILE_SIZE: usize = SCREEN_TILES_X * SCREEN_TILES_Y / 4;
+const ATTRIBUTE_FILE_COUNT: usize = 45;
+/// The amount of palettes that can be transferred with `PAL_TRN` and selected with `PAL_SET`.
+const SYSTEM_PALETTE_COUNT: usize = 512;
+
+/// The palettes the SGB starts out with.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+/// Where the game screen is placed inside of the border.
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+/// The commands that are emulated. Commands for sound or for accessing the SNES directly are
+/// ignored.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Command {
+    /// `PAL01`, `PAL23`, `PAL03` and `PAL12`: Set the colors of two palettes.
+    SetPalettes(usize, usize),
+    /// `ATTR_BLK`: Assign palettes to the inside, border and outside of rectangles.
+    AttributeBlocks,
+    /// `ATTR_LIN`: Assign palettes to whole rows or columns.
+    AttributeLines,
+    /// `ATTR_DIV`: Split the screen in two along a row or column.
+    AttributeDivide,
+    /// `ATTR_CHR`: Assign palettes to areas one after another.
+    AttributeCharacters,
+    /// `PAL_SET`: Copy four of the palettes sent with `PAL_TRN` into the palettes in use.
+    PaletteSet,
+    /// `PAL_TRN`: Transfer 512 palettes.
+    PaletteTransfer,
+    /// `MLT_REQ`: Enable or disable multiplayer mode.
+    MultiplayerRequest,
+    /// `CHR_TRN`: Transfer half of the border's tiles.
+    BorderTileTransfer,
+    /// `PCT_TRN`: Transfer the border's tile map and palettes.
+    BorderMapTransfer,
+    /// `ATTR_TRN`: Transfer 45 attribute files.
+    AttributeTransfer,
+    /// `ATTR_SET`: Apply one of the attribute files sent with `ATTR_TRN`.
+    AttributeSet,
+    /// `MASK_EN`: Hide the game screen, e.g. while VRAM transfers are shown.
+    MaskEnable,
+}
+
+impl Command {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x00 => Some(Command::SetPalettes(0, 1)),
+            0x01 => Some(Command::SetPalettes(2, 3)),
+            0x02 => Some(Command::SetPalettes(0, 3)),
+            0x03 => Some(Command::SetPalettes(1, 2)),
+            0x04 => Some(Command::AttributeBlocks),
+            0x05 => Some(Command::AttributeLines),
+            0x06 => Some(Command::AttributeDivide),
+            0x07 => Some(Command::AttributeCharacters),
+            0x0A => Some(Command::PaletteSet),
+            0x0B => Some(Command::PaletteTransfer),
+            0x11 => Some(Command::MultiplayerRequest),
+            0x13 => Some(Command::BorderTileTransfer),
+            0x14 => Some(Command::BorderMapTransfer),
+            0x15 => Some(Command::AttributeTransfer),
+            0x16 => Some(Command::AttributeSet),
+            0x17 => Some(Command::MaskEnable),
+            _ => None,
+        }
+    }
+}
+
+/// The data a VRAM transfer that is waiting for the next frame is meant for.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum VramTransfer {
+    BorderTiles { upper_half: bool },
+    BorderMap,
+    Palettes,
+    AttributeFiles,
+}
+
+/// What `MASK_EN` shows instead of the game screen.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Mask {
+    /// The game screen is visible.
+    None,
+    /// The last frame before the mask was enabled stays on screen.
+    Freeze,
+    Black,
+    /// Everything is filled with color 0.
+    Backdrop,
+}
+
+impl VramTransfer {
+    /// The transfers in the order they are numbered in save states.
+    const ALL: [Option<Self>; 6] = [
+        None,
+        Some(VramTransfer::BorderTiles { upper_half: false }),
+        Some(VramTransfer::BorderTiles { upper_half: true }),
+        Some(VramTransfer::BorderMap),
+        Some(VramTransfer::Palettes),
+        Some(VramTransfer::AttributeFiles),
+    ];
+}
+
+impl_save_state_enum!(Mask {
+    None,
+    Freeze,
+    Black,
+    Backdrop,
+});
+
+pub(crate) struct Sgb {
+    receiver: PacketReceiver,
+    /// The data of a command consisting of multiple packets that hasn't been received completely.
+    command: Vec<u8>,
+    /// How many packets of [`Self::command`] are still missing.
+    remaining_packets: u8,
+    palettes: [[u16; 4]; 4],
+    /// The palettes sent with `PAL_TRN`.
+    system_palettes: Box<[[u16; 4]; SYSTEM_PALETTE_COUNT]>,
+    /// The attribute files sent with `ATTR_TRN`.
+    attribute_files: Box<[[u8; ATTRIBUTE_FILE_SIZE]; ATTRIBUTE_FILE_COUNT]>,
+    /// The palette of each 8x8 area of the screen.
+    attributes: [u8; SCREEN_TILES_X * SCREEN_TILES_Y],
+    pending_transfer: Option<VramTransfer>,
+    border: Border,
+    mask: Mask,
+    /// How many controllers are read in turn, set by `MLT_REQ`.
+    player_count: u8,
+    /// The controller currently read through `P1`. Only the first one has any buttons pressed.
+    current_player: u8,
+    /// The shades of the last frame that was shown.
+    screen: Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self {
+            receiver: PacketReceiver::default(),
+            command: Vec::new(),
+            remaining_packets: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: Box::new([[0; 4]; SYSTEM_PALETTE_COUNT]),
+            attribute_files: Box::new([[0; ATTRIBUTE_FILE_SIZE]; ATTRIBUTE_FILE_COUNT]),
+            attributes: [0; SCREEN_TILES_X * SCREEN_TILES_Y],
+            pending_transfer: None,
+            border: Border::default(),
+            mask: Mask::None,
+            player_count: 1,
+            current_player: 0,
+            screen: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]),
+        }
+    }
+}
+
+impl SaveState for Sgb {
+    fn save(&self, writer: &mut StateWriter) {
+        self.receiver.save(writer);
+        self.command.save(writer);
+        self.remaining_packets.save(writer);
+        self.palettes.save(writer);
+        self.system_palettes.save(writer);
+        self.attribute_files.save(writer);
+        self.attributes.save(writer);
+        let transfer = VramTransfer::ALL
+            .iter()
+            .position(|transfer| *transfer == self.pending_transfer);
+        writer.write_bytes(&[transfer.unwrap() as u8]);
+        self.border.save(writer);
+        self.mask.save(writer);
+        self.player_count.save(writer);
+        self.current_player.save(writer);
+        self.screen.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.receiver.load(reader)?;
+        self.command.load(reader)?;
+        self.remaining_packets.load(reader)?;
+        self.palettes.load(reader)?;
+        self.system_palettes.load(reader)?;
+        self.attribute_files.load(reader)?;
+        self.attributes.load(reader)?;
+        let [transfer] = reader.read_bytes()?;
+        self.pending_transfer = *VramTransfer::ALL
+            .get(transfer as usize)
+            .ok_or(LoadStateError::InvalidValue)?;
+        self.border.load(reader)?;
+        self.mask.load(reader)?;
+        self.player_count.load(reader)?;
+        self.current_player.load(reader)?;
+        self.screen.load(reader)?;
+
+        let players_valid =
+            matches!(self.player_count, 1 | 2 | 4) && self.current_player < self.player_count;
+        let attributes_valid = self.attributes.iter().all(|palette| *palette < 4);
+        // A partial command is continued with the amount of packets its first one announced.
+        let command_valid = match self.command.first() {
+            None => true,
+            Some(first) => {
+                let received_packets = self.command.len() / PACKET_SIZE;
+                self.command.len().is_multiple_of(PACKET_SIZE)
+                    && self.remaining_packets > 0
+                    && received_packets + self.remaining_packets as usize
+                        == (first & 0b111).max(1) as usize
+            }
+        };
+        if !players_valid || !attributes_valid || !command_valid {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl Sgb {
+    /// Handles a write to `P1`, which may send a packet or select the next controller.
+    /// `previous_selection` are the selection bits of `P1` before the write.
+    pub(crate) fn write_joypad(&mut self, value: u8, previous_selection: u8) {
+        let was_receiving = self.receiver.is_receiving();
+        if let Some(packet) = self.receiver.write(value) {
+            self.receive_packet(packet);
+        }
+
+        // The next controller is selected when `P15` is released.
+        let released_buttons =
+            previous_selection & SELECT_BUTTONS_BIT == 0 && value & SELECT_MASK == SELECT_MASK;
+        if !was_receiving && released_buttons {
+            self.current_player = (self.current_player + 1) % self.player_count;
+        }
+    }
+
+    /// Handles a read from `P1`. With both button groups deselected, the lower bits identify the
+    /// current controller.
+    pub(crate) fn read_joypad(&self, joypad: &Joypad) -> u8 {
+        let value = joypad.read();
+        if joypad.selection() == SELECT_MASK {
+            value & 0xF0 | (0x0F - self.current_player)
+        } else if self.current_player != 0 {
+            value | 0x0F
+        } else {
+            value
+        }
+    }
+
+    /// Called at the end of every frame with the shades that were drawn. Completes pending VRAM
+    /// transfers and updates the screen, unless it is masked.
+    pub(crate) fn finish_frame(&mut self, shades: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT]) {
+        if let Some(transfer) = self.pending_transfer.take() {
+            self.complete_transfer(transfer, &transfer_data(shades));
+        }
+        if self.mask != Mask::Freeze {
+            self.screen.copy_from_slice(shades);
+        }
+    }
+
+    /// The colorized game screen.
+    pub(crate) fn screen(&self) -> Box<[Color; SCREEN_WIDTH * SCREEN_HEIGHT]> {
+        let mut screen = Box::new([Color::default(); SCREEN_WIDTH * SCREEN_HEIGHT]);
+        for (index, pixel) in screen.iter_mut().enumerate() {
+            *pixel = self.screen_pixel(index % SCREEN_WIDTH, index / SCREEN_WIDTH);
+        }
+        screen
+    }
+
+    /// The colorized game screen surrounded by the border, like the SNES outputs it.
+    pub(crate) fn screen_with_border(&self) -> Box<[Color; BORDER_WIDTH * BORDER_HEIGHT]> {
+        let mut output = Box::new([Color::default(); BORDER_WIDTH * BORDER_HEIGHT]);
+        let backdrop = self.backdrop();
+        for (index, pixel) in output.iter_mut().enumerate() {
+            let x = index % BORDER_WIDTH;
+            let y = index / BORDER_WIDTH;
+            let on_screen = (SCREEN_X..SCREEN_X + SCREEN_WIDTH).contains(&x)
+                && (SCREEN_Y..SCREEN_Y + SCREEN_HEIGHT).contains(&y);
+            // The border is drawn above the game screen.
+            *pixel = self.border.pixel(x, y).unwrap_or_else(|| {
+                if on_screen {
+                    self.screen_pixel(x - SCREEN_X, y - SCREEN_Y)
+                } else {
+                    backdrop
+                }
+            });
+        }
+        output
+    }
+
+    /// Color 0 is shared by all palettes, and fills everything that isn't covered otherwise.
+    fn backdrop(&self) -> Color {
+        Color::from_rgb555(self.palettes[0][0])
+    }
+
+    fn screen_pixel(&self, x: usize, y: usize) -> Color {
+        match self.mask {
+            Mask::Black => Color::new(0, 0, 0),
+            Mask::Backdrop => self.backdrop(),
+            Mask::None | Mask::Freeze => {
+                let shade = self.screen[y * SCREEN_WIDTH + x] as usize;
+                if shade == 0 {
+                    return self.backdrop();
+                }
+                let palette = self.attributes[(y / 8) * SCREEN_TILES_X + x / 8] as usize;
+                Color::from_rgb555(self.palettes[palette][shade])
+            }
+        }
+    }
+
+    fn receive_packet(&mut self, packet: Packet) {
+        if self.command.is_empty() {
+            // The lower three bits of the first byte are the amount of packets of the command.
+            self.remaining_packets = (packet[0] & 0b111).max(1);
+        }
+        self.command.extend_from_slice(&packet);
+        self.remaining_packets -= 1;
+        if self.remaining_packets == 0 {
+            let data = std::mem::take(&mut self.command);
+            self.execute(&data);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        let Some(command) = Command::from_byte(data[0] >> 3) else {
+            return;
+        };
+        match command {
+            Command::SetPalettes(first, second) => {
+                let colors: Vec<u16> = (0..7).map(|index| word(data, 1 + index * 2)).collect();
+                self.palettes[first][1..].copy_from_slice(&colors[1..4]);
+                self.palettes[second][1..].copy_from_slice(&colors[4..7]);
+                for palette in &mut self.palettes {
+                    palette[0] = colors[0];
+                }
+            }
+            Command::AttributeBlocks => self.attribute_blocks(data),
+            Command::AttributeLines => self.attribute_lines(data),
+            Command::AttributeDivide => self.attribute_divide(data),
+            Command::AttributeCharacters => self.attribute_characters(data),
+            Command::PaletteSet => {
+                for (index, palette) in self.palettes.iter_mut().enumerate() {
+                    let system_palette = word(data, 1 + index * 2) as usize % SYSTEM_PALETTE_COUNT;
+                    *palette = self.system_palettes[system_palette];
+                }
+                let flags = data[9];
+                if flags & 0b1000_0000 != 0 {
+                    self.apply_attribute_file(flags & 0b0011_1111);
+                }
+                if flags & 0b0100_0000 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            Command::PaletteTransfer => self.pending_transfer = Some(VramTransfer::Palettes),
+            Command::MultiplayerRequest => {
+                self.player_count = match data[1] & 0b11 {
+                    0b01 => 2,
+                    0b11 => 4,
+                    _ => 1,
+                };
+                self.current_player = 0;
+            }
+            Command::BorderTileTransfer => {
+                let upper_half = data[1] & 0b1 != 0;
+                self.pending_transfer = Some(VramTransfer::BorderTiles { upper_half });
+            }
+            Command::BorderMapTransfer => self.pending_transfer = Some(VramTransfer::BorderMap),
+            Command::AttributeTransfer => {
+                self.pending_transfer = Some(VramTransfer::AttributeFiles)
+            }
+            Command::AttributeSet => {
+                self.apply_attribute_file(data[1] & 0b0011_1111);
+                if data[1] & 0b0100_0000 != 0 {
+                    self.mask = Mask::None;
+                }
+            }
+            Command::MaskEnable => {
+                self.mask = match data[1] & 0b11 {
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    3 => Mask::Backdrop,
+                    _ => Mask::None,
+                }
+            }
+        }
+    }
+
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = (data[1] & 0b1_1111) as usize;
+        for set in data[2..].chunks_exact(6).take(count) {
+            let control = set[0] & 0b111;
+            let inside = set[1] & 0b11;
+            let mut border = (set[1] >> 2) & 0b11;
+            let outside = (set[1] >> 4) & 0b11;
+            let (left, top, right, bottom) = (
+                (set[2] & 0b1_1111) as usize,
+                (set[3] & 0b1_1111) as usize,
+                (set[4] & 0b1_1111) as usize,
+                (set[5] & 0b1_1111) as usize,
+            );
+
+            // If only the inside or only the outside is changed, the border changes along with it.
+            let change_border = match control {
+                0b001 => {
+                    border = inside;
+                    true
+                }
+                0b100 => {
+                    border = outside;
+                    true
+                }
+                _ => control & 0b010 != 0,
+            };
+
+            for y in 0..SCREEN_TILES_Y {
+                for x in 0..SCREEN_TILES_X {
+                    let within = (left..=right).contains(&x) && (top..=bottom).contains(&y);
+                    let on_edge = x == left || x == right || y == top || y == bottom;
+                    let palette = match (within, on_edge) {
+                        (true, false) if control & 0b001 != 0 => inside,
+                        (true, true) if change_border => border,
+                        (false, _) if control & 0b100 != 0 => outside,
+                        _ => continue,
+                    };
+                    self.attributes[y * SCREEN_TILES_X + x] = palette;
+                }
+            }
+        }
+    }
+
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for &line in data[2..].iter().take(count) {
+            let index = (line & 0b1_1111) as usize;
+            let palette = (line >> 5) & 0b11;
+            if line & 0b1000_0000 != 0 {
+                if let Some(row) = self.attributes.chunks_exact_mut(SCREEN_TILES_X).nth(index) {
+                    row.fill(palette);
+                }
+            } else if index < SCREEN_TILES_X {
+                for row in self.attributes.chunks_exact_mut(SCREEN_TILES_X) {
+                    row[index] = palette;
+                }
+            }
+        }
+    }
+
+    fn attribute_divide(&mut self, data: &[u8]) {
+        let after = data[1] & 0b11;
+        let before = (data[1] >> 2) & 0b11;
+        let on_line = (data[1] >> 4) & 0b11;
+        let horizontal = data[1] & 0b0100_0000 != 0;
+        let line = (data[2] & 0b1_1111) as usize;
+
+        for y in 0..SCREEN_TILES_Y {
+            for x in 0..SCREEN_TILES_X {
+                let position = if horizontal { y } else { x };
+                self.attributes[y * SCREEN_TILES_X + x] = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let mut x = (data[1] as usize).min(SCREEN_TILES_X - 1);
+        let mut y = (data[2] as usize).min(SCREEN_TILES_Y - 1);
+        let count = (word(data, 3) as usize).min(SCREEN_TILES_X * SCREEN_TILES_Y);
+        let vertical = data[5] & 0b1 != 0;
+
+        for index in 0..count {
+            let Some(&byte) = data.get(6 + index / 4) else {
+                break;
+            };
+            // Every byte contains four palettes, starting at the upper bits.
+            let palette = (byte >> (6 - (index % 4) * 2)) & 0b11;
+            self.attributes[y * SCREEN_TILES_X + x] = palette;
+
+            if vertical {
+                y += 1;
+                if y == SCREEN_TILES_Y {
+                    y = 0;
+                    x = (x + 1) % SCREEN_TILES_X;
+                }
+            } else {
+                x += 1;
+                if x == SCREEN_TILES_X {
+                    x = 0;
+                    y = (y + 1) % SCREEN_TILES_Y;
+                }
+            }
+        }
+    }
+
+    fn apply_attribute_file(&mut self, file: u8) {
+        let Some(file) = self.attribute_files.get(file as usize) else {
+            return;
+        };
+        for (index, attribute) in self.attributes.iter_mut().enumerate() {
+            *attribute = (file[index / 4] >> (6 - (index % 4) * 2)) & 0b11;
+        }
+    }
+
+    fn complete_transfer(&mut self, transfer: VramTransfer, data: &[u8; TRANSFER_SIZE]) {
+        match transfer {
+            VramTransfer::BorderTiles { upper_half } => self.border.load_tiles(upper_half, data),
+            VramTransfer::BorderMap => self.border.load_map(data),
+            VramTransfer::Palettes => {
+                for (palette, bytes) in self.system_palettes.iter_mut().zip(data.chunks_exact(8)) {
+                    for (index, color) in palette.iter_mut().enumerate() {
+                        *color = word(bytes, index * 2);
+                    }
+                }
+            }
+            VramTransfer::AttributeFiles => {
+                let files = data.chunks_exact(ATTRIBUTE_FILE_SIZE);
+                for (file, bytes) in self.attribute_files.iter_mut().zip(files) {
+                    file.copy_from_slice(bytes);
+                }
+            }
+        }
+    }
+}
+
+/// Reads a little endian word.
+fn word(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Recovers the data of a VRAM transfer from the screen. The data is displayed as the first 256
+/// tiles of the screen, from left to right and top to bottom, and read back in the DMG's 2 bits
+/// per pixel tile format.
+fn transfer_data(shades: &[u8; SCREEN_WIDTH * SCREEN_HEIGHT]) -> Box<[u8; TRANSFER_SIZE]> {
+    let mut data = Box::new([0; TRANSFER_SIZE]);
+    for (tile, bytes) in data.chunks_exact_mut(16).enumerate() {
+        let tile_x = (tile % SCREEN_TILES_X) * 8;
+        let tile_y = (tile / SCREEN_TILES_X) * 8;
+        for (row, plane_bytes) in bytes.chunks_exact_mut(2).enumerate() {
+            let start = (tile_y + row) * SCREEN_WIDTH + tile_x;
+            for (column, shade) in shades[start..start + 8].iter().enumerate() {
+                plane_bytes[0] |= (shade & 0b01) << (7 - column);
+                plane_bytes[1] |= ((shade & 0b10) >> 1) << (7 - column);
+            }
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests;
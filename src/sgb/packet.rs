@@ -0,0 +1,168 @@
+//! Games talk to the SGB by pulsing the two selection lines of `P1`. A transfer starts with a reset
+//! pulse, where both lines are pulled low. Afterwards, each bit is sent by pulling one of the lines
+//! low and releasing both again: `P14` low for a 0, `P15` low for a 1. A packet consists of 16
+//! bytes sent least significant bit first, followed by a single 0 as stop bit.
+
+use crate::joypad::{SELECT_BUTTONS_BIT, SELECT_DIRECTIONS_BIT, SELECT_MASK};
+use crate::save_state::{
+    LoadStateError, SaveState, StateReader, StateWriter, impl_save_state_enum,
+};
+
+/// Every packet contains 16 bytes.
+pub(super) const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+
+pub(super) type Packet = [u8; PACKET_SIZE];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum State {
+    /// Waiting for a reset pulse.
+    Idle,
+    /// Both lines were released after the previous pulse, so the next bit can be sent.
+    Ready,
+    /// A bit has been sent, and both lines have to be released before the next one.
+    Pulsed,
+}
+
+impl_save_state_enum!(State {
+    Idle,
+    Ready,
+    Pulsed,
+});
+
+pub(super) struct PacketReceiver {
+    state: State,
+    packet: Packet,
+    /// How many bits of the packet have been received, including the stop bit.
+    received_bits: usize,
+}
+
+impl SaveState for PacketReceiver {
+    fn save(&self, writer: &mut StateWriter) {
+        self.state.save(writer);
+        self.packet.save(writer);
+        self.received_bits.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), LoadStateError> {
+        self.state.load(reader)?;
+        self.packet.load(reader)?;
+        self.received_bits.load(reader)?;
+        if self.received_bits > PACKET_BITS {
+            return Err(LoadStateError::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+impl Default for PacketReceiver {
+    fn default() -> Self {
+        Self {
+            state: State::Idle,
+            packet: [0; PACKET_SIZE],
+            received_bits: 0,
+        }
+    }
+}
+
+impl PacketReceiver {
+    /// Whether a packet is currently being sent.
+    pub(super) fn is_receiving(&self) -> bool {
+        self.state != State::Idle
+    }
+
+    /// Handles a write to `P1`. Returns the packet once it has been received completely.
+    pub(super) fn write(&mut self, value: u8) -> Option<Packet> {
+        let bit = match value & SELECT_MASK {
+            0 => {
+                self.state = State::Pulsed;
+                self.packet = [0; PACKET_SIZE];
+                self.received_bits = 0;
+                return None;
+            }
+            SELECT_MASK => {
+                if self.state == State::Pulsed {
+                    self.state = State::Ready;
+                }
+                return None;
+            }
+            // `P14` is bit 4, so it stays set while `P15` is pulled low.
+            SELECT_DIRECTIONS_BIT => 1,
+            SELECT_BUTTONS_BIT => 0,
+            _ => unreachable!(),
+        };
+        if self.state != State::Ready {
+            return None;
+        }
+        self.state = State::Pulsed;
+
+        if self.received_bits == PACKET_BITS {
+            // A packet with a wrong stop bit is discarded.
+            self.state = State::Idle;
+            return (bit == 0).then_some(self.packet);
+        }
+        self.packet[self.received_bits / 8] |= bit << (self.received_bits % 8);
+        self.received_bits += 1;
+        None
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// The `P1` writes needed to send a packet.
+    pub(in crate::sgb) fn packet_writes(packet: &Packet) -> Vec<u8> {
+        let mut writes = vec![0x00, 0x30];
+        for byte in packet {
+            for bit in 0..8 {
+                let line = if byte >> bit & 1 != 0 { 0x10 } else { 0x20 };
+                writes.extend([line, 0x30]);
+            }
+        }
+        // The stop bit.
+        writes.extend([0x20, 0x30]);
+        writes
+    }
+
+    fn send(receiver: &mut PacketReceiver, writes: &[u8]) -> Option<Packet> {
+        writes.iter().find_map(|value| receiver.write(*value))
+    }
+
+    #[test]
+    fn receive_packet() {
+        let mut receiver = PacketReceiver::default();
+        let packet: Packet = std::array::from_fn(|index| index as u8 * 17);
+
+        assert_eq!(send(&mut receiver, &packet_writes(&packet)), Some(packet));
+        assert!(!receiver.is_receiving());
+    }
+
+    #[test]
+    fn bits_without_release_are_ignored() {
+        let mut receiver = PacketReceiver::default();
+        let mut writes = packet_writes(&[0xFF; PACKET_SIZE]);
+        // Repeating a pulse without releasing the lines doesn't send another bit.
+        writes.insert(3, 0x10);
+
+        assert_eq!(send(&mut receiver, &writes), Some([0xFF; PACKET_SIZE]));
+    }
+
+    #[test]
+    fn wrong_stop_bit() {
+        let mut receiver = PacketReceiver::default();
+        let mut writes = packet_writes(&[0; PACKET_SIZE]);
+        let stop_bit = writes.len() - 2;
+        writes[stop_bit] = 0x10;
+
+        assert_eq!(send(&mut receiver, &writes), None);
+    }
+
+    #[test]
+    fn no_packet_without_reset() {
+        let mut receiver = PacketReceiver::default();
+        let writes = packet_writes(&[0; PACKET_SIZE]);
+
+        assert_eq!(send(&mut receiver, &writes[2..]), None);
+    }
+}
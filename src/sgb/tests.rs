@@ -0,0 +1,322 @@
+use super::packet::PACKET_SIZE;
+use super::packet::tests::packet_writes;
+use super::*;
+use crate::joypad::{Button, SELECT_DIRECTIONS_BIT};
+use crate::save_state::copy_state;
+
+const RED: u16 = 0x001F;
+const GREEN: u16 = 0x03E0;
+const BLUE: u16 = 0x7C00;
+const WHITE: u16 = 0x7FFF;
+
+/// Sends a command with the given parameters, split into as many packets as needed.
+fn send(sgb: &mut Sgb, command: u8, parameters: &[u8]) {
+    let packet_count = (parameters.len() + 1).div_ceil(PACKET_SIZE).max(1);
+    let mut data = vec![command << 3 | packet_count as u8];
+    data.extend_from_slice(parameters);
+    data.resize(packet_count * PACKET_SIZE, 0);
+
+    let mut selection = SELECT_MASK;
+    for packet in data.chunks_exact(PACKET_SIZE) {
+        for value in packet_writes(packet.try_into().unwrap()) {
+            sgb.write_joypad(value, selection);
+            selection = value;
+        }
+    }
+}
+
+fn words(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// A frame where every pixel has the given shade.
+fn frame(shade: u8) -> Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]> {
+    Box::new([shade; SCREEN_WIDTH * SCREEN_HEIGHT])
+}
+
+/// A frame showing the given data the way games do for VRAM transfers.
+fn transfer_frame(data: &[u8; TRANSFER_SIZE]) -> Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]> {
+    let mut shades = frame(0);
+    for (tile, bytes) in data.chunks_exact(16).enumerate() {
+        for row in 0..8 {
+            for column in 0..8 {
+                let low = (bytes[row * 2] >> (7 - column)) & 1;
+                let high = (bytes[row * 2 + 1] >> (7 - column)) & 1;
+                let x = (tile % SCREEN_TILES_X) * 8 + column;
+                let y = (tile / SCREEN_TILES_X) * 8 + row;
+                shades[y * SCREEN_WIDTH + x] = high << 1 | low;
+            }
+        }
+    }
+    shades
+}
+
+fn attributes(sgb: &Sgb) -> Vec<Vec<u8>> {
+    sgb.attributes
+        .chunks_exact(SCREEN_TILES_X)
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+#[test]
+fn default_palette() {
+    let mut sgb = Sgb::default();
+    sgb.finish_frame(&frame(3));
+
+    assert_eq!(sgb.screen()[0], Color::from_rgb555(DEFAULT_PALETTE[3]));
+}
+
+#[test]
+fn set_palettes() {
+    let mut sgb = Sgb::default();
+    send(
+        &mut sgb,
+        0x03,
+        &words(&[WHITE, RED, RED, RED, GREEN, GREEN, BLUE]),
+    );
+
+    assert_eq!(sgb.palettes[1], [WHITE, RED, RED, RED]);
+    assert_eq!(sgb.palettes[2], [WHITE, GREEN, GREEN, BLUE]);
+    // Color 0 is shared by all palettes.
+    assert_eq!(sgb.palettes[0][0], WHITE);
+    assert_eq!(sgb.palettes[3][0], WHITE);
+    assert_eq!(sgb.palettes[0][1], DEFAULT_PALETTE[1]);
+}
+
+#[test]
+fn attribute_block() {
+    let mut sgb = Sgb::default();
+    // Inside gets palette 1, the border palette 2 and the outside palette 3.
+    send(&mut sgb, 0x04, &[1, 0b111, 0b11_10_01, 1, 1, 4, 3]);
+
+    let attributes = attributes(&sgb);
+    assert_eq!(attributes[0][..6], [3, 3, 3, 3, 3, 3]);
+    assert_eq!(attributes[1][..6], [3, 2, 2, 2, 2, 3]);
+    assert_eq!(attributes[2][..6], [3, 2, 1, 1, 2, 3]);
+    assert_eq!(attributes[3][..6], [3, 2, 2, 2, 2, 3]);
+    assert_eq!(attributes[4][..6], [3, 3, 3, 3, 3, 3]);
+}
+
+#[test]
+fn attribute_block_inside_only_changes_border() {
+    let mut sgb = Sgb::default();
+    send(&mut sgb, 0x04, &[1, 0b001, 0b11_10_01, 0, 0, 2, 2]);
+
+    let attributes = attributes(&sgb);
+    assert_eq!(attributes[0][..4], [1, 1, 1, 0]);
+    assert_eq!(attributes[1][..4], [1, 1, 1, 0]);
+    assert_eq!(attributes[3][..4], [0, 0, 0, 0]);
+}
+
+#[test]
+fn attribute_lines() {
+    let mut sgb = Sgb::default();
+    // Column 3 gets palette 1, then row 2 gets palette 2.
+    send(&mut sgb, 0x05, &[2, 0b0010_0011, 0b1100_0010]);
+
+    let attributes = attributes(&sgb);
+    assert_eq!(attributes[0][2..5], [0, 1, 0]);
+    assert_eq!(attributes[2][2..5], [2, 2, 2]);
+    assert_eq!(attributes[17][3], 1);
+}
+
+#[test]
+fn attribute_divide() {
+    let mut sgb = Sgb::default();
+    // Divide horizontally at row 5: palette 1 above, 2 on the line and 3 below.
+    send(&mut sgb, 0x06, &[0b1_10_01_11, 5]);
+
+    let attributes = attributes(&sgb);
+    assert_eq!(attributes[4][0], 1);
+    assert_eq!(attributes[5][19], 2);
+    assert_eq!(attributes[6][10], 3);
+}
+
+#[test]
+fn attribute_characters() {
+    let mut sgb = Sgb::default();
+    // Six palettes, written from column 18 of row 1, wrapping to the next row.
+    send(
+        &mut sgb,
+        0x07,
+        &[18, 1, 6, 0, 0, 0b00_01_10_11, 0b0110_0000],
+    );
+
+    let attributes = attributes(&sgb);
+    assert_eq!(attributes[1][18..], [0, 1]);
+    assert_eq!(attributes[2][..4], [2, 3, 1, 2]);
+}
+
+#[test]
+fn attribute_characters_vertical() {
+    let mut sgb = Sgb::default();
+    send(&mut sgb, 0x07, &[0, 16, 3, 0, 1, 0b11_11_11_00]);
+
+    let attributes = attributes(&sgb);
+    assert_eq!(attributes[16][0], 3);
+    assert_eq!(attributes[17][0], 3);
+    assert_eq!(attributes[0][1], 3);
+}
+
+#[test]
+fn screen_is_colorized_by_attributes() {
+    let mut sgb = Sgb::default();
+    send(
+        &mut sgb,
+        0x00,
+        &words(&[WHITE, RED, RED, RED, GREEN, GREEN, GREEN]),
+    );
+    send(&mut sgb, 0x06, &[0b0_00_00_01, 1]);
+    let mut shades = frame(2);
+    shades[1] = 0;
+    sgb.finish_frame(&shades);
+
+    let screen = sgb.screen();
+    assert_eq!(screen[0], Color::from_rgb555(RED));
+    assert_eq!(screen[1], Color::from_rgb555(WHITE));
+    assert_eq!(screen[8], Color::from_rgb555(RED));
+    assert_eq!(screen[16], Color::from_rgb555(GREEN));
+}
+
+#[test]
+fn palette_transfer_and_set() {
+    let mut sgb = Sgb::default();
+    let mut data = [0; TRANSFER_SIZE];
+    data[8 * 300..8 * 301].copy_from_slice(&words(&[WHITE, RED, GREEN, BLUE]));
+    send(&mut sgb, 0x0B, &[]);
+    sgb.finish_frame(&transfer_frame(&data));
+    send(&mut sgb, 0x0A, &words(&[300, 0, 0, 300]));
+
+    assert_eq!(sgb.palettes[0], [WHITE, RED, GREEN, BLUE]);
+    assert_eq!(sgb.palettes[1], [0; 4]);
+    assert_eq!(sgb.palettes[3], [WHITE, RED, GREEN, BLUE]);
+}
+
+#[test]
+fn attribute_transfer_and_set() {
+    let mut sgb = Sgb::default();
+    let mut data = [0; TRANSFER_SIZE];
+    data[ATTRIBUTE_FILE_SIZE * 2] = 0b11_10_01_00;
+    send(&mut sgb, 0x15, &[]);
+    sgb.finish_frame(&transfer_frame(&data));
+    send(&mut sgb, 0x17, &[2]);
+    send(&mut sgb, 0x16, &[0b0100_0010]);
+
+    assert_eq!(attributes(&sgb)[0][..5], [3, 2, 1, 0, 0]);
+    assert_eq!(sgb.mask, Mask::None);
+}
+
+#[test]
+fn border_transfer() {
+    let mut sgb = Sgb::default();
+    send(
+        &mut sgb,
+        0x00,
+        &words(&[WHITE, RED, RED, RED, GREEN, GREEN, GREEN]),
+    );
+
+    // Tile 0 is filled with color 1.
+    let mut tiles = [0; TRANSFER_SIZE];
+    for row in 0..8 {
+        tiles[row * 2] = 0xFF;
+    }
+    send(&mut sgb, 0x13, &[0]);
+    sgb.finish_frame(&transfer_frame(&tiles));
+
+    // The upper left corner uses tile 0, everything else a transparent tile. Palette 4 has
+    // blue as color 1.
+    let mut map = [0; TRANSFER_SIZE];
+    for entry in map[..0x800].chunks_exact_mut(2) {
+        entry[0] = 1;
+    }
+    map[0] = 0;
+    map[1] = 4 << 2;
+    map[0x800 + 2..0x800 + 4].copy_from_slice(&BLUE.to_le_bytes());
+    send(&mut sgb, 0x14, &[]);
+    sgb.finish_frame(&transfer_frame(&map));
+
+    sgb.finish_frame(&frame(1));
+    let output = sgb.screen_with_border();
+    assert_eq!(output[0], Color::from_rgb555(BLUE));
+    assert_eq!(output[8], Color::from_rgb555(WHITE));
+    let screen_start = SCREEN_Y * BORDER_WIDTH + SCREEN_X;
+    assert_eq!(output[screen_start - 1], Color::from_rgb555(WHITE));
+    assert_eq!(output[screen_start], Color::from_rgb555(RED));
+}
+
+#[test]
+fn mask() {
+    let mut sgb = Sgb::default();
+    sgb.finish_frame(&frame(1));
+    send(&mut sgb, 0x17, &[1]);
+    sgb.finish_frame(&frame(3));
+    assert_eq!(sgb.screen()[0], Color::from_rgb555(DEFAULT_PALETTE[1]));
+
+    send(&mut sgb, 0x17, &[2]);
+    assert_eq!(sgb.screen()[0], Color::new(0, 0, 0));
+
+    send(&mut sgb, 0x17, &[3]);
+    assert_eq!(sgb.screen()[0], Color::from_rgb555(DEFAULT_PALETTE[0]));
+
+    send(&mut sgb, 0x17, &[0]);
+    sgb.finish_frame(&frame(3));
+    assert_eq!(sgb.screen()[0], Color::from_rgb555(DEFAULT_PALETTE[3]));
+}
+
+fn select(sgb: &mut Sgb, joypad: &mut Joypad, value: u8) {
+    let previous = joypad.selection();
+    joypad.write(value);
+    sgb.write_joypad(value, previous);
+}
+
+#[test]
+fn multiplayer() {
+    let mut sgb = Sgb::default();
+    let mut joypad = Joypad::default();
+    joypad.set_button(Button::A, true);
+    joypad.write(SELECT_MASK);
+    assert_eq!(sgb.read_joypad(&joypad) & 0x0F, 0x0F);
+
+    send(&mut sgb, 0x11, &[0b01]);
+    assert_eq!(sgb.read_joypad(&joypad) & 0x0F, 0x0F);
+
+    // Reading the buttons and releasing `P15` switches to the second controller.
+    select(&mut sgb, &mut joypad, SELECT_DIRECTIONS_BIT);
+    assert_eq!(sgb.read_joypad(&joypad) & 0x0F, 0b1110);
+    select(&mut sgb, &mut joypad, SELECT_MASK);
+    assert_eq!(sgb.read_joypad(&joypad) & 0x0F, 0x0E);
+    select(&mut sgb, &mut joypad, SELECT_DIRECTIONS_BIT);
+    assert_eq!(sgb.read_joypad(&joypad) & 0x0F, 0x0F);
+
+    select(&mut sgb, &mut joypad, SELECT_MASK);
+    assert_eq!(sgb.read_joypad(&joypad) & 0x0F, 0x0F);
+}
+
+#[test]
+fn invalid_state() {
+    let load = |sgb: &Sgb| copy_state(sgb, &mut Sgb::default());
+    let no_players = Sgb {
+        player_count: 0,
+        ..Sgb::default()
+    };
+    assert_eq!(load(&no_players), Err(LoadStateError::InvalidValue));
+    let invalid_player = Sgb {
+        player_count: 2,
+        current_player: 2,
+        ..Sgb::default()
+    };
+    assert_eq!(load(&invalid_player), Err(LoadStateError::InvalidValue));
+    let mut invalid_palette = Sgb::default();
+    invalid_palette.attributes[7] = 4;
+    assert_eq!(load(&invalid_palette), Err(LoadStateError::InvalidValue));
+    // The first packet announces two packets, but none are missing.
+    let partial_command = Sgb {
+        command: vec![0x11 << 3 | 2; PACKET_SIZE],
+        ..Sgb::default()
+    };
+    assert_eq!(load(&partial_command), Err(LoadStateError::InvalidValue));
+
+    let mut two_players = Sgb::default();
+    send(&mut two_players, 0x11, &[0b01]);
+    assert_eq!(load(&two_players), Ok(()));
+}
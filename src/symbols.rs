@@ -0,0 +1,194 @@
+//! Symbol files name the addresses of a game's code and variables. RGBDS writes them with one
+//! symbol per line as `<bank>:<address> <name>`, both numbers in hexadecimal, and comments start
+//! with `;`. Local labels appear with the name of their parent, like `Main.loop`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// The start of each memory area. A symbol is only used to describe addresses in its own area,
+/// so a variable in work RAM doesn't turn into the last function in ROM plus an offset.
+const AREA_STARTS: [u16; 11] = [
+    0x0000, 0x4000, 0x8000, 0xA000, 0xC000, 0xD000, 0xE000, 0xFE00, 0xFF00, 0xFF80, 0xFFFF,
+];
+
+/// The names of a game's addresses, as loaded from a symbol file.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Symbols {
+    /// The banks and names of the symbols at each address, in the order they were listed.
+    by_address: BTreeMap<u16, Vec<(u16, String)>>,
+    by_name: HashMap<String, (u16, u16)>,
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Self, ParseSymbolsError> {
+        let mut symbols = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default();
+            let words: Vec<_> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let (bank, address, name) =
+                parse_symbol(&words).map_err(|message| ParseSymbolsError {
+                    line: index + 1,
+                    message,
+                })?;
+            symbols.insert(bank, address, name);
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, bank: u16, address: u16, name: &str) {
+        self.by_address
+            .entry(address)
+            .or_default()
+            .push((bank, name.to_string()));
+        self.by_name.insert(name.to_string(), (bank, address));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// The bank and address of a symbol.
+    pub fn address_of(&self, name: &str) -> Option<(u16, u16)> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The names of the symbols at an address, in the order they were listed. Without a bank,
+    /// symbols of all banks match.
+    pub fn names_at(&self, address: u16, bank: Option<u16>) -> impl Iterator<Item = &str> {
+        self.by_address
+            .get(&address)
+            .into_iter()
+            .flatten()
+            .filter(move |(symbol_bank, _)| bank.is_none_or(|bank| bank == *symbol_bank))
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Describes an address as the closest symbol at or before it in the same memory area, plus
+    /// the offset in bytes if there is one, like `Main+12`. Local labels are only used if there's
+    /// no other symbol at their address, so the name of the function is preferred. Without a
+    /// bank, symbols of all banks match.
+    pub fn describe(&self, address: u16, bank: Option<u16>) -> Option<String> {
+        let area_start = AREA_STARTS
+            .into_iter()
+            .rev()
+            .find(|start| *start <= address)
+            .unwrap_or_default();
+        self.by_address
+            .range(area_start..=address)
+            .rev()
+            .find_map(|(symbol_address, _)| {
+                let mut names = self.names_at(*symbol_address, bank);
+                let first = names.next()?;
+                let name = std::iter::once(first)
+                    .chain(names)
+                    .find(|name| !name.contains('.'))
+                    .unwrap_or(first);
+                Some(match address - symbol_address {
+                    0 => name.to_string(),
+                    offset => format!("{name}+{offset}"),
+                })
+            })
+    }
+}
+
+/// Why a symbol file couldn't be parsed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseSymbolsError {
+    /// The number of the invalid line, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseSymbolsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseSymbolsError {}
+
+fn parse_symbol<'a>(words: &[&'a str]) -> Result<(u16, u16, &'a str), String> {
+    let [location, name] = words else {
+        return Err("expected `<bank>:<address> <name>`".to_string());
+    };
+    let (bank, address) = location
+        .split_once(':')
+        .ok_or(format!("expected `<bank>:<address>`: {location}"))?;
+    let bank = u16::from_str_radix(bank, 16).map_err(|_| format!("invalid bank: {bank}"))?;
+    let address =
+        u16::from_str_radix(address, 16).map_err(|_| format!("invalid address: {address}"))?;
+    Ok((bank, address, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMBOLS: &str = "\
+; File generated by rgblink
+00:0100 EntryPoint
+00:0150 Main
+00:0150 Main.init
+00:0158 Main.loop
+01:4000 Graphics
+02:4000 Music
+00:c000 wCounter
+";
+
+    #[test]
+    fn parse() {
+        let symbols = Symbols::parse(SYMBOLS).unwrap();
+        assert_eq!(symbols.address_of("Main.loop"), Some((0, 0x0158)));
+        assert_eq!(symbols.address_of("Music"), Some((2, 0x4000)));
+        assert_eq!(symbols.address_of("Missing"), None);
+        let names: Vec<_> = symbols.names_at(0x0150, None).collect();
+        assert_eq!(names, ["Main", "Main.init"]);
+        let names: Vec<_> = symbols.names_at(0x4000, Some(2)).collect();
+        assert_eq!(names, ["Music"]);
+    }
+
+    #[test]
+    fn describe() {
+        let symbols = Symbols::parse(SYMBOLS).unwrap();
+        assert_eq!(symbols.describe(0x0150, Some(0)).as_deref(), Some("Main"));
+        assert_eq!(symbols.describe(0x0154, Some(0)).as_deref(), Some("Main+4"));
+        assert_eq!(
+            symbols.describe(0x015A, Some(0)).as_deref(),
+            Some("Main.loop+2")
+        );
+        assert_eq!(
+            symbols.describe(0x4010, Some(1)).as_deref(),
+            Some("Graphics+16")
+        );
+        assert_eq!(
+            symbols.describe(0x4010, Some(2)).as_deref(),
+            Some("Music+16")
+        );
+        assert_eq!(symbols.describe(0x4010, Some(3)), None);
+        assert_eq!(symbols.describe(0x00FF, Some(0)), None);
+        // Work RAM isn't described by a symbol in the ROM.
+        assert_eq!(
+            symbols.describe(0xC001, None).as_deref(),
+            Some("wCounter+1")
+        );
+        assert_eq!(symbols.describe(0xD000, None), None);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let error = Symbols::parse("00:0100 EntryPoint\n0100 Main").unwrap_err();
+        assert_eq!(
+            error,
+            ParseSymbolsError {
+                line: 2,
+                message: "expected `<bank>:<address>`: 0100".to_string()
+            }
+        );
+        assert!(Symbols::parse("00:10000 Main").is_err());
+        assert!(Symbols::parse("0g:0100 Main").is_err());
+        assert!(Symbols::parse("00:0100").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! The timer is driven by a 16-bit counter that increases every CPU cycle. Its upper byte is
+//! readable as `DIV`. `TIMA` is incremented whenever the counter bit selected by `TAC` falls from
+//! 1 to 0, and requests an interrupt when it overflows. The same kind of falling edge on a fixed
+//! bit also clocks the APU's frame sequencer.
+
+use crate::memory_map::*;
+use crate::model::Model;
+use crate::save_state::impl_save_state;
+
+/// Bit 2 of `TAC`: Whether `TIMA` is incremented.
+const TIMER_ENABLE_BIT: u8 = 0b100;
+/// Bits 0-1 of `TAC`: How fast `TIMA` is incremented.
+const CLOCK_SELECT_MASK: u8 = 0b11;
+
+/// The counter bit that increments `TIMA` for each clock select value of `TAC`, i.e. every 1024,
+/// 16, 64 or 256 cycles.
+const TIMA_BITS: [u16; 4] = [9, 3, 5, 7];
+/// The counter bit that clocks the APU's frame sequencer at 512 Hz. In double speed mode, the
+/// next higher bit is used, so the frame sequencer keeps its speed.
+const APU_BIT: u16 = 12;
+
+/// After `TIMA` overflows, it reads as 0 for one machine cycle before `TMA` is loaded into it.
+const RELOAD_DELAY: u32 = 4;
+
+#[derive(Default)]
+pub(crate) struct Timer {
+    /// The internal counter. `DIV` is its upper byte.
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    /// The amount of cycles until `TIMA` is reloaded after an overflow, if one happened.
+    reload_delay: Option<u32>,
+}
+
+impl_save_state!(Timer {
+    counter,
+    tima,
+    tma,
+    tac,
+    reload_delay,
+});
+
+/// What happened during a call to [`Timer::step`].
+#[derive(Default)]
+pub(crate) struct TimerEvents {
+    pub(crate) interrupt: bool,
+    /// How often the APU's frame sequencer has to be clocked.
+    pub(crate) apu_clocks: u32,
+}
+
+impl Timer {
+    /// The timer as the given model's boot ROM leaves it. Only the values of the DMG revisions
+    /// are known; on other models, the boot ROM's run time isn't fixed.
+    pub(crate) fn after_boot(model: Model) -> Self {
+        let counter = match model {
+            Model::Dmg0 => 0x1830,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            _ => 0,
+        };
+        Self {
+            counter,
+            ..Self::default()
+        }
+    }
+
+    /// Advances the timer by the given amount of CPU cycles.
+    pub(crate) fn step(&mut self, cycles: u32, double_speed: bool) -> TimerEvents {
+        let mut events = TimerEvents::default();
+        let apu_bit = if double_speed { APU_BIT + 1 } else { APU_BIT };
+
+        for _ in 0..cycles {
+            if let Some(delay) = self.reload_delay {
+                if delay == 1 {
+                    self.tima = self.tma;
+                    self.reload_delay = None;
+                    events.interrupt = true;
+                } else {
+                    self.reload_delay = Some(delay - 1);
+                }
+            }
+
+            let old = self.counter;
+            self.counter = self.counter.wrapping_add(1);
+            if self.timer_bit(old) && !self.timer_bit(self.counter) {
+                self.increment_tima();
+            }
+            if falling_edge(old, self.counter, apu_bit) {
+                events.apu_clocks += 1;
+            }
+        }
+        events
+    }
+
+    /// Whether the counter bit selected by `TAC` is set, taking into account whether the timer is
+    /// enabled. `TIMA` is incremented whenever this goes from `true` to `false`.
+    fn timer_bit(&self, counter: u16) -> bool {
+        let bit = TIMA_BITS[(self.tac & CLOCK_SELECT_MASK) as usize];
+        self.tac & TIMER_ENABLE_BIT != 0 && (counter >> bit) & 1 != 0
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflow {
+            self.reload_delay = Some(RELOAD_DELAY);
+        }
+    }
+
+    pub(crate) fn read_register(&self, address: usize) -> u8 {
+        match address {
+            DIVIDER_REGISTER => (self.counter >> 8) as u8,
+            TIMER_COUNTER_REGISTER => self.tima,
+            TIMER_MODULO_REGISTER => self.tma,
+            // Only the lower three bits are used, all others read as set.
+            TIMER_CONTROL_REGISTER => 0b1111_1000 | self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes one of the timer's registers. Returns how often the APU's frame sequencer has to be
+    /// clocked, as resetting `DIV` can cause a falling edge.
+    pub(crate) fn write_register(&mut self, address: usize, value: u8, double_speed: bool) -> u32 {
+        match address {
+            DIVIDER_REGISTER => {
+                // Resetting the counter can cause a falling edge, just like an increment.
+                let old = self.counter;
+                self.counter = 0;
+                if self.timer_bit(old) {
+                    self.increment_tima();
+                }
+                let apu_bit = if double_speed { APU_BIT + 1 } else { APU_BIT };
+                return falling_edge(old, 0, apu_bit) as u32;
+            }
+            TIMER_COUNTER_REGISTER => {
+                // Writing during the reload delay cancels the reload.
+                self.tima = value;
+                self.reload_delay = None;
+            }
+            TIMER_MODULO_REGISTER => self.tma = value,
+            TIMER_CONTROL_REGISTER => {
+                // Changing the selected bit or disabling the timer can cause a falling edge too.
+                let was_set = self.timer_bit(self.counter);
+                self.tac = value & (TIMER_ENABLE_BIT | CLOCK_SELECT_MASK);
+                if was_set && !self.timer_bit(self.counter) {
+                    self.increment_tima();
+                }
+            }
+            _ => (),
+        }
+        0
+    }
+}
+
+fn falling_edge(old: u16, new: u16, bit: u16) -> bool {
+    (old >> bit) & 1 != 0 && (new >> bit) & 1 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn div_increments_every_256_cycles() {
+        let mut timer = Timer::default();
+        timer.step(255, false);
+        assert_eq!(timer.read_register(DIVIDER_REGISTER), 0);
+        timer.step(1, false);
+        assert_eq!(timer.read_register(DIVIDER_REGISTER), 1);
+
+        timer.write_register(DIVIDER_REGISTER, 0x12, false);
+        assert_eq!(timer.read_register(DIVIDER_REGISTER), 0);
+    }
+
+    #[test]
+    fn tima_overflow_reloads_after_delay() {
+        let mut timer = Timer::default();
+        timer.write_register(TIMER_MODULO_REGISTER, 0x42, false);
+        timer.write_register(TIMER_COUNTER_REGISTER, 0xFF, false);
+        // Increment every 16 cycles.
+        timer.write_register(TIMER_CONTROL_REGISTER, 0b101, false);
+
+        let events = timer.step(16, false);
+        assert!(!events.interrupt);
+        assert_eq!(timer.read_register(TIMER_COUNTER_REGISTER), 0x00);
+
+        let events = timer.step(4, false);
+        assert!(events.interrupt);
+        assert_eq!(timer.read_register(TIMER_COUNTER_REGISTER), 0x42);
+    }
+
+    #[test]
+    fn disabled_timer() {
+        let mut timer = Timer::default();
+        timer.write_register(TIMER_CONTROL_REGISTER, 0b001, false);
+        timer.step(1024, false);
+
+        assert_eq!(timer.read_register(TIMER_COUNTER_REGISTER), 0);
+        assert_eq!(timer.read_register(TIMER_CONTROL_REGISTER), 0xF9);
+    }
+
+    #[test]
+    fn resetting_div_can_increment_tima() {
+        let mut timer = Timer::default();
+        timer.write_register(TIMER_CONTROL_REGISTER, 0b100, false);
+        timer.step(512, false);
+        timer.write_register(DIVIDER_REGISTER, 0, false);
+
+        assert_eq!(timer.read_register(TIMER_COUNTER_REGISTER), 1);
+    }
+
+    #[test]
+    fn apu_clock() {
+        let mut timer = Timer::default();
+        assert_eq!(timer.step(8192 * 3, false).apu_clocks, 3);
+        assert_eq!(timer.step(8192 * 2, true).apu_clocks, 1);
+    }
+}
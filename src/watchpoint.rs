@@ -0,0 +1,38 @@
+//! Watchpoints record the CPU's accesses to ranges of memory, so a debugger can stop when a game
+//! reads or writes a variable. Accesses of the DMA and the PPU aren't recorded.
+
+use std::ops::RangeInclusive;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// Watches the CPU's reads, writes or both in a range of addresses.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    /// Whether the access is watched.
+    pub fn matches(&self, address: u16, kind: AccessKind) -> bool {
+        let watched = match kind {
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+        };
+        watched && self.range.contains(&address)
+    }
+}
+
+/// An access to a watched address. Reads include fetching instructions and their operands.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct WatchpointHit {
+    pub address: u16,
+    /// The value that was read or written.
+    pub value: u8,
+    pub kind: AccessKind,
+}
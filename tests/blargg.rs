@@ -0,0 +1,223 @@
+//! Runs Blargg's test ROMs for the CPU's instructions and the timing of instructions and memory
+//! accesses. Every ROM of a suite is a subtest, and all of them are run in parallel. The result of
+//! each one is printed, and the suite fails if any of them does.
+//!
+//! The ROMs report their result as text through the serial port, ending in "Passed" or "Failed".
+//! Newer ones write it to cartridge RAM instead, preceded by a status byte and a signature.
+
+mod common;
+
+use gameboy_emu::{GameBoy, Model};
+use std::fmt;
+
+/// How long a ROM may run before it's considered stuck. The slowest ROMs take about 20 seconds.
+const TIMEOUT_FRAMES: u64 = 60 * 60;
+
+/// The status byte is followed by the signature, then by the text as a null-terminated string.
+const STATUS_ADDRESS: u16 = 0xA000;
+const SIGNATURE_ADDRESS: u16 = 0xA001;
+const TEXT_ADDRESS: u16 = 0xA004;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+/// The status while the test is still running. Once it's done, 0 means that it passed.
+const STATUS_RUNNING: u8 = 0x80;
+/// Limits the text read from cartridge RAM, in case the null terminator is missing.
+const MAX_TEXT_LENGTH: u16 = 0x1000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Outcome {
+    Passed,
+    Failed,
+    TimedOut,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::Failed => write!(f, "failed"),
+            Outcome::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// The result of a ROM, together with the text it output.
+struct Report {
+    outcome: Outcome,
+    text: String,
+}
+
+fn run_test_rom(rom: &[u8]) -> Report {
+    let mut gameboy = GameBoy::with_model(rom, Model::Dmg).unwrap();
+    for _ in 0..TIMEOUT_FRAMES {
+        gameboy.run_frame();
+        if let Some(report) = memory_result(&gameboy).or_else(|| serial_result(&gameboy)) {
+            return report;
+        }
+    }
+    Report {
+        outcome: Outcome::TimedOut,
+        text: String::from_utf8_lossy(gameboy.serial_output()).into_owned(),
+    }
+}
+
+fn serial_result(gameboy: &GameBoy) -> Option<Report> {
+    let text = String::from_utf8_lossy(gameboy.serial_output());
+    let outcome = if text.contains("Passed") {
+        Outcome::Passed
+    } else if text.contains("Failed") {
+        Outcome::Failed
+    } else {
+        return None;
+    };
+    Some(Report {
+        outcome,
+        text: text.into_owned(),
+    })
+}
+
+fn memory_result(gameboy: &GameBoy) -> Option<Report> {
+    let signature = [0, 1, 2].map(|offset| gameboy.peek(SIGNATURE_ADDRESS + offset));
+    let status = gameboy.peek(STATUS_ADDRESS);
+    if signature != SIGNATURE || status == STATUS_RUNNING {
+        return None;
+    }
+    let text: Vec<_> = (TEXT_ADDRESS..TEXT_ADDRESS + MAX_TEXT_LENGTH)
+        .map(|address| gameboy.peek(address))
+        .take_while(|byte| *byte != 0)
+        .collect();
+    Some(Report {
+        outcome: if status == 0 {
+            Outcome::Passed
+        } else {
+            Outcome::Failed
+        },
+        text: String::from_utf8_lossy(&text).into_owned(),
+    })
+}
+
+/// Runs all ROMs in a directory of the fixtures directory as subtests.
+fn run_suite(dir: &str) {
+    let roms = common::fixture_roms(dir);
+    assert!(!roms.is_empty(), "no ROMs in {dir}");
+    let reports: Vec<_> = std::thread::scope(|scope| {
+        let threads: Vec<_> = roms
+            .iter()
+            .map(|(_, rom)| scope.spawn(|| run_test_rom(rom)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    });
+
+    let mut failures = Vec::new();
+    for ((name, _), report) in roms.iter().zip(&reports) {
+        println!("{name}: {}", report.outcome);
+        if report.outcome != Outcome::Passed {
+            failures.push(format!(
+                "{name}: {}\n{}",
+                report.outcome,
+                report.text.trim()
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} subtests failed:\n\n{}",
+        failures.len(),
+        roms.len(),
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+#[ignore = "needs Blargg's ROMs, see tests/fixtures/README.md"]
+fn cpu_instrs() {
+    run_suite("blargg/cpu_instrs/individual");
+}
+
+#[test]
+#[ignore = "needs Blargg's ROMs, see tests/fixtures/README.md"]
+fn instr_timing() {
+    run_suite("blargg/instr_timing");
+}
+
+#[test]
+#[ignore = "needs Blargg's ROMs, see tests/fixtures/README.md"]
+fn mem_timing() {
+    run_suite("blargg/mem_timing/individual");
+}
+
+#[test]
+#[ignore = "needs Blargg's ROMs, see tests/fixtures/README.md"]
+fn mem_timing_2() {
+    run_suite("blargg/mem_timing-2/rom_singles");
+}
+
+/// Sends the null-terminated string at 0x0200 through the serial port, like the ROMs do.
+const SERIAL_PROGRAM: [u8; 23] = [
+    0x21, 0x00, 0x02, // LD HL, 0x0200
+    0x2A, // LD A, [HL+]
+    0xB7, // OR A
+    0x28, 0x0E, // JR Z, +14
+    0xE0, 0x01, // LDH [SB], A
+    0x3E, 0x81, // LD A, 0x81
+    0xE0, 0x02, // LDH [SC], A
+    0xF0, 0x02, // LDH A, [SC]
+    0xCB, 0x7F, // BIT 7, A
+    0x20, 0xFA, // JR NZ, -6
+    0x18, 0xEE, // JR -18
+    0x18, 0xFE, // JR -2
+];
+
+/// Reports "OK" in cartridge RAM, with the status byte at offset 29 of the program.
+const MEMORY_PROGRAM: [u8; 35] = [
+    0x3E, 0x0A, // LD A, 0x0A
+    0xEA, 0x00, 0x00, // LD [0x0000], A (enables the RAM)
+    0x21, 0x00, 0xA0, // LD HL, 0xA000
+    0x3E, 0x80, // LD A, 0x80
+    0x22, // LD [HL+], A
+    0x3E, 0xDE, // LD A, 0xDE
+    0x22, // LD [HL+], A
+    0x3E, 0xB0, // LD A, 0xB0
+    0x22, // LD [HL+], A
+    0x3E, 0x61, // LD A, 0x61
+    0x22, // LD [HL+], A
+    0x3E, b'O', // LD A, 'O'
+    0x22, // LD [HL+], A
+    0x3E, b'K', // LD A, 'K'
+    0x22, // LD [HL+], A
+    0xAF, // XOR A
+    0x77, // LD [HL], A
+    0x3E, 0x00, // LD A, <status>
+    0xEA, 0x00, 0xA0, // LD [0xA000], A
+    0x18, 0xFE, // JR -2
+];
+
+#[test]
+fn result_from_serial_output() {
+    let mut rom = common::rom_with_program(&SERIAL_PROGRAM, common::MBC1_RAM);
+    let text = b"01-special\n\n\nPassed\n\0";
+    rom[0x0200..0x0200 + text.len()].copy_from_slice(text);
+    let report = run_test_rom(&rom);
+    assert_eq!(report.outcome, Outcome::Passed);
+    assert_eq!(report.text, "01-special\n\n\nPassed\n");
+
+    let text = b"Failed #2\n\0";
+    rom[0x0200..0x0200 + text.len()].copy_from_slice(text);
+    assert_eq!(run_test_rom(&rom).outcome, Outcome::Failed);
+}
+
+#[test]
+fn result_from_memory() {
+    let mut program = MEMORY_PROGRAM;
+    let report = run_test_rom(&common::rom_with_program(&program, common::MBC1_RAM));
+    assert_eq!(report.outcome, Outcome::Passed);
+    assert_eq!(report.text, "OK");
+
+    program[29] = 0x01;
+    assert_eq!(
+        run_test_rom(&common::rom_with_program(&program, common::MBC1_RAM)).outcome,
+        Outcome::Failed
+    );
+}
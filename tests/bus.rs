@@ -0,0 +1,111 @@
+//! Drives the CPU with buses other than the console's: plain RAM, and a bus that records every
+//! access of another one.
+
+use gameboy_emu::{Bus, Cpu, CpuRegisters, asm};
+
+/// 64 KiB of RAM that counts the machine cycles.
+struct FlatBus {
+    memory: Vec<u8>,
+    cycles: u64,
+}
+
+impl FlatBus {
+    fn with_program(program: &[u8]) -> Self {
+        let mut memory = vec![0; 0x10000];
+        memory[..program.len()].copy_from_slice(program);
+        Self { memory, cycles: 0 }
+    }
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn tick(&mut self) {
+        self.cycles += 1;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+/// Records the accesses to the bus it wraps.
+struct TracingBus<B> {
+    inner: B,
+    trace: Vec<Access>,
+}
+
+impl<B: Bus> Bus for TracingBus<B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.inner.read(address);
+        self.trace.push(Access::Read(address, value));
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.inner.write(address, value);
+        self.trace.push(Access::Write(address, value));
+    }
+
+    fn tick(&mut self) {
+        self.inner.tick();
+    }
+}
+
+/// Adds the numbers from 1 to 10 and stores the sum at 0xC000.
+const SUM_PROGRAM: &str = "
+        xor a
+        ld b, 10
+    loop:
+        add b
+        dec b
+        jr nz, loop
+        ld [$C000], a
+    done:
+        jr done
+";
+
+#[test]
+fn flat_ram() {
+    let mut cpu = Cpu::new(FlatBus::with_program(&asm!(SUM_PROGRAM)));
+    let mut cycles = 0;
+    while cpu.registers().pc != 0x000A {
+        cycles += cpu.step();
+    }
+    assert_eq!(cpu.bus().memory[0xC000], 55);
+    assert_eq!(cpu.registers().a, 55);
+    assert_eq!(cycles as u64, cpu.bus().cycles * 4);
+}
+
+#[test]
+fn tracing() {
+    let bus = TracingBus {
+        inner: FlatBus::with_program(&[0xC5]), // PUSH BC
+        trace: Vec::new(),
+    };
+    let mut cpu = Cpu::new(bus);
+    cpu.set_registers(CpuRegisters {
+        b: 0x12,
+        c: 0x34,
+        sp: 0xD000,
+        ..CpuRegisters::default()
+    });
+    assert_eq!(cpu.step(), 16);
+    assert_eq!(
+        cpu.bus().trace,
+        [
+            Access::Read(0x0000, 0xC5),
+            Access::Write(0xCFFF, 0x12),
+            Access::Write(0xCFFE, 0x34),
+        ]
+    );
+    assert_eq!(cpu.registers().sp, 0xCFFE);
+}
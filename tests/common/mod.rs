@@ -0,0 +1,76 @@
+//! Helpers shared by the integration tests.
+
+// Every test crate includes this module, but not all of them use every helper.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// The directory with test ROMs from third-party test suites, which aren't part of the
+/// repository. Defaults to `tests/fixtures` and can be changed with `GAMEBOY_FIXTURES`.
+pub fn fixtures_dir() -> PathBuf {
+    match std::env::var_os("GAMEBOY_FIXTURES") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"),
+    }
+}
+
+/// The cartridge type of a ROM without an MBC.
+pub const ROM_ONLY: u8 = 0x00;
+/// The cartridge type of an MBC1 with RAM.
+pub const MBC1_RAM: u8 = 0x02;
+
+/// A 32 KiB cartridge of the given type with 8 KiB of RAM. It jumps over the header and runs the
+/// program from 0x0150.
+pub fn rom_with_program(program: &[u8], cartridge_type: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // NOP, then JP 0x0150 to skip the header.
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x0147] = cartridge_type;
+    rom[0x0149] = 0x02;
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+    rom
+}
+
+/// Reads a file from the fixtures directory. Panics if it doesn't exist, so that a missing fixture
+/// fails the test instead of passing it.
+pub fn fixture(path: &str) -> Vec<u8> {
+    let path = fixtures_dir().join(path);
+    std::fs::read(&path).unwrap_or_else(|error| panic!("{}", missing(&path, error)))
+}
+
+/// Lists the ROMs in a directory of the fixtures directory, sorted by name, together with their
+/// file names. Panics if the directory doesn't exist.
+pub fn fixture_roms(dir: &str) -> Vec<(String, Vec<u8>)> {
+    let dir = fixtures_dir().join(dir);
+    let entries =
+        std::fs::read_dir(&dir).unwrap_or_else(|error| panic!("{}", missing(&dir, error)));
+    let mut roms: Vec<_> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "gb" || extension == "gbc")
+        })
+        .map(|path| {
+            let rom = std::fs::read(&path)
+                .unwrap_or_else(|error| panic!("could not read {}: {error}", path.display()));
+            (
+                path.file_name().unwrap().to_string_lossy().into_owned(),
+                rom,
+            )
+        })
+        .collect();
+    roms.sort();
+    roms
+}
+
+/// Explains how to provide a fixture that couldn't be read.
+fn missing(path: &Path, error: std::io::Error) -> String {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        format!(
+            "{} not found; see tests/fixtures/README.md, or set GAMEBOY_FIXTURES",
+            path.display()
+        )
+    } else {
+        format!("could not read {}: {error}", path.display())
+    }
+}
@@ -0,0 +1,195 @@
+//! Golden-image tests: Each runs a ROM for a fixed amount of frames, pressing buttons as its input
+//! script says, and compares the screen to a reference image pixel by pixel. On a mismatch, the
+//! screen and a diff image are written to `target/tmp/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current screens as the new reference images instead.
+
+mod common;
+
+use gameboy_emu::{GameBoy, Image, InputScript, ManualPalette, Model};
+use std::path::{Path, PathBuf};
+
+/// Draws vertical stripes in all four shades, which are inverted while Right is held.
+const STRIPES_PROGRAM: [u8; 45] = [
+    0xF0, 0x44, // LDH A, [LY]
+    0xFE, 0x90, // CP 144
+    0x20, 0xFA, // JR NZ, -6
+    0xAF, // XOR A
+    0xE0, 0x40, // LDH [LCDC], A
+    0x21, 0x00, 0x80, // LD HL, 0x8000
+    0x06, 0x08, // LD B, 8
+    // Fill tile 0 with columns of color indices 0, 1, 2, 3, 0, 1, 2, 3.
+    0x3E, 0x55, // LD A, 0x55
+    0x22, // LD [HL+], A
+    0x3E, 0x33, // LD A, 0x33
+    0x22, // LD [HL+], A
+    0x05, // DEC B
+    0x20, 0xF7, // JR NZ, -9
+    0x3E, 0x91, // LD A, 0x91
+    0xE0, 0x40, // LDH [LCDC], A
+    // Read the directional pad and invert the palette while Right is held.
+    0x3E, 0x20, // LD A, 0x20
+    0xE0, 0x00, // LDH [P1], A
+    0xF0, 0x00, // LDH A, [P1]
+    0xCB, 0x47, // BIT 0, A
+    0x3E, 0xE4, // LD A, 0xE4
+    0x20, 0x02, // JR NZ, +2
+    0x3E, 0x1B, // LD A, 0x1B
+    0xE0, 0x47, // LDH [BGP], A
+    0x18, 0xEE, // JR -18
+];
+
+struct GoldenTest<'a> {
+    /// Names the images written on a mismatch.
+    name: &'a str,
+    rom: &'a [u8],
+    model: Model,
+    /// Colorizes a DMG game on a CGB as if the buttons had been held during the boot animation.
+    palette: Option<ManualPalette>,
+    frames: u64,
+    /// The buttons to press, in the format of [`InputScript`].
+    input: &'a str,
+    reference: PathBuf,
+}
+
+fn check(test: &GoldenTest) {
+    let mut script = InputScript::parse(test.input).unwrap();
+    let mut gameboy = match test.palette {
+        Some(palette) => GameBoy::with_palette(test.rom, test.model, palette),
+        None => GameBoy::with_model(test.rom, test.model),
+    }
+    .unwrap();
+    for frame in 0..test.frames {
+        for event in script.take_due(frame) {
+            gameboy.set_button(event.button, event.pressed);
+        }
+        gameboy.run_frame();
+    }
+    let screen = gameboy.screenshot();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write(&test.reference, &screen.encode_png());
+        return;
+    }
+    let reference = std::fs::read(&test.reference).unwrap_or_else(|error| {
+        panic!(
+            "could not read {}: {error}; run with UPDATE_GOLDEN=1 to create it",
+            test.reference.display()
+        )
+    });
+    let reference = Image::decode_png(&reference)
+        .unwrap_or_else(|error| panic!("{}: {error}", test.reference.display()));
+    assert_eq!(
+        (reference.width(), reference.height()),
+        (screen.width(), screen.height()),
+        "{} has the wrong size",
+        test.reference.display()
+    );
+
+    if let Some(diff) = screen.diff(&reference) {
+        let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        write(
+            &output.join(format!("{}.png", test.name)),
+            &screen.encode_png(),
+        );
+        write(
+            &output.join(format!("{}-diff.png", test.name)),
+            &diff.encode_png(),
+        );
+        let differences = screen
+            .pixels()
+            .iter()
+            .zip(reference.pixels())
+            .filter(|(pixel, reference)| pixel != reference)
+            .count();
+        panic!(
+            "{}: {differences} pixels differ from {}, see {}",
+            test.name,
+            test.reference.display(),
+            output.display()
+        );
+    }
+}
+
+fn write(path: &Path, data: &[u8]) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, data)
+        .unwrap_or_else(|error| panic!("could not write {}: {error}", path.display()));
+}
+
+fn stripes_rom() -> Vec<u8> {
+    common::rom_with_program(&STRIPES_PROGRAM, common::ROM_ONLY)
+}
+
+fn golden_reference(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"))
+}
+
+#[test]
+fn stripes() {
+    check(&GoldenTest {
+        name: "stripes",
+        rom: &stripes_rom(),
+        model: Model::Dmg,
+        palette: None,
+        frames: 8,
+        input: "",
+        reference: golden_reference("stripes"),
+    });
+}
+
+#[test]
+fn stripes_inverted() {
+    check(&GoldenTest {
+        name: "stripes-inverted",
+        rom: &stripes_rom(),
+        model: Model::Dmg,
+        palette: None,
+        frames: 8,
+        input: "3 press right",
+        reference: golden_reference("stripes-inverted"),
+    });
+}
+
+#[test]
+fn stripes_manual_palette() {
+    check(&GoldenTest {
+        name: "stripes-manual-palette",
+        rom: &stripes_rom(),
+        model: Model::Cgb,
+        palette: Some(ManualPalette::DownA),
+        frames: 8,
+        input: "",
+        reference: golden_reference("stripes-manual-palette"),
+    });
+}
+
+#[test]
+#[ignore = "needs the acid2 ROMs, see tests/fixtures/README.md"]
+fn dmg_acid2() {
+    check(&GoldenTest {
+        name: "dmg-acid2",
+        rom: &common::fixture("acid2/dmg-acid2.gb"),
+        model: Model::Dmg,
+        palette: None,
+        frames: 30,
+        input: "",
+        reference: common::fixtures_dir().join("acid2/dmg-acid2.png"),
+    });
+}
+
+#[test]
+#[ignore = "needs the acid2 ROMs, see tests/fixtures/README.md"]
+fn cgb_acid2() {
+    check(&GoldenTest {
+        name: "cgb-acid2",
+        rom: &common::fixture("acid2/cgb-acid2.gbc"),
+        model: Model::Cgb,
+        palette: None,
+        frames: 30,
+        input: "",
+        reference: common::fixtures_dir().join("acid2/cgb-acid2.png"),
+    });
+}
@@ -0,0 +1,202 @@
+//! Runs the acceptance tests of the Mooneye test suite, grouped by the subsystem they test. Every
+//! ROM of a group is a subtest, and all of them are run in parallel. The result of each one is
+//! printed, and the group fails if any of them does.
+//!
+//! The ROMs end by executing `LD B,B`. A test passed if the registers then contain the first
+//! Fibonacci numbers, and failed if they contain anything else, usually 0x42 in all of them.
+//!
+//! The ROMs aren't part of the repository, so these tests only run with `cargo test -- --ignored`.
+
+mod common;
+
+use gameboy_emu::{CpuRegisters, GameBoy, Model};
+use std::fmt;
+
+/// The opcode of `LD B,B`, which the ROMs execute once they are done.
+const BREAKPOINT_OPCODE: u8 = 0x40;
+/// B, C, D, E, H and L after a test passed.
+const PASS_REGISTERS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+/// How long a ROM may run before it's considered stuck. Most finish within a second.
+const TIMEOUT_SECONDS: u64 = 20;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Outcome {
+    Passed,
+    Failed(CpuRegisters),
+    TimedOut,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::Failed(registers) => {
+                let CpuRegisters {
+                    b, c, d, e, h, l, ..
+                } = registers;
+                write!(
+                    f,
+                    "failed (B={b:02X} C={c:02X} D={d:02X} E={e:02X} H={h:02X} L={l:02X})"
+                )
+            }
+            Outcome::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// Runs the ROM until it reaches the breakpoint, or until the given amount of CPU cycles passed.
+fn run_test_rom(rom: &[u8], model: Model, timeout_cycles: u64) -> Outcome {
+    let mut gameboy = GameBoy::with_model(rom, model).unwrap();
+    let mut cycles = 0;
+    while cycles < timeout_cycles {
+        let registers = gameboy.registers();
+        if gameboy.peek(registers.pc) == BREAKPOINT_OPCODE {
+            let CpuRegisters {
+                b, c, d, e, h, l, ..
+            } = registers;
+            return if [b, c, d, e, h, l] == PASS_REGISTERS {
+                Outcome::Passed
+            } else {
+                Outcome::Failed(registers)
+            };
+        }
+        cycles += gameboy.step_instruction() as u64;
+    }
+    Outcome::TimedOut
+}
+
+/// Picks a model the ROM supports from the suffix of its file name, like `-dmgABC` or `-GS`.
+/// ROMs without a suffix run on every model, and so do the ones with an unknown suffix.
+fn model_for(file_name: &str) -> Model {
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    let Some((_, suffix)) = stem.rsplit_once('-') else {
+        return Model::Dmg;
+    };
+    match suffix {
+        "dmg0" => Model::Dmg0,
+        "mgb" => Model::Mgb,
+        "sgb" | "S" => Model::Sgb,
+        "sgb2" => Model::Sgb2,
+        "A" => Model::Agb,
+        _ if suffix.starts_with("cgb") || suffix == "C" => Model::Cgb,
+        _ => Model::Dmg,
+    }
+}
+
+/// Runs all ROMs in a directory of the acceptance tests as subtests.
+fn run_group(dir: &str) {
+    let dir = format!("mooneye/acceptance/{dir}");
+    let roms = common::fixture_roms(&dir);
+    assert!(!roms.is_empty(), "no ROMs in {dir}");
+    let outcomes: Vec<_> = std::thread::scope(|scope| {
+        let threads: Vec<_> = roms
+            .iter()
+            .map(|(name, rom)| {
+                let model = model_for(name);
+                let timeout = TIMEOUT_SECONDS * model.clock_rate() as u64;
+                scope.spawn(move || (model, run_test_rom(rom, model, timeout)))
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    });
+
+    let mut failures = Vec::new();
+    for ((name, _), (model, outcome)) in roms.iter().zip(&outcomes) {
+        let line = format!("{name} ({model:?}): {outcome}");
+        println!("{line}");
+        if *outcome != Outcome::Passed {
+            failures.push(line);
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} tests in {dir} failed:\n{}",
+        failures.len(),
+        roms.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+#[ignore = "needs the Mooneye ROMs, see tests/fixtures/README.md"]
+fn general() {
+    run_group("");
+}
+
+#[test]
+#[ignore = "needs the Mooneye ROMs, see tests/fixtures/README.md"]
+fn timer() {
+    run_group("timer");
+}
+
+#[test]
+#[ignore = "needs the Mooneye ROMs, see tests/fixtures/README.md"]
+fn ppu() {
+    run_group("ppu");
+}
+
+#[test]
+#[ignore = "needs the Mooneye ROMs, see tests/fixtures/README.md"]
+fn oam_dma() {
+    run_group("oam_dma");
+}
+
+#[test]
+#[ignore = "needs the Mooneye ROMs, see tests/fixtures/README.md"]
+fn interrupts() {
+    run_group("interrupts");
+}
+
+#[test]
+#[ignore = "needs the Mooneye ROMs, see tests/fixtures/README.md"]
+fn bits() {
+    run_group("bits");
+}
+
+/// Loads the Fibonacci numbers into the registers, then stops at the breakpoint.
+const PASSING_PROGRAM: [u8; 13] = [
+    0x06, 0x03, // LD B, 3
+    0x0E, 0x05, // LD C, 5
+    0x16, 0x08, // LD D, 8
+    0x1E, 0x0D, // LD E, 13
+    0x26, 0x15, // LD H, 21
+    0x2E, 0x22, // LD L, 34
+    0x40, // LD B, B
+];
+
+#[test]
+fn pass_and_fail_signatures() {
+    let rom = common::rom_with_program(&PASSING_PROGRAM, common::ROM_ONLY);
+    assert_eq!(run_test_rom(&rom, Model::Dmg, 1000), Outcome::Passed);
+
+    let mut program = PASSING_PROGRAM;
+    program[11] = 0x42;
+    let rom = common::rom_with_program(&program, common::ROM_ONLY);
+    let Outcome::Failed(registers) = run_test_rom(&rom, Model::Dmg, 1000) else {
+        panic!("the test should fail");
+    };
+    assert_eq!(registers.l, 0x42);
+}
+
+#[test]
+fn timeout() {
+    // JR -2
+    let rom = common::rom_with_program(&[0x18, 0xFE], common::ROM_ONLY);
+    assert_eq!(run_test_rom(&rom, Model::Dmg, 1000), Outcome::TimedOut);
+}
+
+#[test]
+fn models_from_file_names() {
+    assert_eq!(model_for("div_timing.gb"), Model::Dmg);
+    assert_eq!(model_for("boot_regs-dmg0.gb"), Model::Dmg0);
+    assert_eq!(model_for("boot_regs-dmgABC.gb"), Model::Dmg);
+    assert_eq!(model_for("boot_hwio-S.gb"), Model::Sgb);
+    assert_eq!(model_for("boot_regs-cgb.gb"), Model::Cgb);
+    assert_eq!(model_for("hblank_ly_scx_timing-GS.gb"), Model::Dmg);
+    assert_eq!(model_for("unused_hwio-GS.gb"), Model::Dmg);
+}